@@ -1,39 +1,37 @@
-extern crate discord;
-
-use discord::model::Event;
-use discord::Discord;
+use discord_tokio::model::Event;
+use discord_tokio::{ConnectExt, Discord, Error, LoginExt, MessageExt};
 use std::env;
 
-fn main() {
-	// Log in to Discord using a bot token from the environment
-	let discord = Discord::from_bot_token(&env::var("DISCORD_TOKEN").expect("Expected token"))
-		.expect("login failed");
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    // Log in to Discord using a bot token from the environment
+    let discord = Discord::from_bot_token(&env::var("DISCORD_TOKEN").expect("Expected token"))
+        .expect("login failed");
 
-	// Establish and use a websocket connection
-	let (mut connection, _) = discord.connect().expect("connect failed");
-	println!("Ready.");
-	loop {
-		match connection.recv_event() {
-			Ok(Event::MessageCreate(message)) => {
-				println!("{} says: {}", message.author.name, message.content);
-				if message.content == "!test" {
-					let _ = discord.send_message(
-						message.channel_id,
-						"This is a reply to the test.",
-						"",
-						false,
-					);
-				} else if message.content == "!quit" {
-					println!("Quitting.");
-					break;
-				}
-			}
-			Ok(_) => {}
-			Err(discord::Error::Closed(code, body)) => {
-				println!("Gateway closed on us with code {:?}: {}", code, body);
-				break;
-			}
-			Err(err) => println!("Receive error: {:?}", err),
-		}
-	}
+    // Establish and use a websocket connection
+    let (mut connection, _) = discord.connect().await.expect("connect failed");
+    println!("Ready.");
+    loop {
+        match connection.recv_event().await {
+            Ok(Event::MessageCreate(message)) => {
+                println!("{} says: {}", message.author.name, message.content);
+                if message.content == "!test" {
+                    let _ = discord
+                        .send_message(message.channel_id, |m| {
+                            m.content("This is a reply to the test.")
+                        })
+                        .await;
+                } else if message.content == "!quit" {
+                    println!("Quitting.");
+                    break;
+                }
+            }
+            Ok(_) => {}
+            Err(Error::Closed(code, body)) => {
+                println!("Gateway closed on us with code {:?}: {}", code, body);
+                break;
+            }
+            Err(err) => println!("Receive error: {:?}", err),
+        }
+    }
 }
@@ -1,86 +1,82 @@
-extern crate discord;
-
-use discord::model::{ChannelType, Event};
-use discord::{ChannelRef, Discord, State};
+use discord_tokio::model::{Event, ServerChannel};
+use discord_tokio::{ChannelRef, ConnectExt, Discord, Error, LoginExt, State};
 use std::env;
 
-fn main() {
-	// Log in to Discord using a bot token from the environment
-	let discord = Discord::from_bot_token(&env::var("DISCORD_TOKEN").expect("Expected token"))
-		.expect("login failed");
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    // Log in to Discord using a bot token from the environment
+    let discord = Discord::from_bot_token(&env::var("DISCORD_TOKEN").expect("Expected token"))
+        .expect("login failed");
+
+    // Establish the websocket connection
+    let (mut connection, ready) = discord.connect().await.expect("connect failed");
+    let mut state = State::new(ready);
+    println!(
+        "[Ready] {} logging {} servers",
+        state.user().username,
+        state.all_servers().len(),
+    );
 
-	// Establish the websocket connection
-	let (mut connection, ready) = discord.connect().expect("connect failed");
-	let mut state = State::new(ready);
-	let channel_count: usize = state
-		.servers()
-		.iter()
-		.map(|srv| {
-			srv.channels
-				.iter()
-				.filter(|chan| chan.kind == ChannelType::Text)
-				.count()
-		})
-		.fold(0, |v, s| v + s);
-	println!(
-		"[Ready] {} logging {} servers with {} text channels",
-		state.user().username,
-		state.servers().len(),
-		channel_count
-	);
+    loop {
+        // Receive an event and update the state with it
+        let event = match connection.recv_event().await {
+            Ok(event) => event,
+            Err(Error::Closed(code, body)) => {
+                println!("[Error] Connection closed with status {:?}: {}", code, body);
+                break;
+            }
+            Err(err) => {
+                println!("[Warning] Receive error: {:?}", err);
+                continue;
+            }
+        };
+        state.update(&event);
 
-	loop {
-		// Receive an event and update the state with it
-		let event = match connection.recv_event() {
-			Ok(event) => event,
-			Err(discord::Error::Closed(code, body)) => {
-				println!("[Error] Connection closed with status {:?}: {}", code, body);
-				break;
-			}
-			Err(err) => {
-				println!("[Warning] Receive error: {:?}", err);
-				continue;
-			}
-		};
-		state.update(&event);
+        // Log messages
+        if let Event::MessageCreate(message) = event {
+            match state.find_channel(message.channel_id) {
+                Some(ChannelRef::Server(server, channel)) => {
+                    println!(
+                        "[{} #{}] {}: {}",
+                        server.name,
+                        channel_name(channel),
+                        message.author.name,
+                        message.content
+                    );
+                }
+                Some(ChannelRef::Group(group)) => {
+                    println!(
+                        "[Group {}] {}: {}",
+                        group.name(),
+                        message.author.name,
+                        message.content
+                    );
+                }
+                Some(ChannelRef::Private(channel)) => {
+                    let [recipient] = &channel.recipient;
+                    if message.author.id == recipient.id {
+                        println!("[Private] {}: {}", message.author.name, message.content);
+                    } else {
+                        println!("[Private] To {}: {}", recipient.name, message.content);
+                    }
+                }
+                None => println!(
+                    "[Unknown Channel] {}: {}",
+                    message.author.name, message.content
+                ),
+            }
+        }
+    }
+}
 
-		// Log messages
-		match event {
-			Event::MessageCreate(message) => match state.find_channel(message.channel_id) {
-				Some(ChannelRef::Public(server, channel)) => {
-					println!(
-						"[{} #{}] {}: {}",
-						server.name, channel.name, message.author.name, message.content
-					);
-				}
-				Some(ChannelRef::Group(group)) => {
-					println!(
-						"[Group {}] {}: {}",
-						group.name(),
-						message.author.name,
-						message.content
-					);
-				}
-				Some(ChannelRef::Private(channel)) => {
-					if message.author.name == channel.recipient.name {
-						println!("[Private] {}: {}", message.author.name, message.content);
-					} else {
-						println!(
-							"[Private] To {}: {}",
-							channel.recipient.name, message.content
-						);
-					}
-				}
-				None => println!(
-					"[Unknown Channel] {}: {}",
-					message.author.name, message.content
-				),
-			},
-			Event::Unknown(name, data) => {
-				// log unknown event types for later study
-				println!("[Unknown Event] {}: {:?}", name, data);
-			}
-			_ => {} // discard other known events
-		}
-	}
+fn channel_name(channel: &ServerChannel) -> String {
+    match channel {
+        ServerChannel::Text { channel, .. } => channel.name.clone(),
+        ServerChannel::Announcement { channel, .. } => channel.name.clone(),
+        ServerChannel::Voice { channel, .. } => channel.name.clone(),
+        ServerChannel::AnnouncementThread { thread, .. }
+        | ServerChannel::PublicThread { thread, .. }
+        | ServerChannel::PrivateThread { thread, .. } => thread.name.clone(),
+        other => other.id().to_string(),
+    }
 }
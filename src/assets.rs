@@ -0,0 +1,205 @@
+//! Uploading server assets in bulk, for setting up servers from files.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use tracing::{debug, warn};
+
+use crate::model::{Emoji, Image, ServerId};
+use crate::{Discord, Error, Result, ServerExt};
+
+/// The largest emoji image Discord accepts, in bytes.
+const MAX_EMOJI_SIZE: u64 = 256 * 1024;
+
+/// The file extensions of the image formats emojis can be uploaded from.
+const EMOJI_EXTENSIONS: [&str; 5] = ["png", "jpg", "jpeg", "gif", "webp"];
+
+/// How many times an upload is retried after being rate limited.
+const RATE_LIMIT_RETRIES: usize = 3;
+
+/// Why a file was not uploaded as an emoji by [`bulk_upload_emojis`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmojiRejection {
+    /// The file isn't a PNG, JPEG, GIF or WebP image.
+    UnsupportedFormat,
+    /// The file name isn't a valid emoji name, which is 2 to 32 ASCII letters, digits or
+    /// underscores.
+    InvalidName,
+    /// The file is larger than 256 KiB, with its size in bytes.
+    TooLarge(u64),
+}
+
+/// Progress through a [`bulk_upload_emojis`], reported after each file.
+#[derive(Debug, Clone, Copy)]
+pub struct EmojiProgress<'a> {
+    /// The name of the emoji just handled.
+    pub name: &'a str,
+    /// How many files have been handled.
+    pub done: usize,
+    /// How many files there are in total.
+    pub total: usize,
+}
+
+/// What happened to each file of a [`bulk_upload_emojis`].
+#[derive(Debug, Default)]
+pub struct EmojiUploadSummary {
+    /// The emojis created.
+    pub uploaded: Vec<Emoji>,
+    /// The names skipped because the server already has an emoji by that name,
+    /// or an earlier file had the same name.
+    pub skipped: Vec<String>,
+    /// The files which couldn't be uploaded as emojis.
+    pub rejected: Vec<(PathBuf, EmojiRejection)>,
+    /// The names whose upload failed, with the error.
+    pub failed: Vec<(String, Error)>,
+}
+
+/// Create an emoji in a server from each image in a directory, named after the file.
+///
+/// Files are validated before uploading, and names the server already has an emoji by are
+/// skipped. Uploads are made one at a time, waiting out the tight rate limit on creating
+/// emojis, and a failed upload doesn't stop the others. `progress` is called after each file.
+///
+/// ```ignore
+/// let summary = assets::bulk_upload_emojis(&discord, server, "emojis", |p| {
+///     println!("{}/{} {}", p.done, p.total, p.name);
+/// })
+/// .await?;
+/// ```
+pub async fn bulk_upload_emojis<P: AsRef<Path>, F: FnMut(EmojiProgress<'_>)>(
+    discord: &Discord,
+    server: ServerId,
+    dir: P,
+    mut progress: F,
+) -> Result<EmojiUploadSummary> {
+    let mut files = Vec::new();
+    let mut entries = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        if entry.file_type().await?.is_file() {
+            files.push(entry.path());
+        }
+    }
+    files.sort();
+
+    let mut names: HashSet<String> = discord
+        .get_server(server)
+        .await?
+        .emojis
+        .into_iter()
+        .map(|emoji| emoji.name)
+        .collect();
+
+    let mut summary = EmojiUploadSummary::default();
+    let total = files.len();
+    for (done, path) in files.into_iter().enumerate() {
+        let name = emoji_name(&path).unwrap_or_default();
+        let size = tokio::fs::metadata(&path).await?.len();
+
+        match check_emoji(&path, &name, size) {
+            Err(rejection) => {
+                debug!("Not uploading {:?} as an emoji: {:?}", path, rejection);
+                summary.rejected.push((path, rejection));
+            }
+            Ok(()) if !names.insert(name.clone()) => summary.skipped.push(name.clone()),
+            Ok(()) => {
+                let image = Image::new(tokio::fs::read(&path).await?);
+                match create_emoji(discord, server, &name, image).await {
+                    Ok(emoji) => summary.uploaded.push(emoji),
+                    Err(err) => {
+                        warn!("Failed to upload the {} emoji: {:?}", name, err);
+                        summary.failed.push((name.clone(), err));
+                    }
+                }
+            }
+        }
+
+        progress(EmojiProgress {
+            name: &name,
+            done: done + 1,
+            total,
+        });
+    }
+
+    Ok(summary)
+}
+
+/// Create an emoji, waiting and retrying if the route is rate limited.
+async fn create_emoji(
+    discord: &Discord,
+    server: ServerId,
+    name: &str,
+    image: Image,
+) -> Result<Emoji> {
+    let mut retries = 0;
+    loop {
+        match discord.create_emoji(server, name, image.clone()).await {
+            Err(Error::RateLimited(millis)) if retries < RATE_LIMIT_RETRIES => {
+                debug!("Rate limited creating emojis, waiting {}ms", millis);
+                tokio::time::sleep(Duration::from_millis(millis)).await;
+                retries += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// The name of the emoji made from a file, which is its name without the extension.
+fn emoji_name(path: &Path) -> Option<String> {
+    Some(path.file_stem()?.to_str()?.to_owned())
+}
+
+/// Check that a file can be uploaded as an emoji.
+fn check_emoji(path: &Path, name: &str, size: u64) -> std::result::Result<(), EmojiRejection> {
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_ascii_lowercase);
+    if !extension.is_some_and(|ext| EMOJI_EXTENSIONS.contains(&ext.as_str())) {
+        return Err(EmojiRejection::UnsupportedFormat);
+    }
+    let valid_name = (2..=32).contains(&name.chars().count())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid_name {
+        return Err(EmojiRejection::InvalidName);
+    }
+    if size > MAX_EMOJI_SIZE {
+        return Err(EmojiRejection::TooLarge(size));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{check_emoji, EmojiRejection};
+
+    #[test]
+    fn validates_emoji_files() {
+        assert_eq!(
+            check_emoji(Path::new("party_parrot.GIF"), "party_parrot", 1024),
+            Ok(())
+        );
+        assert_eq!(
+            check_emoji(Path::new("notes.txt"), "notes", 10),
+            Err(EmojiRejection::UnsupportedFormat)
+        );
+        assert_eq!(
+            check_emoji(Path::new("a.png"), "a", 10),
+            Err(EmojiRejection::InvalidName)
+        );
+        assert_eq!(
+            check_emoji(Path::new("big one.png"), "big one", 10),
+            Err(EmojiRejection::InvalidName)
+        );
+        assert_eq!(
+            check_emoji(Path::new("café.png"), "café", 10),
+            Err(EmojiRejection::InvalidName)
+        );
+        assert_eq!(
+            check_emoji(Path::new("huge.png"), "huge", 300 * 1024),
+            Err(EmojiRejection::TooLarge(300 * 1024))
+        );
+    }
+}
@@ -0,0 +1,325 @@
+//! Automated responses to server activity, driven by gateway events.
+//!
+//! These helpers are fed every event received, and act on the ones they concern.
+//! Failing requests are logged rather than returned, so that one member with closed
+//! direct messages doesn't interrupt the event loop.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::Duration;
+
+use tokio::time::Instant;
+use tracing::{debug, warn};
+
+use crate::model::{ChannelId, Event, Message, ServerId, User, UserId};
+use crate::{Discord, MessageExt, State, UserExt};
+
+/// Where a [`WelcomeFlow`] sends its welcome messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WelcomeTarget {
+    /// Send the welcome privately to the new member.
+    DirectMessage,
+    /// Post the welcome in a channel of the server.
+    Channel(ChannelId),
+}
+
+/// Welcomes new members of a server with a templated message.
+///
+/// Members who must pass membership screening are welcomed once they do,
+/// rather than as soon as they join. The template may contain these placeholders:
+///
+/// - `{user}`: a mention of the new member
+/// - `{username}`: the new member's username
+/// - `{server}`: the name of the server
+///
+/// ```ignore
+/// let mut welcome = WelcomeFlow::new("Welcome to {server}, {user}!", WelcomeTarget::DirectMessage)
+///     .rate_limit(Duration::from_secs(2));
+///
+/// loop {
+///     let event = connection.recv_event().await?;
+///     state.update(&event);
+///     welcome.handle(&discord, &state, &event).await;
+///     welcome.welcome_queued(&discord, &state).await;
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct WelcomeFlow {
+    template: String,
+    target: WelcomeTarget,
+    /// The shortest time between two welcomes in the same server.
+    interval: Duration,
+    /// Users who have asked not to be welcomed.
+    opted_out: HashSet<UserId>,
+    /// Members who joined but have yet to pass membership screening.
+    pending: HashSet<(ServerId, UserId)>,
+    /// Members waiting for the rate limit to let their welcome through, oldest first.
+    queued: HashMap<ServerId, VecDeque<User>>,
+    /// When a welcome was last sent in each server.
+    last_sent: HashMap<ServerId, Instant>,
+}
+
+impl WelcomeFlow {
+    /// Welcome members with the given template, without any rate limit.
+    pub fn new(template: &str, target: WelcomeTarget) -> WelcomeFlow {
+        WelcomeFlow {
+            template: template.to_owned(),
+            target,
+            interval: Duration::ZERO,
+            opted_out: HashSet::new(),
+            pending: HashSet::new(),
+            queued: HashMap::new(),
+            last_sent: HashMap::new(),
+        }
+    }
+
+    /// Send at most one welcome per `interval` in each server.
+    ///
+    /// Members who become ready to be welcomed faster than that are queued, and welcomed
+    /// in turn by later calls to `handle` or `welcome_queued`. This keeps a raid of joining
+    /// accounts from flooding the welcome channel.
+    pub fn rate_limit(mut self, interval: Duration) -> WelcomeFlow {
+        self.interval = interval;
+        self
+    }
+
+    /// Stop welcoming a user in any server.
+    pub fn opt_out(&mut self, user: UserId) {
+        self.opted_out.insert(user);
+    }
+
+    /// Resume welcoming a user who opted out.
+    pub fn opt_in(&mut self, user: UserId) {
+        self.opted_out.remove(&user);
+    }
+
+    /// Fill in the template for a member of a server.
+    pub fn render(&self, user: &User, server_name: &str) -> String {
+        self.template
+            .replace("{user}", &user.mention().to_string())
+            .replace("{username}", &user.name)
+            .replace("{server}", server_name)
+    }
+
+    /// Handle an event, welcoming the member it concerns if they are ready to be welcomed.
+    ///
+    /// The server's name is looked up in `state`, which should be up to date with the event.
+    /// Returns the welcome message, if one was sent. With a rate limit, this may be the
+    /// welcome of a member queued earlier.
+    pub async fn handle(
+        &mut self,
+        discord: &Discord,
+        state: &State,
+        event: &Event,
+    ) -> Option<Message> {
+        let (server, user) = self.ready_member(event)?;
+        if self.opted_out.contains(&user.id) {
+            return None;
+        }
+
+        self.queued
+            .entry(server)
+            .or_default()
+            .push_back(user.clone());
+        self.welcome_next(discord, state, server).await
+    }
+
+    /// Send the queued welcomes which the rate limit now lets through, one per server.
+    ///
+    /// Call this regularly, such as once `next_due` has passed, so that welcomes queued
+    /// by a burst of joins are sent even if no further events arrive.
+    pub async fn welcome_queued(&mut self, discord: &Discord, state: &State) -> Vec<Message> {
+        let servers: Vec<_> = self.queued.keys().copied().collect();
+        let mut sent = Vec::new();
+        for server in servers {
+            sent.extend(self.welcome_next(discord, state, server).await);
+        }
+        sent
+    }
+
+    /// When the next queued welcome may be sent, if any are queued.
+    pub fn next_due(&self) -> Option<Instant> {
+        self.queued
+            .keys()
+            .map(|&server| self.due(server).unwrap_or_else(Instant::now))
+            .min()
+    }
+
+    /// When the rate limit next lets a welcome through in a server,
+    /// or `None` if no welcome has been sent there yet.
+    fn due(&self, server: ServerId) -> Option<Instant> {
+        self.last_sent
+            .get(&server)
+            .map(|&last| last + self.interval)
+    }
+
+    /// Take the next queued member of a server, if the rate limit lets their welcome through.
+    fn take_due(&mut self, server: ServerId) -> Option<User> {
+        if self.due(server).is_some_and(|due| Instant::now() < due) {
+            debug!("Delaying welcomes in {:?}, rate limited", server);
+            return None;
+        }
+        let queue = self.queued.get_mut(&server)?;
+        let mut next = None;
+        while let Some(user) = queue.pop_front() {
+            if !self.opted_out.contains(&user.id) {
+                next = Some(user);
+                break;
+            }
+        }
+        if queue.is_empty() {
+            self.queued.remove(&server);
+        }
+        next
+    }
+
+    /// Welcome queued members of a server until one welcome is sent or none are due.
+    ///
+    /// Only a successful welcome counts against the rate limit.
+    async fn welcome_next(
+        &mut self,
+        discord: &Discord,
+        state: &State,
+        server: ServerId,
+    ) -> Option<Message> {
+        while let Some(user) = self.take_due(server) {
+            if let Some(message) = self.welcome(discord, state, server, &user).await {
+                self.last_sent.insert(server, Instant::now());
+                return Some(message);
+            }
+        }
+        None
+    }
+
+    /// Send the welcome of a member, logging any failure.
+    async fn welcome(
+        &self,
+        discord: &Discord,
+        state: &State,
+        server: ServerId,
+        user: &User,
+    ) -> Option<Message> {
+        let server_name = state.find_server(server).map_or("the server", |s| &s.name);
+        let content = self.render(user, server_name);
+
+        let channel = match self.target {
+            WelcomeTarget::Channel(channel) => channel,
+            WelcomeTarget::DirectMessage => match discord.create_dm(user.id).await {
+                Ok(dm) => dm.id,
+                Err(err) => {
+                    warn!("Failed to open a DM to welcome {:?}: {:?}", user.id, err);
+                    return None;
+                }
+            },
+        };
+
+        match discord.send_message(channel, |m| m.content(&content)).await {
+            Ok(message) => Some(message),
+            Err(err) => {
+                warn!("Failed to welcome {:?} in {:?}: {:?}", user.id, server, err);
+                None
+            }
+        }
+    }
+
+    /// Track members through screening, returning the member the event made ready to welcome.
+    fn ready_member<'a>(&mut self, event: &'a Event) -> Option<(ServerId, &'a User)> {
+        match event {
+            Event::ServerMemberAdd(server, member) => {
+                let user = member.user.as_ref()?;
+                if member.pending {
+                    self.pending.insert((*server, user.id));
+                    None
+                } else {
+                    Some((*server, user))
+                }
+            }
+            Event::ServerMemberUpdate {
+                server_id,
+                user,
+                pending: false,
+                ..
+            } => self
+                .pending
+                .remove(&(*server_id, user.id))
+                .then_some((*server_id, user)),
+            Event::ServerMemberRemove(server, user) => {
+                self.pending.remove(&(*server, user.id));
+                if let Some(queue) = self.queued.get_mut(server) {
+                    queue.retain(|queued| queued.id != user.id);
+                }
+                None
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::time::Instant;
+
+    use super::{WelcomeFlow, WelcomeTarget};
+    use crate::model::{Event, Member, ServerId, User, UserId};
+
+    #[test]
+    fn waits_for_screening() {
+        let mut member = crate::fixtures::member(1, &[]);
+        member["user"]["username"] = "newbie".into();
+        member["pending"] = true.into();
+        let user: User = serde_json::from_value(member["user"].clone()).unwrap();
+        let member: Member = serde_json::from_value(member).unwrap();
+
+        let mut flow = WelcomeFlow::new(
+            "Hi {user}, welcome to {server}!",
+            WelcomeTarget::DirectMessage,
+        );
+        assert_eq!(flow.render(&user, "Rust"), "Hi <@1>, welcome to Rust!");
+
+        let join = Event::ServerMemberAdd(ServerId(5), member);
+        assert!(flow.ready_member(&join).is_none());
+
+        let screened = Event::ServerMemberUpdate {
+            server_id: ServerId(5),
+            roles: vec![],
+            user: user.clone(),
+            nick: None,
+            pending: false,
+        };
+        let (server, ready) = flow.ready_member(&screened).unwrap();
+        assert_eq!((server, ready.id), (ServerId(5), user.id));
+        // members are only welcomed once
+        assert!(flow.ready_member(&screened).is_none());
+    }
+
+    #[test]
+    fn queues_rate_limited_welcomes() {
+        let user = |id: u64| -> User {
+            serde_json::from_value(crate::fixtures::member(id, &[])["user"].clone()).unwrap()
+        };
+        let mut flow = WelcomeFlow::new("Hi {user}!", WelcomeTarget::DirectMessage)
+            .rate_limit(Duration::from_secs(60));
+        let server = ServerId(5);
+        flow.queued
+            .entry(server)
+            .or_default()
+            .extend([user(1), user(2), user(3)]);
+
+        // nothing has been sent yet, so the first welcome is due
+        assert_eq!(flow.take_due(server).map(|u| u.id), Some(UserId(1)));
+
+        // a welcome which was sent holds back the rest
+        let sent = Instant::now();
+        flow.last_sent.insert(server, sent);
+        assert!(flow.take_due(server).is_none());
+        assert_eq!(flow.next_due(), Some(sent + Duration::from_secs(60)));
+
+        // members who leave or opt out aren't welcomed once due
+        flow.ready_member(&Event::ServerMemberRemove(server, user(2)));
+        flow.opt_out(UserId(3));
+        flow.last_sent.remove(&server);
+        assert!(flow.take_due(server).is_none());
+        assert_eq!(flow.next_due(), None);
+    }
+}
@@ -9,18 +9,37 @@ use chrono::offset::FixedOffset;
 use chrono::DateTime;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use tracing::warn;
 
 use crate::model::*;
-use crate::Object;
+use crate::{Object, State};
 
 macro_rules! builder {
     ($(#[$attr:meta] $name:ident($inner:ty);)*) => {
         $(
             #[$attr]
-            #[derive(Debug, Serialize, Deserialize)]
+            #[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
             pub struct $name($inner);
 
             impl $name {
+                /// Start with empty contents.
+                ///
+                /// Builders are normally created by the call they are passed to,
+                /// but may be created directly, e.g. to check their contents in tests.
+                pub fn new() -> $name {
+                    $name::default()
+                }
+
+                /// The contents built so far, as they will be sent to Discord.
+                pub fn as_inner(&self) -> &$inner {
+                    &self.0
+                }
+
+                /// Take the contents built so far.
+                pub fn into_inner(self) -> $inner {
+                    self.0
+                }
+
                 #[doc(hidden)]
                 #[inline(always)]
                 pub fn build<F: FnOnce($name) -> $name>(f: F) -> $inner where $inner: Default {
@@ -87,6 +106,36 @@ builder! {
 
     /// Inner patch content for the `send_embed` call.
     EmbedFieldsBuilder(Vec<Value>);
+
+    /// The action rows of components for `send_message`.
+    CreateComponents(Vec<Value>);
+
+    /// A row of components, containing up to five buttons or a single select menu.
+    CreateActionRow(Vec<Value>);
+
+    /// A button in an action row.
+    CreateButton(Object);
+
+    /// A select menu in an action row.
+    CreateSelectMenu(Object);
+
+    /// Patch content for the `edit_server_sticker` call.
+    EditSticker(Object);
+
+    /// Content for the `create_server_soundboard_sound` and `edit_server_soundboard_sound` calls.
+    EditSoundboardSound(Object);
+
+    /// Content for the `create_scheduled_event` call.
+    CreateScheduledEvent(Object);
+
+    /// Patch content for the `edit_scheduled_event` call.
+    EditScheduledEvent(Object);
+
+    /// Content for creating or editing an application command.
+    CreateCommand(Object);
+
+    /// An option or subcommand of an application command.
+    CreateCommandOption(Object);
 }
 
 macro_rules! set {
@@ -162,6 +211,28 @@ impl EditChannel {
     pub fn user_limit(self, user_limit: u64) -> Self {
         set!(self, "user_limit", user_limit)
     }
+
+    /// Edit how many seconds users must wait between sending messages, from 0 to 21600.
+    /// Zero (`0`) disables slowmode.
+    pub fn slowmode(self, seconds: u64) -> Self {
+        set!(self, "rate_limit_per_user", seconds)
+    }
+
+    /// Edit whether the channel is marked as NSFW.
+    pub fn nsfw(self, nsfw: bool) -> Self {
+        set!(self, "nsfw", nsfw)
+    }
+
+    /// Edit whether the thread is archived.
+    pub fn archived(self, archived: bool) -> Self {
+        set!(self, "archived", archived)
+    }
+
+    /// Edit how many minutes of inactivity it takes for the thread to be archived.
+    /// Can be set to 60, 1440, 4320 or 10080.
+    pub fn auto_archive_duration(self, minutes: u16) -> Self {
+        set!(self, "auto_archive_duration", minutes)
+    }
 }
 
 impl EditMember {
@@ -269,6 +340,16 @@ impl SendMessage {
         set!(self, "nonce", nonce)
     }
 
+    /// Set to true to have Discord deduplicate sends with the same nonce.
+    ///
+    /// A message sent again with the same nonce within a few minutes isn't posted twice,
+    /// and the message posted the first time is returned instead. When no nonce is set,
+    /// `send_message` generates one, and if the request fails ambiguously (e.g. it times
+    /// out after being sent) sends it again with the same nonce.
+    pub fn enforce_nonce(self, enforce_nonce: bool) -> Self {
+        set!(self, "enforce_nonce", enforce_nonce)
+    }
+
     /// Set to true to use text-to-speech.
     pub fn tts(self, tts: bool) -> Self {
         set!(self, "tts", tts)
@@ -276,27 +357,109 @@ impl SendMessage {
 
     /// Embed rich content.
     pub fn embed<F: FnOnce(EmbedBuilder) -> EmbedBuilder>(self, f: F) -> Self {
-        set!(self, "embed", EmbedBuilder::build(f))
+        self.set_embed(f(EmbedBuilder::new()))
+    }
+
+    /// Embed rich content built beforehand.
+    pub fn set_embed(self, embed: EmbedBuilder) -> Self {
+        set!(self, "embed", embed)
     }
 
     /// Restrict allowed mentions for this message.
     pub fn allowed_mentions<F: FnOnce(AllowedMentions) -> AllowedMentions>(self, f: F) -> Self {
-        set!(self, "allowed_mentions", AllowedMentions::build(f))
+        self.set_allowed_mentions(f(AllowedMentions::new()))
+    }
+
+    /// Restrict allowed mentions for this message, as built beforehand.
+    pub fn set_allowed_mentions(self, allowed_mentions: AllowedMentions) -> Self {
+        set!(self, "allowed_mentions", allowed_mentions)
+    }
+
+    /// Attach interactive components, like buttons and select menus, to the message.
+    pub fn components<F: FnOnce(CreateComponents) -> CreateComponents>(self, f: F) -> Self {
+        self.set_components(f(CreateComponents::new()))
+    }
+
+    /// Attach interactive components built beforehand.
+    pub fn set_components(self, components: CreateComponents) -> Self {
+        set!(self, "components", components)
     }
 
     /// Reply to the given message, optionally mentioning the sender.
     ///
     /// The given `message_id` must be in the same channel that this message is
-    /// being sent to.
+    /// being sent to. Other mentions are allowed as they were set before, or as the
+    /// client's default allowed mentions have them; see `AllowedMentions::replied_user`.
     pub fn reply(self, message_id: MessageId, mention: bool) -> Self {
-        set!(
-            self,
-            "message_reference",
-            json! {{
-                "message_id": message_id,
-            }}
-        )
-        .allowed_mentions(|b| b.replied_user(mention))
+        let mut message = self.reply_to(message_id);
+        // keep any allowed mentions set before
+        let allowed_mentions = match message.0.remove("allowed_mentions") {
+            Some(Value::Object(allowed_mentions)) => AllowedMentions(allowed_mentions),
+            _ => AllowedMentions::new(),
+        };
+        message.set_allowed_mentions(allowed_mentions.replied_user(mention))
+    }
+
+    /// Reply to the given message, which must be in the same channel that this message is
+    /// being sent to. Mentions of the replied user follow Discord's defaults; see `reply`.
+    pub fn reply_to(self, message_id: MessageId) -> Self {
+        self.reference(json! {{
+            "type": MessageReferenceType::Default,
+            "message_id": message_id,
+        }})
+    }
+
+    /// Forward the given message, from any channel the current user can read.
+    ///
+    /// Forwarded messages carry a snapshot of the original and can't have content of their own.
+    pub fn forward(self, channel_id: ChannelId, message_id: MessageId) -> Self {
+        self.reference(json! {{
+            "type": MessageReferenceType::Forward,
+            "channel_id": channel_id,
+            "message_id": message_id,
+        }})
+    }
+
+    /// Whether sending fails when the replied or forwarded message doesn't exist, rather than
+    /// sending a normal message. Discord defaults to `true`.
+    pub fn fail_if_not_exists(self, fail_if_not_exists: bool) -> Self {
+        self.reference(json! {{ "fail_if_not_exists": fail_if_not_exists }})
+    }
+
+    /// Merge the given fields into the message's `message_reference`.
+    fn reference(mut self, fields: Value) -> Self {
+        let reference = self
+            .0
+            .entry("message_reference")
+            .or_insert_with(|| Value::Object(Object::new()));
+        if let (Value::Object(reference), Value::Object(fields)) = (reference, fields) {
+            reference.extend(fields);
+        }
+        self
+    }
+
+    /// Attach a sticker to the message. Up to 3 stickers may be sent at once.
+    pub fn sticker(mut self, sticker: StickerId) -> Self {
+        push(&mut self.0, "sticker_ids", sticker);
+        self
+    }
+
+    /// Attach one of a server's stickers to the message by name,
+    /// looking it up in the stickers known to the given `State`.
+    ///
+    /// If the server has no sticker with that name, a warning is logged and the message
+    /// is sent without it. Use `State::find_sticker` to check for the sticker beforehand.
+    pub fn sticker_named(self, state: &State, server: ServerId, name: &str) -> Self {
+        match state.find_sticker(server, name) {
+            Some(sticker) => self.sticker(sticker.id),
+            None => {
+                warn!(
+                    "No sticker named {:?} in {}, sending without it",
+                    name, server
+                );
+                self
+            }
+        }
     }
 
     /// Change the message's flags.
@@ -307,13 +470,50 @@ impl SendMessage {
         set!(self, "flags", flags)
     }
 
-    // TODO: file, payload_json, message_reference
+    // TODO: file, payload_json
 }
 
 impl AllowedMentions {
-    // TODO: parse, roles, users
+    /// Allow no mentions to notify anyone, which is the safe choice when repeating text
+    /// written by users. Specific users and roles may still be allowed with `users` and `roles`.
+    pub fn none() -> AllowedMentions {
+        AllowedMentions::new().parse(&[])
+    }
+
+    /// Allow mentions of every kind to notify, as they do in messages which don't
+    /// restrict their allowed mentions at all.
+    pub fn all() -> AllowedMentions {
+        AllowedMentions::new().parse(&[
+            MentionType::Users,
+            MentionType::Roles,
+            MentionType::Everyone,
+        ])
+    }
+
+    /// Allow every mention of these kinds to notify.
+    ///
+    /// Without this, only the users and roles listed with `users` and `roles` are notified,
+    /// and no one if neither is set. Discord rejects a message which allows all user mentions and also lists `users`,
+    /// and likewise for roles.
+    pub fn parse(self, types: &[MentionType]) -> Self {
+        set!(self, "parse", types)
+    }
+
+    /// Allow mentions of these users to notify them, up to 100.
+    pub fn users(self, users: &[UserId]) -> Self {
+        set!(self, "users", users)
+    }
+
+    /// Allow mentions of these roles to notify their members, up to 100.
+    pub fn roles(self, roles: &[RoleId]) -> Self {
+        set!(self, "roles", roles)
+    }
 
     /// Set to `false` to disable mentioning a replied-to user.
+    ///
+    /// When this is all that is set, the client fills in the other mentions from its default
+    /// allowed mentions when sending, or allows every kind if it has none, rather than
+    /// notifying no one.
     pub fn replied_user(self, replied_user: bool) -> Self {
         set!(self, "replied_user", replied_user)
     }
@@ -347,7 +547,12 @@ impl EmbedBuilder {
 
     /// Add "footer information". See the `EmbedFooterBuilder` struct for the editable fields.
     pub fn footer<F: FnOnce(EmbedFooterBuilder) -> EmbedFooterBuilder>(self, f: F) -> Self {
-        set!(self, "footer", EmbedFooterBuilder::build(f))
+        self.set_footer(f(EmbedFooterBuilder::new()))
+    }
+
+    /// Add "footer information" built beforehand.
+    pub fn set_footer(self, footer: EmbedFooterBuilder) -> Self {
+        set!(self, "footer", footer)
     }
 
     /// Add "source url of image". Only supports http(s).
@@ -362,12 +567,22 @@ impl EmbedBuilder {
 
     /// Add "author information". See the `EmbedAuthorBuilder` struct for the editable fields.
     pub fn author<F: FnOnce(EmbedAuthorBuilder) -> EmbedAuthorBuilder>(self, f: F) -> Self {
-        set!(self, "author", EmbedAuthorBuilder::build(f))
+        self.set_author(f(EmbedAuthorBuilder::new()))
+    }
+
+    /// Add "author information" built beforehand.
+    pub fn set_author(self, author: EmbedAuthorBuilder) -> Self {
+        set!(self, "author", author)
     }
 
     /// Add "fields information". See the `EmbedFieldsBuilder` struct for the editable fields.
     pub fn fields<F: FnOnce(EmbedFieldsBuilder) -> EmbedFieldsBuilder>(self, f: F) -> Self {
-        set!(self, "fields", EmbedFieldsBuilder::build(f))
+        self.set_fields(f(EmbedFieldsBuilder::new()))
+    }
+
+    /// Add "fields information" built beforehand.
+    pub fn set_fields(self, fields: EmbedFieldsBuilder) -> Self {
+        set!(self, "fields", fields)
     }
 }
 
@@ -413,3 +628,483 @@ impl EmbedFieldsBuilder {
         self
     }
 }
+
+impl CreateComponents {
+    /// Add a row of components. A message can have up to five rows.
+    pub fn action_row<F: FnOnce(CreateActionRow) -> CreateActionRow>(mut self, f: F) -> Self {
+        self.0.push(json! {{
+            "type": ComponentType::ActionRow,
+            "components": CreateActionRow::build(f),
+        }});
+        self
+    }
+}
+
+impl CreateActionRow {
+    /// Add a button to the row.
+    pub fn button<F: FnOnce(CreateButton) -> CreateButton>(mut self, f: F) -> Self {
+        let button = CreateButton::apply(f, component(ComponentType::Button));
+        self.0.push(Value::Object(button));
+        self
+    }
+
+    /// Add a select menu of developer-defined strings to the row.
+    pub fn string_select<F: FnOnce(CreateSelectMenu) -> CreateSelectMenu>(self, f: F) -> Self {
+        self.select(ComponentType::StringSelect, f)
+    }
+
+    /// Add a select menu of the server's members to the row.
+    pub fn user_select<F: FnOnce(CreateSelectMenu) -> CreateSelectMenu>(self, f: F) -> Self {
+        self.select(ComponentType::UserSelect, f)
+    }
+
+    /// Add a select menu of the server's roles to the row.
+    pub fn role_select<F: FnOnce(CreateSelectMenu) -> CreateSelectMenu>(self, f: F) -> Self {
+        self.select(ComponentType::RoleSelect, f)
+    }
+
+    fn select<F: FnOnce(CreateSelectMenu) -> CreateSelectMenu>(
+        mut self,
+        kind: ComponentType,
+        f: F,
+    ) -> Self {
+        let menu = CreateSelectMenu::apply(f, component(kind));
+        self.0.push(Value::Object(menu));
+        self
+    }
+}
+
+impl CreateButton {
+    /// Set the look of the button.
+    pub fn style(self, style: ButtonStyle) -> Self {
+        set!(self, "style", style)
+    }
+
+    /// Set the text on the button.
+    pub fn label(self, label: &str) -> Self {
+        set!(self, "label", label)
+    }
+
+    /// Set the emoji on the button.
+    pub fn emoji(self, emoji: ReactionEmoji) -> Self {
+        set!(self, "emoji", emoji)
+    }
+
+    /// Set the ID sent back in the interaction when the button is clicked.
+    /// Required for all buttons except links.
+    pub fn custom_id(self, custom_id: &str) -> Self {
+        set!(self, "custom_id", custom_id)
+    }
+
+    /// Set the link opened by a `Link` style button.
+    pub fn url(self, url: &str) -> Self {
+        set!(self, "url", url)
+    }
+
+    /// Set whether the button is greyed out and can not be clicked.
+    pub fn disabled(self, disabled: bool) -> Self {
+        set!(self, "disabled", disabled)
+    }
+}
+
+impl CreateSelectMenu {
+    /// Set the ID sent back in the interaction when a selection is made.
+    pub fn custom_id(self, custom_id: &str) -> Self {
+        set!(self, "custom_id", custom_id)
+    }
+
+    /// Set the text shown when nothing is selected.
+    pub fn placeholder(self, placeholder: &str) -> Self {
+        set!(self, "placeholder", placeholder)
+    }
+
+    /// Set the minimum number of items which must be selected, from 0 to 25.
+    pub fn min_values(self, min_values: u8) -> Self {
+        set!(self, "min_values", min_values)
+    }
+
+    /// Set the maximum number of items which can be selected, up to 25.
+    pub fn max_values(self, max_values: u8) -> Self {
+        set!(self, "max_values", max_values)
+    }
+
+    /// Set whether the menu is greyed out and can not be used.
+    pub fn disabled(self, disabled: bool) -> Self {
+        set!(self, "disabled", disabled)
+    }
+
+    /// Add a choice to a string select menu.
+    pub fn option(
+        mut self,
+        label: &str,
+        value: &str,
+        description: Option<&str>,
+        default: bool,
+    ) -> Self {
+        push(
+            &mut self.0,
+            "options",
+            json! {{
+                "label": label,
+                "value": value,
+                "description": description,
+                "default": default,
+            }},
+        );
+        self
+    }
+}
+
+impl EditSticker {
+    /// Edit the name of the sticker (2-30 characters).
+    pub fn name(self, name: &str) -> Self {
+        set!(self, "name", name)
+    }
+
+    /// Edit the description of the sticker (2-100 characters). Use `None` to remove it.
+    pub fn description(self, description: Option<&str>) -> Self {
+        set!(self, "description", description)
+    }
+
+    /// Edit the autocomplete tags of the sticker (up to 200 characters).
+    pub fn tags(self, tags: &str) -> Self {
+        set!(self, "tags", tags)
+    }
+}
+
+impl EditSoundboardSound {
+    /// Edit the name of the sound (2-32 characters).
+    pub fn name(self, name: &str) -> Self {
+        set!(self, "name", name)
+    }
+
+    /// Edit the volume the sound is played at, from 0 to 1.
+    pub fn volume(self, volume: f64) -> Self {
+        set!(self, "volume", volume)
+    }
+
+    /// Show a custom emoji with the sound. Use `None` to remove it.
+    pub fn emoji_id(self, emoji: Option<EmojiId>) -> Self {
+        set!(self, "emoji_id", emoji)
+    }
+
+    /// Show a standard emoji with the sound. Use `None` to remove it.
+    pub fn emoji_name(self, emoji: Option<&str>) -> Self {
+        set!(self, "emoji_name", emoji)
+    }
+}
+
+impl CreateScheduledEvent {
+    /// Set the name of the event.
+    pub fn name(self, name: &str) -> Self {
+        set!(self, "name", name)
+    }
+
+    /// Set the description of the event.
+    pub fn description(self, description: &str) -> Self {
+        set!(self, "description", description)
+    }
+
+    /// Set when the event will start.
+    pub fn start_time(self, start_time: DateTime<FixedOffset>) -> Self {
+        set!(self, "scheduled_start_time", start_time.to_rfc3339())
+    }
+
+    /// Set who is able to see and subscribe to the event.
+    pub fn privacy(self, privacy: ScheduledEventPrivacy) -> Self {
+        set!(self, "privacy_level", privacy)
+    }
+
+    /// Upload a cover image for the event.
+    pub fn cover_image(self, image: Image) -> Self {
+        set!(self, "image", image)
+    }
+
+    /// Host the event on a stage channel.
+    pub fn stage(mut self, channel: ChannelId) -> Self {
+        event_host(&mut self.0, 1, Some(channel), None);
+        self
+    }
+
+    /// Host the event on a voice channel.
+    pub fn voice(mut self, channel: ChannelId) -> Self {
+        event_host(&mut self.0, 2, Some(channel), None);
+        self
+    }
+
+    /// Host the event somewhere outside of Discord, until the given end time.
+    pub fn external(mut self, location: &str, end_time: DateTime<FixedOffset>) -> Self {
+        event_host(&mut self.0, 3, None, Some((location, end_time)));
+        self
+    }
+}
+
+impl EditScheduledEvent {
+    /// Edit the name of the event.
+    pub fn name(self, name: &str) -> Self {
+        set!(self, "name", name)
+    }
+
+    /// Edit the description of the event.
+    pub fn description(self, description: &str) -> Self {
+        set!(self, "description", description)
+    }
+
+    /// Edit when the event will start.
+    pub fn start_time(self, start_time: DateTime<FixedOffset>) -> Self {
+        set!(self, "scheduled_start_time", start_time.to_rfc3339())
+    }
+
+    /// Edit who is able to see and subscribe to the event.
+    pub fn privacy(self, privacy: ScheduledEventPrivacy) -> Self {
+        set!(self, "privacy_level", privacy)
+    }
+
+    /// Edit the status of the event, to start, end or cancel it.
+    pub fn status(self, status: ScheduledEventStatus) -> Self {
+        set!(self, "status", status)
+    }
+
+    /// Replace the cover image of the event. Use `None` to remove the cover image.
+    pub fn cover_image(self, image: Option<Image>) -> Self {
+        set!(self, "image", image)
+    }
+
+    /// Move the event to a stage channel.
+    pub fn stage(mut self, channel: ChannelId) -> Self {
+        event_host(&mut self.0, 1, Some(channel), None);
+        self
+    }
+
+    /// Move the event to a voice channel.
+    pub fn voice(mut self, channel: ChannelId) -> Self {
+        event_host(&mut self.0, 2, Some(channel), None);
+        self
+    }
+
+    /// Move the event outside of Discord, until the given end time.
+    pub fn external(mut self, location: &str, end_time: DateTime<FixedOffset>) -> Self {
+        event_host(&mut self.0, 3, None, Some((location, end_time)));
+        self
+    }
+}
+
+impl CreateCommand {
+    /// Set the name of the command, 1-32 lowercase characters.
+    pub fn name(self, name: &str) -> Self {
+        set!(self, "name", name)
+    }
+
+    /// Set the name of the command as shown to users of the given locale.
+    pub fn localized_name(mut self, locale: Locale, name: &str) -> Self {
+        localize(&mut self.0, "name_localizations", locale, name);
+        self
+    }
+
+    /// Set the description of the command, 1-100 characters.
+    pub fn description(self, description: &str) -> Self {
+        set!(self, "description", description)
+    }
+
+    /// Set the description of the command as shown to users of the given locale.
+    pub fn localized_description(mut self, locale: Locale, description: &str) -> Self {
+        localize(
+            &mut self.0,
+            "description_localizations",
+            locale,
+            description,
+        );
+        self
+    }
+
+    /// Set how the command is invoked. Commands are slash commands by default.
+    pub fn kind(self, kind: CommandType) -> Self {
+        set!(self, "type", kind)
+    }
+
+    /// Add an option or subcommand to the command.
+    pub fn option<F: FnOnce(CreateCommandOption) -> CreateCommandOption>(mut self, f: F) -> Self {
+        push(&mut self.0, "options", CreateCommandOption::build(f));
+        self
+    }
+}
+
+impl CreateCommandOption {
+    /// Set the type of the option.
+    pub fn kind(self, kind: CommandOptionType) -> Self {
+        set!(self, "type", kind)
+    }
+
+    /// Set the name of the option, 1-32 lowercase characters.
+    pub fn name(self, name: &str) -> Self {
+        set!(self, "name", name)
+    }
+
+    /// Set the name of the option as shown to users of the given locale.
+    pub fn localized_name(mut self, locale: Locale, name: &str) -> Self {
+        localize(&mut self.0, "name_localizations", locale, name);
+        self
+    }
+
+    /// Set the description of the option, 1-100 characters.
+    pub fn description(self, description: &str) -> Self {
+        set!(self, "description", description)
+    }
+
+    /// Set the description of the option as shown to users of the given locale.
+    pub fn localized_description(mut self, locale: Locale, description: &str) -> Self {
+        localize(
+            &mut self.0,
+            "description_localizations",
+            locale,
+            description,
+        );
+        self
+    }
+
+    /// Set whether the option must be filled in. Options are optional by default.
+    pub fn required(self, required: bool) -> Self {
+        set!(self, "required", required)
+    }
+
+    /// Add a predefined value for the user to pick from.
+    pub fn choice<V: Serialize>(mut self, name: &str, value: V) -> Self {
+        push(
+            &mut self.0,
+            "choices",
+            json! {{
+                "name": name,
+                "value": value,
+            }},
+        );
+        self
+    }
+
+    /// Add a nested option, for subcommands and subcommand groups.
+    pub fn option<F: FnOnce(CreateCommandOption) -> CreateCommandOption>(mut self, f: F) -> Self {
+        push(&mut self.0, "options", CreateCommandOption::build(f));
+        self
+    }
+}
+
+/// Set where a scheduled event is hosted, clearing the fields of other host types.
+fn event_host(
+    event: &mut Object,
+    entity_type: u8,
+    channel: Option<ChannelId>,
+    external: Option<(&str, DateTime<FixedOffset>)>,
+) {
+    event.insert("entity_type".into(), json!(entity_type));
+    event.insert("channel_id".into(), json!(channel));
+    match external {
+        Some((location, end_time)) => {
+            event.insert("entity_metadata".into(), json!({ "location": location }));
+            event.insert("scheduled_end_time".into(), json!(end_time.to_rfc3339()));
+        }
+        None => {
+            event.insert("entity_metadata".into(), Value::Null);
+        }
+    }
+}
+
+/// Add an entry to a localization map, such as `name_localizations`.
+fn localize(object: &mut Object, key: &str, locale: Locale, value: &str) {
+    let map = object
+        .entry(key)
+        .or_insert_with(|| Value::Object(Object::new()));
+    if let Value::Object(map) = map {
+        map.insert(locale.code().into(), value.into());
+    }
+}
+
+/// Start a component object of the given type.
+fn component(kind: ComponentType) -> Object {
+    let mut object = Object::new();
+    object.insert("type".into(), json!(kind));
+    object
+}
+
+/// Push a value onto an array, such as `options`.
+fn push<V: Serialize>(object: &mut Object, key: &str, value: V) {
+    let array = object
+        .entry(key)
+        .or_insert_with(|| Value::Array(Vec::new()));
+    if let Value::Array(array) = array {
+        array.push(json!(value));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{AllowedMentions, EditRole, EmbedBuilder, SendMessage};
+    use crate::model::{ChannelId, MentionType, MessageId, UserId};
+
+    #[test]
+    fn builders_without_closures() {
+        let embed = EmbedBuilder::new().title("Release").color(0x00ff00);
+        let message = SendMessage::new()
+            .content("Out now")
+            .tts(false)
+            .set_embed(embed.clone());
+        assert_eq!(message.as_inner()["content"], "Out now");
+        assert_eq!(message.as_inner()["embed"], json!(embed));
+        // the closure form builds the same thing
+        assert_eq!(
+            message,
+            SendMessage::new()
+                .content("Out now")
+                .tts(false)
+                .embed(|e| e.title("Release").color(0x00ff00))
+        );
+
+        assert_eq!(
+            json!(EditRole::new().name("Mods").hoist(true)),
+            json!({ "name": "Mods", "hoist": true })
+        );
+        assert!(EditRole::default().into_inner().is_empty());
+    }
+
+    #[test]
+    fn allowed_mentions() {
+        let message = SendMessage::new()
+            .content("@everyone look")
+            .allowed_mentions(|m| m.parse(&[MentionType::Roles]).users(&[UserId(2)]));
+        assert_eq!(
+            message.as_inner()["allowed_mentions"],
+            json!({ "parse": ["roles"], "users": [UserId(2)] })
+        );
+
+        // replying keeps the mentions allowed before, or leaves them to the client's defaults
+        let reply = SendMessage::new().reply(MessageId(1), false);
+        assert_eq!(
+            reply.as_inner()["allowed_mentions"],
+            json!({ "replied_user": false })
+        );
+        let reply = SendMessage::new()
+            .set_allowed_mentions(AllowedMentions::none())
+            .reply(MessageId(1), false);
+        assert_eq!(
+            reply.as_inner()["allowed_mentions"],
+            json!({ "parse": [], "replied_user": false })
+        );
+    }
+
+    #[test]
+    fn message_references() {
+        let reply = SendMessage::new()
+            .fail_if_not_exists(false)
+            .reply_to(MessageId(1));
+        assert_eq!(
+            reply.as_inner()["message_reference"],
+            json!({ "type": 0, "message_id": MessageId(1), "fail_if_not_exists": false })
+        );
+
+        let forward = SendMessage::new().forward(ChannelId(2), MessageId(3));
+        assert_eq!(
+            forward.as_inner()["message_reference"],
+            json!({ "type": 1, "channel_id": ChannelId(2), "message_id": MessageId(3) })
+        );
+    }
+}
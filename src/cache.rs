@@ -0,0 +1,164 @@
+//! Storage for the parts of the [`State`](crate::State) which grow with the size of a bot.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+
+use crate::model::{ChannelId, Member, Message, MessageId, ServerId, UserId};
+
+/// Where a [`State`](crate::State) keeps server members and cached messages.
+///
+/// Members and messages are by far the largest part of the state of a bot in many servers,
+/// so they can be kept elsewhere than in memory, such as in Redis or on disk,
+/// by implementing this trait and passing it to `State::with_backend`.
+/// The state's update logic stays the same whichever backend is used.
+///
+/// Members are stored by the server they are in and their user ID.
+/// Members without a `user` are never stored. Messages are stored by channel,
+/// and are only given to the backend if the state caches messages.
+///
+/// The methods are synchronous, since they are called from `State::update`.
+/// A backend for a networked store must answer them without awaiting the network,
+/// for example by keeping a local copy which it writes through to the store
+/// from a task of its own, or by using a blocking client outside of async code.
+pub trait CacheBackend: fmt::Debug + Send + Sync {
+    /// Look up a member of a server.
+    fn get_member(&self, server: ServerId, user: UserId) -> Option<Member>;
+
+    /// Insert or replace a member of a server.
+    fn put_member(&mut self, server: ServerId, user: UserId, member: Member);
+
+    /// Remove a member of a server.
+    fn remove_member(&mut self, server: ServerId, user: UserId);
+
+    /// Get every known member of a server.
+    fn members(&self, server: ServerId) -> Vec<Member>;
+
+    /// Count the known members of a server.
+    fn member_count(&self, server: ServerId) -> usize {
+        self.members(server).len()
+    }
+
+    /// Remove every member of a server.
+    fn clear_members(&mut self, server: ServerId);
+
+    /// Look up a cached message of a channel.
+    fn get_message(&self, channel: ChannelId, message: MessageId) -> Option<Message>;
+
+    /// Add the newest message of a channel, dropping the oldest beyond `max` messages.
+    fn push_message(&mut self, channel: ChannelId, message: Message, max: usize);
+
+    /// Replace a cached message of a channel after it was edited, if it is cached.
+    fn replace_message(&mut self, channel: ChannelId, message: Message);
+
+    /// Remove cached messages of a channel.
+    fn remove_messages(&mut self, channel: ChannelId, messages: &[MessageId]);
+
+    /// Get the cached messages of a channel, oldest first.
+    fn messages(&self, channel: ChannelId) -> Vec<Message>;
+
+    /// Remove every cached message of a channel.
+    fn clear_messages(&mut self, channel: ChannelId);
+
+    /// Clone the backend, for cloning the state which owns it.
+    ///
+    /// Backends which are handles to an external store may return a handle to the same store.
+    fn boxed_clone(&self) -> Box<dyn CacheBackend>;
+}
+
+impl Clone for Box<dyn CacheBackend> {
+    fn clone(&self) -> Self {
+        self.boxed_clone()
+    }
+}
+
+/// The default [`CacheBackend`], keeping members and messages in memory.
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryCache {
+    members: HashMap<ServerId, HashMap<UserId, Member>>,
+    messages: HashMap<ChannelId, VecDeque<Message>>,
+}
+
+impl InMemoryCache {
+    /// Create an empty cache.
+    pub fn new() -> InMemoryCache {
+        InMemoryCache::default()
+    }
+}
+
+impl CacheBackend for InMemoryCache {
+    fn get_member(&self, server: ServerId, user: UserId) -> Option<Member> {
+        self.members.get(&server)?.get(&user).cloned()
+    }
+
+    fn put_member(&mut self, server: ServerId, user: UserId, member: Member) {
+        self.members.entry(server).or_default().insert(user, member);
+    }
+
+    fn remove_member(&mut self, server: ServerId, user: UserId) {
+        if let Some(members) = self.members.get_mut(&server) {
+            members.remove(&user);
+        }
+    }
+
+    fn members(&self, server: ServerId) -> Vec<Member> {
+        self.members
+            .get(&server)
+            .map(|members| members.values().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    fn member_count(&self, server: ServerId) -> usize {
+        self.members.get(&server).map_or(0, HashMap::len)
+    }
+
+    fn clear_members(&mut self, server: ServerId) {
+        self.members.remove(&server);
+    }
+
+    fn get_message(&self, channel: ChannelId, message: MessageId) -> Option<Message> {
+        self.messages
+            .get(&channel)?
+            .iter()
+            .find(|m| m.id == message)
+            .cloned()
+    }
+
+    fn push_message(&mut self, channel: ChannelId, message: Message, max: usize) {
+        let messages = self.messages.entry(channel).or_default();
+        messages.push_back(message);
+        while messages.len() > max {
+            messages.pop_front();
+        }
+    }
+
+    fn replace_message(&mut self, channel: ChannelId, message: Message) {
+        let cached = self
+            .messages
+            .get_mut(&channel)
+            .and_then(|messages| messages.iter_mut().find(|m| m.id == message.id));
+        if let Some(cached) = cached {
+            *cached = message;
+        }
+    }
+
+    fn remove_messages(&mut self, channel: ChannelId, messages: &[MessageId]) {
+        if let Some(cached) = self.messages.get_mut(&channel) {
+            cached.retain(|m| !messages.contains(&m.id));
+        }
+    }
+
+    fn messages(&self, channel: ChannelId) -> Vec<Message> {
+        self.messages
+            .get(&channel)
+            .map(|messages| messages.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    fn clear_messages(&mut self, channel: ChannelId) {
+        self.messages.remove(&channel);
+    }
+
+    fn boxed_clone(&self) -> Box<dyn CacheBackend> {
+        Box::new(self.clone())
+    }
+}
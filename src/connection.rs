@@ -1,2 +1,1734 @@
+//! The websocket connection to the Discord gateway.
+
+use std::borrow::BorrowMut;
+#[cfg(feature = "voice")]
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, Read};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use flate2::read::ZlibDecoder;
+use flate2::{Decompress, FlushDecompress};
+use futures::stream::{self, Stream};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::{broadcast, mpsc, Mutex};
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use tracing::{debug, warn};
+use websockets::{Frame, WebSocket, WebSocketReadHalf, WebSocketWriteHalf};
+
+use crate::model::{
+    ChannelId, Event, IdentifyPayload, Intents, Member, ReadyEvent, ReceivedMessage,
+    RequestGuildMembersPayload, ResumePayload, SentMessage, ServerId, UpdatePresencePayload,
+    UpdateVoiceStatePayload,
+};
+use crate::serial::Eq;
+#[cfg(feature = "signal")]
+use crate::signal::ShutdownSignal;
+#[cfg(feature = "voice")]
+use crate::voice::VoiceConnection;
+use crate::{Error, GatewayCloseCode, ReconnectPolicy, Result, State};
+
+/// The gateway version and encoding requested when connecting.
+const GATEWAY_QUERY: &str = "?v=6&encoding=json";
+
+/// The query string added to request `zlib-stream` transport compression.
+const ZLIB_STREAM_QUERY: &str = "&compress=zlib-stream";
+
+/// The bytes every message ends with when using `zlib-stream` transport compression.
+const ZLIB_SUFFIX: [u8; 4] = [0x00, 0x00, 0xff, 0xff];
+
+/// How many outgoing messages are held while reconnecting before the oldest are dropped.
+const BUFFER_CAPACITY: usize = 64;
+
+/// How long outgoing messages are held while reconnecting before they are considered stale.
+const BUFFER_MAX_AGE: Duration = Duration::from_secs(60);
+
+/// How many events are kept for subscribers before the slowest ones start missing events.
+const BROADCAST_CAPACITY: usize = 256;
+
+/// How long a member request waits for its next chunk before giving up, unless changed.
+const MEMBER_CHUNK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Websocket connection to the Discord servers, over which events are received.
+///
+/// The connection keeps itself alive by heartbeating in the background,
+/// and transparently reconnects and resumes the session when the gateway asks it to,
+/// or when the underlying websocket is closed.
+pub struct Connection {
+    /// Messages received from the gateway websocket by the reader task.
+    ///
+    /// The websocket is read in a task of its own, so that a message is never left
+    /// half received when waiting for one is cut off by a timeout or a signal.
+    received: mpsc::UnboundedReceiver<Result<Value>>,
+    /// The background task reading the gateway websocket.
+    reader: JoinHandle<()>,
+    /// The sending half of the gateway websocket, shared with the keep-alive task.
+    sink: SharedSink,
+
+    /// The background task sending heartbeats to the gateway.
+    keepalive: JoinHandle<()>,
+    /// The sequence number of the last dispatch received, or zero if none has been received.
+    last_sequence: Arc<AtomicU64>,
+
+    /// The URL of the gateway, including the query string.
+    gateway_url: String,
+    /// The identify payload, kept for identifying again after the session is invalidated.
+    identify: IdentifyPayload,
+    /// The ID of the current session, used for resuming.
+    session_id: Option<String>,
+    /// The URL given by the gateway for resuming the current session, without the query string.
+    resume_gateway_url: Option<String>,
+    /// Whether the websocket uses `zlib-stream` transport compression.
+    transport_compression: bool,
+    /// Every received event is published here for subscribers.
+    subscribers: broadcast::Sender<Arc<Event>>,
+    /// How reconnecting is retried.
+    reconnect_policy: ReconnectPolicy,
+    /// How many times reconnecting has been attempted since the session was last resumed.
+    reconnect_attempts: u32,
+    /// When a message was last received from the gateway, or the websocket was opened.
+    last_received: Instant,
+    /// The voice connection of each server, created on demand.
+    #[cfg(feature = "voice")]
+    voice: HashMap<ServerId, VoiceConnection>,
+    /// Whether the process has been asked to stop, if the connection shuts down on signals.
+    #[cfg(feature = "signal")]
+    shutdown_signal: Option<ShutdownSignal>,
+}
+
+impl Connection {
+    /// Establish a connection to the gateway at the given URL, and wait for the `Ready` event.
+    ///
+    /// Most users should use `ConnectExt::connect` instead, which looks up the gateway URL
+    /// and fills in the identify payload.
+    pub async fn new(
+        gateway_url: &str,
+        identify: IdentifyPayload,
+    ) -> Result<(Connection, ReadyEvent)> {
+        ConnectionBuilder::new(gateway_url, identify)
+            .connect()
+            .await
+    }
+
+    /// Establish a connection as with `new`, compressing everything the gateway sends
+    /// as a single `zlib-stream`, which uses much less bandwidth than compressing
+    /// only large payloads as requested by the identify payload.
+    ///
+    /// Payload compression is turned off in the identify payload, as the two can't be combined.
+    pub async fn with_transport_compression(
+        gateway_url: &str,
+        identify: IdentifyPayload,
+    ) -> Result<(Connection, ReadyEvent)> {
+        ConnectionBuilder::new(gateway_url, identify)
+            .transport_compression(true)
+            .connect()
+            .await
+    }
+
+    /// Open a websocket to the gateway and start heartbeating, without identifying or resuming.
+    ///
+    /// If a session to resume is given, the websocket is opened to its resume URL.
+    async fn open(builder: ConnectionBuilder, resume: Option<&ResumeInfo>) -> Result<Connection> {
+        let ConnectionBuilder {
+            gateway_url,
+            mut identify,
+            transport_compression,
+            reconnect_policy,
+            #[cfg(feature = "signal")]
+            shutdown_on_signal,
+        } = builder;
+
+        let mut zlib = None;
+        if transport_compression {
+            identify.compress = None;
+            zlib = Some(ZlibStream::new());
+        }
+        let gateway_url = with_query(&gateway_url, transport_compression);
+        let url = match resume.and_then(|info| info.resume_gateway_url.as_deref()) {
+            Some(resume_url) => with_query(resume_url, transport_compression),
+            None => gateway_url.clone(),
+        };
+
+        let (mut stream, sink) = WebSocket::connect(&url).await?.split();
+        let heartbeat_interval = recv_hello(&mut stream, &mut zlib).await?;
+        let (received, reader) = spawn_reader(stream, zlib);
+
+        let sink = SharedSink::new(sink);
+        let last_sequence = Arc::new(AtomicU64::new(resume.map_or(0, |info| info.last_sequence)));
+        let keepalive = spawn_keepalive(heartbeat_interval, sink.clone(), last_sequence.clone());
+
+        Ok(Connection {
+            received,
+            reader,
+            sink,
+            keepalive,
+            last_sequence,
+            gateway_url,
+            identify,
+            session_id: resume.map(|info| info.session_id.clone()),
+            resume_gateway_url: resume.and_then(|info| info.resume_gateway_url.clone()),
+            transport_compression,
+            subscribers: broadcast::channel(BROADCAST_CAPACITY).0,
+            reconnect_policy,
+            reconnect_attempts: 0,
+            last_received: Instant::now(),
+            #[cfg(feature = "voice")]
+            voice: HashMap::new(),
+            #[cfg(feature = "signal")]
+            shutdown_signal: shutdown_on_signal.then(ShutdownSignal::listen),
+        })
+    }
+
+    /// Identify with the gateway, and wait for the `Ready` event.
+    async fn identify_ready(mut self) -> Result<(Connection, ReadyEvent)> {
+        self.send_identify().await?;
+
+        loop {
+            match self.recv_message().await? {
+                ReceivedMessage::Dispatch { dispatch, .. } => match dispatch.event {
+                    Event::Ready(ready) => {
+                        self.session_id = Some(ready.session_id.clone());
+                        self.resume_gateway_url = ready.resume_gateway_url.clone();
+                        return Ok((self, ready));
+                    }
+                    other => debug!("Unexpected event before Ready: {:?}", other),
+                },
+                ReceivedMessage::Heartbeat { .. } => self.send_heartbeat().await?,
+                ReceivedMessage::InvalidSession { .. } => {
+                    return Err(Error::Protocol(
+                        "Session invalidated while identifying, check the token and shard",
+                    ))
+                }
+                ReceivedMessage::HeartbeatAck { .. } => {}
+                other => debug!("Unexpected message before Ready: {:?}", other),
+            }
+        }
+    }
+
+    /// Receive an event over the websocket, waiting until one is available.
+    ///
+    /// Heartbeats, requests to reconnect and invalidated sessions are handled
+    /// without surfacing them to the caller.
+    pub async fn recv_event(&mut self) -> Result<Event> {
+        let event = self.recv_dispatch().await?;
+        if self.subscribers.receiver_count() > 0 {
+            let _ = self.subscribers.send(Arc::new(event.clone()));
+        }
+        Ok(event)
+    }
+
+    /// Receive an event as with `recv_event`, unless the gateway goes quiet for `timeout`.
+    ///
+    /// Heartbeat acknowledgements count as activity, so a healthy connection is never quiet
+    /// for much longer than its heartbeat interval, even while no events happen. Returns `None`
+    /// if nothing was received for `timeout` since the last message, in which case the connection
+    /// is most likely dead without having been closed, and `resume` should be called before
+    /// receiving again.
+    ///
+    /// ```ignore
+    /// loop {
+    ///     match connection.recv_event_timeout(Duration::from_secs(120)).await? {
+    ///         Some(event) => handle(event),
+    ///         None => connection.resume().await?,
+    ///     }
+    /// }
+    /// ```
+    pub async fn recv_event_timeout(&mut self, timeout: Duration) -> Result<Option<Event>> {
+        let event = self.recv_dispatch_within(Some(timeout)).await?;
+        if let Some(event) = &event {
+            if self.subscribers.receiver_count() > 0 {
+                let _ = self.subscribers.send(Arc::new(event.clone()));
+            }
+        }
+        Ok(event)
+    }
+
+    /// When a message, including a heartbeat acknowledgement, was last received from the gateway.
+    ///
+    /// Messages are only received while an event is being waited for, and opening
+    /// a new websocket counts as receiving a message.
+    pub fn idle_since(&self) -> Instant {
+        self.last_received
+    }
+
+    /// Open a new websocket to the gateway, and resume the session, or identify again
+    /// if there is no session to resume.
+    ///
+    /// The connection does this by itself when the websocket is closed or the gateway asks it
+    /// to, but a connection which has gone quiet without being closed needs to be told to.
+    /// Retries as the reconnect policy allows.
+    pub async fn resume(&mut self) -> Result<()> {
+        debug!("Resuming a connection idle since {:?}", self.last_received);
+        self.reconnect().await
+    }
+
+    /// Subscribe to every event received from now on, independently of other subscribers.
+    ///
+    /// Events reach subscribers as they are received by `recv_event` or `events`,
+    /// or by the background task started with `spawn_pump`.
+    /// A subscriber which falls more than 256 events behind misses the oldest of them,
+    /// and is told how many it missed by a `RecvError::Lagged` error.
+    ///
+    /// ```ignore
+    /// let mut logging = connection.subscribe();
+    /// let mut commands = connection.subscribe();
+    /// let pump = connection.spawn_pump();
+    ///
+    /// tokio::spawn(async move {
+    ///     while let Ok(event) = logging.recv().await {
+    ///         println!("{:?}", event);
+    ///     }
+    /// });
+    /// ```
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<Event>> {
+        self.subscribers.subscribe()
+    }
+
+    /// The intents this connection identified with, or `None` if it receives every event.
+    pub fn intents(&self) -> Option<Intents> {
+        self.identify.intents
+    }
+
+    /// Check that the events a handler expects, by gateway name such as `MESSAGE_CREATE`,
+    /// are covered by the intents this connection identified with.
+    ///
+    /// Logs a warning naming the intents which are missing for each event, and returns
+    /// every missing intent. Without them, the gateway silently never sends those events,
+    /// or sends messages without their content.
+    pub fn expect_events(&self, events: &[&str]) -> Intents {
+        let intents = match self.identify.intents {
+            Some(intents) => intents,
+            None => return Intents::empty(),
+        };
+        let mut missing = Intents::empty();
+        for event in events {
+            let needed = Intents::required_for(event) - intents;
+            if !needed.is_empty() {
+                warn!(
+                    "{} is expected, but the connection lacks the {:?} intents",
+                    event, needed
+                );
+                missing |= needed;
+            }
+        }
+        missing
+    }
+
+    /// Receive events in a background task, publishing them to subscribers only.
+    ///
+    /// More subscribers can be added after the pump has started with `Receiver::resubscribe`.
+    /// The task shuts the connection down and finishes once every subscriber is dropped,
+    /// or finishes with an error if the connection could not be kept alive.
+    pub fn spawn_pump(mut self) -> JoinHandle<Result<()>> {
+        tokio::spawn(async move {
+            loop {
+                let event = self.recv_dispatch().await?;
+                if self.subscribers.send(Arc::new(event)).is_err() {
+                    debug!("Every subscriber was dropped, shutting down");
+                    return self.shutdown().await;
+                }
+            }
+        })
+    }
+
+    /// Receive the next dispatched event, handling every other message.
+    async fn recv_dispatch(&mut self) -> Result<Event> {
+        loop {
+            if let Some(event) = self.recv_dispatch_within(None).await? {
+                return Ok(event);
+            }
+        }
+    }
+
+    /// Receive the next dispatched event, handling every other message, or return `None`
+    /// if nothing at all is received for `idle_timeout` since the last message.
+    ///
+    /// If the connection shuts down on signals, `Event::Shutdown` is returned once one
+    /// is received, and nothing can be received after it.
+    async fn recv_dispatch_within(
+        &mut self,
+        idle_timeout: Option<Duration>,
+    ) -> Result<Option<Event>> {
+        #[cfg(feature = "signal")]
+        if let Some(signal) = &self.shutdown_signal {
+            if signal.delivered {
+                return Err(Error::Other("The connection was shut down by a signal"));
+            }
+            let signalled = signal.wait();
+            // messages are never left half received, but a reconnect may be cut off halfway,
+            // which is why nothing can be received after
+            tokio::select! {
+                biased;
+                () = signalled => {
+                    if let Some(signal) = &mut self.shutdown_signal {
+                        signal.delivered = true;
+                    }
+                    return Ok(Some(Event::Shutdown));
+                }
+                result = self.recv_gateway_dispatch(idle_timeout) => return result,
+            }
+        }
+        self.recv_gateway_dispatch(idle_timeout).await
+    }
+
+    /// Receive the next dispatched event from the gateway, as with `recv_dispatch_within`.
+    async fn recv_gateway_dispatch(
+        &mut self,
+        idle_timeout: Option<Duration>,
+    ) -> Result<Option<Event>> {
+        loop {
+            let message = match idle_timeout {
+                Some(timeout) => {
+                    let deadline = self.last_received + timeout;
+                    match tokio::time::timeout_at(deadline, self.recv_message()).await {
+                        Ok(message) => message,
+                        Err(_) => {
+                            debug!("Nothing received from the gateway for {:?}", timeout);
+                            return Ok(None);
+                        }
+                    }
+                }
+                None => self.recv_message().await,
+            };
+            match message {
+                Ok(ReceivedMessage::Dispatch { dispatch, .. }) => {
+                    match dispatch.event {
+                        Event::Ready(ref ready) => {
+                            self.session_id = Some(ready.session_id.clone());
+                            self.resume_gateway_url = ready.resume_gateway_url.clone();
+                            self.reconnect_attempts = 0;
+                            self.sink.flush().await;
+                        }
+                        Event::Resumed { .. } => {
+                            self.reconnect_attempts = 0;
+                            self.sink.flush().await;
+                        }
+                        _ => {}
+                    }
+                    #[cfg(feature = "voice")]
+                    self.update_voice(&dispatch.event);
+                    return Ok(Some(dispatch.event));
+                }
+                Ok(ReceivedMessage::Heartbeat { .. }) => self.send_heartbeat().await?,
+                Ok(ReceivedMessage::HeartbeatAck { .. }) => {}
+                Ok(ReceivedMessage::Hello { payload, .. }) => {
+                    debug!("Mysterious late-game hello: {}", payload.heartbeat_interval);
+                    self.restart_keepalive(payload.heartbeat_interval);
+                }
+                Ok(ReceivedMessage::Reconnect { .. }) => {
+                    debug!("Gateway requested a reconnect");
+                    self.reconnect().await?;
+                }
+                Ok(ReceivedMessage::InvalidSession { .. }) => {
+                    debug!("Session invalidated, identifying again");
+                    self.session_id = None;
+                    self.sink.hold().await;
+
+                    // the gateway expects a random wait of 1 to 5 seconds before identifying again
+                    let delay = rand::thread_rng().gen_range(1000..=5000);
+                    tokio::time::sleep(Duration::from_millis(delay)).await;
+                    self.send_identify().await?;
+                }
+                Err(Error::WebSocket(err)) => {
+                    warn!("Websocket error, reconnecting: {:?}", err);
+                    self.reconnect().await?;
+                }
+                Err(Error::Closed(code, reason)) => {
+                    let close = code.map(GatewayCloseCode::from);
+                    if close.is_some_and(GatewayCloseCode::is_fatal) {
+                        return Err(Error::Closed(code, reason));
+                    }
+                    debug!("Closure, reconnecting: {:?}: {}", close, reason);
+                    if close.is_some_and(GatewayCloseCode::invalidates_session) {
+                        self.session_id = None;
+                    }
+                    if close == Some(GatewayCloseCode::RateLimited) {
+                        // back off before the first attempt too
+                        self.reconnect_attempts = self.reconnect_attempts.max(1);
+                    }
+                    self.reconnect().await?;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Receive events as a stream, for use with `StreamExt` and other stream combinators.
+    ///
+    /// Reconnects are handled inside the stream just as by `recv_event`. If an error is
+    /// yielded, the connection could not be kept alive and the stream ends.
+    ///
+    /// ```ignore
+    /// let mut events = connection.events();
+    /// while let Some(event) = events.next().await {
+    ///     let event = event?;
+    ///     // handle the event
+    /// }
+    /// ```
+    pub fn events(&mut self) -> impl Stream<Item = Result<Event>> + Send + '_ {
+        event_stream(self)
+    }
+
+    /// Turn the connection into a stream of events, as with `events`.
+    pub fn into_stream(self) -> impl Stream<Item = Result<Event>> + Send {
+        event_stream(self)
+    }
+
+    /// Change the game information that this client reports as playing.
+    ///
+    /// If the connection is reconnecting, the update is sent once the session is re-established.
+    pub async fn set_presence(&self, presence: UpdatePresencePayload) -> Result<()> {
+        self.sink
+            .send(&SentMessage::UpdatePresence {
+                op: Eq,
+                payload: presence,
+            })
+            .await
+    }
+
+    /// Join, move between or leave (with `None`) voice channels in a server,
+    /// and set whether this client is muted and deafened.
+    ///
+    /// This only changes the voice state; no audio is sent or received. To play audio,
+    /// use the `VoiceConnection` from `voice` instead, which keeps track of its own state.
+    ///
+    /// If the connection is reconnecting, the update is sent once the session is re-established.
+    pub async fn update_voice_state(
+        &self,
+        server: ServerId,
+        channel: Option<ChannelId>,
+        mute: bool,
+        deaf: bool,
+    ) -> Result<()> {
+        self.sink
+            .send(&SentMessage::UpdateVoiceState {
+                op: Eq,
+                payload: UpdateVoiceStatePayload {
+                    guild_id: server,
+                    channel_id: channel,
+                    self_mute: mute,
+                    self_deaf: deaf,
+                },
+            })
+            .await
+    }
+
+    /// Request a chunk of server members, delivered as `ServerMembersChunk` events.
+    ///
+    /// If the connection is reconnecting, the request is sent once the session is re-established.
+    pub async fn request_members(&self, request: RequestGuildMembersPayload) -> Result<()> {
+        self.sink
+            .send(&SentMessage::RequestGuildMembers {
+                op: Eq,
+                payload: request,
+            })
+            .await
+    }
+
+    /// Request the members of a server whose username starts with `query`,
+    /// returning a handle which collects every chunk of the answer.
+    ///
+    /// An empty query with a `limit` of 0 requests every member of the server.
+    /// The chunks are matched to the request by its nonce, and only arrive while events
+    /// are being received with `recv_event`, `events` or `spawn_pump`, so the handle
+    /// should be awaited in another task than the one receiving events.
+    ///
+    /// ```ignore
+    /// let request = connection.fetch_members(server_id, "", 0).await?;
+    /// let members = tokio::spawn(request.collect());
+    /// // keep receiving events meanwhile
+    /// ```
+    pub async fn fetch_members(
+        &self,
+        server_id: ServerId,
+        query: &str,
+        limit: u32,
+    ) -> Result<MemberRequest> {
+        let nonce = format!("{:016x}", rand::random::<u64>());
+        // subscribe before sending, so that no chunk is missed
+        let events = self.subscribe();
+        self.request_members(RequestGuildMembersPayload {
+            server_id,
+            username_query: Some(query.to_owned()),
+            limit,
+            presences: false,
+            user_ids: None,
+            nonce: nonce.clone(),
+        })
+        .await?;
+
+        Ok(MemberRequest {
+            events,
+            nonce,
+            timeout: MEMBER_CHUNK_TIMEOUT,
+        })
+    }
+
+    /// Requests a download of all member information for large servers.
+    ///
+    /// The members lists are cleared on call, and then refilled as chunks are received. When
+    /// `unknown_members()` returns 0, the download has completed.
+    pub async fn download_all_members(&self, state: &mut State) -> Result<()> {
+        for server_id in state.__download_members() {
+            self.request_members(RequestGuildMembersPayload {
+                server_id,
+                username_query: Some(String::new()),
+                limit: 0,
+                presences: false,
+                user_ids: None,
+                nonce: String::new(),
+            })
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// Get a handle to the voice connection for a server, creating it if needed.
+    ///
+    /// The voice connection is kept up to date with the voice events received over
+    /// this connection, so events must keep being received for it to join channels.
+    #[cfg(feature = "voice")]
+    pub fn voice(&mut self, server_id: ServerId) -> &mut VoiceConnection {
+        let sink = &self.sink;
+        self.voice
+            .entry(server_id)
+            .or_insert_with(|| VoiceConnection::new(server_id, sink.clone()))
+    }
+
+    /// Drop the voice connection for a server, leaving its voice channel.
+    #[cfg(feature = "voice")]
+    pub fn drop_voice(&mut self, server_id: ServerId) {
+        self.voice.remove(&server_id);
+    }
+
+    /// Pass the voice events of servers with a voice connection on to the connection.
+    #[cfg(feature = "voice")]
+    fn update_voice(&mut self, event: &Event) {
+        match *event {
+            Event::VoiceStateUpdate(Some(server_id), ref voice_state) => {
+                if let Some(voice) = self.voice.get_mut(&server_id) {
+                    voice.update_state(self.session_id.as_deref(), voice_state);
+                }
+            }
+            Event::VoiceServerUpdate {
+                server_id: Some(server_id),
+                ref endpoint,
+                ref token,
+                ..
+            } => {
+                if let Some(voice) = self.voice.get_mut(&server_id) {
+                    voice.update_server(endpoint, token);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// The ID of the current gateway session, if one has been established.
+    pub fn session_id(&self) -> Option<&str> {
+        self.session_id.as_deref()
+    }
+
+    /// What is needed to resume the current gateway session later with
+    /// `ConnectionBuilder::resume`, possibly from another process.
+    ///
+    /// Returns `None` if no session has been established.
+    pub fn resume_info(&self) -> Option<ResumeInfo> {
+        Some(ResumeInfo {
+            session_id: self.session_id.clone()?,
+            last_sequence: self.last_sequence.load(Ordering::Relaxed),
+            resume_gateway_url: self.resume_gateway_url.clone(),
+        })
+    }
+
+    /// Cleanly shut down the websocket connection, ending the session.
+    pub async fn shutdown(self) -> Result<()> {
+        self.keepalive.abort();
+        self.sink.close(1000, "").await
+    }
+
+    /// Shut down the websocket connection while keeping the session open,
+    /// returning it so that it can be resumed with `ConnectionBuilder::resume`.
+    ///
+    /// The gateway only keeps sessions open for a short while, so this is meant for
+    /// restarting a bot quickly without missing events, such as when deploying.
+    pub async fn shutdown_resumable(self) -> Result<Option<ResumeInfo>> {
+        self.keepalive.abort();
+        // closing with 1000 would end the session
+        self.sink.close(4000, "Shutting down").await?;
+        Ok(self.resume_info())
+    }
+
+    /// Receive a single message from the gateway, keeping track of the sequence number.
+    ///
+    /// This is cancel-safe, as the websocket is read by the reader task.
+    async fn recv_message(&mut self) -> Result<ReceivedMessage> {
+        let value = match self.received.recv().await {
+            Some(value) => value?,
+            // the reader stops after passing on an error, so this is only reached
+            // if receiving is tried again without reconnecting
+            None => return Err(Error::Closed(None, "The gateway reader stopped".to_owned())),
+        };
+        self.last_received = Instant::now();
+        if let Some(sequence) = value.get("s").and_then(Value::as_u64) {
+            self.last_sequence.store(sequence, Ordering::Relaxed);
+        }
+        Ok(serde_json::from_value(value)?)
+    }
+
+    /// Reconnect to the gateway, retrying failed attempts as the reconnect policy allows.
+    ///
+    /// Gives up straight away if the gateway closes the connection for a reason
+    /// reconnecting can't fix.
+    async fn reconnect(&mut self) -> Result<()> {
+        let mut last_error = None;
+        loop {
+            let delay = match self.reconnect_policy.delay(self.reconnect_attempts) {
+                Some(delay) => delay,
+                None => {
+                    return Err(
+                        last_error.unwrap_or(Error::Other("Gave up reconnecting to the gateway"))
+                    )
+                }
+            };
+            self.reconnect_attempts += 1;
+            if !delay.is_zero() {
+                debug!("Waiting {:?} before reconnecting", delay);
+                tokio::time::sleep(delay).await;
+            }
+
+            match self.open_socket().await {
+                Err(err) if !err.close_code().is_some_and(GatewayCloseCode::is_fatal) => {
+                    warn!("Failed to reconnect: {:?}", err);
+                    last_error = Some(err);
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Open a new websocket to the gateway, and resume the session if possible.
+    async fn open_socket(&mut self) -> Result<()> {
+        let url = match (&self.session_id, &self.resume_gateway_url) {
+            (Some(_), Some(resume_url)) => with_query(resume_url, self.transport_compression),
+            _ => self.gateway_url.clone(),
+        };
+        debug!("Reconnecting to {}", url);
+        self.sink.hold().await;
+
+        let (mut stream, sink) = WebSocket::connect(&url).await?.split();
+        // each websocket has its own zlib context
+        let mut zlib = self.transport_compression.then(ZlibStream::new);
+        let heartbeat_interval = recv_hello(&mut stream, &mut zlib).await?;
+
+        self.reader.abort();
+        (self.received, self.reader) = spawn_reader(stream, zlib);
+        self.last_received = Instant::now();
+        self.sink.replace(sink).await;
+        self.restart_keepalive(heartbeat_interval);
+
+        match self.session_id.clone() {
+            Some(session_id) => self.send_resume(session_id).await,
+            None => {
+                self.last_sequence.store(0, Ordering::Relaxed);
+                self.send_identify().await
+            }
+        }
+    }
+
+    /// Replace the keep-alive task with one using a new heartbeat interval.
+    fn restart_keepalive(&mut self, heartbeat_interval: u64) {
+        self.keepalive.abort();
+        self.keepalive = spawn_keepalive(
+            heartbeat_interval,
+            self.sink.clone(),
+            self.last_sequence.clone(),
+        );
+    }
+
+    async fn send_identify(&self) -> Result<()> {
+        self.sink
+            .send_now(&SentMessage::Identify {
+                op: Eq,
+                payload: self.identify.clone(),
+            })
+            .await
+    }
+
+    async fn send_resume(&self, session_id: String) -> Result<()> {
+        let resume = ResumePayload {
+            token: self.identify.token.clone(),
+            session_id,
+            last_sequence: self.last_sequence.load(Ordering::Relaxed),
+        };
+        self.sink
+            .send_now(&SentMessage::Resume {
+                op: Eq,
+                payload: resume,
+            })
+            .await
+    }
+
+    async fn send_heartbeat(&self) -> Result<()> {
+        self.sink
+            .send_now(&heartbeat(self.last_sequence.load(Ordering::Relaxed)))
+            .await
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.keepalive.abort();
+        self.reader.abort();
+    }
+}
+
+impl fmt::Debug for Connection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Connection")
+            .field("gateway_url", &self.gateway_url)
+            .field("session_id", &self.session_id)
+            .field("last_sequence", &self.last_sequence)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Configures a gateway connection before opening it.
+///
+/// ```ignore
+/// let (connection, ready) = ConnectionBuilder::new(&url, identify)
+///     .transport_compression(true)
+///     .reconnect_policy(ReconnectPolicy::new().max_attempts(None))
+///     .connect()
+///     .await?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct ConnectionBuilder {
+    gateway_url: String,
+    identify: IdentifyPayload,
+    transport_compression: bool,
+    reconnect_policy: ReconnectPolicy,
+    #[cfg(feature = "signal")]
+    shutdown_on_signal: bool,
+}
+
+impl ConnectionBuilder {
+    /// Start configuring a connection to the gateway at the given URL.
+    pub fn new(gateway_url: &str, identify: IdentifyPayload) -> ConnectionBuilder {
+        ConnectionBuilder {
+            gateway_url: gateway_url.to_owned(),
+            identify,
+            transport_compression: false,
+            reconnect_policy: ReconnectPolicy::default(),
+            #[cfg(feature = "signal")]
+            shutdown_on_signal: false,
+        }
+    }
+
+    /// Compress everything sent by the gateway, as with `Connection::with_transport_compression`.
+    /// Off by default.
+    pub fn transport_compression(mut self, transport_compression: bool) -> Self {
+        self.transport_compression = transport_compression;
+        self
+    }
+
+    /// Retry reconnecting as the policy allows, instead of the default policy.
+    pub fn reconnect_policy(mut self, policy: ReconnectPolicy) -> Self {
+        self.reconnect_policy = policy;
+        self
+    }
+
+    /// Receive `Event::Shutdown` as the last event once the process gets Ctrl-C, or `SIGTERM`
+    /// on Unix, instead of being killed by it. Off by default.
+    ///
+    /// ```ignore
+    /// loop {
+    ///     match connection.recv_event().await? {
+    ///         Event::Shutdown => break,
+    ///         event => handle(event),
+    ///     }
+    /// }
+    /// connection.voice(server_id).disconnect().await?;
+    /// connection.shutdown().await?;
+    /// ```
+    #[cfg(feature = "signal")]
+    pub fn shutdown_on_signal(mut self, shutdown_on_signal: bool) -> Self {
+        self.shutdown_on_signal = shutdown_on_signal;
+        self
+    }
+
+    /// Establish the connection, and wait for the `Ready` event.
+    pub async fn connect(self) -> Result<(Connection, ReadyEvent)> {
+        Connection::open(self, None).await?.identify_ready().await
+    }
+
+    /// Establish the connection by resuming a session instead of starting a new one,
+    /// which doesn't count against the limit on identifying.
+    ///
+    /// The events missed since the session was left are received first, followed by `Resumed`.
+    /// If the session can no longer be resumed, a new session is identified instead
+    /// and `Ready` is received.
+    pub async fn resume(self, info: ResumeInfo) -> Result<Connection> {
+        let connection = Connection::open(self, Some(&info)).await?;
+        // hold messages sent before the session is resumed, as when reconnecting
+        connection.sink.hold().await;
+        connection.send_resume(info.session_id).await?;
+        Ok(connection)
+    }
+}
+
+/// What is needed to resume a gateway session, from `Connection::resume_info`.
+///
+/// This can be serialized and saved, so that a bot which restarts quickly can resume
+/// its previous session with `ConnectionBuilder::resume`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResumeInfo {
+    /// The ID of the session.
+    pub session_id: String,
+    /// The sequence number of the last dispatch received.
+    pub last_sequence: u64,
+    /// The gateway URL to resume the session at, if the gateway gave one.
+    pub resume_gateway_url: Option<String>,
+}
+
+/// The chunks answering a request made with `Connection::fetch_members`.
 #[derive(Debug)]
-pub struct Connection;
+pub struct MemberRequest {
+    events: broadcast::Receiver<Arc<Event>>,
+    nonce: String,
+    /// How long to wait for each chunk.
+    timeout: Duration,
+}
+
+impl MemberRequest {
+    /// Set how long to wait for each chunk before giving up. 30 seconds by default.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Wait for every chunk of the answer, and collect their members.
+    ///
+    /// Fails if the connection is dropped, if chunks were missed by falling
+    /// too far behind the events received, or if the next chunk takes longer
+    /// than the timeout to arrive, before the last chunk arrives.
+    pub async fn collect(mut self) -> Result<Vec<Member>> {
+        let mut collected = Vec::new();
+        let mut received = 0;
+        let mut deadline = Instant::now() + self.timeout;
+        loop {
+            let event = match tokio::time::timeout_at(deadline, self.events.recv()).await {
+                Err(_) => return Err(Error::Other("Timed out waiting for member chunks")),
+                Ok(Ok(event)) => event,
+                Ok(Err(broadcast::error::RecvError::Lagged(missed))) => {
+                    warn!("Missed {} events while waiting for member chunks", missed);
+                    return Err(Error::Other("Missed events while collecting member chunks"));
+                }
+                Ok(Err(broadcast::error::RecvError::Closed)) => {
+                    return Err(Error::Other(
+                        "Connection closed while collecting member chunks",
+                    ));
+                }
+            };
+            if let Event::ServerMembersChunk {
+                ref members,
+                chunk_count,
+                nonce: Some(ref nonce),
+                ..
+            } = *event
+            {
+                if *nonce == self.nonce {
+                    collected.extend_from_slice(members);
+                    received += 1;
+                    deadline = Instant::now() + self.timeout;
+                    if received >= chunk_count {
+                        return Ok(collected);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Receive events from a connection until an error is received, which ends the stream.
+fn event_stream<C>(connection: C) -> impl Stream<Item = Result<Event>> + Send
+where
+    C: BorrowMut<Connection> + Send,
+{
+    stream::unfold(Some(connection), |connection| async move {
+        let mut connection = connection?;
+        match connection.borrow_mut().recv_event().await {
+            Ok(event) => Some((Ok(event), Some(connection))),
+            Err(err) => Some((Err(err), None)),
+        }
+    })
+}
+
+/// The sending half of the gateway websocket, shared between the connection and its keep-alive task.
+///
+/// All outgoing gateway messages pass through here. While the connection is
+/// reconnecting, messages other than those establishing the session are held
+/// back, and sent once the session is re-established.
+#[derive(Clone)]
+pub(crate) struct SharedSink(Arc<Mutex<SinkState>>);
+
+struct SinkState {
+    sink: WebSocketWriteHalf,
+    /// Whether messages are being held back until the session is re-established.
+    holding: bool,
+    /// Messages held back while reconnecting, along with when they were sent.
+    buffer: VecDeque<(Instant, String)>,
+}
+
+impl SharedSink {
+    fn new(sink: WebSocketWriteHalf) -> Self {
+        SharedSink(Arc::new(Mutex::new(SinkState {
+            sink,
+            holding: false,
+            buffer: VecDeque::new(),
+        })))
+    }
+
+    /// Serialize and send a message to the gateway,
+    /// or hold it back if the connection is reconnecting.
+    ///
+    /// If sending fails, the message is held back as well,
+    /// as the connection will be reconnecting shortly.
+    pub async fn send(&self, message: &SentMessage) -> Result<()> {
+        let payload = serde_json::to_string(message)?;
+        let mut state = self.0.lock().await;
+
+        if !state.holding {
+            match state.sink.send_text(payload.clone()).await {
+                Ok(()) => return Ok(()),
+                Err(err) => {
+                    debug!(
+                        "Failed to send, holding messages until reconnected: {:?}",
+                        err
+                    );
+                    state.holding = true;
+                }
+            }
+        }
+
+        if state.buffer.len() == BUFFER_CAPACITY {
+            warn!("Too many messages held while reconnecting, dropping the oldest");
+            state.buffer.pop_front();
+        }
+        state.buffer.push_back((Instant::now(), payload));
+        Ok(())
+    }
+
+    /// Serialize and send a message to the gateway immediately,
+    /// even if other messages are being held back.
+    pub async fn send_now(&self, message: &SentMessage) -> Result<()> {
+        let payload = serde_json::to_string(message)?;
+        self.0.lock().await.sink.send_text(payload).await?;
+        Ok(())
+    }
+
+    /// Send a close frame with the given code and reason.
+    pub async fn close(&self, code: u16, reason: &str) -> Result<()> {
+        self.0
+            .lock()
+            .await
+            .sink
+            .close(Some((code, reason.to_owned())))
+            .await?;
+        Ok(())
+    }
+
+    /// Start holding back messages until `flush` is called.
+    async fn hold(&self) {
+        self.0.lock().await.holding = true;
+    }
+
+    /// Send the messages held back while reconnecting, dropping those which have gone stale.
+    ///
+    /// If sending fails, the unsent messages stay held back for the next reconnect to flush.
+    async fn flush(&self) {
+        let mut state = self.0.lock().await;
+
+        while let Some((sent_at, payload)) = state.buffer.pop_front() {
+            if sent_at.elapsed() > BUFFER_MAX_AGE {
+                debug!("Dropping stale message held while reconnecting");
+                continue;
+            }
+            if let Err(err) = state.sink.send_text(payload.clone()).await {
+                debug!(
+                    "Failed to send held messages, holding them until reconnected: {:?}",
+                    err
+                );
+                state.buffer.push_front((sent_at, payload));
+                return;
+            }
+        }
+        state.holding = false;
+    }
+
+    /// Swap in the sending half of a new websocket, closing the old one.
+    async fn replace(&self, sink: WebSocketWriteHalf) {
+        let mut old = std::mem::replace(&mut self.0.lock().await.sink, sink);
+
+        // closing with 1000 would end the session, so use a non-standard code to keep it resumable
+        if let Err(err) = old.close(Some((4000, "Reconnecting".to_owned()))).await {
+            debug!("Failed to close the old websocket: {:?}", err);
+        }
+    }
+}
+
+impl fmt::Debug for SharedSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SharedSink")
+    }
+}
+
+/// Add the query string requesting the gateway version, encoding and compression to a gateway URL.
+fn with_query(url: &str, transport_compression: bool) -> String {
+    let mut url = format!("{}{}", url, GATEWAY_QUERY);
+    if transport_compression {
+        url.push_str(ZLIB_STREAM_QUERY);
+    }
+    url
+}
+
+/// Spawn a task sending heartbeats with the latest sequence number on the given interval.
+fn spawn_keepalive(
+    heartbeat_interval: u64,
+    sink: SharedSink,
+    last_sequence: Arc<AtomicU64>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        // the first heartbeat is jittered so that clients don't heartbeat in lockstep
+        let jitter = rand::thread_rng().gen_range(0..=heartbeat_interval);
+        tokio::time::sleep(Duration::from_millis(jitter)).await;
+
+        let mut interval = tokio::time::interval(Duration::from_millis(heartbeat_interval));
+        loop {
+            interval.tick().await;
+
+            let message = heartbeat(last_sequence.load(Ordering::Relaxed));
+            if let Err(err) = sink.send_now(&message).await {
+                warn!("Failed to send heartbeat: {:?}", err);
+            }
+        }
+    })
+}
+
+fn heartbeat(last_sequence: u64) -> SentMessage {
+    SentMessage::Heartbeat {
+        op: Eq,
+        last_sequence: Some(last_sequence).filter(|&s| s != 0),
+    }
+}
+
+/// Read messages from the gateway websocket in a background task, until it fails or is closed.
+fn spawn_reader(
+    mut stream: WebSocketReadHalf,
+    mut zlib: Option<ZlibStream>,
+) -> (mpsc::UnboundedReceiver<Result<Value>>, JoinHandle<()>) {
+    let (tx, received) = mpsc::unbounded_channel();
+    let reader = tokio::spawn(async move {
+        loop {
+            let value = recv_json(&mut stream, &mut zlib).await;
+            // a message which fails to decode doesn't stop the websocket
+            let failed = matches!(value, Err(Error::WebSocket(_) | Error::Closed(..)));
+            if tx.send(value).is_err() || failed {
+                return;
+            }
+        }
+    });
+    (received, reader)
+}
+
+/// Wait for the `Hello` message sent when opening a websocket, returning the heartbeat interval.
+async fn recv_hello(stream: &mut WebSocketReadHalf, zlib: &mut Option<ZlibStream>) -> Result<u64> {
+    loop {
+        match serde_json::from_value(recv_json(stream, zlib).await?)? {
+            ReceivedMessage::Hello { payload, .. } => return Ok(payload.heartbeat_interval),
+            other => debug!("Unexpected message before Hello: {:?}", other),
+        }
+    }
+}
+
+/// Receive a complete JSON message, joining fragmented frames and inflating compressed ones.
+///
+/// With transport compression, binary frames are inflated through the connection's zlib context.
+async fn recv_json(stream: &mut WebSocketReadHalf, zlib: &mut Option<ZlibStream>) -> Result<Value> {
+    let mut text = String::new();
+    let mut binary = Vec::new();
+
+    loop {
+        match stream.receive().await? {
+            Frame::Text { payload, fin, .. } => {
+                text.push_str(&payload);
+                if fin {
+                    return Ok(serde_json::from_str(&text)?);
+                }
+            }
+            Frame::Binary { payload, .. } if zlib.is_some() => {
+                if let Some(value) = zlib.as_mut().unwrap().push(&payload)? {
+                    return Ok(value);
+                }
+            }
+            Frame::Binary { payload, fin, .. } => {
+                binary.extend_from_slice(&payload);
+                if fin {
+                    let mut inflated = String::new();
+                    ZlibDecoder::new(&binary[..]).read_to_string(&mut inflated)?;
+                    return Ok(serde_json::from_str(&inflated)?);
+                }
+            }
+            Frame::Close { payload } => {
+                let (code, reason) = match payload {
+                    Some((code, reason)) => (Some(code), reason),
+                    None => (None, String::new()),
+                };
+                return Err(Error::Closed(code, reason));
+            }
+            Frame::Ping { .. } | Frame::Pong { .. } => {}
+        }
+    }
+}
+
+/// The zlib context shared by every message received over a websocket
+/// using `zlib-stream` transport compression.
+struct ZlibStream {
+    decompress: Decompress,
+    /// Compressed data received since the end of the last message.
+    buffer: Vec<u8>,
+}
+
+impl ZlibStream {
+    fn new() -> ZlibStream {
+        ZlibStream {
+            decompress: Decompress::new(true),
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Add a frame of compressed data, returning the message it completes, if any.
+    fn push(&mut self, payload: &[u8]) -> Result<Option<Value>> {
+        self.buffer.extend_from_slice(payload);
+        if !self.buffer.ends_with(&ZLIB_SUFFIX) {
+            return Ok(None);
+        }
+
+        let mut input = &self.buffer[..];
+        let mut inflated = Vec::with_capacity(input.len() * 4);
+        loop {
+            if inflated.len() == inflated.capacity() {
+                inflated.reserve(inflated.capacity().max(1024));
+            }
+            let (total_in, total_out) = (self.decompress.total_in(), self.decompress.total_out());
+            self.decompress
+                .decompress_vec(input, &mut inflated, FlushDecompress::Sync)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+            let consumed = (self.decompress.total_in() - total_in) as usize;
+            input = &input[consumed..];
+
+            // stop once all input is consumed and there was room left for more output,
+            // or if no progress can be made
+            let stalled = consumed == 0 && self.decompress.total_out() == total_out;
+            if stalled || input.is_empty() && inflated.len() < inflated.capacity() {
+                break;
+            }
+        }
+        self.buffer.clear();
+
+        Ok(Some(serde_json::from_slice(&inflated)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use flate2::{Compress, Compression, FlushCompress};
+    use serde_json::json;
+
+    use websockets::{WebSocket, WebSocketWriteHalf};
+
+    use super::{Connection, ConnectionBuilder, SharedSink, ZlibStream};
+    use crate::model::{
+        ChannelId, Event, IdentifyConnection, IdentifyPayload, MessageId, SentMessage, ServerId,
+        Token, UserId,
+    };
+    use crate::serial::Eq;
+    #[cfg(feature = "signal")]
+    use crate::signal::ShutdownSignal;
+    use crate::test_gateway::{TestGateway, TestSession};
+
+    fn identify() -> IdentifyPayload {
+        IdentifyPayload {
+            token: Token::bot("Bot token"),
+            shard: None,
+            intents: None,
+            compress: None,
+            large_threshold: None,
+            fingerprint: IdentifyConnection {
+                os: "test".to_owned(),
+                browser: "test".to_owned(),
+                device: "test".to_owned(),
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn heartbeat_dispatch_and_resume() {
+        let gateway = TestGateway::bind().await.unwrap();
+        let url = gateway.url();
+
+        let server = tokio::spawn(async move {
+            let mut session = gateway.accept().await.unwrap();
+            session.hello(50).await.unwrap();
+
+            let identify = session.expect_identify().await.unwrap();
+            // the gateway takes bot tokens without their prefix
+            assert_eq!(identify["token"], "token");
+            session.ready("session").await.unwrap();
+
+            // heartbeats carry the sequence number of the last dispatch
+            while session.expect_heartbeat().await.unwrap() != Some(1) {}
+
+            let data = json!({ "channel_id": "10", "message_id": "20" });
+            session.dispatch("MESSAGE_DELETE", data).await.unwrap();
+            session.reconnect().await.unwrap();
+            drop(session);
+
+            let mut session = gateway.accept().await.unwrap();
+            session.hello(50).await.unwrap();
+
+            let resume = session.expect_resume().await.unwrap();
+            assert_eq!(resume["token"], "token");
+            assert_eq!(resume["session_id"], "session");
+            assert_eq!(resume["seq"], 2);
+            session.resumed().await.unwrap();
+        });
+
+        let (mut connection, ready) = Connection::new(&url, identify()).await.unwrap();
+        assert_eq!(ready.session_id, "session");
+
+        match connection.recv_event().await.unwrap() {
+            Event::MessageDelete { message_id, .. } => assert_eq!(message_id, MessageId(20)),
+            other => panic!("unexpected event: {:?}", other),
+        }
+        match connection.recv_event().await.unwrap() {
+            Event::Resumed { .. } => {}
+            other => panic!("unexpected event: {:?}", other),
+        }
+
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn resumes_after_shutdown() {
+        let gateway = TestGateway::bind().await.unwrap();
+        let url = gateway.url();
+
+        let server = tokio::spawn(async move {
+            let mut session = gateway.accept().await.unwrap();
+            session.hello(45000).await.unwrap();
+            session.expect_identify().await.unwrap();
+            session.ready("session").await.unwrap();
+            assert_eq!(session.expect_close().await.unwrap(), Some(4000));
+
+            let mut session = gateway.accept().await.unwrap();
+            session.hello(45000).await.unwrap();
+            let resume = session.expect_resume().await.unwrap();
+            assert_eq!(resume["session_id"], "session");
+            assert_eq!(resume["seq"], 1);
+            session.resumed().await.unwrap();
+        });
+
+        let (connection, _) = Connection::new(&url, identify()).await.unwrap();
+        let saved = connection.shutdown_resumable().await.unwrap().unwrap();
+        assert_eq!(saved.last_sequence, 1);
+
+        let mut connection = ConnectionBuilder::new(&url, identify())
+            .resume(saved)
+            .await
+            .unwrap();
+        match connection.recv_event().await.unwrap() {
+            Event::Resumed { .. } => {}
+            other => panic!("unexpected event: {:?}", other),
+        }
+
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn resumes_after_going_quiet() {
+        let gateway = TestGateway::bind().await.unwrap();
+        let url = gateway.url();
+
+        let server = tokio::spawn(async move {
+            let mut quiet = gateway.accept().await.unwrap();
+            quiet.hello(45000).await.unwrap();
+            quiet.expect_identify().await.unwrap();
+            quiet.ready("session").await.unwrap();
+
+            // stop answering without closing the websocket
+            let mut session = gateway.accept().await.unwrap();
+            session.hello(45000).await.unwrap();
+            let resume = session.expect_resume().await.unwrap();
+            assert_eq!(resume["session_id"], "session");
+            session.resumed().await.unwrap();
+            drop(quiet);
+        });
+
+        let (mut connection, _) = Connection::new(&url, identify()).await.unwrap();
+        let timeout = Duration::from_millis(100);
+        let event = connection.recv_event_timeout(timeout).await.unwrap();
+        assert!(event.is_none());
+        assert!(connection.idle_since().elapsed() >= timeout);
+
+        connection.resume().await.unwrap();
+        match connection.recv_event_timeout(timeout).await.unwrap() {
+            Some(Event::Resumed { .. }) => {}
+            other => panic!("unexpected event: {:?}", other),
+        }
+
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn timeouts_keep_partial_messages() {
+        let gateway = TestGateway::bind().await.unwrap();
+        let url = gateway.url();
+        let (split_tx, split_rx) = tokio::sync::oneshot::channel();
+
+        let server = tokio::spawn(async move {
+            let mut session = gateway.accept().await.unwrap();
+            session.hello(45000).await.unwrap();
+            session.expect_identify().await.unwrap();
+            session.ready("session").await.unwrap();
+
+            let message = json!({
+                "op": 0,
+                "s": 2,
+                "t": "MESSAGE_DELETE",
+                "d": { "channel_id": "10", "message_id": "20" },
+            })
+            .to_string();
+            let (first, rest) = message.as_bytes().split_at(20);
+            session.send_frame(false, 0x1, first).await.unwrap();
+            // the rest arrives after the client stopped waiting
+            split_rx.await.unwrap();
+            session.send_frame(true, 0x0, rest).await.unwrap();
+        });
+
+        let (mut connection, _) = Connection::new(&url, identify()).await.unwrap();
+        let timeout = Duration::from_millis(100);
+        assert!(connection
+            .recv_event_timeout(timeout)
+            .await
+            .unwrap()
+            .is_none());
+        split_tx.send(()).unwrap();
+
+        match connection.recv_event().await.unwrap() {
+            Event::MessageDelete { message_id, .. } => assert_eq!(message_id, MessageId(20)),
+            other => panic!("unexpected event: {:?}", other),
+        }
+
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn updates_voice_state() {
+        let gateway = TestGateway::bind().await.unwrap();
+        let url = gateway.url();
+
+        let server = tokio::spawn(async move {
+            let mut session = gateway.accept().await.unwrap();
+            session.hello(45000).await.unwrap();
+            session.expect_identify().await.unwrap();
+            session.ready("session").await.unwrap();
+
+            let update = session.expect_op(4).await.unwrap();
+            assert_eq!(update["guild_id"], json!(ServerId(5)));
+            assert_eq!(update["channel_id"], json!(ChannelId(6)));
+            assert_eq!(update["self_deaf"], true);
+        });
+
+        let (connection, _) = Connection::new(&url, identify()).await.unwrap();
+        connection
+            .update_voice_state(ServerId(5), Some(ChannelId(6)), false, true)
+            .await
+            .unwrap();
+
+        server.await.unwrap();
+    }
+
+    #[cfg(feature = "signal")]
+    #[tokio::test]
+    async fn shuts_down_on_signal() {
+        let gateway = TestGateway::bind().await.unwrap();
+        let url = gateway.url();
+
+        let server = tokio::spawn(async move {
+            let mut session = gateway.accept().await.unwrap();
+            session.hello(45000).await.unwrap();
+            session.expect_identify().await.unwrap();
+            session.ready("session").await.unwrap();
+            session
+                .dispatch("RESUMED", json!({ "_trace": [] }))
+                .await
+                .unwrap();
+            assert_eq!(session.expect_close().await.unwrap(), Some(1000));
+        });
+
+        let (mut connection, _) = Connection::new(&url, identify()).await.unwrap();
+        let (signal, received) = tokio::sync::watch::channel(false);
+        connection.shutdown_signal = Some(ShutdownSignal::new(received));
+
+        let event = connection.recv_event().await.unwrap();
+        assert!(matches!(event, Event::Resumed { .. }));
+        signal.send(true).unwrap();
+        assert!(matches!(connection.recv_event().await, Ok(Event::Shutdown)));
+        // nothing more is received, but the connection can still be shut down cleanly
+        assert!(connection.recv_event().await.is_err());
+        connection.shutdown().await.unwrap();
+
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn invalid_session_close_identifies_again() {
+        let gateway = TestGateway::bind().await.unwrap();
+        let url = gateway.url();
+
+        let server = tokio::spawn(async move {
+            let mut session = gateway.accept().await.unwrap();
+            session.hello(50).await.unwrap();
+            session.expect_identify().await.unwrap();
+            session.ready("first").await.unwrap();
+            session
+                .close(4006, "Session no longer valid")
+                .await
+                .unwrap();
+
+            let mut session = gateway.accept().await.unwrap();
+            session.hello(50).await.unwrap();
+            session.expect_identify().await.unwrap();
+            session.ready("second").await.unwrap();
+        });
+
+        let (mut connection, _) = Connection::new(&url, identify()).await.unwrap();
+        assert_eq!(connection.session_id(), Some("first"));
+
+        match connection.recv_event().await.unwrap() {
+            Event::Ready(ready) => assert_eq!(ready.session_id, "second"),
+            other => panic!("unexpected event: {:?}", other),
+        }
+        assert_eq!(connection.session_id(), Some("second"));
+
+        server.await.unwrap();
+    }
+
+    #[test]
+    fn inflates_zlib_stream() {
+        let mut compress = Compress::new(Compression::default(), true);
+        let mut deflate = |value: &serde_json::Value| {
+            let mut output = Vec::with_capacity(1024);
+            let input = serde_json::to_vec(value).unwrap();
+            compress
+                .compress_vec(&input, &mut output, FlushCompress::Sync)
+                .unwrap();
+            output
+        };
+        let first = deflate(&json!({"op": 10, "d": {"heartbeat_interval": 41250}}));
+        let second = deflate(&json!({"op": 11}));
+
+        let mut zlib = ZlibStream::new();
+        let (start, end) = first.split_at(first.len() / 2);
+        assert_eq!(zlib.push(start).unwrap(), None);
+        assert_eq!(zlib.push(end).unwrap().unwrap()["op"], 10);
+        // the second message refers back to the context of the first
+        assert_eq!(zlib.push(&second).unwrap(), Some(json!({"op": 11})));
+    }
+
+    #[tokio::test]
+    async fn pump_broadcasts_to_subscribers() {
+        let gateway = TestGateway::bind().await.unwrap();
+        let url = gateway.url();
+
+        let server = tokio::spawn(async move {
+            let mut session = gateway.accept().await.unwrap();
+            session.hello(45000).await.unwrap();
+            session.expect_identify().await.unwrap();
+            session.ready("session").await.unwrap();
+
+            let data = json!({ "channel_id": "10", "message_id": "20" });
+            session.dispatch("MESSAGE_DELETE", data).await.unwrap();
+            session
+        });
+
+        let (connection, _) = Connection::new(&url, identify()).await.unwrap();
+        let mut first = connection.subscribe();
+        let mut second = first.resubscribe();
+        let pump = connection.spawn_pump();
+
+        for subscriber in [&mut first, &mut second] {
+            match &*subscriber.recv().await.unwrap() {
+                Event::MessageDelete { channel_id, .. } => assert_eq!(*channel_id, ChannelId(10)),
+                other => panic!("unexpected event: {:?}", other),
+            }
+        }
+
+        let _session = server.await.unwrap();
+        pump.abort();
+    }
+
+    #[tokio::test]
+    async fn collects_member_chunks() {
+        let gateway = TestGateway::bind().await.unwrap();
+        let url = gateway.url();
+
+        let server = tokio::spawn(async move {
+            let mut session = gateway.accept().await.unwrap();
+            session.hello(45000).await.unwrap();
+            session.expect_identify().await.unwrap();
+            session.ready("session").await.unwrap();
+
+            let request = session.expect_op(8).await.unwrap();
+            assert_eq!(request["query"], "ab");
+            let chunk = |index: u32, nonce: &serde_json::Value, user: u64| {
+                json!({
+                    "guild_id": "5",
+                    "members": [crate::fixtures::member(user, &[])],
+                    "chunk_index": index,
+                    "chunk_count": 2,
+                    "nonce": nonce,
+                })
+            };
+            let other = json!("another request");
+            for data in [
+                chunk(0, &request["nonce"], 1),
+                chunk(0, &other, 3),
+                chunk(1, &request["nonce"], 2),
+            ] {
+                session.dispatch("GUILD_MEMBERS_CHUNK", data).await.unwrap();
+            }
+            session
+        });
+
+        let (connection, _) = Connection::new(&url, identify()).await.unwrap();
+        let request = connection
+            .fetch_members(ServerId(5), "ab", 0)
+            .await
+            .unwrap();
+        let pump = connection.spawn_pump();
+
+        let members = request.collect().await.unwrap();
+        let ids: Vec<_> = members
+            .iter()
+            .map(|m| m.user.as_ref().unwrap().id)
+            .collect();
+        assert_eq!(ids, [UserId(1), UserId(2)]);
+
+        let _session = server.await.unwrap();
+        pump.abort();
+    }
+
+    #[tokio::test]
+    async fn gives_up_on_missing_chunks() {
+        let gateway = TestGateway::bind().await.unwrap();
+        let url = gateway.url();
+
+        let server = tokio::spawn(async move {
+            let mut session = gateway.accept().await.unwrap();
+            session.hello(45000).await.unwrap();
+            session.expect_identify().await.unwrap();
+            session.ready("session").await.unwrap();
+            session.expect_op(8).await.unwrap();
+            // never answer the request
+            session
+        });
+
+        let (connection, _) = Connection::new(&url, identify()).await.unwrap();
+        let request = connection
+            .fetch_members(ServerId(5), "", 0)
+            .await
+            .unwrap()
+            .timeout(Duration::from_millis(100));
+        let pump = connection.spawn_pump();
+
+        let _session = server.await.unwrap();
+        match request.collect().await {
+            Err(crate::Error::Other(reason)) => assert!(reason.starts_with("Timed out")),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        pump.abort();
+    }
+
+    /// Connect a bare websocket to the gateway, without identifying.
+    async fn connect_bare(gateway: &TestGateway) -> (WebSocketWriteHalf, TestSession) {
+        let url = gateway.url();
+        let (socket, session) = tokio::join!(WebSocket::connect(&url), gateway.accept());
+        (socket.unwrap().split().1, session.unwrap())
+    }
+
+    fn heartbeat(sequence: u64) -> SentMessage {
+        SentMessage::Heartbeat {
+            op: Eq,
+            last_sequence: Some(sequence),
+        }
+    }
+
+    #[tokio::test]
+    async fn holds_messages_until_flushed() {
+        let gateway = TestGateway::bind().await.unwrap();
+        let (socket, mut session) = connect_bare(&gateway).await;
+        let sink = SharedSink::new(socket);
+
+        sink.hold().await;
+        sink.send(&heartbeat(1)).await.unwrap();
+        sink.send(&heartbeat(2)).await.unwrap();
+        sink.send_now(&heartbeat(0)).await.unwrap();
+        sink.flush().await;
+        sink.send(&heartbeat(3)).await.unwrap();
+
+        for sequence in 0..=3 {
+            assert_eq!(session.recv().await.unwrap()["d"], sequence);
+        }
+    }
+
+    #[tokio::test]
+    async fn keeps_held_messages_when_flushing_fails() {
+        let gateway = TestGateway::bind().await.unwrap();
+        let (socket, session) = connect_bare(&gateway).await;
+        let sink = SharedSink::new(socket);
+
+        sink.hold().await;
+        sink.send(&heartbeat(1)).await.unwrap();
+        sink.send(&heartbeat(2)).await.unwrap();
+
+        // writes only start failing once the closed socket has been noticed
+        drop(session);
+        while sink.send_now(&heartbeat(0)).await.is_ok() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        sink.flush().await;
+        {
+            let state = sink.0.lock().await;
+            assert!(state.holding);
+            assert_eq!(state.buffer.len(), 2);
+        }
+
+        // so the next session receives them once flushed again
+        let (socket, mut session) = connect_bare(&gateway).await;
+        sink.replace(socket).await;
+        sink.flush().await;
+        for sequence in 1..=2 {
+            assert_eq!(session.recv().await.unwrap()["d"], sequence);
+        }
+    }
+}
@@ -0,0 +1,173 @@
+//! Cooldowns for throttling how often commands can be used.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::model::{ChannelId, ServerId, UserId};
+
+/// Who shares a cooldown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CooldownScope {
+    /// Each user has their own cooldown.
+    User,
+    /// Everyone in a channel shares a cooldown.
+    Channel,
+    /// Everyone in a server shares a cooldown.
+    ///
+    /// Outside of servers, this falls back to the channel.
+    Server,
+}
+
+/// A command was used while it was cooling down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CooldownHit {
+    /// How long until the command can be used again.
+    pub remaining: Duration,
+}
+
+/// A cooldown on a single command, tracked separately for each user, channel or server.
+#[derive(Debug, Clone)]
+pub struct Cooldown {
+    scope: CooldownScope,
+    duration: Duration,
+    /// When each bucket was last used, keyed by the ID of the user, channel or server.
+    buckets: HashMap<u64, Instant>,
+}
+
+impl Cooldown {
+    /// Create a cooldown which allows a command to be used once per `duration` in each bucket.
+    pub fn new(scope: CooldownScope, duration: Duration) -> Cooldown {
+        Cooldown {
+            scope,
+            duration,
+            buckets: HashMap::new(),
+        }
+    }
+
+    /// Record a use of the command, unless it is still cooling down for the given invocation.
+    pub fn check(
+        &mut self,
+        user: UserId,
+        channel: ChannelId,
+        server: Option<ServerId>,
+    ) -> Result<(), CooldownHit> {
+        self.check_at(self.key(user, channel, server), Instant::now())
+    }
+
+    /// Forget a past use of the command, so that it can be used again right away.
+    pub fn reset(&mut self, user: UserId, channel: ChannelId, server: Option<ServerId>) {
+        let key = self.key(user, channel, server);
+        self.buckets.remove(&key);
+    }
+
+    /// Forget the buckets which are no longer cooling down, to save memory.
+    pub fn prune(&mut self) {
+        let now = Instant::now();
+        let duration = self.duration;
+        self.buckets.retain(|_, &mut used| now < used + duration);
+    }
+
+    fn key(&self, user: UserId, channel: ChannelId, server: Option<ServerId>) -> u64 {
+        match (self.scope, server) {
+            (CooldownScope::User, _) => user.0,
+            (CooldownScope::Channel, _) | (CooldownScope::Server, None) => channel.0,
+            (CooldownScope::Server, Some(server)) => server.0,
+        }
+    }
+
+    fn check_at(&mut self, key: u64, now: Instant) -> Result<(), CooldownHit> {
+        if let Some(&used) = self.buckets.get(&key) {
+            let ready = used + self.duration;
+            if now < ready {
+                return Err(CooldownHit {
+                    remaining: ready - now,
+                });
+            }
+        }
+        self.buckets.insert(key, now);
+        Ok(())
+    }
+}
+
+/// Cooldowns for a set of commands, looked up by command name.
+///
+/// Commands without a registered cooldown can always be used.
+///
+/// ```ignore
+/// let mut cooldowns = Cooldowns::new();
+/// cooldowns.register("roll", Cooldown::new(CooldownScope::User, Duration::from_secs(5)));
+///
+/// if let Err(hit) = cooldowns.check("roll", message.author.id, message.channel_id, message.server_id) {
+///     // tell the user to wait for `hit.remaining`
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Cooldowns {
+    commands: HashMap<String, Cooldown>,
+}
+
+impl Cooldowns {
+    /// Create an empty set of cooldowns.
+    pub fn new() -> Cooldowns {
+        Cooldowns::default()
+    }
+
+    /// Set the cooldown of a command, replacing any previous one.
+    pub fn register(&mut self, command: &str, cooldown: Cooldown) {
+        self.commands.insert(command.to_owned(), cooldown);
+    }
+
+    /// Record a use of a command, unless it is still cooling down for the given invocation.
+    pub fn check(
+        &mut self,
+        command: &str,
+        user: UserId,
+        channel: ChannelId,
+        server: Option<ServerId>,
+    ) -> Result<(), CooldownHit> {
+        match self.commands.get_mut(command) {
+            Some(cooldown) => cooldown.check(user, channel, server),
+            None => Ok(()),
+        }
+    }
+
+    /// Forget the buckets of every command which are no longer cooling down.
+    pub fn prune(&mut self) {
+        for cooldown in self.commands.values_mut() {
+            cooldown.prune();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::time::Instant;
+
+    use super::{Cooldown, CooldownHit, CooldownScope};
+    use crate::model::{ChannelId, ServerId, UserId};
+
+    #[test]
+    fn buckets_by_scope() {
+        let mut cooldown = Cooldown::new(CooldownScope::Server, Duration::from_secs(10));
+        let start = Instant::now();
+        let server = cooldown.key(UserId(1), ChannelId(2), Some(ServerId(3)));
+        let dm = cooldown.key(UserId(1), ChannelId(4), None);
+
+        assert_eq!(cooldown.check_at(server, start), Ok(()));
+        assert_eq!(cooldown.check_at(dm, start), Ok(()));
+        assert_eq!(
+            cooldown.check_at(server, start + Duration::from_secs(4)),
+            Err(CooldownHit {
+                remaining: Duration::from_secs(6)
+            })
+        );
+        assert_eq!(
+            cooldown.check_at(server, start + Duration::from_secs(10)),
+            Ok(())
+        );
+    }
+}
@@ -10,6 +10,8 @@ use std::io::Error as IoError;
 use tracing::debug;
 use websockets::WebSocketError;
 
+use crate::GatewayCloseCode;
+
 /// Discord API `Result` alias type.
 pub type Result<T> = ::std::result::Result<T, Error>;
 
@@ -33,8 +35,10 @@ pub enum Error {
     Closed(Option<u16>, String),
     /// A json decoding error, with a description and the offending value
     Decode(&'static str, Value),
-    /// A generic non-success response from the REST API
-    Status(reqwest::StatusCode, Option<Value>),
+    /// A generic non-success response from the REST API, with its body and metadata
+    Status(reqwest::StatusCode, Option<Value>, ResponseMeta),
+    /// A non-success response from the REST API, with Discord's description of the error
+    Api(Box<ApiError>),
     /// A rate limit error, with how many milliseconds to wait before retrying
     RateLimited(u64),
     /// A Discord protocol error, with a description
@@ -43,10 +47,287 @@ pub enum Error {
     Other(&'static str),
 }
 
+/// Details of a REST API response, worth including when reporting a problem to Discord.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResponseMeta {
+    /// The status of the response.
+    pub status: reqwest::StatusCode,
+    /// The rate limit bucket of the route, from the `X-RateLimit-Bucket` header.
+    pub bucket: Option<String>,
+    /// The ID of the request, from the `X-Request-Id` header, if Discord sent one.
+    pub request_id: Option<String>,
+    /// The Cloudflare ID of the request, from the `CF-Ray` header.
+    pub ray_id: Option<String>,
+}
+
+/// An error described by Discord in the body of a non-success response.
+///
+/// https://discord.com/developers/docs/topics/opcodes-and-status-codes#json
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApiError {
+    /// What went wrong, to match on.
+    pub code: DiscordErrorCode,
+    /// A description of the error, meant for developers.
+    pub message: String,
+    /// The problems with each invalid field of the request, if any.
+    /// See `field_errors` for a flattened list.
+    pub errors: Option<Value>,
+    /// The metadata of the response.
+    pub meta: ResponseMeta,
+}
+
+impl ApiError {
+    /// Read an error from the body of a response, if it has Discord's error format.
+    fn from_body(body: &Value, meta: ResponseMeta) -> Option<ApiError> {
+        let code = body.get("code")?.as_u64()?;
+        let message = body.get("message")?.as_str()?;
+        Some(ApiError {
+            code: DiscordErrorCode::from(u32::try_from(code).ok()?),
+            message: message.to_owned(),
+            errors: body.get("errors").cloned(),
+            meta,
+        })
+    }
+
+    /// List the problems with each invalid field of the request, with the path to the field,
+    /// like `embeds.0.title`, and a description of the problem.
+    pub fn field_errors(&self) -> Vec<(String, String)> {
+        let mut found = Vec::new();
+        if let Some(errors) = &self.errors {
+            collect_field_errors(errors, String::new(), &mut found);
+        }
+        found
+    }
+}
+
+/// Walk the nested `errors` of an API error, collecting the `_errors` listed at each field.
+fn collect_field_errors(value: &Value, path: String, found: &mut Vec<(String, String)>) {
+    let Value::Object(map) = value else { return };
+    for (key, value) in map {
+        if key == "_errors" {
+            let errors = value.as_array().into_iter().flatten();
+            for message in errors.filter_map(|error| error.get("message")?.as_str()) {
+                found.push((path.clone(), message.to_owned()));
+            }
+        } else if path.is_empty() {
+            collect_field_errors(value, key.clone(), found);
+        } else {
+            collect_field_errors(value, format!("{path}.{key}"), found);
+        }
+    }
+}
+
+/// A JSON error code of the Discord API, telling apart errors with the same HTTP status.
+///
+/// Only the most common codes are named.
+/// https://discord.com/developers/docs/topics/opcodes-and-status-codes#json-json-error-codes
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum DiscordErrorCode {
+    /// An error without a more specific code. (0)
+    General,
+    /// Unknown account. (10001)
+    UnknownAccount,
+    /// Unknown application. (10002)
+    UnknownApplication,
+    /// Unknown channel. (10003)
+    UnknownChannel,
+    /// Unknown server. (10004)
+    UnknownServer,
+    /// Unknown invite. (10006)
+    UnknownInvite,
+    /// Unknown member. (10007)
+    UnknownMember,
+    /// Unknown message. (10008)
+    UnknownMessage,
+    /// Unknown permission overwrite. (10009)
+    UnknownOverwrite,
+    /// Unknown role. (10011)
+    UnknownRole,
+    /// Unknown user. (10013)
+    UnknownUser,
+    /// Unknown emoji. (10014)
+    UnknownEmoji,
+    /// Unknown webhook. (10015)
+    UnknownWebhook,
+    /// Unknown ban. (10026)
+    UnknownBan,
+    /// Unknown interaction. (10062)
+    UnknownInteraction,
+    /// Bots cannot use this endpoint. (20001)
+    BotsCannotUseEndpoint,
+    /// Only bots can use this endpoint. (20002)
+    OnlyBotsCanUseEndpoint,
+    /// The maximum number of pins in the channel has been reached. (30003)
+    MaximumPins,
+    /// The maximum number of reactions on the message has been reached. (30010)
+    MaximumReactions,
+    /// Unauthorized, as with an invalid token. (40001)
+    Unauthorized,
+    /// The interaction has already been acknowledged. (40060)
+    InteractionAlreadyAcknowledged,
+    /// Missing access to the resource, like a channel the bot can't see. (50001)
+    MissingAccess,
+    /// The server's widget is disabled. (50004)
+    WidgetDisabled,
+    /// Cannot send an empty message. (50006)
+    CannotSendEmptyMessage,
+    /// Cannot send messages to this user, who may not accept direct messages. (50007)
+    CannotSendMessagesToUser,
+    /// Missing permissions for the action. (50013)
+    MissingPermissions,
+    /// Invalid webhook token. (50027)
+    InvalidWebhookToken,
+    /// A message was too old to be bulk deleted. (50034)
+    MessageTooOldToBulkDelete,
+    /// The body of the request is invalid; see `ApiError::field_errors`. (50035)
+    InvalidFormBody,
+    /// Two-factor authentication is required for the action. (60003)
+    TwoFactorRequired,
+    /// The reaction was blocked, as the user has blocked the bot. (90001)
+    ReactionBlocked,
+    /// Any other code.
+    Other(u32),
+}
+
+impl DiscordErrorCode {
+    /// The numeric error code.
+    pub fn code(self) -> u32 {
+        match self {
+            DiscordErrorCode::General => 0,
+            DiscordErrorCode::UnknownAccount => 10001,
+            DiscordErrorCode::UnknownApplication => 10002,
+            DiscordErrorCode::UnknownChannel => 10003,
+            DiscordErrorCode::UnknownServer => 10004,
+            DiscordErrorCode::UnknownInvite => 10006,
+            DiscordErrorCode::UnknownMember => 10007,
+            DiscordErrorCode::UnknownMessage => 10008,
+            DiscordErrorCode::UnknownOverwrite => 10009,
+            DiscordErrorCode::UnknownRole => 10011,
+            DiscordErrorCode::UnknownUser => 10013,
+            DiscordErrorCode::UnknownEmoji => 10014,
+            DiscordErrorCode::UnknownWebhook => 10015,
+            DiscordErrorCode::UnknownBan => 10026,
+            DiscordErrorCode::UnknownInteraction => 10062,
+            DiscordErrorCode::BotsCannotUseEndpoint => 20001,
+            DiscordErrorCode::OnlyBotsCanUseEndpoint => 20002,
+            DiscordErrorCode::MaximumPins => 30003,
+            DiscordErrorCode::MaximumReactions => 30010,
+            DiscordErrorCode::Unauthorized => 40001,
+            DiscordErrorCode::InteractionAlreadyAcknowledged => 40060,
+            DiscordErrorCode::MissingAccess => 50001,
+            DiscordErrorCode::WidgetDisabled => 50004,
+            DiscordErrorCode::CannotSendEmptyMessage => 50006,
+            DiscordErrorCode::CannotSendMessagesToUser => 50007,
+            DiscordErrorCode::MissingPermissions => 50013,
+            DiscordErrorCode::InvalidWebhookToken => 50027,
+            DiscordErrorCode::MessageTooOldToBulkDelete => 50034,
+            DiscordErrorCode::InvalidFormBody => 50035,
+            DiscordErrorCode::TwoFactorRequired => 60003,
+            DiscordErrorCode::ReactionBlocked => 90001,
+            DiscordErrorCode::Other(code) => code,
+        }
+    }
+
+    /// Whether the error is about a resource which doesn't exist, like `UnknownMessage`.
+    pub fn is_unknown_resource(self) -> bool {
+        (10001..20000).contains(&self.code())
+    }
+}
+
+impl From<u32> for DiscordErrorCode {
+    fn from(code: u32) -> DiscordErrorCode {
+        match code {
+            0 => DiscordErrorCode::General,
+            10001 => DiscordErrorCode::UnknownAccount,
+            10002 => DiscordErrorCode::UnknownApplication,
+            10003 => DiscordErrorCode::UnknownChannel,
+            10004 => DiscordErrorCode::UnknownServer,
+            10006 => DiscordErrorCode::UnknownInvite,
+            10007 => DiscordErrorCode::UnknownMember,
+            10008 => DiscordErrorCode::UnknownMessage,
+            10009 => DiscordErrorCode::UnknownOverwrite,
+            10011 => DiscordErrorCode::UnknownRole,
+            10013 => DiscordErrorCode::UnknownUser,
+            10014 => DiscordErrorCode::UnknownEmoji,
+            10015 => DiscordErrorCode::UnknownWebhook,
+            10026 => DiscordErrorCode::UnknownBan,
+            10062 => DiscordErrorCode::UnknownInteraction,
+            20001 => DiscordErrorCode::BotsCannotUseEndpoint,
+            20002 => DiscordErrorCode::OnlyBotsCanUseEndpoint,
+            30003 => DiscordErrorCode::MaximumPins,
+            30010 => DiscordErrorCode::MaximumReactions,
+            40001 => DiscordErrorCode::Unauthorized,
+            40060 => DiscordErrorCode::InteractionAlreadyAcknowledged,
+            50001 => DiscordErrorCode::MissingAccess,
+            50004 => DiscordErrorCode::WidgetDisabled,
+            50006 => DiscordErrorCode::CannotSendEmptyMessage,
+            50007 => DiscordErrorCode::CannotSendMessagesToUser,
+            50013 => DiscordErrorCode::MissingPermissions,
+            50027 => DiscordErrorCode::InvalidWebhookToken,
+            50034 => DiscordErrorCode::MessageTooOldToBulkDelete,
+            50035 => DiscordErrorCode::InvalidFormBody,
+            60003 => DiscordErrorCode::TwoFactorRequired,
+            90001 => DiscordErrorCode::ReactionBlocked,
+            other => DiscordErrorCode::Other(other),
+        }
+    }
+}
+
+impl ResponseMeta {
+    /// Collect the metadata of a response.
+    pub(crate) fn from_response(response: &reqwest::Response) -> ResponseMeta {
+        let header = |name: &str| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_owned)
+        };
+        ResponseMeta {
+            status: response.status(),
+            bucket: header("x-ratelimit-bucket"),
+            request_id: header("x-request-id"),
+            ray_id: header("cf-ray"),
+        }
+    }
+}
+
 impl Error {
+    /// The close code of the gateway connection, if it was closed with one.
+    pub fn close_code(&self) -> Option<GatewayCloseCode> {
+        match self {
+            Error::Closed(code, _) => code.map(GatewayCloseCode::from),
+            _ => None,
+        }
+    }
+
+    /// The HTTP status of the response which caused this error, if it was a non-success
+    /// response.
+    pub fn status(&self) -> Option<reqwest::StatusCode> {
+        self.response_meta().map(|meta| meta.status)
+    }
+
+    /// Discord's error code for the response which caused this error, if it gave one.
+    pub fn api_code(&self) -> Option<DiscordErrorCode> {
+        match self {
+            Error::Api(err) => Some(err.code),
+            _ => None,
+        }
+    }
+
+    /// The metadata of the response which caused this error, if it was a non-success response.
+    pub fn response_meta(&self) -> Option<&ResponseMeta> {
+        match self {
+            Error::Status(_, _, meta) => Some(meta),
+            Error::Api(err) => Some(&err.meta),
+            _ => None,
+        }
+    }
+
     #[doc(hidden)]
     pub async fn from_response(response: reqwest::Response) -> Error {
         let status = response.status();
+        let meta = ResponseMeta::from_response(&response);
 
         let value = response
             .bytes()
@@ -62,7 +343,13 @@ impl Error {
                 }
             }
         }
-        Error::Status(status, value)
+        if let Some(err) = value
+            .as_ref()
+            .and_then(|value| ApiError::from_body(value, meta.clone()))
+        {
+            return Error::Api(Box::new(err));
+        }
+        Error::Status(status, value, meta)
     }
 }
 
@@ -114,6 +401,7 @@ impl Display for Error {
             Error::Io(ref inner) => inner.fmt(f),
             #[cfg(feature = "voice")]
             Error::Opus(ref inner) => inner.fmt(f),
+            Error::Api(ref err) => write!(f, "{} (error code {})", err.message, err.code.code()),
             _ => f.write_str(self.description()),
         }
     }
@@ -132,10 +420,11 @@ impl StdError for Error {
             Error::Opus(ref inner) => inner.description(),
             Error::Closed(_, _) => "Connection closed",
             Error::Decode(msg, _) | Error::Protocol(msg) | Error::Other(msg) => msg,
-            Error::Status(status, _) => status
+            Error::Status(status, _, _) => status
                 .canonical_reason()
                 .unwrap_or("Unknown bad HTTP status"),
             Error::RateLimited(_) => "Rate limited",
+            Error::Api(ref err) => &err.message,
         }
     }
 
@@ -191,3 +480,51 @@ impl StatusChecks for reqwest::Response {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use reqwest::StatusCode;
+    use serde_json::json;
+
+    use super::{ApiError, DiscordErrorCode, ResponseMeta};
+
+    #[test]
+    fn reads_api_errors() {
+        let meta = ResponseMeta {
+            status: StatusCode::BAD_REQUEST,
+            bucket: None,
+            request_id: None,
+            ray_id: None,
+        };
+        let body = json!({
+            "code": 50035,
+            "message": "Invalid Form Body",
+            "errors": {
+                "embeds": { "0": { "title": { "_errors": [
+                    { "code": "BASE_TYPE_MAX_LENGTH", "message": "Too long" }
+                ] } } },
+                "content": { "_errors": [{ "code": "X", "message": "Bad" }] }
+            }
+        });
+
+        let err = ApiError::from_body(&body, meta.clone()).unwrap();
+        assert_eq!(err.code, DiscordErrorCode::InvalidFormBody);
+        let mut fields = err.field_errors();
+        fields.sort();
+        assert_eq!(
+            fields,
+            [
+                ("content".to_owned(), "Bad".to_owned()),
+                ("embeds.0.title".to_owned(), "Too long".to_owned()),
+            ]
+        );
+
+        let err = ApiError::from_body(&json!({ "code": 10008, "message": "x" }), meta.clone());
+        assert_eq!(err.unwrap().code, DiscordErrorCode::UnknownMessage);
+        assert_eq!(
+            DiscordErrorCode::from(12345),
+            DiscordErrorCode::Other(12345)
+        );
+        assert!(ApiError::from_body(&json!({ "message": "x" }), meta).is_none());
+    }
+}
@@ -0,0 +1,133 @@
+//! Complete programs showing how the parts of the library fit together.
+//!
+//! Each example here is compiled by `cargo test --doc`, so they are kept up to date with the
+//! API. They read the bot token from the `DISCORD_TOKEN` environment variable.
+//!
+//! # Ping pong
+//!
+//! The smallest useful bot: reply to `!ping` in any channel the bot can see.
+//!
+//! ```no_run
+//! use discord_tokio::model::Event;
+//! use discord_tokio::{ConnectExt, Discord, LoginExt, MessageExt};
+//!
+//! #[tokio::main(flavor = "current_thread")]
+//! async fn main() -> discord_tokio::Result<()> {
+//!     let token = std::env::var("DISCORD_TOKEN").expect("DISCORD_TOKEN is not set");
+//!     let discord = Discord::from_bot_token(&token)?;
+//!     let (mut connection, ready) = discord.connect().await?;
+//!     println!("Logged in as {}", ready.user.username);
+//!
+//!     loop {
+//!         match connection.recv_event().await? {
+//!             Event::MessageCreate(message) if message.content == "!ping" => {
+//!                 discord
+//!                     .send_message(message.channel_id, |m| m.content("Pong!"))
+//!                     .await?;
+//!             }
+//!             _ => {}
+//!         }
+//!     }
+//! }
+//! ```
+//!
+//! # State mirror
+//!
+//! Keep a [`State`](crate::State) up to date with every event, and answer questions from it
+//! instead of making REST requests.
+//!
+//! ```no_run
+//! use discord_tokio::model::Event;
+//! use discord_tokio::{ConnectExt, Discord, LoginExt, MessageExt, State};
+//!
+//! #[tokio::main(flavor = "current_thread")]
+//! async fn main() -> discord_tokio::Result<()> {
+//!     let token = std::env::var("DISCORD_TOKEN").expect("DISCORD_TOKEN is not set");
+//!     let discord = Discord::from_bot_token(&token)?;
+//!     let (mut connection, ready) = discord.connect().await?;
+//!     let mut state = State::new(ready);
+//!
+//!     loop {
+//!         let event = connection.recv_event().await?;
+//!         state.update(&event);
+//!
+//!         if let Event::MessageCreate(message) = event {
+//!             if message.content == "!servers" {
+//!                 let names: Vec<_> = state.servers().iter().map(|s| s.name.as_str()).collect();
+//!                 let content = format!("I'm in {}", names.join(", "));
+//!                 discord
+//!                     .send_message(message.channel_id, |m| m.content(&content))
+//!                     .await?;
+//!             }
+//!         }
+//!     }
+//! }
+//! ```
+//!
+//! # Voice player
+//!
+//! Join the voice channel of whoever says `!play <file>`, and queue the file to be played.
+//! Requires the `voice` feature, and `ffmpeg` to be installed.
+//!
+#![cfg_attr(feature = "voice", doc = "```no_run")]
+#![cfg_attr(not(feature = "voice"), doc = "```ignore")]
+//! use discord_tokio::model::Event;
+//! use discord_tokio::{voice, ConnectExt, Discord, LoginExt, State};
+//!
+//! #[tokio::main(flavor = "current_thread")]
+//! async fn main() -> discord_tokio::Result<()> {
+//!     let token = std::env::var("DISCORD_TOKEN").expect("DISCORD_TOKEN is not set");
+//!     let discord = Discord::from_bot_token(&token)?;
+//!     let (mut connection, ready) = discord.connect().await?;
+//!     let mut state = State::new(ready);
+//!
+//!     loop {
+//!         let event = connection.recv_event().await?;
+//!         state.update(&event);
+//!
+//!         let Event::MessageCreate(message) = event else {
+//!             continue;
+//!         };
+//!         let Some(path) = message.content.strip_prefix("!play ") else {
+//!             continue;
+//!         };
+//!         if let Some((Some(server_id), channel_id)) = state.find_voice_user(message.author.id) {
+//!             let voice = connection.voice(server_id);
+//!             voice.connect(channel_id).await?;
+//!             voice.queue().enqueue(voice::open_ffmpeg_stream(path)?);
+//!         }
+//!     }
+//! }
+//! ```
+//!
+//! # Shard manager
+//!
+//! Run every shard of a large bot, receiving their events as one stream.
+//!
+//! ```no_run
+//! use discord_tokio::model::Event;
+//! use discord_tokio::{Discord, LoginExt, MessageExt, ShardManager};
+//! use futures::StreamExt;
+//!
+//! #[tokio::main(flavor = "current_thread")]
+//! async fn main() -> discord_tokio::Result<()> {
+//!     let token = std::env::var("DISCORD_TOKEN").expect("DISCORD_TOKEN is not set");
+//!     let discord = Discord::from_bot_token(&token)?;
+//!     let mut shards = ShardManager::new(discord.clone()).await?;
+//!     println!("Running {} shards", shards.shard_count());
+//!
+//!     while let Some((shard, event)) = shards.next().await {
+//!         match event? {
+//!             Event::Ready(_) => println!("Shard {} is ready", shard),
+//!             Event::MessageCreate(message) if message.content == "!shard" => {
+//!                 let content = format!("This server is on shard {}", shard);
+//!                 discord
+//!                     .send_message(message.channel_id, |m| m.content(&content))
+//!                     .await?;
+//!             }
+//!             _ => {}
+//!         }
+//!     }
+//!     Ok(())
+//! }
+//! ```
@@ -0,0 +1,132 @@
+//! Recorded payloads shared by the unit tests.
+
+use serde_json::{json, Value};
+
+/// A server member as the gateway sends it, for the user with the given ID.
+///
+/// Tests adjust the fields they care about before decoding it.
+pub fn member(user: u64, roles: &[u64]) -> Value {
+    json!({
+        "user": { "id": user.to_string(), "username": "user", "discriminator": "0", "avatar": null },
+        "nick": null,
+        "avatar": null,
+        "roles": roles.iter().map(u64::to_string).collect::<Vec<_>>(),
+        "joined_at": "2024-01-01T00:00:00+00:00",
+        "mute": false,
+        "deaf": false,
+        "flags": 0,
+        "premium_since": null,
+    })
+}
+
+/// An available server as sent in `GUILD_CREATE`, with no channels and only the
+/// `@everyone` role, which lets members read and send messages.
+pub fn live_server(id: u64, members: Vec<Value>) -> Value {
+    let everyone =
+        crate::model::Permissions::READ_MESSAGES | crate::model::Permissions::SEND_MESSAGES;
+    let mut server = json!({
+        "id": id.to_string(),
+        "name": "server",
+        "icon": null,
+        "splash": null,
+        "discovery_splash": null,
+        "owner": false,
+        "owner_id": "1",
+        "permissions": everyone,
+        "region": "",
+        "afk_timeout": 300,
+        "afk_channel_id": null,
+        "widget_enabled": false,
+        "widget_channel_id": null,
+        "verification_level": 0,
+        "default_message_notifications": 0,
+        "explicit_content_filter": 0,
+        "roles": [{
+            "id": id.to_string(),
+            "name": "@everyone",
+            "color": 0,
+            "icon": null,
+            "unicode_emoji": null,
+            "hoist": false,
+            "managed": false,
+            "position": 0,
+            "permissions": everyone,
+            "flags": 0,
+        }],
+        "emojis": [],
+        "features": [],
+        "mfa_level": 0,
+        "system_channel_id": null,
+        "system_channel_flags": 0,
+        "rules_channel_id": null,
+        "max_presences": null,
+        "max_members": null,
+        "vanity_url_code": null,
+        "description": null,
+        "banner": null,
+        "premium_tier": 0,
+        "premium_subscription_count": null,
+        "preferred_locale": "en-US",
+        "public_updates_channel_id": null,
+        "max_video_channel_users": 25,
+        "max_stage_video_channel_users": 50,
+        "approximate_member_count": members.len(),
+        "approximate_presence_count": 0,
+        "welcome_screen": { "description": null, "welcome_channels": [] },
+        "nsfw_level": 0,
+        "stickers": null,
+        "premium_progress_bar_enabled": false,
+        "safety_alerts_channel_id": null,
+    });
+    // the live extensions are added apart, to stay within the `json!` recursion limit
+    let live = json!({
+        "joined_at": "2024-01-01T00:00:00+00:00",
+        "large": false,
+        "member_count": members.len(),
+        "voice_states": [],
+        "channels": [],
+        "threads": [],
+        "stage_instances": [],
+        "guild_scheduled_events": [],
+        "members": members,
+        "presences": [],
+    });
+    if let (Value::Object(server), Value::Object(live)) = (&mut server, live) {
+        server.extend(live);
+    }
+    server
+}
+
+/// A text channel in the given server, with no permission overwrites.
+pub fn text_channel(id: u64, server: u64) -> Value {
+    json!({
+        "type": 0,
+        "id": id.to_string(),
+        "guild_id": server.to_string(),
+        "name": "channel",
+        "position": 0,
+        "parent_id": null,
+        "permission_overwrites": [],
+    })
+}
+
+/// A message in the given channel as fetched over REST, without a server ID.
+pub fn message(id: u64, channel: u64) -> Value {
+    json!({
+        "id": id.to_string(),
+        "channel_id": channel.to_string(),
+        "content": format!("message {}", id),
+        "attachments": [],
+        "embeds": [],
+        "author": { "id": "2", "username": "member", "discriminator": "0", "avatar": null },
+        "timestamp": "2024-01-01T00:00:00+00:00",
+        "edited_timestamp": null,
+        "tts": false,
+        "mention_everyone": false,
+        "mentions": [],
+        "mention_roles": [],
+        "pinned": false,
+        "type": 0,
+        "flags": 0,
+    })
+}
@@ -0,0 +1,230 @@
+//! Keeping threads from being archived automatically.
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Duration, Utc};
+use tracing::warn;
+
+use crate::model::{ChannelId, Event, Thread};
+use crate::{ChannelExt, Discord, Result};
+
+/// How long before a thread would be archived it is bumped, by default.
+const DEFAULT_MARGIN: Duration = Duration::minutes(10);
+
+/// A thread being kept alive.
+#[derive(Debug, Clone, Copy)]
+struct Watched {
+    /// The auto-archive duration of the thread, in minutes.
+    auto_archive_duration: u16,
+    /// When the thread last saw activity which resets its archive timer.
+    last_activity: DateTime<Utc>,
+    archived: bool,
+}
+
+impl Watched {
+    fn archives_at(&self) -> DateTime<Utc> {
+        self.last_activity + Duration::minutes(self.auto_archive_duration.into())
+    }
+}
+
+/// Keeps a set of threads from being archived for inactivity.
+///
+/// Threads are archived by Discord once nothing happens in them for their auto-archive
+/// duration, which is a problem for long-lived threads like support tickets. The keep-alive
+/// follows activity in the watched threads through `MessageCreate` and `ThreadUpdate` events,
+/// and [`keep_alive`](ThreadKeepAlive::keep_alive) bumps the ones about to be archived,
+/// and unarchives the ones which were archived anyway.
+///
+/// ```ignore
+/// let mut keep_alive = ThreadKeepAlive::new();
+/// keep_alive.watch(&ticket);
+/// loop {
+///     let event = connection.recv_event().await?;
+///     keep_alive.update(&event);
+///     keep_alive.keep_alive(&discord).await;
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct ThreadKeepAlive {
+    margin: Duration,
+    threads: BTreeMap<ChannelId, Watched>,
+}
+
+impl ThreadKeepAlive {
+    /// Create a keep-alive which bumps threads 10 minutes before they would be archived.
+    pub fn new() -> ThreadKeepAlive {
+        ThreadKeepAlive::with_margin(DEFAULT_MARGIN)
+    }
+
+    /// Create a keep-alive which bumps threads the given duration before they would be archived.
+    pub fn with_margin(margin: Duration) -> ThreadKeepAlive {
+        ThreadKeepAlive {
+            margin,
+            threads: BTreeMap::new(),
+        }
+    }
+
+    /// Start keeping a thread alive.
+    pub fn watch(&mut self, thread: &Thread) {
+        self.threads.insert(thread.id, Watched::from(thread));
+    }
+
+    /// Stop keeping a thread alive, leaving it to be archived normally.
+    pub fn unwatch(&mut self, thread: ChannelId) {
+        self.threads.remove(&thread);
+    }
+
+    /// Check whether a thread is being kept alive.
+    pub fn is_watched(&self, thread: ChannelId) -> bool {
+        self.threads.contains_key(&thread)
+    }
+
+    /// Update the activity of the watched threads according to the given event.
+    pub fn update(&mut self, event: &Event) {
+        match *event {
+            Event::ThreadUpdate(ref thread) => {
+                let thread = thread.thread();
+                if let Some(watched) = self.threads.get_mut(&thread.id) {
+                    *watched = Watched::from(thread);
+                }
+            }
+            Event::ThreadDelete { id, .. } => {
+                self.threads.remove(&id);
+            }
+            Event::MessageCreate(ref message) => {
+                if let Some(watched) = self.threads.get_mut(&message.channel_id) {
+                    let timestamp = message.timestamp.with_timezone(&Utc);
+                    watched.last_activity = watched.last_activity.max(timestamp);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// When the next watched thread is due to be bumped, if any threads are watched.
+    pub fn next_due(&self) -> Option<DateTime<Utc>> {
+        self.threads
+            .values()
+            .map(|watched| watched.archives_at() - self.margin)
+            .min()
+    }
+
+    /// Bump the watched threads which are about to be archived, and unarchive
+    /// the ones which have been, returning the IDs of the threads that were edited.
+    ///
+    /// Discord restarts a thread's archive timer when its archive status or its auto-archive
+    /// duration changes, but not when the same duration is sent again. So threads are
+    /// bumped by switching to another duration and back, while archived threads are
+    /// simply unarchived. Locked threads can only be unarchived with `MANAGE_THREADS`.
+    ///
+    /// A thread which can't be edited is logged and skipped, and is tried again on the
+    /// next call, without keeping the other due threads from being bumped.
+    pub async fn keep_alive(&mut self, discord: &Discord) -> Vec<ChannelId> {
+        let mut bumped = Vec::new();
+        for id in self.due_at(Utc::now()) {
+            let watched = self.threads[&id];
+            if let Err(err) = bump(discord, id, &watched).await {
+                warn!("Couldn't keep thread {} alive: {:?}", id, err);
+                continue;
+            }
+
+            if let Some(watched) = self.threads.get_mut(&id) {
+                watched.last_activity = Utc::now();
+                watched.archived = false;
+            }
+            bumped.push(id);
+        }
+        bumped
+    }
+
+    fn due_at(&self, now: DateTime<Utc>) -> Vec<ChannelId> {
+        self.threads
+            .iter()
+            .filter(|(_, watched)| watched.archived || now + self.margin >= watched.archives_at())
+            .map(|(&id, _)| id)
+            .collect()
+    }
+}
+
+/// Restart the archive timer of a thread, unarchiving it if it was archived.
+async fn bump(discord: &Discord, id: ChannelId, watched: &Watched) -> Result<()> {
+    if watched.archived {
+        discord.edit_channel(id, |ch| ch.archived(false)).await?;
+        return Ok(());
+    }
+
+    let duration = watched.auto_archive_duration;
+    discord
+        .edit_channel(id, |ch| ch.auto_archive_duration(other_duration(duration)))
+        .await?;
+    discord
+        .edit_channel(id, |ch| ch.auto_archive_duration(duration))
+        .await?;
+    Ok(())
+}
+
+/// Another valid auto-archive duration to switch a thread to for a moment.
+fn other_duration(duration: u16) -> u16 {
+    if duration == 60 {
+        1440
+    } else {
+        60
+    }
+}
+
+impl Default for ThreadKeepAlive {
+    fn default() -> Self {
+        ThreadKeepAlive::new()
+    }
+}
+
+impl From<&Thread> for Watched {
+    fn from(thread: &Thread) -> Watched {
+        Watched {
+            auto_archive_duration: thread.thread_info.auto_archive_duration,
+            last_activity: thread.thread_info.archive_timestamp.with_timezone(&Utc),
+            archived: thread.thread_info.archived,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+
+    use super::{other_duration, ThreadKeepAlive, Watched};
+    use crate::model::ChannelId;
+
+    #[test]
+    fn bumps_before_archiving() {
+        let mut keep_alive = ThreadKeepAlive::with_margin(Duration::minutes(10));
+        let start = Utc::now();
+        let watched = Watched {
+            auto_archive_duration: 60,
+            last_activity: start,
+            archived: false,
+        };
+        keep_alive.threads.insert(ChannelId(1), watched);
+        keep_alive.threads.insert(
+            ChannelId(2),
+            Watched {
+                archived: true,
+                ..watched
+            },
+        );
+
+        assert_eq!(keep_alive.due_at(start), vec![ChannelId(2)]);
+        assert_eq!(
+            keep_alive.due_at(start + Duration::minutes(50)),
+            vec![ChannelId(1), ChannelId(2)]
+        );
+        assert_eq!(keep_alive.next_due(), Some(start + Duration::minutes(50)));
+    }
+
+    #[test]
+    fn switches_to_a_different_duration() {
+        for duration in [60, 1440, 4320, 10080] {
+            assert_ne!(other_duration(duration), duration);
+        }
+    }
+}
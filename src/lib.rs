@@ -23,10 +23,15 @@
 //! to join a channel, then `play` and `stop` to control playback. Manipulating deaf/mute state
 //! and receiving audio are also possible.
 //!
-//! For examples, see the `examples` directory in the source tree.
+//! For complete example programs, see the [`examples`] module.
 
 #![warn(missing_docs, missing_debug_implementations)]
 
+#[cfg(all(feature = "native-tls", feature = "rustls"))]
+compile_error!("the `native-tls` and `rustls` features are mutually exclusive");
+#[cfg(not(any(feature = "native-tls", feature = "rustls")))]
+compile_error!("one of the `native-tls` or `rustls` features must be enabled");
+
 type Object = serde_json::Map<String, serde_json::Value>;
 
 macro_rules! cdn_concat {
@@ -36,13 +41,29 @@ macro_rules! cdn_concat {
     };
 }
 
+pub mod assets;
+
+pub mod automation;
+
 pub mod builders;
 
+#[cfg(any(test, doc, doctest))]
+pub mod examples;
+
+mod cache;
+pub use cache::{CacheBackend, InMemoryCache};
+
 mod connection;
-pub use connection::Connection;
+pub use connection::{Connection, ConnectionBuilder, MemberRequest, ResumeInfo};
+
+mod cooldown;
+pub use cooldown::{Cooldown, CooldownHit, CooldownScope, Cooldowns};
+
+mod reconnect;
+pub use reconnect::{GatewayCloseCode, ReconnectPolicy};
 
 mod error;
-pub use error::{Error, Result};
+pub use error::{ApiError, DiscordErrorCode, Error, ResponseMeta, Result};
 
 /// Struct and enum definitions of values in the Discord model.
 pub mod model {
@@ -54,8 +75,14 @@ pub mod model {
 
     mod live;
     pub use live::*;
+
+    pub use crate::serial::{Eq as OpCode, Kind};
 }
 
+pub mod moderation;
+
+pub mod permissions;
+
 mod ratelimit {
     pub mod rest;
 }
@@ -63,14 +90,32 @@ mod ratelimit {
 mod rest;
 pub use rest::*;
 
+mod sharding;
+pub use sharding::{ShardId, ShardManager};
+
 mod state;
-pub use state::{ChannelRef, State};
+pub use state::{ChannelRef, Inconsistency, ProfileChange, State, StateConfig, SNAPSHOT_VERSION};
+
+mod keep_alive;
+pub use keep_alive::ThreadKeepAlive;
+
+mod typing;
+pub use typing::{TypingChange, TypingTracker};
 
 #[macro_use]
 mod serial;
 
-// #[cfg(feature = "voice")]
-// pub mod voice;
+#[cfg(feature = "signal")]
+mod signal;
+
+#[cfg(feature = "voice")]
+pub mod voice;
+
+#[cfg(any(test, feature = "test-gateway"))]
+pub mod test_gateway;
+
+#[cfg(test)]
+mod fixtures;
 
 /// Read an image from a file into a string suitable for upload.
 ///
@@ -1,19 +1,23 @@
 //! Events returned by the gateway.
 
 use std::collections::BTreeMap;
+use std::fmt;
 
+use bitflags::bitflags;
 use chrono::{DateTime, FixedOffset, Utc};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Serialize, Serializer};
 use serde_json::Value;
 
 use crate::serial::Eq;
 
 use super::{
-    Activity, Attachment, Call, Channel, ChannelId, CurrentUser, CurrentUserPatch, Emoji,
-    FriendSourceFlags, LiveServer, Member, Message, MessageId, MessageType, OnlineStatus,
-    PossibleServer, Presence, PrivateChannel, Relationship, RelationshipType, Role, RoleId, Server,
-    ServerId, SingleReaction, Tutorial, UnreadMessages, User, UserId, UserServerSettings,
-    UserSettings, VoiceState,
+    ActiveStage, Activity, Attachment, Call, Channel, ChannelId, ChannelType, CurrentUser,
+    CurrentUserPatch, Emoji, FriendSourceFlags, Interaction, JoinRequestId, JoinRequestStatus,
+    LiveServer, Member, Message, MessageId, MessageType, OnlineStatus, PossibleServer, Presence,
+    PrivateChannel, Relationship, RelationshipType, Role, RoleId, Server, ServerId,
+    ServerJoinRequest, ServerThread, SingleReaction, SoundId, SoundboardSound, Sticker,
+    ThreadMember, Tutorial, UnreadMessages, User, UserId, UserServerSettings, UserSettings,
+    VoiceState,
 };
 
 /// A JSON payload message sent to the gateway.
@@ -110,15 +114,83 @@ pub enum SentMessage {
     },
 }
 
+/// A token to authenticate with, as a bot or as a user.
+///
+/// The REST API expects bot tokens to be prefixed with `Bot `, while the gateway expects
+/// the bare token, so the token is kept bare and the prefix is added where it is needed.
+/// The token itself is left out of its `Debug` output, so that it isn't logged by accident.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub enum Token {
+    /// The token of a bot account, without the `Bot ` prefix.
+    Bot(String),
+    /// The token of a user account.
+    User(String),
+}
+
+impl Token {
+    /// A bot token, trimming whitespace and any `Bot ` prefix it was copied with.
+    pub fn bot(token: &str) -> Token {
+        let token = token.trim();
+        Token::Bot(
+            token
+                .strip_prefix("Bot ")
+                .unwrap_or(token)
+                .trim_start()
+                .to_owned(),
+        )
+    }
+
+    /// A user token, trimming whitespace.
+    pub fn user(token: &str) -> Token {
+        Token::User(token.trim().to_owned())
+    }
+
+    /// The bare token, as sent to the gateway when identifying or resuming.
+    pub fn secret(&self) -> &str {
+        match self {
+            Token::Bot(token) | Token::User(token) => token,
+        }
+    }
+
+    /// The value of the `Authorization` header of REST requests.
+    pub fn authorization(&self) -> String {
+        match self {
+            Token::Bot(token) => format!("Bot {}", token),
+            Token::User(token) => token.clone(),
+        }
+    }
+
+    /// Whether this is the token of a bot account.
+    pub fn is_bot(&self) -> bool {
+        matches!(self, Token::Bot(_))
+    }
+}
+
+impl fmt::Debug for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Bot(_) => f.write_str("Token::Bot(..)"),
+            Token::User(_) => f.write_str("Token::User(..)"),
+        }
+    }
+}
+
+/// Tokens are sent to the gateway bare.
+impl Serialize for Token {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.secret())
+    }
+}
+
 /// The payload sent along with the `Identify` message (opcode 2).
 #[derive(Debug, Clone, Serialize)]
 pub struct IdentifyPayload {
     /// Authentication token.
-    pub token: String,
+    pub token: Token,
     /// A tuple of the two values `(shard_id, num_shards)`, used for guild sharding.
-    pub shard: Option<(u8, u8)>,
-    /// Gateway Intents you wish to receive.
-    pub intents: (),
+    pub shard: Option<(u32, u32)>,
+    /// Gateway Intents you wish to receive, or every event if `None`.
+    pub intents: Option<Intents>,
 
     /// Whether this connection supports compression of packets
     pub compress: Option<bool>,
@@ -130,6 +202,135 @@ pub struct IdentifyPayload {
     pub fingerprint: IdentifyConnection,
 }
 
+bitflags! {
+    /// The groups of gateway events a connection asks to receive.
+    ///
+    /// Events outside of the requested intents are never sent by the gateway.
+    /// `SERVER_MEMBERS`, `SERVER_PRESENCES` and `MESSAGE_CONTENT` are privileged,
+    /// and must also be enabled for the bot in the developer portal.
+    #[derive(Default, Serialize, Deserialize)]
+    #[serde(transparent)]
+    pub struct Intents: u64 {
+        /// Servers, their roles, channels and threads.
+        const SERVERS = 1 << 0;
+        /// Members joining, leaving and being updated.
+        const SERVER_MEMBERS = 1 << 1;
+        /// Bans and audit log entries.
+        const SERVER_MODERATION = 1 << 2;
+        /// Emoji, sticker and soundboard sound updates.
+        const SERVER_EMOJIS_AND_STICKERS = 1 << 3;
+        /// Integration updates.
+        const SERVER_INTEGRATIONS = 1 << 4;
+        /// Webhook updates.
+        const SERVER_WEBHOOKS = 1 << 5;
+        /// Invites being created and deleted.
+        const SERVER_INVITES = 1 << 6;
+        /// Voice state updates.
+        const SERVER_VOICE_STATES = 1 << 7;
+        /// Presence updates.
+        const SERVER_PRESENCES = 1 << 8;
+        /// Messages in servers.
+        const SERVER_MESSAGES = 1 << 9;
+        /// Reactions to messages in servers.
+        const SERVER_MESSAGE_REACTIONS = 1 << 10;
+        /// Typing in server channels.
+        const SERVER_MESSAGE_TYPING = 1 << 11;
+        /// Messages in private channels.
+        const DIRECT_MESSAGES = 1 << 12;
+        /// Reactions to messages in private channels.
+        const DIRECT_MESSAGE_REACTIONS = 1 << 13;
+        /// Typing in private channels.
+        const DIRECT_MESSAGE_TYPING = 1 << 14;
+        /// The content, embeds, attachments and components of messages.
+        const MESSAGE_CONTENT = 1 << 15;
+        /// Scheduled events.
+        const SERVER_SCHEDULED_EVENTS = 1 << 16;
+        /// Auto moderation rules being changed.
+        const AUTO_MODERATION_CONFIGURATION = 1 << 20;
+        /// Auto moderation rules being triggered.
+        const AUTO_MODERATION_EXECUTION = 1 << 21;
+        /// Votes on polls in servers.
+        const SERVER_MESSAGE_POLLS = 1 << 24;
+        /// Votes on polls in private channels.
+        const DIRECT_MESSAGE_POLLS = 1 << 25;
+
+        /// The intents which must be enabled in the developer portal.
+        const PRIVILEGED =
+            Self::SERVER_MEMBERS.bits | Self::SERVER_PRESENCES.bits | Self::MESSAGE_CONTENT.bits;
+    }
+}
+
+impl Intents {
+    /// The intents needed to receive an event from servers,
+    /// by its gateway name such as `MESSAGE_CREATE`.
+    ///
+    /// Events sent regardless of intents, and unknown events, need none.
+    pub fn required_for(event: &str) -> Intents {
+        match event {
+            "GUILD_CREATE"
+            | "GUILD_UPDATE"
+            | "GUILD_DELETE"
+            | "GUILD_ROLE_CREATE"
+            | "GUILD_ROLE_UPDATE"
+            | "GUILD_ROLE_DELETE"
+            | "CHANNEL_CREATE"
+            | "CHANNEL_UPDATE"
+            | "CHANNEL_DELETE"
+            | "CHANNEL_PINS_UPDATE"
+            | "THREAD_CREATE"
+            | "THREAD_UPDATE"
+            | "THREAD_DELETE"
+            | "THREAD_LIST_SYNC"
+            | "THREAD_MEMBER_UPDATE"
+            | "STAGE_INSTANCE_CREATE"
+            | "STAGE_INSTANCE_UPDATE"
+            | "STAGE_INSTANCE_DELETE" => Intents::SERVERS,
+            "GUILD_MEMBER_ADD"
+            | "GUILD_MEMBER_UPDATE"
+            | "GUILD_MEMBER_REMOVE"
+            | "THREAD_MEMBERS_UPDATE" => Intents::SERVER_MEMBERS,
+            "GUILD_BAN_ADD" | "GUILD_BAN_REMOVE" | "GUILD_AUDIT_LOG_ENTRY_CREATE" => {
+                Intents::SERVER_MODERATION
+            }
+            "GUILD_EMOJIS_UPDATE"
+            | "GUILD_STICKERS_UPDATE"
+            | "GUILD_SOUNDBOARD_SOUND_CREATE"
+            | "GUILD_SOUNDBOARD_SOUND_UPDATE"
+            | "GUILD_SOUNDBOARD_SOUND_DELETE"
+            | "GUILD_SOUNDBOARD_SOUNDS_UPDATE" => Intents::SERVER_EMOJIS_AND_STICKERS,
+            "GUILD_INTEGRATIONS_UPDATE"
+            | "INTEGRATION_CREATE"
+            | "INTEGRATION_UPDATE"
+            | "INTEGRATION_DELETE" => Intents::SERVER_INTEGRATIONS,
+            "WEBHOOKS_UPDATE" => Intents::SERVER_WEBHOOKS,
+            "INVITE_CREATE" | "INVITE_DELETE" => Intents::SERVER_INVITES,
+            "VOICE_STATE_UPDATE" => Intents::SERVER_VOICE_STATES,
+            "PRESENCE_UPDATE" => Intents::SERVER_PRESENCES,
+            // without MESSAGE_CONTENT, messages arrive with empty content
+            "MESSAGE_CREATE" | "MESSAGE_UPDATE" => {
+                Intents::SERVER_MESSAGES | Intents::MESSAGE_CONTENT
+            }
+            "MESSAGE_DELETE" | "MESSAGE_DELETE_BULK" => Intents::SERVER_MESSAGES,
+            "MESSAGE_REACTION_ADD"
+            | "MESSAGE_REACTION_REMOVE"
+            | "MESSAGE_REACTION_REMOVE_ALL"
+            | "MESSAGE_REACTION_REMOVE_EMOJI" => Intents::SERVER_MESSAGE_REACTIONS,
+            "TYPING_START" => Intents::SERVER_MESSAGE_TYPING,
+            "GUILD_SCHEDULED_EVENT_CREATE"
+            | "GUILD_SCHEDULED_EVENT_UPDATE"
+            | "GUILD_SCHEDULED_EVENT_DELETE"
+            | "GUILD_SCHEDULED_EVENT_USER_ADD"
+            | "GUILD_SCHEDULED_EVENT_USER_REMOVE" => Intents::SERVER_SCHEDULED_EVENTS,
+            "AUTO_MODERATION_RULE_CREATE"
+            | "AUTO_MODERATION_RULE_UPDATE"
+            | "AUTO_MODERATION_RULE_DELETE" => Intents::AUTO_MODERATION_CONFIGURATION,
+            "AUTO_MODERATION_ACTION_EXECUTION" => Intents::AUTO_MODERATION_EXECUTION,
+            "MESSAGE_POLL_VOTE_ADD" | "MESSAGE_POLL_VOTE_REMOVE" => Intents::SERVER_MESSAGE_POLLS,
+            _ => Intents::empty(),
+        }
+    }
+}
+
 /// A connection fingerprint of sorts, including information about the bot's environment.
 ///
 /// This is useful for discord to collect, because bots don't include normal user agent strings.
@@ -152,14 +353,14 @@ pub struct IdentifyConnection {
 #[derive(Debug, Clone, Serialize)]
 pub struct ResumePayload {
     /// The token of the authenticating user.
-    token: String,
+    pub token: Token,
 
     /// The session ID sent by the gateway during the failed connection.
-    session_id: String,
+    pub session_id: String,
 
     /// The number of the last sequence number received
     #[serde(rename = "seq")]
-    last_sequence: u64,
+    pub last_sequence: u64,
 }
 
 /// The request payload sent along with the `RequestGuildMembers` message (opcode 8).
@@ -233,6 +434,13 @@ pub enum ReceivedMessage {
         dispatch: DispatchPayload,
     },
 
+    /// The gateway asks the client to send a heartbeat immediately.
+    Heartbeat {
+        /// The parsed opcode from the event.
+        #[doc(hidden)]
+        op: Eq<1>,
+    },
+
     /// The gateway asks the bot to reconnect to the gateway.
     Reconnect {
         /// The parsed opcode from the event.
@@ -288,7 +496,10 @@ pub struct DispatchPayload {
 /// Event received over a websocket connection.
 ///
 /// When deserialized as part of a `struct` use `#[serde(flatten)]`.
-#[derive(Debug, Clone, Deserialize)]
+///
+/// Events serialize back to the same `t` and `d` fields they are received in,
+/// so recorded events can be replayed, e.g. through `State::update` in tests.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 #[serde(tag = "t", content = "d")]
 pub enum Event {
@@ -414,9 +625,30 @@ pub enum Event {
         roles: Vec<RoleId>,
         user: User,
         nick: Option<String>,
+        /// Whether the member has yet to pass the server's membership screening.
+        #[serde(default)]
+        pending: bool,
     },
     ServerMemberRemove(ServerId, User),
-    ServerMembersChunk(ServerId, Vec<Member>),
+    /// Members sent in answer to a request made with `Connection::request_members`.
+    ///
+    /// Large answers are split into chunks of up to 1000 members.
+    #[serde(rename = "GUILD_MEMBERS_CHUNK")]
+    ServerMembersChunk {
+        #[serde(rename = "guild_id")]
+        server_id: ServerId,
+        members: Vec<Member>,
+        /// The index of this chunk, from 0 to `chunk_count - 1`.
+        chunk_index: u32,
+        /// How many chunks the answer is split into.
+        chunk_count: u32,
+        /// The requested user IDs which aren't members of the server.
+        #[serde(default)]
+        not_found: Vec<UserId>,
+        /// The nonce of the request this chunk answers.
+        #[serde(default)]
+        nonce: Option<String>,
+    },
     ServerSync {
         server_id: ServerId,
         large: bool,
@@ -431,12 +663,113 @@ pub enum Event {
     ServerBanAdd(ServerId, User),
     ServerBanRemove(ServerId, User),
 
+    /// A user has started or submitted a request to join a server with Membership Screening.
+    ///
+    /// Only received by user accounts able to review join requests.
+    #[serde(rename = "GUILD_JOIN_REQUEST_CREATE")]
+    ServerJoinRequestCreate {
+        #[serde(rename = "guild_id")]
+        server_id: ServerId,
+        status: JoinRequestStatus,
+        request: ServerJoinRequest,
+    },
+    /// A join request has been submitted, approved or rejected.
+    #[serde(rename = "GUILD_JOIN_REQUEST_UPDATE")]
+    ServerJoinRequestUpdate {
+        #[serde(rename = "guild_id")]
+        server_id: ServerId,
+        status: JoinRequestStatus,
+        request: ServerJoinRequest,
+    },
+    /// A join request has been withdrawn or removed.
+    #[serde(rename = "GUILD_JOIN_REQUEST_DELETE")]
+    ServerJoinRequestDelete {
+        #[serde(rename = "id")]
+        request_id: JoinRequestId,
+        #[serde(rename = "guild_id")]
+        server_id: ServerId,
+        user_id: UserId,
+    },
+
     ServerIntegrationsUpdate(ServerId),
     ServerEmojisUpdate(ServerId, Vec<Emoji>),
+    /// A server's custom stickers have been created, edited or deleted.
+    #[serde(rename = "GUILD_STICKERS_UPDATE")]
+    ServerStickersUpdate {
+        #[serde(rename = "guild_id")]
+        server_id: ServerId,
+        /// The full list of the server's stickers after the change.
+        stickers: Vec<Sticker>,
+    },
+    /// A sound was added to a server's soundboard.
+    #[serde(rename = "GUILD_SOUNDBOARD_SOUND_CREATE")]
+    ServerSoundboardSoundCreate(SoundboardSound),
+    /// A sound on a server's soundboard was edited.
+    #[serde(rename = "GUILD_SOUNDBOARD_SOUND_UPDATE")]
+    ServerSoundboardSoundUpdate(SoundboardSound),
+    /// A sound was removed from a server's soundboard.
+    #[serde(rename = "GUILD_SOUNDBOARD_SOUND_DELETE")]
+    ServerSoundboardSoundDelete {
+        sound_id: SoundId,
+        #[serde(rename = "guild_id")]
+        server_id: ServerId,
+    },
+    /// Several sounds on a server's soundboard were edited at once.
+    #[serde(rename = "GUILD_SOUNDBOARD_SOUNDS_UPDATE")]
+    ServerSoundboardSoundsUpdate {
+        #[serde(rename = "guild_id")]
+        server_id: ServerId,
+        /// The sounds which were edited.
+        #[serde(rename = "soundboard_sounds")]
+        sounds: Vec<SoundboardSound>,
+    },
 
     ChannelCreate(Channel),
     ChannelUpdate(Channel),
     ChannelDelete(Channel),
+    /// A thread was created, or the current user was added to a private thread.
+    ThreadCreate(ServerThread),
+    /// A thread was edited, archived or unarchived.
+    ThreadUpdate(ServerThread),
+    /// A thread was deleted, or the current user was removed from a private thread.
+    ThreadDelete {
+        id: ChannelId,
+        #[serde(rename = "guild_id")]
+        server_id: ServerId,
+        parent_id: ChannelId,
+        #[serde(rename = "type")]
+        kind: ChannelType,
+    },
+    /// The current user gained access to channels, and is sent the active threads in them.
+    ThreadListSync {
+        #[serde(rename = "guild_id")]
+        server_id: ServerId,
+        /// The channels whose threads are being synced,
+        /// or `None` if the threads of the whole server are.
+        channel_ids: Option<Vec<ChannelId>>,
+        /// The active threads in the synced channels.
+        threads: Vec<ServerThread>,
+        /// The current user's membership of the synced threads which they have joined.
+        members: Vec<ThreadMember>,
+    },
+    /// Users were added to or removed from a thread.
+    ThreadMembersUpdate {
+        id: ChannelId,
+        #[serde(rename = "guild_id")]
+        server_id: ServerId,
+        /// An approximate count of users in the thread, which stops counting at 50.
+        member_count: u64,
+        #[serde(default)]
+        added_members: Vec<ThreadMember>,
+        #[serde(default, rename = "removed_member_ids")]
+        removed_members: Vec<UserId>,
+    },
+    /// A stage channel went live.
+    StageInstanceCreate(ActiveStage),
+    /// The topic or privacy of a live stage was changed.
+    StageInstanceUpdate(ActiveStage),
+    /// A stage channel stopped being live.
+    StageInstanceDelete(ActiveStage),
     ChannelPinsAck {
         channel_id: ChannelId,
         timestamp: DateTime<FixedOffset>,
@@ -449,6 +782,16 @@ pub enum Event {
     ReactionAdd(SingleReaction),
     ReactionRemove(SingleReaction),
 
+    /// A user invoked a command, clicked a component, or submitted a modal.
+    InteractionCreate(Interaction),
+
+    /// The process was asked to stop, by Ctrl-C or `SIGTERM`.
+    ///
+    /// Never sent by the gateway, but received last by connections which shut down on signals,
+    /// with the `signal` feature. The connection can't receive anything after it, and is left
+    /// open so that voice connections can be left before calling `Connection::shutdown`.
+    Shutdown,
+
     /// An event type not covered by the above
     #[serde(other)]
     Unknown,
@@ -467,6 +810,9 @@ pub struct ReadyEvent {
     /// The ID of the current session, used for reconnecting.
     pub session_id: String,
 
+    /// The gateway URL to use when resuming the session.
+    pub resume_gateway_url: Option<String>,
+
     /// A list of servers the user is in.
     /// Servers will be eventually populated with discrete server create events.
     #[serde(rename = "guilds")]
@@ -494,7 +840,7 @@ pub struct ReadyEvent {
     // Bot Users
     /// For bot users, the shard info for this session;
     /// the shard ID used and the total number of shards.
-    pub shard: Option<(u8, u8)>,
+    pub shard: Option<(u32, u32)>,
 
     /// The trace of discord gateway servers involved in serving this connection.
     #[serde(rename = "_trace")]
@@ -536,3 +882,98 @@ pub enum VoiceEvent {
     HeartbeatAck,
     Unknown(u64, Value),
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{Event, Intents};
+    use crate::model::{ServerId, StickerFormat, StickerType};
+
+    #[test]
+    fn decodes_sticker_updates() {
+        let recorded = json!({
+            "t": "GUILD_STICKERS_UPDATE",
+            "d": {
+                "guild_id": "4",
+                "stickers": [{
+                    "id": "749054660769218631",
+                    "name": "Wave",
+                    "tags": "wave",
+                    "type": 2,
+                    "format_type": 3,
+                    "description": "Wumpus waves hello",
+                    "available": true,
+                    "guild_id": "4",
+                    "user": {
+                        "id": "2",
+                        "username": "uploader",
+                        "discriminator": "0",
+                        "avatar": null,
+                    },
+                }],
+            },
+        });
+
+        let event: Event = serde_json::from_value(recorded).unwrap();
+        let Event::ServerStickersUpdate {
+            server_id,
+            stickers,
+        } = event
+        else {
+            panic!("unexpected event: {:?}", event);
+        };
+        assert_eq!(server_id, ServerId(4));
+        assert_eq!(stickers[0].name, "Wave");
+        assert!(matches!(stickers[0].format, StickerFormat::Lottie));
+        assert!(matches!(
+            stickers[0].kind,
+            StickerType::Server {
+                available: true,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn events_replay() {
+        let recorded = [
+            json!({
+                "t": "TYPING_START",
+                "d": { "channel_id": "1", "user_id": "2", "timestamp": "2024-01-01T00:00:00Z" },
+            }),
+            json!({
+                "t": "THREAD_DELETE",
+                "d": { "id": "3", "guild_id": "4", "parent_id": "1", "type": 11 },
+            }),
+            json!({ "t": "RESUMED", "d": { "_trace": ["gateway"] } }),
+            json!({
+                "t": "GUILD_SOUNDBOARD_SOUND_DELETE",
+                "d": { "sound_id": "5", "guild_id": "4" },
+            }),
+        ];
+
+        for value in recorded {
+            let event: Event = serde_json::from_value(value).unwrap();
+            let written = serde_json::to_value(&event).unwrap();
+            let replayed: Event = serde_json::from_value(written.clone()).unwrap();
+            assert_eq!(serde_json::to_value(&replayed).unwrap(), written);
+        }
+    }
+
+    #[test]
+    fn intents_for_events() {
+        assert_eq!(
+            Intents::required_for("MESSAGE_CREATE"),
+            Intents::SERVER_MESSAGES | Intents::MESSAGE_CONTENT
+        );
+        assert!(Intents::PRIVILEGED.contains(Intents::MESSAGE_CONTENT));
+        assert!(Intents::required_for("READY").is_empty());
+        assert_eq!(
+            Intents::required_for("GUILD_SOUNDBOARD_SOUND_CREATE"),
+            Intents::SERVER_EMOJIS_AND_STICKERS
+        );
+        let intents = Intents::SERVERS | Intents::SERVER_MESSAGES;
+        assert_eq!(serde_json::to_value(intents).unwrap(), json!(513));
+    }
+}
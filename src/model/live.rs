@@ -4,16 +4,15 @@
 //! Types in this category include `LiveServer`, `Presences` and so on.
 
 use bitflags::bitflags;
-use chrono::{DateTime, FixedOffset, Utc};
+use chrono::{DateTime, FixedOffset, TimeZone, Utc};
 use serde::{Deserialize, Serialize};
 use serde_repr::{Deserialize_repr, Serialize_repr};
-use tracing::warn;
 
 use super::{
-    ApplicationId, ChannelId, ChannelType, Emoji, EmojiId, EventId, MessageId, NsfwLevel,
-    PermissionOverwrite, Permissions, Role, RoleId, ScheduledEvent, Server, ServerChannel,
-    ServerFeature, ServerId, ServerThread, StageId, Sticker, StickerItem, Thread, User, UserId,
-    VerificationLevel, WelcomeScreen,
+    ApplicationId, Channel, ChannelId, ChannelType, CommandId, CommandOptionType, CommandType,
+    Emoji, EmojiId, EventId, InteractionId, MessageId, MfaLevel, NsfwLevel, Permissions, Role,
+    RoleId, ScheduledEvent, Server, ServerChannel, ServerFeature, ServerId, ServerThread, StageId,
+    Sticker, StickerItem, Thread, User, UserId, VerificationLevel, WelcomeScreen,
 };
 
 // Live Server
@@ -93,7 +92,7 @@ pub struct LiveServer {
     /// Required multi factor authentication level.
     ///
     /// https://discord.com/developers/docs/resources/guild#guild-object-mfa-level
-    pub mfa_level: u8,
+    pub mfa_level: MfaLevel,
 
     // pub application_id: ApplicationId
     /// The id of the channel where server notices
@@ -200,7 +199,10 @@ pub struct LiveServer {
     #[serde(rename = "guild_scheduled_events")]
     pub scheduled_events: Vec<ScheduledEvent>,
     
-    /// The server's members.
+    /// The server's members, as received from the gateway.
+    ///
+    /// `State` moves these into its cache backend, so this is empty for servers
+    /// kept in a `State`. Look members up with `State::member` instead.
     pub members: Vec<Member>,
     
     /// The presences of the server's members.
@@ -217,177 +219,78 @@ impl LiveServer {
             .map(|icon| format!(cdn_concat!("/icons/{}/{}.jpg"), self.id, icon))
     }
 
-    /// Calculate the effective permissions for a specific user in a specific
-    /// channel on this server.
-    pub fn permissions_for(&self, channel: ChannelId, user: UserId) -> Permissions {
-        // Owner has all permissions
-        if user == self.owner_id {
-            return Permissions::all();
+    /// Summarize the server, with the counts dashboards usually display.
+    pub fn summary(&self) -> ServerSummary {
+        ServerSummary {
+            id: self.id,
+            name: self.name.clone(),
+            member_count: self.member_count,
+            online_count: self
+                .presences
+                .iter()
+                .filter(|p| !matches!(p.status, OnlineStatus::Offline | OnlineStatus::Invisible))
+                .count() as u64,
+            channel_count: self.channels.len(),
+            boost_tier: self.boost_tier,
         }
+    }
 
-        // OR together all the user's roles
-        let everyone = match self.roles.iter().find(|r| r.id == self.id.everyone()) {
-            Some(r) => r,
-            None => {
-                warn!(
-                    "Missing @everyone role in permissions lookup on {} ({})",
-                    self.name, self.id
-                );
-                return Permissions::empty();
-            }
-        };
-
-        // Permissions acquired through granted roles
-        let mut role_permissions = everyone.permissions;
-
-        let member = match self
+    /// Calculate the effective permissions for a specific user in a specific
+    /// channel on this server, looking the user up in `members`.
+    ///
+    /// Servers kept in a `State` have no `members`, so this gives everyone the
+    /// permissions of `@everyone`. Use `State::permissions_for` instead.
+    #[deprecated(note = "State keeps members apart from servers; use State::permissions_for")]
+    pub fn permissions_for(&self, channel: ChannelId, user: UserId) -> Permissions {
+        let member = self
             .members
             .iter()
-            .find(|u| u.user.as_ref().unwrap().id == user)
-        {
-            Some(u) => u,
-            None => return everyone.permissions,
-        };
-
-        for &role in &member.roles {
-            if let Some(role) = self.roles.iter().find(|r| r.id == role) {
-                role_permissions |= role.permissions;
-            } else {
-                warn!(
-                    "perms: {:?} on {:?} has non-existent role {:?}",
-                    member.user.as_ref().unwrap().id,
-                    self.id,
-                    role
-                );
-            }
-        }
-
-        // Administrators have all permissions in any channel
-        if role_permissions.contains(Permissions::ADMINISTRATOR) {
-            return Permissions::all();
-        }
-
-        let mut strip_voice_perms = false;
-        if let Some(channel) = self.channels.iter().find(|c| c.id() == &channel) {
-            strip_voice_perms = channel.contains_text();
-
-            match channel {
-                ServerChannel::Text { .. }
-                | ServerChannel::Voice { .. }
-                | ServerChannel::Announcement { .. }
-                | ServerChannel::Category { .. } => {
-                    let overwrites = channel.permission_overwrites().unwrap();
-
-                    // Apply role overwrites, denied then allowed
-                    for overwrite in overwrites {
-                        if let PermissionOverwrite::Role {
-                            id, allow, deny, ..
-                        } = overwrite
-                        {
-                            // if the member has this role, or it is the @everyone role
-                            if member.roles.contains(id) || id.0 == self.id.0 {
-                                role_permissions = (role_permissions & !*deny) | *allow;
-                            }
-                        }
-                    }
-
-                    // Apply member overwrites, denied then allowed
-                    for overwrite in overwrites {
-                        if let PermissionOverwrite::Member {
-                            id, allow, deny, ..
-                        } = overwrite
-                        {
-                            if &user == id {
-                                role_permissions = (role_permissions & !*deny) | *allow;
-                            }
-                        }
-                    }
-                }
-
-                // channel is a thread and inherits overwrites from its parent
-                ServerChannel::PublicThread { thread, .. }
-                | ServerChannel::PrivateThread { thread, .. }
-                | ServerChannel::AnnouncementThread { thread, .. } => {
-                    let parent_channel = self.channels.iter().find(|c| c.id() == &thread.parent_id);
-                    if let Some(parent_channel) = parent_channel {
-                        let overwrites = parent_channel.permission_overwrites().unwrap();
-
-                        // Apply role overwrites, denied then allowed
-                        for overwrite in overwrites {
-                            if let PermissionOverwrite::Role {
-                                id, allow, deny, ..
-                            } = overwrite
-                            {
-                                // if the member has this role, or it is the @everyone role
-                                if member.roles.contains(id) || id.0 == self.id.0 {
-                                    role_permissions = (role_permissions & !*deny) | *allow;
-                                }
-                            }
-                        }
-
-                        // Apply member overwrites, denied then allowed
-                        for overwrite in overwrites {
-                            if let PermissionOverwrite::Member {
-                                id, allow, deny, ..
-                            } = overwrite
-                            {
-                                if &user == id {
-                                    role_permissions = (role_permissions & !*deny) | *allow;
-                                }
-                            }
-                        }
-                    } else {
-                        warn!(
-                            "guild with id {:?} does not contain channel {:?}, but it is referenced as thread {:?}'s parent", 
-                            self.id, 
-                            thread.parent_id, 
-                            thread.id
-                        );
-                    }
-                }
-            }
-        } else {
-            warn!("guild with id {:?} does not contain channel ID {:?}, but it is referenced in role overwrites", self.id, channel);
-        }
-
-        // Default channel is always readable
-        if channel.0 == self.id.0 {
-            role_permissions |= Permissions::READ_MESSAGES;
-        }
-
-        // calculate implicit permissions
-
-        // No SEND_MESSAGES => no message-sending-related actions
-        if !role_permissions.contains(Permissions::SEND_MESSAGES) {
-            role_permissions &= !(Permissions::SEND_TTS_MESSAGES
-                | Permissions::MENTION_EVERYONE
-                | Permissions::EMBED_LINKS
-                | Permissions::ATTACH_FILES);
-        }
-
-        // No READ_MESSAGES => no channel actions
-        if !role_permissions.contains(Permissions::READ_MESSAGES) {
-            role_permissions &= Permissions::KICK_MEMBERS
-                | Permissions::BAN_MEMBERS
-                | Permissions::ADMINISTRATOR
-                | Permissions::MANAGE_SERVER
-                | Permissions::CHANGE_NICKNAMES
-                | Permissions::MANAGE_NICKNAMES;
-        }
+            .find(|u| u.user.as_ref().map(|u| u.id) == Some(user));
+        self.member_permissions(channel, user, member)
+    }
 
-        // Text channel => no voice actions
-        if strip_voice_perms {
-            role_permissions &= !(Permissions::VOICE_CONNECT
-                | Permissions::VOICE_SPEAK
-                | Permissions::VOICE_MUTE_MEMBERS
-                | Permissions::VOICE_DEAFEN_MEMBERS
-                | Permissions::VOICE_MOVE_MEMBERS
-                | Permissions::VOICE_USE_VOICE_ACTIVITY);
-        }
-        role_permissions
+    /// Calculate the effective permissions of a user in a channel on this server,
+    /// given their member data, which is looked up elsewhere than in `members`.
+    ///
+    /// A user who is not a member, given as `None`, only has the permissions of `@everyone`.
+    pub fn member_permissions(
+        &self,
+        channel: ChannelId,
+        user: UserId,
+        member: Option<&Member>,
+    ) -> Permissions {
+        crate::permissions::compute(
+            self.id,
+            self.owner_id,
+            &self.roles,
+            &self.channels,
+            channel,
+            user,
+            member,
+        )
     }
 }
 
+/// A compact summary of a [`LiveServer`], made with `LiveServer::summary`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServerSummary {
+    /// The ID of the server.
+    pub id: ServerId,
+    /// The name of the server.
+    pub name: String,
+    /// The number of members in the server.
+    pub member_count: u64,
+    /// The number of members known to be online, from the presences received.
+    ///
+    /// This is zero if presences aren't cached, and may be less than the actual
+    /// count in large servers, for which only some presences are sent.
+    pub online_count: u64,
+    /// The number of channels in the server, not counting threads.
+    pub channel_count: usize,
+    /// The server's boost level.
+    pub boost_tier: u8,
+}
+
 /// A server which may be unavailable
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(untagged)]
@@ -450,6 +353,16 @@ pub struct Member {
     /// When the user started boosting this server.
     #[serde(rename = "premium_since")]
     pub boosting_since: Option<DateTime<FixedOffset>>,
+
+    /// Whether the user has yet to pass the server's membership screening.
+    #[serde(default)]
+    pub pending: bool,
+
+    /// Fields of the member which this crate does not model yet,
+    /// kept as received and written back out when serialized.
+    #[cfg(feature = "extra-fields")]
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
 }
 
 impl Member {
@@ -463,6 +376,22 @@ impl Member {
     }
 }
 
+/// A user subscribed to a scheduled event.
+///
+/// https://discord.com/developers/docs/resources/guild-scheduled-event#guild-scheduled-event-user-object
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduledEventUser {
+    /// The ID of the event the user is subscribed to.
+    #[serde(rename = "guild_scheduled_event_id")]
+    pub event_id: EventId,
+
+    /// The user subscribed to the event.
+    pub user: User,
+
+    /// The user's member data for the event's server, if requested.
+    pub member: Option<Member>,
+}
+
 bitflags! {
     /// Odd member information.
     ///
@@ -487,8 +416,8 @@ bitflags! {
 /// This structure does not apply for the current user, as more fields are available. 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Presence {
-    /// The user this ID belongs to
-    pub user: User,
+    /// The user this presence belongs to
+    pub user: PresenceUser,
 
     /// The status of this user
     pub status: OnlineStatus,
@@ -499,6 +428,74 @@ pub struct Presence {
     pub activities: Vec<Activity>,
 }
 
+/// The partial user sent along with a presence.
+///
+/// Only the ID is always present; the other fields are usually
+/// only sent when they have changed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PresenceUser {
+    /// The user's ID
+    pub id: UserId,
+
+    /// The user's username, if sent.
+    #[serde(rename = "username", default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// The user's Discord-tag, if sent.
+    #[serde(
+        default,
+        deserialize_with = "crate::serial::deserialize_discrim_opt",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub discriminator: Option<u16>,
+
+    /// The user's avatar hash if sent, which is `Some(None)` if the avatar was removed.
+    #[serde(
+        default,
+        deserialize_with = "crate::serial::deserialize_some",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub avatar: Option<Option<String>>,
+
+    /// Whether the user belongs to an OAuth2 application, if sent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bot: Option<bool>,
+}
+
+impl PresenceUser {
+    /// Copy the fields which were sent onto a full user,
+    /// returning whether the username, discriminator or avatar changed.
+    pub fn update(&self, user: &mut User) -> bool {
+        let mut changed = false;
+        if let Some(ref name) = self.name {
+            changed |= user.name != *name;
+            user.name.clone_from(name);
+        }
+        if let Some(discriminator) = self.discriminator {
+            changed |= user.discriminator != discriminator;
+            user.discriminator = discriminator;
+        }
+        if let Some(ref avatar) = self.avatar {
+            changed |= user.avatar != *avatar;
+            user.avatar.clone_from(avatar);
+        }
+        if let Some(bot) = self.bot {
+            user.bot = bot;
+        }
+        changed
+    }
+
+    /// Build a full user, if the username and discriminator were sent.
+    pub fn to_user(&self) -> Option<User> {
+        Some(User {
+            id: self.id,
+            name: self.name.clone()?,
+            discriminator: self.discriminator?,
+            avatar: self.avatar.clone().flatten(),
+            bot: self.bot.unwrap_or(false),
+        })
+    }
+}
+
 /// A user's online presence status.
 /// This enum is deserialized from a string field.
 ///
@@ -623,6 +620,13 @@ pub struct Message {
     pub id: MessageId,
     /// The ID of the channel the message was sent in.
     pub channel_id: ChannelId,
+    /// The ID of the server the message was sent in.
+    ///
+    /// Only present on messages received over the gateway;
+    /// messages fetched over REST never include it.
+    #[serde(rename = "guild_id")]
+    #[serde(default)]
+    pub server_id: Option<ServerId>,
 
     /// The content of the message
     pub content: String,
@@ -703,13 +707,138 @@ pub struct Message {
     #[serde(rename = "position")]
     pub thread_position: Option<u64>,
 
-    // todo interactions and components
+    /// Interactive components attached to the message, such as buttons.
+    #[serde(default)]
+    pub components: Vec<Component>,
+
+    // todo interactions
     // todo role subscriptions
 
     // carry on if nonce is absent or for some reason not a string
     #[serde(deserialize_with = "crate::serial::ignore_errors")]
     #[serde(default)]
     pub nonce: Option<String>,
+
+    /// Fields of the message which this crate does not model yet,
+    /// kept as received and written back out when serialized.
+    #[cfg(feature = "extra-fields")]
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl Message {
+    /// Get the URL which jumps to this message in the Discord client.
+    ///
+    /// The server is taken from the channel the message was sent in, as messages
+    /// fetched over REST don't say which server they belong to.
+    pub fn link(&self, channel: &Channel) -> String {
+        let server_id = match channel {
+            Channel::Server(channel) => Some(*channel.server_id()),
+            Channel::DirectMessage(_) | Channel::Group(_) => None,
+        };
+        MessageLink {
+            server_id,
+            channel_id: self.channel_id,
+            message_id: self.id,
+        }
+        .url()
+    }
+}
+
+/// The IDs a message link points to, as copied with "Copy Message Link" in the Discord client.
+///
+/// See [`parse_message_link`] to read one from a URL.
+#[derive(Copy, Clone, Hash, Eq, PartialEq, Debug)]
+pub struct MessageLink {
+    /// The server of the message, or `None` for messages in private channels (`@me` links).
+    pub server_id: Option<ServerId>,
+    /// The channel of the message.
+    pub channel_id: ChannelId,
+    /// The ID of the message itself.
+    pub message_id: MessageId,
+}
+
+impl MessageLink {
+    /// Get the URL which jumps to the message in the Discord client.
+    pub fn url(&self) -> String {
+        match self.server_id {
+            Some(server_id) => format!(
+                "https://discord.com/channels/{}/{}/{}",
+                server_id, self.channel_id, self.message_id
+            ),
+            None => format!(
+                "https://discord.com/channels/@me/{}/{}",
+                self.channel_id, self.message_id
+            ),
+        }
+    }
+}
+
+/// Parse a link to a message into the IDs it points to.
+///
+/// Links from the stable, PTB and Canary clients are accepted, on both `discord.com` and the
+/// older `discordapp.com`. Private channel links use `@me` in place of the server ID.
+///
+/// ```
+/// # use discord_tokio::model::{parse_message_link, ChannelId, MessageId, ServerId};
+/// let link = parse_message_link("https://discord.com/channels/1/2/3").unwrap();
+/// assert_eq!(link.server_id, Some(ServerId(1)));
+/// assert_eq!(link.channel_id, ChannelId(2));
+/// assert_eq!(link.message_id, MessageId(3));
+///
+/// let link = parse_message_link("https://ptb.discord.com/channels/@me/2/3").unwrap();
+/// assert_eq!(link.server_id, None);
+///
+/// assert_eq!(parse_message_link("https://discord.com/channels/1/2"), None);
+/// ```
+pub fn parse_message_link(url: &str) -> Option<MessageLink> {
+    let url = url.trim().trim_start_matches('<').trim_end_matches('>');
+    let rest = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))
+        .unwrap_or(url);
+    let (host, path) = rest.split_once('/')?;
+    let host = host
+        .strip_prefix("ptb.")
+        .or_else(|| host.strip_prefix("canary."))
+        .unwrap_or(host);
+    let host = host.strip_prefix("www.").unwrap_or(host);
+    if host != "discord.com" && host != "discordapp.com" {
+        return None;
+    }
+
+    let path = path.split(['?', '#']).next()?;
+    let mut segments = path.trim_end_matches('/').split('/');
+    if segments.next()? != "channels" {
+        return None;
+    }
+    let server_id = match segments.next()? {
+        "@me" => None,
+        id => Some(ServerId(id.parse().ok()?)),
+    };
+    let channel_id = ChannelId(segments.next()?.parse().ok()?);
+    let message_id = MessageId(segments.next()?.parse().ok()?);
+    if segments.next().is_some() {
+        return None;
+    }
+
+    Some(MessageLink {
+        server_id,
+        channel_id,
+        message_id,
+    })
+}
+
+/// A kind of mention which may be allowed to notify in a message's `allowed_mentions`.
+#[derive(Copy, Clone, Hash, Eq, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MentionType {
+    /// Mentions of roles.
+    Roles,
+    /// Mentions of users.
+    Users,
+    /// The `@everyone` and `@here` mentions.
+    Everyone,
 }
 
 /// The type of a message
@@ -807,6 +936,11 @@ bitflags! {
         const SUPPRESS_EMBEDS = 1 << 2;
         const SOURCE_MESSAGE_DELETED = 1 << 3;
         const URGENT = 1 << 4;
+        const HAS_THREAD = 1 << 5;
+        /// Only the user who invoked the interaction can see the message.
+        const EPHEMERAL = 1 << 6;
+        /// The message is an interaction response, and the bot is "thinking".
+        const LOADING = 1 << 7;
     }
 }
 
@@ -840,6 +974,27 @@ impl Attachment {
             None
         }
     }
+
+    /// Get when the signed `url` of the attachment stops working, from its `ex` parameter.
+    ///
+    /// Returns `None` for URLs which aren't signed.
+    ///
+    /// ```
+    /// # use discord_tokio::model::Attachment;
+    /// # let mut attachment: Attachment = serde_json::from_value(serde_json::json!({
+    /// #     "id": "1", "filename": "a.png", "url": "", "proxy_url": "", "size": 0,
+    /// # })).unwrap();
+    /// attachment.url = "https://cdn.discordapp.com/attachments/1/2/a.png?ex=65a0b1c2&hm=ff".into();
+    /// assert_eq!(attachment.expires_at().unwrap().timestamp(), 0x65a0b1c2);
+    /// ```
+    pub fn expires_at(&self) -> Option<DateTime<Utc>> {
+        let (_, query) = self.url.split_once('?')?;
+        let expiry = query
+            .split('&')
+            .find_map(|param| param.strip_prefix("ex="))?;
+        let expiry = i64::from_str_radix(expiry, 16).ok()?;
+        Utc.timestamp_opt(expiry, 0).single()
+    }
 }
 
 /// An embed attached to a message.
@@ -1011,6 +1166,24 @@ pub struct MessageReference {
     /// When sending, whether to error if the referenced message
     /// doesn't exist instead of sending as a normal (non-reply) message, default `true`.
     pub fail_if_not_exists: Option<bool>,
+
+    /// Whether the message replies to or forwards the originating message.
+    #[serde(rename = "type")]
+    #[serde(default)]
+    pub kind: MessageReferenceType,
+}
+
+/// How a message refers to the message in its `message_reference`.
+///
+/// https://discord.com/developers/docs/resources/message#message-reference-types
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq, Serialize_repr, Deserialize_repr)]
+#[repr(u8)]
+pub enum MessageReferenceType {
+    /// A reply, or another kind of message pointing back at the originating one.
+    #[default]
+    Default = 0,
+    /// A forward, which carries a snapshot of the originating message.
+    Forward = 1,
 }
 
 /// Information about a mentioned channel.
@@ -1033,6 +1206,117 @@ pub struct ChannelMention {
     pub kind: ChannelType,
 }
 
+// Message components
+
+/// An interactive component of a message or modal.
+///
+/// Action rows contain the other components. The fields which do not
+/// apply to a component's type are left empty.
+///
+/// https://discord.com/developers/docs/interactions/message-components#component-object
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Component {
+    /// The type of the component.
+    #[serde(rename = "type")]
+    pub kind: ComponentType,
+    /// The developer-defined ID of the component, sent back in interactions.
+    #[serde(default)]
+    pub custom_id: Option<String>,
+    /// Whether the component can not be used.
+    #[serde(default)]
+    pub disabled: bool,
+
+    /// The components inside an action row.
+    #[serde(default)]
+    pub components: Vec<Component>,
+
+    /// The style of a button.
+    #[serde(default)]
+    pub style: Option<ButtonStyle>,
+    /// The text on a button.
+    #[serde(default)]
+    pub label: Option<String>,
+    /// The emoji on a button.
+    #[serde(default)]
+    pub emoji: Option<ReactionEmoji>,
+    /// The link opened by a link button.
+    #[serde(default)]
+    pub url: Option<String>,
+
+    /// The choices of a string select menu.
+    #[serde(default)]
+    pub options: Vec<SelectOption>,
+    /// The text shown in a select menu when nothing is selected.
+    #[serde(default)]
+    pub placeholder: Option<String>,
+    /// The minimum number of items which must be selected in a select menu.
+    #[serde(default)]
+    pub min_values: Option<u8>,
+    /// The maximum number of items which can be selected in a select menu.
+    #[serde(default)]
+    pub max_values: Option<u8>,
+
+    /// The text entered into a text input, in submitted modals.
+    #[serde(default)]
+    pub value: Option<String>,
+}
+
+/// The type of a message component.
+#[derive(Copy, Clone, Hash, Eq, PartialEq, Debug, Serialize_repr, Deserialize_repr)]
+#[repr(u8)]
+pub enum ComponentType {
+    /// A container for other components.
+    ActionRow = 1,
+    /// A clickable button.
+    Button = 2,
+    /// A select menu of developer-defined strings.
+    StringSelect = 3,
+    /// A text input, in modals.
+    TextInput = 4,
+    /// A select menu of users.
+    UserSelect = 5,
+    /// A select menu of roles.
+    RoleSelect = 6,
+    /// A select menu of users and roles.
+    MentionableSelect = 7,
+    /// A select menu of channels.
+    ChannelSelect = 8,
+}
+
+/// The look of a button.
+#[derive(Copy, Clone, Hash, Eq, PartialEq, Debug, Serialize_repr, Deserialize_repr)]
+#[repr(u8)]
+pub enum ButtonStyle {
+    /// Blurple.
+    Primary = 1,
+    /// Grey.
+    Secondary = 2,
+    /// Green.
+    Success = 3,
+    /// Red.
+    Danger = 4,
+    /// Grey, opening a link instead of sending an interaction.
+    Link = 5,
+}
+
+/// A choice in a string select menu.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SelectOption {
+    /// The name of the choice as shown to users.
+    pub label: String,
+    /// The developer-defined value of the choice.
+    pub value: String,
+    /// Additional text shown under the label.
+    #[serde(default)]
+    pub description: Option<String>,
+    /// An emoji shown next to the label.
+    #[serde(default)]
+    pub emoji: Option<ReactionEmoji>,
+    /// Whether the choice is selected by default.
+    #[serde(default)]
+    pub default: bool,
+}
+
 // Message reactions
 
 /// A full single reaction interaction.
@@ -1045,17 +1329,57 @@ pub struct SingleReaction {
     pub message_id: MessageId,
     pub user_id: UserId,
     pub emoji: ReactionEmoji,
+
+    /// Whether this is a super reaction.
+    #[serde(default)]
+    pub burst: bool,
+    /// The colors used for the super reaction animation, as hex strings like `#ff0000`.
+    #[serde(default)]
+    pub burst_colors: Vec<String>,
+    /// The type of the reaction.
+    #[serde(rename = "type")]
+    #[serde(default)]
+    pub kind: ReactionType,
 }
 
 /// Information on a reaction as available at a glance on a message.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Reaction {
-    /// The amount of people that have reacted with this emoji
+    /// The amount of people that have reacted with this emoji, including super reactions
     pub count: u64,
+    /// The amount of normal and super reactions with this emoji
+    #[serde(default)]
+    pub count_details: ReactionCountDetails,
     /// If the current user has placed this reaction
     pub me: bool,
+    /// If the current user has super reacted with this emoji
+    #[serde(default)]
+    pub me_burst: bool,
     /// The emoji used to react
     pub emoji: ReactionEmoji,
+    /// The colors used for the super reaction animation, as hex strings like `#ff0000`.
+    #[serde(default)]
+    pub burst_colors: Vec<String>,
+}
+
+/// A breakdown of a reaction's count into normal and super reactions.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct ReactionCountDetails {
+    /// The amount of super reactions
+    pub burst: u64,
+    /// The amount of normal reactions
+    pub normal: u64,
+}
+
+/// Whether a reaction is a normal reaction or a super reaction.
+#[derive(Copy, Clone, Hash, Eq, PartialEq, Debug, Default, Serialize_repr, Deserialize_repr)]
+#[repr(u8)]
+pub enum ReactionType {
+    /// A normal reaction
+    #[default]
+    Normal = 0,
+    /// A super reaction, which is animated and shown separately from normal reactions
+    Burst = 1,
 }
 
 /// Emoji information sent only from reaction events
@@ -1079,6 +1403,191 @@ pub enum ReactionEmoji {
     },
 }
 
+#[cfg(feature = "emoji")]
+impl ReactionEmoji {
+    /// Look up a unicode emoji by its shortcode, such as `:thumbsup:`.
+    /// The surrounding colons are optional.
+    ///
+    /// Only available with the `emoji` feature.
+    pub fn from_shortcode(shortcode: &str) -> Option<ReactionEmoji> {
+        let shortcode = shortcode.strip_prefix(':').unwrap_or(shortcode);
+        let shortcode = shortcode.strip_suffix(':').unwrap_or(shortcode);
+        emojis::get_by_shortcode(shortcode).map(|emoji| ReactionEmoji::Unicode {
+            name: emoji.as_str().to_owned(),
+        })
+    }
+
+    /// Check that a unicode reaction is actually an emoji.
+    /// Custom emojis are always considered valid.
+    ///
+    /// Only available with the `emoji` feature.
+    pub fn is_valid(&self) -> bool {
+        match self {
+            ReactionEmoji::Custom { .. } => true,
+            ReactionEmoji::Unicode { name } => emojis::get(name).is_some(),
+        }
+    }
+}
+
+// Interactions
+
+/// An interaction with the application, such as a slash command invocation,
+/// a click on a message component, or a submitted modal.
+///
+/// https://discord.com/developers/docs/interactions/receiving-and-responding#interaction-object
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Interaction {
+    /// The ID of the interaction.
+    pub id: InteractionId,
+    /// The application the interaction is for.
+    pub application_id: ApplicationId,
+    /// What kind of interaction this is.
+    #[serde(rename = "type")]
+    pub kind: InteractionType,
+    /// The command, component or modal data, absent for pings.
+    #[serde(default)]
+    pub data: Option<InteractionData>,
+
+    /// The server the interaction was sent from, if any.
+    #[serde(rename = "guild_id", default)]
+    pub server_id: Option<ServerId>,
+    /// The channel the interaction was sent from.
+    #[serde(default)]
+    pub channel_id: Option<ChannelId>,
+    /// The member who invoked the interaction, when invoked in a server.
+    #[serde(default)]
+    pub member: Option<Member>,
+    /// The user who invoked the interaction, when invoked in a DM.
+    #[serde(default)]
+    pub user: Option<User>,
+
+    /// A token for responding to the interaction, valid for 15 minutes.
+    pub token: String,
+    /// Always 1.
+    pub version: u8,
+
+    /// The message a clicked component is attached to.
+    #[serde(default)]
+    pub message: Option<Message>,
+
+    /// The selected language of the invoking user.
+    #[serde(default)]
+    pub locale: Option<String>,
+    /// The preferred locale of the server the interaction was sent from.
+    #[serde(rename = "guild_locale", default)]
+    pub server_locale: Option<String>,
+
+    /// Fields of the interaction which this crate does not model yet,
+    /// kept as received and written back out when serialized.
+    #[cfg(feature = "extra-fields")]
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl Interaction {
+    /// Get the user who invoked the interaction, whether in a server or a DM.
+    pub fn user(&self) -> Option<&User> {
+        self.member
+            .as_ref()
+            .and_then(|member| member.user.as_ref())
+            .or(self.user.as_ref())
+    }
+}
+
+/// The kind of an interaction.
+#[derive(Copy, Clone, Hash, Eq, PartialEq, Debug, Serialize_repr, Deserialize_repr)]
+#[repr(u8)]
+pub enum InteractionType {
+    /// Sent by Discord to check an interactions endpoint URL.
+    Ping = 1,
+    /// A slash command or context menu command was used.
+    ApplicationCommand = 2,
+    /// A message component was clicked or selected.
+    MessageComponent = 3,
+    /// A command option with autocomplete is being filled in.
+    Autocomplete = 4,
+    /// A modal was submitted.
+    ModalSubmit = 5,
+}
+
+/// The data of an interaction, which depends on the kind of interaction.
+///
+/// Fields which do not apply to the interaction are left empty.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InteractionData {
+    /// The ID of the invoked command.
+    #[serde(default)]
+    pub id: Option<CommandId>,
+    /// The name of the invoked command.
+    #[serde(default)]
+    pub name: Option<String>,
+    /// The kind of the invoked command.
+    #[serde(rename = "type", default)]
+    pub command_kind: Option<CommandType>,
+    /// The parameters the command was invoked with.
+    #[serde(default)]
+    pub options: Vec<InteractionOption>,
+    /// Users, members, roles, channels and attachments referenced by the options.
+    #[serde(default)]
+    pub resolved: Option<serde_json::Value>,
+    /// The ID of the user or message targeted by a context menu command.
+    #[serde(default)]
+    pub target_id: Option<String>,
+
+    /// The developer-defined ID of the clicked component or submitted modal.
+    #[serde(default)]
+    pub custom_id: Option<String>,
+    /// The type of the clicked component.
+    #[serde(default)]
+    pub component_type: Option<ComponentType>,
+    /// The values chosen in a select menu.
+    #[serde(default)]
+    pub values: Vec<String>,
+    /// The components of a submitted modal, with the values filled in.
+    #[serde(default)]
+    pub components: Vec<Component>,
+}
+
+/// A parameter a command was invoked with.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InteractionOption {
+    /// The name of the parameter.
+    pub name: String,
+    /// The type of the parameter.
+    #[serde(rename = "type")]
+    pub kind: CommandOptionType,
+    /// The value given, absent for subcommands and subcommand groups.
+    #[serde(default)]
+    pub value: Option<serde_json::Value>,
+    /// The parameters of a subcommand or subcommand group.
+    #[serde(default)]
+    pub options: Vec<InteractionOption>,
+    /// Whether the user is currently filling in this option, for autocomplete.
+    #[serde(default)]
+    pub focused: bool,
+}
+
+/// How to respond to an interaction.
+#[derive(Copy, Clone, Hash, Eq, PartialEq, Debug, Serialize_repr, Deserialize_repr)]
+#[repr(u8)]
+pub enum InteractionCallbackType {
+    /// Acknowledge a ping.
+    Pong = 1,
+    /// Respond with a message.
+    ChannelMessageWithSource = 4,
+    /// Acknowledge the interaction, and edit in a message later.
+    /// The user sees a loading state until then.
+    DeferredChannelMessageWithSource = 5,
+    /// For components, acknowledge the interaction and edit the original message later.
+    DeferredUpdateMessage = 6,
+    /// For components, edit the message the component was attached to.
+    UpdateMessage = 7,
+    /// Respond to an autocomplete interaction with suggested choices.
+    AutocompleteResult = 8,
+    /// Respond with a popup modal.
+    Modal = 9,
+}
+
 // Voice States & Regions
 
 /// A member's state within a voice channel
@@ -1219,6 +1728,7 @@ pub struct ActiveStage {
     /// The topic set for the stage session.
     pub topic: String,
 
+    /// Who can see and join the stage.
     #[serde(rename = "privacy_level")]
     pub privacy: StagePrivacyLevel,
 
@@ -1235,3 +1745,35 @@ pub enum StagePrivacyLevel {
     #[default]
     ServerOnly,
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{Channel, Message};
+    use crate::fixtures;
+
+    #[test]
+    fn links_messages_through_their_channel() {
+        let message: Message = serde_json::from_value(fixtures::message(3, 2)).unwrap();
+        assert_eq!(message.server_id, None);
+
+        let channel: Channel = serde_json::from_value(fixtures::text_channel(2, 1)).unwrap();
+        assert_eq!(message.link(&channel), "https://discord.com/channels/1/2/3");
+
+        let channel: Channel = serde_json::from_value(json!({
+            "type": 1,
+            "id": "2",
+            "last_message_id": "3",
+            "last_pin_timestamp": null,
+            "recipients": [
+                { "id": "2", "username": "member", "discriminator": "0", "avatar": null },
+            ],
+        }))
+        .unwrap();
+        assert_eq!(
+            message.link(&channel),
+            "https://discord.com/channels/@me/2/3"
+        );
+    }
+}
@@ -8,14 +8,15 @@
 //! instances of them are managed by an active gateway connection,
 //! are defined in the sister module `live`.
 
-use std::{borrow::Cow, fmt};
+use std::{borrow::Cow, collections::BTreeMap, fmt};
 
 use bitflags::bitflags;
-use chrono::{DateTime, FixedOffset, NaiveDateTime, TimeZone, Utc};
+use chrono::{DateTime, FixedOffset, TimeZone, Utc};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use serde_repr::{Deserialize_repr, Serialize_repr};
 
+use super::OnlineStatus;
 use crate::serial::Eq;
 
 // IDs
@@ -31,6 +32,19 @@ const fn to_unix_millis(snowflake: u64) -> u64 {
     relative_timestamp + DISCORD_EPOCH
 }
 
+/// Make the lowest snowflake with the given creation date in unix millis.
+/// Dates before the discord epoch give 0, and dates too late to be stored give `u64::MAX`.
+const fn from_unix_millis(unix_millis: i64) -> u64 {
+    if unix_millis < DISCORD_EPOCH as i64 {
+        return 0;
+    }
+    let relative_timestamp = unix_millis as u64 - DISCORD_EPOCH;
+    if relative_timestamp >= 1 << 42 {
+        return u64::MAX;
+    }
+    relative_timestamp << 22
+}
+
 macro_rules! snowflake {
     ($(#[$attr:meta] $name:ident;)*) => {
         $(
@@ -41,7 +55,13 @@ macro_rules! snowflake {
             /// Some identifiers have `mention()` methods as well.
             #[derive(Copy, Clone, Hash, Eq, PartialEq, Debug, Ord, PartialOrd)]
             #[derive(Serialize, Deserialize)]
-            pub struct $name(#[serde(deserialize_with = "crate::serial::deserialize_id")] pub u64);
+            pub struct $name(
+                #[serde(
+                    serialize_with = "crate::serial::serialize_id",
+                    deserialize_with = "crate::serial::deserialize_id"
+                )]
+                pub u64,
+            );
 
             impl $name {
                 /// Get the creation date of the object referred to by this ID.
@@ -50,8 +70,18 @@ macro_rules! snowflake {
                 /// (https://github.com/twitter/snowflake/tree/b3f6a3c6ca8e1b6847baa6ff42bf72201e2c2231#snowflake).
                 pub fn creation_date(&self) -> DateTime<Utc> {
                     let unix_timestamp = to_unix_millis(self.0);
-                    let naive = NaiveDateTime::from_timestamp_opt((unix_timestamp / 1000) as i64, 0).expect("invalid and/or out of range timestamp");
-                    Utc.from_utc_datetime(&naive)
+                    Utc.timestamp_millis_opt(unix_timestamp as i64)
+                        .single()
+                        .expect("invalid and/or out of range timestamp")
+                }
+
+                /// Make the lowest ID which could refer to an object created at the given time.
+                ///
+                /// Such an ID doesn't refer to anything, but marks a point in time in lists
+                /// ordered by ID, so that e.g. `Cursor::After(MessageId::from_timestamp(date))`
+                /// gets the messages sent since `date`.
+                pub fn from_timestamp(date: DateTime<Utc>) -> Self {
+                    $name(from_unix_millis(date.timestamp_millis()))
                 }
             }
 
@@ -83,10 +113,24 @@ snowflake! {
     StickerId;
     /// An identifier for a standard sticker pack.
     StickerPackId;
+    /// An identifier for a soundboard sound
+    SoundId;
     /// An identifier for a scheduled server event
     EventId;
     /// An identifier for an active stage instance.
     StageId;
+    /// An identifier for a request to join a server with membership screening.
+    JoinRequestId;
+    /// An identifier for an entry in a server's audit log.
+    AuditLogEntryId;
+    /// An identifier for an application command.
+    CommandId;
+    /// An identifier for an interaction with an application.
+    InteractionId;
+    /// An identifier for a tag which can be applied to posts in a forum channel.
+    TagId;
+    /// An identifier for a webhook.
+    WebhookId;
 }
 
 // Users
@@ -329,7 +373,7 @@ pub struct Server {
     /// Required multi factor authentication level.
     ///
     /// https://discord.com/developers/docs/resources/guild#guild-object-mfa-level
-    pub mfa_level: u8,
+    pub mfa_level: MfaLevel,
 
     // pub application_id: ApplicationId
     /// The id of the channel where server notices
@@ -409,6 +453,12 @@ pub struct Server {
     /// The ID of the channel where admins and moderators
     /// of Community guilds receive safety alerts from Discord.
     pub safety_alerts_channel_id: Option<ChannelId>,
+
+    /// Fields of the server which this crate does not model yet,
+    /// kept as received and written back out when serialized.
+    #[cfg(feature = "extra-fields")]
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
 }
 
 impl Server {
@@ -448,12 +498,26 @@ pub struct WelcomeChannels {
     /// The description shown for the channel.
     pub description: String,
     /// The emoji id, if the emoji is custom.
-    pub emoji_id: EmojiId,
+    pub emoji_id: Option<EmojiId>,
     /// The emoji name if custom, the unicode character if standard,
     /// or null if no emoji is set.
     pub emoji_name: Option<String>,
 }
 
+impl WelcomeChannels {
+    /// Render the channel's emoji for use in a message.
+    ///
+    /// Custom emojis are rendered in their `<:name:id>` form, standard emojis as their unicode
+    /// character, and `None` is returned if no emoji is set.
+    pub fn render_emoji(&self) -> Option<String> {
+        match (self.emoji_id, &self.emoji_name) {
+            (Some(id), name) => Some(format!("<:{}:{}>", name.as_deref().unwrap_or("_"), id)),
+            (None, Some(name)) => Some(name.clone()),
+            (None, None) => None,
+        }
+    }
+}
+
 /// Basic information about a Discord server.
 /// Viewable without needing to be a member if
 /// the guild is in the official public server discovery.
@@ -500,6 +564,66 @@ pub struct ServerPreview {
     pub description: Option<String>,
 }
 
+/// The public summary of a server shown by its widget, which anyone can fetch
+/// without a token while the widget is enabled.
+///
+/// https://discord.com/developers/docs/resources/guild#get-guild-widget
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerWidget {
+    /// The ID of the server.
+    pub id: ServerId,
+    /// The name of the server.
+    pub name: String,
+    /// An invite to the server's widget channel, if it has one.
+    pub instant_invite: Option<String>,
+    /// The voice channels everyone can join.
+    #[serde(default)]
+    pub channels: Vec<WidgetChannel>,
+    /// A sample of the online members, of at most 100.
+    #[serde(default)]
+    pub members: Vec<WidgetMember>,
+    /// How many members are online.
+    pub presence_count: u64,
+}
+
+/// A voice channel listed by a server widget.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WidgetChannel {
+    /// The ID of the channel.
+    pub id: ChannelId,
+    /// The name of the channel.
+    pub name: String,
+    /// The sorting position of the channel.
+    pub position: i64,
+}
+
+/// An online member listed by a server widget.
+///
+/// Widgets hide who their members are, so the ID is only an index into the list
+/// rather than the member's user ID.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WidgetMember {
+    /// The position of the member in the list.
+    pub id: String,
+    /// The name of the member.
+    pub username: String,
+    /// The URL of the member's avatar.
+    pub avatar_url: Option<String>,
+    /// The online status of the member.
+    pub status: OnlineStatus,
+    /// What the member is playing, if anything.
+    pub activity: Option<WidgetActivity>,
+    /// The voice channel the member is connected to, if listed by the widget.
+    pub channel_id: Option<ChannelId>,
+}
+
+/// The activity of a member listed by a server widget.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WidgetActivity {
+    /// The name of the activity, like the game being played.
+    pub name: String,
+}
+
 impl ServerPreview {
     /// Returns the formatted URL of the server's icon.
     ///
@@ -630,6 +754,18 @@ pub enum VerificationLevel {
     Phone = 4,
 }
 
+/// Whether moderators of a server must have two-factor authentication enabled.
+///
+/// https://discord.com/developers/docs/resources/guild#guild-object-mfa-level
+#[derive(Copy, Clone, Hash, Eq, PartialEq, Debug, Serialize_repr, Deserialize_repr)]
+#[repr(u8)]
+pub enum MfaLevel {
+    /// Moderators don't need two-factor authentication.
+    None = 0,
+    /// Moderators need two-factor authentication to take moderation actions.
+    Elevated = 1,
+}
+
 /// A server's NSFW rating.
 ///
 /// https://discord.com/developers/docs/resources/guild#guild-object-guild-nsfw-level
@@ -646,8 +782,17 @@ pub enum NsfwLevel {
 /// A banning of a user
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Ban {
-    reason: Option<String>,
-    user: User,
+    pub reason: Option<String>,
+    pub user: User,
+}
+
+/// The outcome of banning several users at once.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BulkBan {
+    /// The users who were banned.
+    pub banned_users: Vec<UserId>,
+    /// The users who could not be banned.
+    pub failed_users: Vec<UserId>,
 }
 
 /// Representation of the number of member that would be pruned by a server
@@ -657,6 +802,299 @@ pub struct ServerPrune {
     pub pruned: u64,
 }
 
+/// A user's request to join a server which has Membership Screening enabled.
+///
+/// Join requests can only be seen and actioned by user accounts with the
+/// permission to kick members.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerJoinRequest {
+    /// The ID of the join request.
+    pub id: JoinRequestId,
+
+    /// The ID of the server the user wants to join.
+    #[serde(rename = "guild_id")]
+    pub server_id: ServerId,
+
+    /// The ID of the user requesting to join.
+    pub user_id: UserId,
+    /// The user requesting to join, if included.
+    pub user: Option<User>,
+
+    /// When the request was created.
+    pub created_at: DateTime<FixedOffset>,
+
+    /// Where in the screening process the request is.
+    #[serde(rename = "application_status")]
+    pub status: JoinRequestStatus,
+
+    /// The reason given when the request was rejected.
+    pub rejection_reason: Option<String>,
+
+    /// The moderator who approved or rejected the request.
+    #[serde(rename = "actioned_by_user")]
+    pub actioned_by: Option<User>,
+
+    /// The user's answers to the server's screening questions.
+    #[serde(default)]
+    pub form_responses: Vec<Value>,
+}
+
+/// The progress of a server join request.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum JoinRequestStatus {
+    /// The user has started filling out the screening form.
+    Started,
+    /// The user has submitted the request and is awaiting review.
+    Submitted,
+    /// The request was rejected by a moderator.
+    Rejected,
+    /// The request was approved by a moderator.
+    Approved,
+}
+
+impl JoinRequestStatus {
+    /// The name of the status as used in query strings.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            JoinRequestStatus::Started => "STARTED",
+            JoinRequestStatus::Submitted => "SUBMITTED",
+            JoinRequestStatus::Rejected => "REJECTED",
+            JoinRequestStatus::Approved => "APPROVED",
+        }
+    }
+}
+
+/// A page of a server's audit log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditLog {
+    /// The entries of the audit log, newest first.
+    #[serde(rename = "audit_log_entries")]
+    pub entries: Vec<AuditLogEntry>,
+    /// The users referenced by the entries.
+    #[serde(default)]
+    pub users: Vec<User>,
+}
+
+/// A single administrative action recorded in a server's audit log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditLogEntry {
+    /// The ID of the entry.
+    pub id: AuditLogEntryId,
+    /// The type of action that occurred.
+    pub action_type: AuditLogAction,
+    /// The user or bot who performed the action.
+    pub user_id: Option<UserId>,
+    /// The ID of the affected entity, such as a user, channel or role.
+    pub target_id: Option<String>,
+    /// The reason given for the action.
+    pub reason: Option<String>,
+    /// The changes made to the target.
+    #[serde(default)]
+    pub changes: Vec<AuditLogChange>,
+    /// Additional information for certain action types.
+    pub options: Option<AuditLogOptions>,
+}
+
+/// A change made to a single property of the target of an audit log entry.
+///
+/// https://discord.com/developers/docs/resources/audit-log#audit-log-change-object
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditLogChange {
+    /// The name of the changed property, such as `nick` or `$add` for added roles.
+    pub key: String,
+    /// The value of the property before the change, if it had one.
+    pub old_value: Option<Value>,
+    /// The value of the property after the change, if it has one.
+    pub new_value: Option<Value>,
+}
+
+/// Additional information recorded with certain types of audit log entries.
+///
+/// Which fields are present depends on the action type.
+///
+/// https://discord.com/developers/docs/resources/audit-log#audit-log-entry-object-optional-audit-entry-info
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AuditLogOptions {
+    /// The channel in which the entities were targeted,
+    /// for member moves, message deletes, pins and auto moderation actions.
+    pub channel_id: Option<ChannelId>,
+    /// The number of entities targeted, for member moves and disconnects and message deletes.
+    pub count: Option<String>,
+    /// The number of days of inactivity after which members were pruned.
+    pub delete_member_days: Option<String>,
+    /// The number of members removed by a prune.
+    pub members_removed: Option<String>,
+    /// The ID of the pinned or unpinned message.
+    pub message_id: Option<MessageId>,
+    /// The ID of the overwritten member or role, for permission overwrite changes.
+    pub id: Option<String>,
+    /// The type of the overwritten entity, `"0"` for a role and `"1"` for a member.
+    #[serde(rename = "type")]
+    pub kind: Option<String>,
+    /// The name of the overwritten role.
+    pub role_name: Option<String>,
+    /// The ID of the application whose command permissions were changed.
+    pub application_id: Option<ApplicationId>,
+    /// The name of the auto moderation rule which was triggered.
+    pub auto_moderation_rule_name: Option<String>,
+    /// The trigger type of the auto moderation rule which was triggered.
+    pub auto_moderation_rule_trigger_type: Option<String>,
+    /// The type of integration which performed the action.
+    pub integration_type: Option<String>,
+}
+
+/// The type of action recorded by an audit log entry.
+///
+/// Every documented action type is named,
+/// but any other action type can still be represented.
+///
+/// https://discord.com/developers/docs/resources/audit-log#audit-log-entry-object-audit-log-events
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct AuditLogAction(pub u16);
+
+impl AuditLogAction {
+    /// A server's settings were changed.
+    pub const SERVER_UPDATE: AuditLogAction = AuditLogAction(1);
+    /// A channel was created.
+    pub const CHANNEL_CREATE: AuditLogAction = AuditLogAction(10);
+    /// A channel's settings were changed.
+    pub const CHANNEL_UPDATE: AuditLogAction = AuditLogAction(11);
+    /// A channel was deleted.
+    pub const CHANNEL_DELETE: AuditLogAction = AuditLogAction(12);
+    /// A permission overwrite was added to a channel.
+    pub const CHANNEL_OVERWRITE_CREATE: AuditLogAction = AuditLogAction(13);
+    /// A permission overwrite of a channel was changed.
+    pub const CHANNEL_OVERWRITE_UPDATE: AuditLogAction = AuditLogAction(14);
+    /// A permission overwrite was removed from a channel.
+    pub const CHANNEL_OVERWRITE_DELETE: AuditLogAction = AuditLogAction(15);
+    /// A member was kicked from the server.
+    pub const MEMBER_KICK: AuditLogAction = AuditLogAction(20);
+    /// Inactive members were pruned from the server.
+    pub const MEMBER_PRUNE: AuditLogAction = AuditLogAction(21);
+    /// A user was banned from the server.
+    pub const MEMBER_BAN_ADD: AuditLogAction = AuditLogAction(22);
+    /// A user was unbanned from the server.
+    pub const MEMBER_BAN_REMOVE: AuditLogAction = AuditLogAction(23);
+    /// A member's nickname or server mute/deafen state was changed.
+    pub const MEMBER_UPDATE: AuditLogAction = AuditLogAction(24);
+    /// Roles were added to or removed from a member.
+    pub const MEMBER_ROLE_UPDATE: AuditLogAction = AuditLogAction(25);
+    /// Members were moved to a different voice channel.
+    pub const MEMBER_MOVE: AuditLogAction = AuditLogAction(26);
+    /// Members were disconnected from a voice channel.
+    pub const MEMBER_DISCONNECT: AuditLogAction = AuditLogAction(27);
+    /// A bot was added to the server.
+    pub const BOT_ADD: AuditLogAction = AuditLogAction(28);
+    /// A role was created.
+    pub const ROLE_CREATE: AuditLogAction = AuditLogAction(30);
+    /// A role was edited.
+    pub const ROLE_UPDATE: AuditLogAction = AuditLogAction(31);
+    /// A role was deleted.
+    pub const ROLE_DELETE: AuditLogAction = AuditLogAction(32);
+    /// An invite was created.
+    pub const INVITE_CREATE: AuditLogAction = AuditLogAction(40);
+    /// An invite was edited.
+    pub const INVITE_UPDATE: AuditLogAction = AuditLogAction(41);
+    /// An invite was deleted.
+    pub const INVITE_DELETE: AuditLogAction = AuditLogAction(42);
+    /// A webhook was created.
+    pub const WEBHOOK_CREATE: AuditLogAction = AuditLogAction(50);
+    /// A webhook's settings or channel were changed.
+    pub const WEBHOOK_UPDATE: AuditLogAction = AuditLogAction(51);
+    /// A webhook was deleted.
+    pub const WEBHOOK_DELETE: AuditLogAction = AuditLogAction(52);
+    /// An emoji was created.
+    pub const EMOJI_CREATE: AuditLogAction = AuditLogAction(60);
+    /// An emoji's name was changed.
+    pub const EMOJI_UPDATE: AuditLogAction = AuditLogAction(61);
+    /// An emoji was deleted.
+    pub const EMOJI_DELETE: AuditLogAction = AuditLogAction(62);
+    /// A message of another user was deleted.
+    pub const MESSAGE_DELETE: AuditLogAction = AuditLogAction(72);
+    /// Messages were deleted in bulk.
+    pub const MESSAGE_BULK_DELETE: AuditLogAction = AuditLogAction(73);
+    /// A message was pinned.
+    pub const MESSAGE_PIN: AuditLogAction = AuditLogAction(74);
+    /// A message was unpinned.
+    pub const MESSAGE_UNPIN: AuditLogAction = AuditLogAction(75);
+    /// An integration was added to the server.
+    pub const INTEGRATION_CREATE: AuditLogAction = AuditLogAction(80);
+    /// An integration was edited.
+    pub const INTEGRATION_UPDATE: AuditLogAction = AuditLogAction(81);
+    /// An integration was removed from the server.
+    pub const INTEGRATION_DELETE: AuditLogAction = AuditLogAction(82);
+    /// A stage went live.
+    pub const STAGE_INSTANCE_CREATE: AuditLogAction = AuditLogAction(83);
+    /// The settings of a live stage were changed.
+    pub const STAGE_INSTANCE_UPDATE: AuditLogAction = AuditLogAction(84);
+    /// A live stage was ended.
+    pub const STAGE_INSTANCE_DELETE: AuditLogAction = AuditLogAction(85);
+    /// A sticker was created.
+    pub const STICKER_CREATE: AuditLogAction = AuditLogAction(90);
+    /// A sticker was edited.
+    pub const STICKER_UPDATE: AuditLogAction = AuditLogAction(91);
+    /// A sticker was deleted.
+    pub const STICKER_DELETE: AuditLogAction = AuditLogAction(92);
+    /// A scheduled event was created.
+    pub const SCHEDULED_EVENT_CREATE: AuditLogAction = AuditLogAction(100);
+    /// A scheduled event was edited.
+    pub const SCHEDULED_EVENT_UPDATE: AuditLogAction = AuditLogAction(101);
+    /// A scheduled event was cancelled.
+    pub const SCHEDULED_EVENT_DELETE: AuditLogAction = AuditLogAction(102);
+    /// A thread was created.
+    pub const THREAD_CREATE: AuditLogAction = AuditLogAction(110);
+    /// A thread was edited.
+    pub const THREAD_UPDATE: AuditLogAction = AuditLogAction(111);
+    /// A thread was deleted.
+    pub const THREAD_DELETE: AuditLogAction = AuditLogAction(112);
+    /// The permissions of an application command were changed.
+    pub const COMMAND_PERMISSION_UPDATE: AuditLogAction = AuditLogAction(121);
+    /// An auto moderation rule was created.
+    pub const AUTO_MODERATION_RULE_CREATE: AuditLogAction = AuditLogAction(140);
+    /// An auto moderation rule was edited.
+    pub const AUTO_MODERATION_RULE_UPDATE: AuditLogAction = AuditLogAction(141);
+    /// An auto moderation rule was deleted.
+    pub const AUTO_MODERATION_RULE_DELETE: AuditLogAction = AuditLogAction(142);
+    /// A message was blocked by auto moderation.
+    pub const AUTO_MODERATION_BLOCK_MESSAGE: AuditLogAction = AuditLogAction(143);
+    /// A message was flagged by auto moderation.
+    pub const AUTO_MODERATION_FLAG_TO_CHANNEL: AuditLogAction = AuditLogAction(144);
+    /// A member was timed out by auto moderation.
+    pub const AUTO_MODERATION_USER_COMMUNICATION_DISABLED: AuditLogAction = AuditLogAction(145);
+    /// A creator monetization request was created.
+    pub const CREATOR_MONETIZATION_REQUEST_CREATED: AuditLogAction = AuditLogAction(150);
+    /// The creator monetization terms were accepted.
+    pub const CREATOR_MONETIZATION_TERMS_ACCEPTED: AuditLogAction = AuditLogAction(151);
+    /// An onboarding prompt was created.
+    pub const ONBOARDING_PROMPT_CREATE: AuditLogAction = AuditLogAction(163);
+    /// An onboarding prompt was edited.
+    pub const ONBOARDING_PROMPT_UPDATE: AuditLogAction = AuditLogAction(164);
+    /// An onboarding prompt was deleted.
+    pub const ONBOARDING_PROMPT_DELETE: AuditLogAction = AuditLogAction(165);
+    /// Onboarding was set up for the server.
+    pub const ONBOARDING_CREATE: AuditLogAction = AuditLogAction(166);
+    /// The server's onboarding was changed.
+    pub const ONBOARDING_UPDATE: AuditLogAction = AuditLogAction(167);
+    /// The server's home settings were set up.
+    pub const HOME_SETTINGS_CREATE: AuditLogAction = AuditLogAction(190);
+    /// The server's home settings were changed.
+    pub const HOME_SETTINGS_UPDATE: AuditLogAction = AuditLogAction(191);
+}
+
+/// A ban, together with who issued it and why, as recorded in the audit log.
+#[derive(Debug, Clone)]
+pub struct BanInfo {
+    /// The server the user was banned from.
+    pub server_id: ServerId,
+    /// The banned user.
+    pub user: User,
+    /// The moderator who issued the ban, if the audit log entry was found.
+    pub actor: Option<User>,
+    /// The reason given for the ban.
+    pub reason: Option<String>,
+}
+
 impl ServerId {
     /// Get the ID of the server's `@everyone` role.
     ///
@@ -747,7 +1185,7 @@ bitflags! {
 
 bitflags! {
     /// Set of permissions assignable to a [Role] or a [PermissionOverwrite]
-    #[derive(Default, Serialize, Deserialize)]
+    #[derive(Default)]
     pub struct Permissions: u64 {
         /// Freely create invites to all channels of this server
         const CREATE_INVITE = 1;
@@ -817,6 +1255,20 @@ bitflags! {
     }
 }
 
+/// Permissions are sent as strings, since they may not fit in a JavaScript number.
+impl Serialize for Permissions {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&self.bits)
+    }
+}
+
+impl<'de> Deserialize<'de> for Permissions {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // permissions added by Discord since are kept, so they survive being sent back
+        crate::serial::deserialize_id(deserializer).map(|bits| Permissions { bits })
+    }
+}
+
 /// A channel-specific permission overwrite for a role or member.
 ///
 /// https://discord.com/developers/docs/resources/channel#overwrite-object
@@ -856,8 +1308,34 @@ pub enum PermissionOverwrite {
     },
 }
 
+impl PermissionOverwrite {
+    /// Get the ID of the role or member this overwrite is about.
+    pub fn id(&self) -> PermissionOverwriteId {
+        match *self {
+            PermissionOverwrite::Role { id, .. } => PermissionOverwriteId::Role(id),
+            PermissionOverwrite::Member { id, .. } => PermissionOverwriteId::Member(id),
+        }
+    }
+
+    /// Get the permissions this overwrite allows.
+    pub fn allow(&self) -> Permissions {
+        match *self {
+            PermissionOverwrite::Role { allow, .. } => allow,
+            PermissionOverwrite::Member { allow, .. } => allow,
+        }
+    }
+
+    /// Get the permissions this overwrite denies.
+    pub fn deny(&self) -> Permissions {
+        match *self {
+            PermissionOverwrite::Role { deny, .. } => deny,
+            PermissionOverwrite::Member { deny, .. } => deny,
+        }
+    }
+}
+
 /// The ID of a permission overwrite entity.
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize)]
 pub enum PermissionOverwriteId {
     /// The permission overwrite concerns a member.
     Member(UserId),
@@ -1025,6 +1503,17 @@ pub enum ServerChannel {
         _type: Eq<5>,
     },
 
+    /// A forum channel in a server, which only contains threads.
+    Forum {
+        /// The forum channel in question.
+        #[serde(flatten)]
+        channel: ForumChannel,
+
+        #[doc(hidden)]
+        #[serde(rename = "type")]
+        _type: Eq<15>,
+    },
+
     /// A temporary sub-channel within an [AnnouncementChannel].
     AnnouncementThread {
         /// The thread in question.
@@ -1036,7 +1525,7 @@ pub enum ServerChannel {
         _type: Eq<10>,
     },
 
-    /// A temporary sub-channel within a [TextChannel] or GUILD_FORUM channel
+    /// A temporary sub-channel within a [TextChannel] or [ForumChannel]
     PublicThread {
         /// The thread in question.
         #[serde(flatten)]
@@ -1067,6 +1556,7 @@ impl ServerChannel {
             Self::Text { channel, .. } => &channel.id,
             Self::Voice { channel, .. } => &channel.id,
             Self::Announcement { channel, .. } => &channel.id,
+            Self::Forum { channel, .. } => &channel.id,
             Self::Category { category, .. } => &category.id,
             Self::AnnouncementThread { thread, .. } => &thread.id,
             Self::PublicThread { thread, .. } => &thread.id,
@@ -1081,6 +1571,7 @@ impl ServerChannel {
             Self::Text { channel, .. } => &channel.server_id,
             Self::Voice { channel, .. } => &channel.server_id,
             Self::Announcement { channel, .. } => &channel.server_id,
+            Self::Forum { channel, .. } => &channel.server_id,
             Self::Category { category, .. } => &category.server_id,
             Self::AnnouncementThread { thread, .. } => &thread.server_id,
             Self::PublicThread { thread, .. } => &thread.server_id,
@@ -1095,6 +1586,7 @@ impl ServerChannel {
             Self::Text { .. } => ChannelType::Text,
             Self::Voice { .. } => ChannelType::Voice,
             Self::Announcement { .. } => ChannelType::Announcement,
+            Self::Forum { .. } => ChannelType::Forum,
             Self::Category { .. } => ChannelType::Category,
             Self::AnnouncementThread { .. } => ChannelType::AnnouncementThread,
             Self::PublicThread { .. } => ChannelType::PublicThread,
@@ -1113,6 +1605,7 @@ impl ServerChannel {
 
             Self::Voice { .. } => false,
             Self::Category { .. } => false,
+            Self::Forum { .. } => false,
         }
     }
 
@@ -1125,6 +1618,7 @@ impl ServerChannel {
             Self::Text { channel, .. } => Some(channel.permission_overwrites.as_ref()),
             Self::Voice { channel, .. } => Some(channel.permission_overwrites.as_ref()),
             Self::Announcement { channel, .. } => Some(channel.permission_overwrites.as_ref()),
+            Self::Forum { channel, .. } => Some(channel.permission_overwrites.as_ref()),
             Self::Category { category, .. } => Some(category.permission_overwrites.as_ref()),
 
             // threads effectively inherit their overwrites from the parent channel
@@ -1133,6 +1627,40 @@ impl ServerChannel {
             Self::PrivateThread { .. } => None,
         }
     }
+
+    /// Get the sorting position of this channel among its siblings.
+    ///
+    /// Threads are not sorted, and `None` is returned for them.
+    pub fn position(&self) -> Option<i64> {
+        match self {
+            Self::Text { channel, .. } => Some(channel.position as i64),
+            Self::Voice { channel, .. } => Some(channel.position as i64),
+            Self::Announcement { channel, .. } => Some(channel.position as i64),
+            Self::Forum { channel, .. } => Some(channel.position as i64),
+            Self::Category { category, .. } => Some(category.position),
+
+            Self::AnnouncementThread { .. } => None,
+            Self::PublicThread { .. } => None,
+            Self::PrivateThread { .. } => None,
+        }
+    }
+
+    /// Get the ID of the category this channel is in, if any.
+    ///
+    /// Categories and threads are never in a category.
+    pub fn category_id(&self) -> Option<ChannelId> {
+        match self {
+            Self::Text { channel, .. } => channel.category_id,
+            Self::Voice { channel, .. } => channel.category_id,
+            Self::Announcement { channel, .. } => channel.category_id,
+            Self::Forum { channel, .. } => channel.category_id,
+
+            Self::Category { .. } => None,
+            Self::AnnouncementThread { .. } => None,
+            Self::PublicThread { .. } => None,
+            Self::PrivateThread { .. } => None,
+        }
+    }
 }
 
 /// A textual channel of a server.
@@ -1334,6 +1862,124 @@ pub struct AnnouncementChannel {
     pub default_auto_archive_duration: Option<u16>,
 }
 
+/// A channel which only contains threads, called posts,
+/// each of which starts with a message and can be tagged.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ForumChannel {
+    /// The ID of the channel, unique across the server.
+    pub id: ChannelId,
+
+    /// The ID of the server this channel belongs to.
+    #[serde(rename = "guild_id")]
+    pub server_id: ServerId,
+
+    /// The name of the channel.
+    pub name: String,
+
+    /// The order of the channel in relation to others.
+    ///
+    /// This value is only useful with access to the rest of the channels.
+    pub position: i32,
+
+    /// ID of the parent category for a channel (each parent category can contain up to 50 channels).
+    #[serde(rename = "parent_id")]
+    pub category_id: Option<ChannelId>,
+
+    /// Permission overwrites for members or whole roles.
+    pub permission_overwrites: Vec<PermissionOverwrite>,
+
+    /// Amount of seconds a user has to wait before creating another post (0-21600).
+    ///
+    /// Bots, as well as users with the permission `MANAGE_MESSAGES` or `MANAGE_CHANNEL`, are unaffected.
+    #[serde(rename = "rate_limit_per_user")]
+    pub user_rate_limit: Option<u16>,
+
+    /// If the channel is marked as Not Safe For Work
+    #[serde(default)]
+    pub nsfw: bool,
+
+    /// The guidelines of the forum, shown when creating a post (0-4096 characters).
+    pub topic: Option<String>,
+
+    /// The ID of the most recently created post (may not point to an existing or valid thread).
+    #[serde(rename = "last_message_id")]
+    pub last_message: Option<MessageId>,
+
+    /// Default duration, copied onto newly created posts, in minutes,
+    /// posts will stop showing in the channel list after the specified period of inactivity, can be set to: 60, 1440, 4320, 10080.
+    pub default_auto_archive_duration: Option<u16>,
+
+    /// The tags which can be applied to posts in the channel (up to 20).
+    #[serde(default)]
+    pub available_tags: Vec<ForumTag>,
+
+    /// The emoji shown on the add reaction button of posts.
+    pub default_reaction_emoji: Option<DefaultReaction>,
+
+    /// How posts are sorted by default, or `None` if no default has been set by a moderator.
+    pub default_sort_order: Option<SortOrder>,
+
+    /// How posts are displayed by default.
+    #[serde(default)]
+    pub default_forum_layout: ForumLayout,
+
+    /// The user rate limit copied onto newly created posts, applying to messages sent in them.
+    #[serde(rename = "default_thread_rate_limit_per_user")]
+    pub default_post_rate_limit: Option<u16>,
+}
+
+/// A tag which can be applied to posts in a forum channel.
+///
+/// https://discord.com/developers/docs/resources/channel#forum-tag-object
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ForumTag {
+    /// The ID of the tag.
+    pub id: TagId,
+    /// The name of the tag (0-20 characters).
+    pub name: String,
+    /// Whether the tag can only be applied by members with the `MANAGE_THREADS` permission.
+    #[serde(default)]
+    pub moderated: bool,
+    /// The ID of the tag's custom emoji.
+    pub emoji_id: Option<EmojiId>,
+    /// The unicode character of the tag's emoji.
+    pub emoji_name: Option<String>,
+}
+
+/// The emoji shown on the add reaction button of posts in a forum channel.
+///
+/// Exactly one of the fields is set.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DefaultReaction {
+    /// The ID of a custom emoji.
+    pub emoji_id: Option<EmojiId>,
+    /// The unicode character of a standard emoji.
+    pub emoji_name: Option<String>,
+}
+
+/// The order in which posts in a forum channel are listed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize_repr, Deserialize_repr)]
+#[repr(u8)]
+pub enum SortOrder {
+    /// Sort posts by their most recent activity
+    LatestActivity = 0,
+    /// Sort posts by when they were created, from most recent to oldest
+    CreationDate = 1,
+}
+
+/// How posts in a forum channel are displayed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize_repr, Deserialize_repr, Default)]
+#[repr(u8)]
+pub enum ForumLayout {
+    /// No default has been set by a moderator
+    #[default]
+    NotSet = 0,
+    /// Display posts as a list
+    ListView = 1,
+    /// Display posts as a collection of tiles
+    GalleryView = 2,
+}
+
 /// A thread within a discord server.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(untagged)]
@@ -1368,10 +2014,33 @@ pub enum ServerThread {
         thread: Thread,
 
         #[doc(hidden)]
+        #[serde(rename = "type")]
         _type: Eq<12>,
     },
 }
 
+impl ServerThread {
+    /// Access the thread, whatever kind of thread it is.
+    #[inline]
+    pub fn thread(&self) -> &Thread {
+        match self {
+            Self::AnnouncementThread { thread, .. } => thread,
+            Self::PublicThread { thread, .. } => thread,
+            Self::PrivateThread { thread, .. } => thread,
+        }
+    }
+
+    /// Mutably access the thread, whatever kind of thread it is.
+    #[inline]
+    pub fn thread_mut(&mut self) -> &mut Thread {
+        match self {
+            Self::AnnouncementThread { thread, .. } => thread,
+            Self::PublicThread { thread, .. } => thread,
+            Self::PrivateThread { thread, .. } => thread,
+        }
+    }
+}
+
 /// A thread within a channel.
 ///
 /// Threads can be thought of as temporary sub-channels inside an existing channel,
@@ -1419,6 +2088,26 @@ pub struct Thread {
     /// Additional data about a thread
     #[serde(rename = "thread_metadata")]
     pub thread_info: ThreadInfo,
+
+    /// The IDs of the tags applied to the thread, if it is a post in a forum channel.
+    #[serde(default)]
+    pub applied_tags: Vec<TagId>,
+}
+
+/// A user who has joined a thread.
+///
+/// https://discord.com/developers/docs/resources/channel#thread-member-object
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ThreadMember {
+    /// The ID of the thread, omitted in server creation events.
+    #[serde(rename = "id")]
+    pub thread_id: Option<ChannelId>,
+    /// The ID of the user, omitted in server creation events.
+    pub user_id: Option<UserId>,
+    /// When the user last joined the thread.
+    pub join_timestamp: DateTime<FixedOffset>,
+    /// Notification settings of the user for the thread.
+    pub flags: u64,
 }
 
 /// Additional info about a thread channel.
@@ -1616,6 +2305,7 @@ pub struct Sticker {
     pub tags: String,
 
     /// How the sticker image is stored.
+    #[serde(rename = "format_type")]
     pub format: StickerFormat,
 
     /// Where the sticker is from.
@@ -1684,6 +2374,67 @@ pub struct StickerItem {
     pub format: StickerFormat,
 }
 
+/// A pack of standard stickers, available to Nitro subscribers.
+///
+/// https://discord.com/developers/docs/resources/sticker#sticker-pack-object
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StickerPack {
+    /// ID of the sticker pack
+    pub id: StickerPackId,
+    /// The stickers in the pack
+    pub stickers: Vec<Sticker>,
+    /// Name of the sticker pack
+    pub name: String,
+    /// Description of the sticker pack
+    pub description: String,
+    /// ID of a sticker in the pack which is shown as the pack's icon
+    pub cover_sticker_id: Option<StickerId>,
+    /// ID of the sticker pack's banner image
+    pub banner_asset_id: Option<String>,
+}
+
+// Soundboard
+
+/// A sound which can be played in voice channels from the soundboard.
+///
+/// https://discord.com/developers/docs/resources/soundboard#soundboard-sound-object
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SoundboardSound {
+    /// ID of the sound
+    #[serde(rename = "sound_id")]
+    pub id: SoundId,
+    /// Name of the sound
+    pub name: String,
+    /// The volume the sound is played at, from 0 to 1
+    pub volume: f64,
+
+    /// ID of the sound's custom emoji
+    pub emoji_id: Option<EmojiId>,
+    /// The sound's standard emoji
+    pub emoji_name: Option<String>,
+
+    /// The server the sound is from, or `None` for the default sounds.
+    #[serde(rename = "guild_id")]
+    #[serde(default)]
+    pub server_id: Option<ServerId>,
+    /// Whether the sound can be used, may be false due to loss of Server Boosts
+    pub available: bool,
+
+    /// The user that uploaded the sound.
+    ///
+    /// Only included with the `MANAGE_EMOJIS` permission.
+    #[serde(rename = "user")]
+    #[serde(default)]
+    pub uploader: Option<User>,
+}
+
+impl SoundboardSound {
+    /// Returns the URL of the sound file.
+    pub fn url(&self) -> String {
+        format!(cdn_concat!("/soundboard-sounds/{}"), self.id)
+    }
+}
+
 // Application
 
 /// Information about the current application and the owner.
@@ -1700,6 +2451,259 @@ pub struct ApplicationInfo {
     pub owner: User,
 }
 
+// Application commands
+
+/// A command registered by an application, either globally or for a single server.
+///
+/// https://discord.com/developers/docs/interactions/application-commands#application-command-object
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApplicationCommand {
+    /// The ID of the command.
+    pub id: CommandId,
+    /// How the command is invoked.
+    #[serde(rename = "type", default)]
+    pub kind: CommandType,
+    /// The application the command belongs to.
+    pub application_id: ApplicationId,
+    /// The server the command is registered in, or `None` for global commands.
+    #[serde(rename = "guild_id", default)]
+    pub server_id: Option<ServerId>,
+
+    /// The name of the command, 1-32 characters.
+    pub name: String,
+    /// The name of the command as shown to users of other locales.
+    #[serde(default)]
+    pub name_localizations: Option<BTreeMap<Locale, String>>,
+    /// The description of the command, 1-100 characters, or empty for user and message commands.
+    pub description: String,
+    /// The description of the command as shown to users of other locales.
+    #[serde(default)]
+    pub description_localizations: Option<BTreeMap<Locale, String>>,
+
+    /// The parameters of the command, if it is a slash command.
+    #[serde(default)]
+    pub options: Vec<CommandOption>,
+
+    /// An ID which is changed every time the command is updated.
+    pub version: String,
+}
+
+/// How an application command is invoked.
+#[derive(Copy, Clone, Hash, Eq, PartialEq, Debug, Default, Serialize_repr, Deserialize_repr)]
+#[repr(u8)]
+pub enum CommandType {
+    /// A slash command, typed into the message box.
+    #[default]
+    ChatInput = 1,
+    /// A command in the context menu of a user.
+    User = 2,
+    /// A command in the context menu of a message.
+    Message = 3,
+}
+
+/// A parameter, subcommand or subcommand group of an application command.
+///
+/// https://discord.com/developers/docs/interactions/application-commands#application-command-object-application-command-option-structure
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommandOption {
+    /// The type of the option.
+    #[serde(rename = "type")]
+    pub kind: CommandOptionType,
+
+    /// The name of the option, 1-32 characters.
+    pub name: String,
+    /// The name of the option as shown to users of other locales.
+    #[serde(default)]
+    pub name_localizations: Option<BTreeMap<Locale, String>>,
+    /// The description of the option, 1-100 characters.
+    pub description: String,
+    /// The description of the option as shown to users of other locales.
+    #[serde(default)]
+    pub description_localizations: Option<BTreeMap<Locale, String>>,
+
+    /// Whether the option must be filled in.
+    #[serde(default)]
+    pub required: bool,
+    /// The values the user must pick from, if restricted.
+    #[serde(default)]
+    pub choices: Vec<CommandChoice>,
+    /// The nested options of a subcommand or subcommand group.
+    #[serde(default)]
+    pub options: Vec<CommandOption>,
+}
+
+/// A predefined value for a command option.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommandChoice {
+    /// The name of the choice as shown to users.
+    pub name: String,
+    /// The value of the choice, a string or number depending on the option type.
+    pub value: Value,
+}
+
+/// The type of an application command option.
+#[derive(Copy, Clone, Hash, Eq, PartialEq, Debug, Serialize_repr, Deserialize_repr)]
+#[repr(u8)]
+pub enum CommandOptionType {
+    SubCommand = 1,
+    SubCommandGroup = 2,
+    String = 3,
+    /// Any integer between -2^53 and 2^53
+    Integer = 4,
+    Boolean = 5,
+    User = 6,
+    /// Includes all channel types and categories
+    Channel = 7,
+    Role = 8,
+    /// Includes users and roles
+    Mentionable = 9,
+    /// Any double between -2^53 and 2^53
+    Number = 10,
+    Attachment = 11,
+}
+
+/// A language supported by the Discord client,
+/// used to localize application commands.
+#[derive(Copy, Clone, Hash, Eq, PartialEq, Ord, PartialOrd, Debug, Serialize, Deserialize)]
+pub enum Locale {
+    /// Indonesian
+    #[serde(rename = "id")]
+    Indonesian,
+    /// Danish
+    #[serde(rename = "da")]
+    Danish,
+    /// German
+    #[serde(rename = "de")]
+    German,
+    /// English, UK
+    #[serde(rename = "en-GB")]
+    EnglishUk,
+    /// English, US
+    #[serde(rename = "en-US")]
+    EnglishUs,
+    /// Spanish
+    #[serde(rename = "es-ES")]
+    Spanish,
+    /// Spanish, LATAM
+    #[serde(rename = "es-419")]
+    SpanishLatam,
+    /// French
+    #[serde(rename = "fr")]
+    French,
+    /// Croatian
+    #[serde(rename = "hr")]
+    Croatian,
+    /// Italian
+    #[serde(rename = "it")]
+    Italian,
+    /// Lithuanian
+    #[serde(rename = "lt")]
+    Lithuanian,
+    /// Hungarian
+    #[serde(rename = "hu")]
+    Hungarian,
+    /// Dutch
+    #[serde(rename = "nl")]
+    Dutch,
+    /// Norwegian
+    #[serde(rename = "no")]
+    Norwegian,
+    /// Polish
+    #[serde(rename = "pl")]
+    Polish,
+    /// Portuguese, Brazilian
+    #[serde(rename = "pt-BR")]
+    PortugueseBrazil,
+    /// Romanian
+    #[serde(rename = "ro")]
+    Romanian,
+    /// Finnish
+    #[serde(rename = "fi")]
+    Finnish,
+    /// Swedish
+    #[serde(rename = "sv-SE")]
+    Swedish,
+    /// Vietnamese
+    #[serde(rename = "vi")]
+    Vietnamese,
+    /// Turkish
+    #[serde(rename = "tr")]
+    Turkish,
+    /// Czech
+    #[serde(rename = "cs")]
+    Czech,
+    /// Greek
+    #[serde(rename = "el")]
+    Greek,
+    /// Bulgarian
+    #[serde(rename = "bg")]
+    Bulgarian,
+    /// Russian
+    #[serde(rename = "ru")]
+    Russian,
+    /// Ukrainian
+    #[serde(rename = "uk")]
+    Ukrainian,
+    /// Hindi
+    #[serde(rename = "hi")]
+    Hindi,
+    /// Thai
+    #[serde(rename = "th")]
+    Thai,
+    /// Chinese, China
+    #[serde(rename = "zh-CN")]
+    ChineseChina,
+    /// Japanese
+    #[serde(rename = "ja")]
+    Japanese,
+    /// Chinese, Taiwan
+    #[serde(rename = "zh-TW")]
+    ChineseTaiwan,
+    /// Korean
+    #[serde(rename = "ko")]
+    Korean,
+}
+
+impl Locale {
+    /// The locale code as used by the API, such as `en-US`.
+    pub fn code(&self) -> &'static str {
+        match *self {
+            Locale::Indonesian => "id",
+            Locale::Danish => "da",
+            Locale::German => "de",
+            Locale::EnglishUk => "en-GB",
+            Locale::EnglishUs => "en-US",
+            Locale::Spanish => "es-ES",
+            Locale::SpanishLatam => "es-419",
+            Locale::French => "fr",
+            Locale::Croatian => "hr",
+            Locale::Italian => "it",
+            Locale::Lithuanian => "lt",
+            Locale::Hungarian => "hu",
+            Locale::Dutch => "nl",
+            Locale::Norwegian => "no",
+            Locale::Polish => "pl",
+            Locale::PortugueseBrazil => "pt-BR",
+            Locale::Romanian => "ro",
+            Locale::Finnish => "fi",
+            Locale::Swedish => "sv-SE",
+            Locale::Vietnamese => "vi",
+            Locale::Turkish => "tr",
+            Locale::Czech => "cs",
+            Locale::Greek => "el",
+            Locale::Bulgarian => "bg",
+            Locale::Russian => "ru",
+            Locale::Ukrainian => "uk",
+            Locale::Hindi => "hi",
+            Locale::Thai => "th",
+            Locale::ChineseChina => "zh-CN",
+            Locale::Japanese => "ja",
+            Locale::ChineseTaiwan => "zh-TW",
+            Locale::Korean => "ko",
+        }
+    }
+}
+
 // User account settings
 
 /// User settings usually used to influence client behavior
@@ -1764,6 +2768,34 @@ pub struct Tutorial {
     pub indicators_confirmed: Vec<String>,
 }
 
+// Gateway
+
+/// The gateway URL and sharding information for a bot account.
+///
+/// https://discord.com/developers/docs/topics/gateway#get-gateway-bot
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GatewayBot {
+    /// The URL of the gateway to connect to.
+    pub url: String,
+    /// The recommended number of shards to connect with.
+    pub shards: u32,
+    /// How many more sessions can be started.
+    pub session_start_limit: SessionStartLimit,
+}
+
+/// The limits on starting new gateway sessions, which each identify counts towards.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionStartLimit {
+    /// The total number of session starts allowed in a day.
+    pub total: u64,
+    /// The number of session starts remaining.
+    pub remaining: u64,
+    /// The number of milliseconds until the limit resets.
+    pub reset_after: u64,
+    /// How many shards may identify at the same time, once every 5 seconds.
+    pub max_concurrency: u32,
+}
+
 // Discord Status
 
 /// Discord status maintenance message.
@@ -1997,7 +3029,7 @@ impl InviteServer {
 /// Trim an invite URL, so that the returned string only contains the code itself.
 ///
 /// ```
-/// # use discord_tokio::model::rest::trim_invite_code;
+/// # use discord_tokio::model::trim_invite_code;
 /// # fn main() {
 /// assert_eq!(trim_invite_code("http://discord.gg/gqwtqX3"), Some("gqwtqX3"));
 /// assert_eq!(trim_invite_code("https://discord.gg/gqwtqX3"), Some("gqwtqX3"));
@@ -2058,6 +3090,9 @@ pub struct ScheduledEvent {
     pub privacy: ScheduledEventPrivacy,
 
     /// How many users will be notified when the event goes live.
+    ///
+    /// Only sent when requested, and zero otherwise.
+    #[serde(default)]
     pub user_count: u64,
 
     /// What state is the event in.
@@ -2065,13 +3100,13 @@ pub struct ScheduledEvent {
 }
 
 impl ScheduledEvent {
-    /// Returns the formatted URL of the server's icon.
+    /// Returns the formatted URL of the event's cover image.
     ///
-    /// Returns None if the server does not have an icon.
+    /// Returns None if the event does not have a cover image.
     pub fn cover_image_url(&self) -> Option<String> {
         self.cover_image
             .as_ref()
-            .map(|icon| format!(cdn_concat!("guild-events/{}/{}.png"), self.id, icon))
+            .map(|image| format!(cdn_concat!("/guild-events/{}/{}.png"), self.id, image))
     }
 }
 
@@ -2144,8 +3179,70 @@ pub enum ScheduledEventStatus {
     Canceled,
 }
 
-/// An image serialized as base64.
+/// An image to be uploaded, serialized as a base64 data URI.
+///
+/// The media type is detected from the image's contents,
+/// and PNG, JPEG, GIF and WebP images are supported.
 #[derive(Debug, Clone)]
 pub struct Image {
+    /// The raw contents of the image file.
     pub data: Vec<u8>,
 }
+
+impl Image {
+    /// Create an image from the raw contents of an image file.
+    pub fn new(data: Vec<u8>) -> Image {
+        Image { data }
+    }
+
+    /// The media type of the image, detected from its magic number.
+    ///
+    /// Defaults to `image/jpeg` when the format is not recognized.
+    pub fn media_type(&self) -> &'static str {
+        match self.data.as_slice() {
+            [0x89, b'P', b'N', b'G', ..] => "image/png",
+            [b'G', b'I', b'F', b'8', ..] => "image/gif",
+            [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => "image/webp",
+            _ => "image/jpeg",
+        }
+    }
+
+    /// Encode the image as a data URI, the format Discord expects for uploads.
+    pub fn to_data_uri(&self) -> String {
+        format!(
+            "data:{};base64,{}",
+            self.media_type(),
+            base64::encode(&self.data)
+        )
+    }
+}
+
+impl Serialize for Image {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_data_uri())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use super::MessageId;
+
+    #[test]
+    fn snowflake_timestamps() {
+        // created at 2016-04-30 11:18:25.796 UTC
+        let id = MessageId(175928847299117063);
+        let created = Utc.timestamp_millis_opt(1462015105796).unwrap();
+        assert_eq!(id.creation_date(), created);
+
+        let boundary = MessageId::from_timestamp(created);
+        assert!(boundary <= id && boundary.creation_date() == created);
+        assert!(MessageId::from_timestamp(created + chrono::Duration::milliseconds(1)) > id);
+
+        assert_eq!(
+            MessageId::from_timestamp(Utc.timestamp_millis_opt(0).unwrap()),
+            MessageId(0)
+        );
+    }
+}
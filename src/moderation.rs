@@ -0,0 +1,181 @@
+//! Bulk moderation tasks built on top of the REST API.
+//!
+//! These helpers make one request per affected member, which are throttled
+//! by the client's rate limiting like any other request.
+
+use reqwest::StatusCode;
+use tracing::warn;
+
+use crate::model::{ServerId, UserId};
+use crate::{Cursor, Discord, Result, ServerExt};
+
+/// How many members are fetched per page.
+const MEMBERS_PAGE: u64 = 1000;
+
+/// The maximum length of a nickname, in characters.
+const MAX_NICKNAME: usize = 32;
+
+/// How nicknames are normalized by [`normalize_nicknames`].
+#[derive(Debug, Clone)]
+pub struct NicknamePolicy {
+    /// Replace lookalike "fancy" letters, like fullwidth, circled and mathematical letters,
+    /// with plain ones, and remove combining marks and invisible characters.
+    pub strip_fancy: bool,
+    /// Remove leading symbols, which are used to hoist members to the top of the member list.
+    pub strip_hoisting: bool,
+    /// The nickname given to members whose name has nothing left after normalizing.
+    pub fallback: String,
+    /// Only report the changes which would be made, without editing any nicknames.
+    pub dry_run: bool,
+}
+
+impl Default for NicknamePolicy {
+    fn default() -> Self {
+        NicknamePolicy {
+            strip_fancy: true,
+            strip_hoisting: true,
+            fallback: "nickname".to_owned(),
+            dry_run: false,
+        }
+    }
+}
+
+impl NicknamePolicy {
+    /// Normalize a name according to the policy.
+    pub fn normalize(&self, name: &str) -> String {
+        let mut normalized: String = if self.strip_fancy {
+            name.chars().filter_map(defancy).collect()
+        } else {
+            name.to_owned()
+        };
+        if self.strip_hoisting {
+            normalized = normalized
+                .trim_start_matches(|c: char| !c.is_alphanumeric())
+                .to_owned();
+        }
+
+        let normalized: String = normalized.trim().chars().take(MAX_NICKNAME).collect();
+        if normalized.is_empty() {
+            self.fallback.clone()
+        } else {
+            normalized
+        }
+    }
+}
+
+/// A nickname changed by [`normalize_nicknames`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NicknameChange {
+    /// The member whose nickname was changed.
+    pub user_id: UserId,
+    /// The name the member was displayed with.
+    pub before: String,
+    /// The name the member is displayed with now.
+    pub after: String,
+}
+
+/// Normalize the display name of every member of a server, returning the changes made.
+///
+/// Members are displayed by their nickname if they have one, or their username otherwise.
+/// When normalizing leaves a member with their username, their nickname is removed.
+///
+/// Members whose nickname can't be changed, like the server owner or members with a
+/// higher role than the current user, are skipped.
+pub async fn normalize_nicknames(
+    discord: &Discord,
+    server: ServerId,
+    policy: &NicknamePolicy,
+) -> Result<Vec<NicknameChange>> {
+    let mut changes = Vec::new();
+    let mut cursor = Cursor::Start;
+
+    loop {
+        let members = discord
+            .get_server_members(server, cursor, Some(MEMBERS_PAGE))
+            .await?;
+        let last = match members.last().and_then(|member| member.user.as_ref()) {
+            Some(user) => user.id,
+            None => break,
+        };
+
+        for member in &members {
+            let Some(user) = &member.user else { continue };
+            let before = member.display_name().unwrap_or(&user.name);
+            let after = policy.normalize(before);
+            if after == before {
+                continue;
+            }
+
+            if !policy.dry_run {
+                let nick = if after == user.name { "" } else { &after };
+                match discord.edit_nickname(server, user.id, nick).await {
+                    Ok(()) => {}
+                    Err(err) if err.status() == Some(StatusCode::FORBIDDEN) => {
+                        warn!("Not allowed to change the nickname of {:?}", user.id);
+                        continue;
+                    }
+                    Err(err) => return Err(err),
+                }
+            }
+            changes.push(NicknameChange {
+                user_id: user.id,
+                before: before.to_owned(),
+                after,
+            });
+        }
+
+        if (members.len() as u64) < MEMBERS_PAGE {
+            break;
+        }
+        cursor = Cursor::After(last);
+    }
+
+    Ok(changes)
+}
+
+/// Replace a lookalike letter or digit with its plain form,
+/// or remove the character if it is a combining mark or invisible.
+fn defancy(c: char) -> Option<char> {
+    let code = c as u32;
+    let offset = |start: u32, base: char| char::from_u32(base as u32 + code - start);
+
+    match code {
+        // combining marks, as used to make "zalgo" text
+        0x0300..=0x036F | 0x1AB0..=0x1AFF | 0x1DC0..=0x1DFF | 0x20D0..=0x20FF => None,
+        // zero-width and other invisible formatting characters
+        0x200B..=0x200F | 0x2060..=0x2064 | 0xFEFF => None,
+        // fullwidth forms of ASCII
+        0xFF01..=0xFF5E => offset(0xFF01, '!'),
+        // circled letters
+        0x24B6..=0x24CF => offset(0x24B6, 'A'),
+        0x24D0..=0x24E9 => offset(0x24D0, 'a'),
+        // parenthesized, squared and negative squared capital letters
+        0x1F110..=0x1F129 => offset(0x1F110, 'A'),
+        0x1F130..=0x1F149 => offset(0x1F130, 'A'),
+        0x1F170..=0x1F189 => offset(0x1F170, 'A'),
+        // mathematical letters, in styles of 26 capital then 26 small letters
+        0x1D400..=0x1D6A3 => match (code - 0x1D400) % 52 {
+            letter @ 0..=25 => char::from_u32('A' as u32 + letter),
+            letter => char::from_u32('a' as u32 + letter - 26),
+        },
+        // mathematical digits, in styles of 10
+        0x1D7CE..=0x1D7FF => char::from_u32('0' as u32 + (code - 0x1D7CE) % 10),
+        _ => Some(c),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::NicknamePolicy;
+
+    #[test]
+    fn normalizes_fancy_and_hoisted_names() {
+        let policy = NicknamePolicy::default();
+
+        assert_eq!(policy.normalize("!!! 𝐟𝐚𝐧𝐜𝐲 Ｎａｍｅ"), "fancy Name");
+        assert_eq!(policy.normalize("ⓑⓞⓑ\u{200B}𝟏𝟐"), "bob12");
+        assert_eq!(policy.normalize("z\u{0336}a\u{0301}lgo"), "zalgo");
+        assert_eq!(policy.normalize("..."), "nickname");
+        assert_eq!(policy.normalize("plain"), "plain");
+    }
+}
@@ -0,0 +1,365 @@
+//! Helpers for reasoning about permissions and channel permission overwrites.
+//!
+//! Channels inside a category can be "synced" with it, in which case their
+//! permission overwrites are an exact copy of the category's. These helpers
+//! compute what syncing would do, without making any requests.
+//!
+//! The effective permissions of a member can also be calculated from data fetched
+//! over REST with [`calculate`], for bots which don't keep a `State`.
+
+use tracing::warn;
+
+use crate::model::{
+    ChannelId, Member, PermissionOverwrite, Permissions, Role, Server, ServerChannel, ServerId,
+    UserId,
+};
+
+/// A difference between a channel's permission overwrites and those of its category.
+#[derive(Debug, Clone)]
+pub enum OverwriteDiff {
+    /// The channel has an overwrite for a role or member which the category does not.
+    Added(PermissionOverwrite),
+    /// The category has an overwrite for a role or member which the channel does not.
+    Removed(PermissionOverwrite),
+    /// Both have an overwrite for the same role or member, but they differ.
+    Changed {
+        /// The overwrite on the category.
+        category: PermissionOverwrite,
+        /// The overwrite on the channel.
+        channel: PermissionOverwrite,
+    },
+}
+
+/// Compute what a channel's permission overwrites would be if it was synced with a category.
+///
+/// Returns `None` if `category` is not a category, or `channel` is a thread,
+/// which has no overwrites of its own.
+pub fn effective_overwrites(
+    category: &ServerChannel,
+    channel: &ServerChannel,
+) -> Option<Vec<PermissionOverwrite>> {
+    match category {
+        ServerChannel::Category { category, .. } => {
+            channel.permission_overwrites()?;
+            Some(category.permission_overwrites.clone())
+        }
+        _ => None,
+    }
+}
+
+/// List the ways in which a channel's permission overwrites differ from its category's.
+///
+/// An empty list means the channel is synced with the category.
+/// Returns `None` under the same conditions as [`effective_overwrites`].
+pub fn diff_overwrites(
+    category: &ServerChannel,
+    channel: &ServerChannel,
+) -> Option<Vec<OverwriteDiff>> {
+    let parent = effective_overwrites(category, channel)?;
+    let own = channel.permission_overwrites()?;
+    Some(diff(&parent, own))
+}
+
+/// Calculate the effective permissions of a member in a channel of a server.
+///
+/// `channels` are the server's channels, such as those returned by
+/// `ServerExt::get_server_channels`, among which threads find the parent channel
+/// whose overwrites they inherit. This is the same calculation as
+/// `State::permissions_for`, for servers, members and channels fetched over REST.
+///
+/// A member without a `user` is only given the overwrites of their roles.
+pub fn calculate(
+    server: &Server,
+    channels: &[ServerChannel],
+    channel: ChannelId,
+    member: &Member,
+) -> Permissions {
+    let user = member.user.as_ref().map_or(UserId(0), |user| user.id);
+    compute(
+        server.id,
+        server.owner_id,
+        &server.roles,
+        channels,
+        channel,
+        user,
+        Some(member),
+    )
+}
+
+/// Calculate the effective permissions of a user in a channel, from the parts of a server.
+///
+/// A user who is not a member, given as `None`, only has the permissions of `@everyone`.
+pub(crate) fn compute(
+    server: ServerId,
+    owner: UserId,
+    roles: &[Role],
+    channels: &[ServerChannel],
+    channel: ChannelId,
+    user: UserId,
+    member: Option<&Member>,
+) -> Permissions {
+    // Owner has all permissions
+    if user == owner {
+        return Permissions::all();
+    }
+
+    let everyone = match roles.iter().find(|r| r.id == server.everyone()) {
+        Some(r) => r,
+        None => {
+            warn!("Missing @everyone role in permissions lookup on {}", server);
+            return Permissions::empty();
+        }
+    };
+
+    let member = match member {
+        Some(member) => member,
+        None => return everyone.permissions,
+    };
+
+    // OR together all the member's roles
+    let mut permissions = everyone.permissions;
+    for &role in &member.roles {
+        if let Some(role) = roles.iter().find(|r| r.id == role) {
+            permissions |= role.permissions;
+        } else {
+            warn!(
+                "perms: {:?} on {:?} has non-existent role {:?}",
+                user, server, role
+            );
+        }
+    }
+
+    // Administrators have all permissions in any channel
+    if permissions.contains(Permissions::ADMINISTRATOR) {
+        return Permissions::all();
+    }
+
+    let mut strip_voice_perms = false;
+    if let Some(found) = channels.iter().find(|c| c.id() == &channel) {
+        strip_voice_perms = found.contains_text();
+
+        // threads inherit the overwrites of their parent channel
+        let overwrites = match found {
+            ServerChannel::PublicThread { thread, .. }
+            | ServerChannel::PrivateThread { thread, .. }
+            | ServerChannel::AnnouncementThread { thread, .. } => {
+                let parent = channels.iter().find(|c| c.id() == &thread.parent_id);
+                if parent.is_none() {
+                    warn!(
+                        "guild with id {:?} does not contain channel {:?}, but it is referenced as thread {:?}'s parent",
+                        server, thread.parent_id, thread.id
+                    );
+                }
+                parent.and_then(ServerChannel::permission_overwrites)
+            }
+            _ => found.permission_overwrites(),
+        };
+        permissions = apply_overwrites(
+            permissions,
+            overwrites.unwrap_or_default(),
+            server,
+            user,
+            member,
+        );
+    } else {
+        warn!("guild with id {:?} does not contain channel ID {:?}, but it is referenced in role overwrites", server, channel);
+    }
+
+    // Default channel is always readable
+    if channel.0 == server.0 {
+        permissions |= Permissions::READ_MESSAGES;
+    }
+
+    // calculate implicit permissions
+
+    // No SEND_MESSAGES => no message-sending-related actions
+    if !permissions.contains(Permissions::SEND_MESSAGES) {
+        permissions &= !(Permissions::SEND_TTS_MESSAGES
+            | Permissions::MENTION_EVERYONE
+            | Permissions::EMBED_LINKS
+            | Permissions::ATTACH_FILES);
+    }
+
+    // No READ_MESSAGES => no channel actions
+    if !permissions.contains(Permissions::READ_MESSAGES) {
+        permissions &= Permissions::KICK_MEMBERS
+            | Permissions::BAN_MEMBERS
+            | Permissions::ADMINISTRATOR
+            | Permissions::MANAGE_SERVER
+            | Permissions::CHANGE_NICKNAMES
+            | Permissions::MANAGE_NICKNAMES;
+    }
+
+    // Text channel => no voice actions
+    if strip_voice_perms {
+        permissions &= !(Permissions::VOICE_CONNECT
+            | Permissions::VOICE_SPEAK
+            | Permissions::VOICE_MUTE_MEMBERS
+            | Permissions::VOICE_DEAFEN_MEMBERS
+            | Permissions::VOICE_MOVE_MEMBERS
+            | Permissions::VOICE_USE_VOICE_ACTIVITY);
+    }
+    permissions
+}
+
+/// Apply a channel's role overwrites, then its member overwrites, each denying then allowing.
+fn apply_overwrites(
+    mut permissions: Permissions,
+    overwrites: &[PermissionOverwrite],
+    server: ServerId,
+    user: UserId,
+    member: &Member,
+) -> Permissions {
+    for overwrite in overwrites {
+        if let PermissionOverwrite::Role {
+            id, allow, deny, ..
+        } = overwrite
+        {
+            // if the member has this role, or it is the @everyone role
+            if member.roles.contains(id) || id.0 == server.0 {
+                permissions = (permissions & !*deny) | *allow;
+            }
+        }
+    }
+    for overwrite in overwrites {
+        if let PermissionOverwrite::Member {
+            id, allow, deny, ..
+        } = overwrite
+        {
+            if &user == id {
+                permissions = (permissions & !*deny) | *allow;
+            }
+        }
+    }
+    permissions
+}
+
+fn diff(parent: &[PermissionOverwrite], own: &[PermissionOverwrite]) -> Vec<OverwriteDiff> {
+    let mut diffs = Vec::new();
+
+    for overwrite in own {
+        match parent.iter().find(|other| other.id() == overwrite.id()) {
+            None => diffs.push(OverwriteDiff::Added(overwrite.clone())),
+            Some(other) => {
+                if other.allow() != overwrite.allow() || other.deny() != overwrite.deny() {
+                    diffs.push(OverwriteDiff::Changed {
+                        category: other.clone(),
+                        channel: overwrite.clone(),
+                    });
+                }
+            }
+        }
+    }
+    for overwrite in parent {
+        if !own.iter().any(|other| other.id() == overwrite.id()) {
+            diffs.push(OverwriteDiff::Removed(overwrite.clone()));
+        }
+    }
+
+    diffs
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{compute, diff, OverwriteDiff};
+    use crate::model::{
+        ChannelId, Member, PermissionOverwrite, Permissions, Role, RoleId, ServerChannel, ServerId,
+        UserId,
+    };
+    use crate::serial::Eq;
+
+    fn role(id: u64, allow: Permissions) -> PermissionOverwrite {
+        PermissionOverwrite::Role {
+            id: RoleId(id),
+            allow,
+            deny: Permissions::empty(),
+            _type: Eq,
+        }
+    }
+
+    #[test]
+    fn diffs_against_category() {
+        let member = PermissionOverwrite::Member {
+            id: UserId(3),
+            allow: Permissions::empty(),
+            deny: Permissions::SEND_MESSAGES,
+            _type: Eq,
+        };
+        let parent = [
+            role(1, Permissions::READ_MESSAGES),
+            role(2, Permissions::empty()),
+        ];
+        let own = [role(1, Permissions::SEND_MESSAGES), member];
+
+        let diffs = diff(&parent, &own);
+        assert!(matches!(
+            &diffs[..],
+            [
+                OverwriteDiff::Changed { .. },
+                OverwriteDiff::Added(PermissionOverwrite::Member { .. }),
+                OverwriteDiff::Removed(PermissionOverwrite::Role { id: RoleId(2), .. }),
+            ]
+        ));
+        assert!(diff(&parent, &parent).is_empty());
+    }
+
+    fn server_role(id: u64, permissions: Permissions) -> Role {
+        serde_json::from_value(json!({
+            "id": id.to_string(),
+            "name": "role",
+            "color": 0,
+            "icon": null,
+            "unicode_emoji": null,
+            "hoist": false,
+            "managed": false,
+            "position": 0,
+            "permissions": permissions,
+            "flags": 0,
+        }))
+        .unwrap()
+    }
+
+    fn member(id: u64, roles: &[u64]) -> Member {
+        serde_json::from_value(crate::fixtures::member(id, roles)).unwrap()
+    }
+
+    #[test]
+    fn applies_role_then_member_overwrites() {
+        let roles = [
+            server_role(100, Permissions::READ_MESSAGES | Permissions::SEND_MESSAGES),
+            server_role(2, Permissions::MANAGE_MESSAGES),
+        ];
+        let channels: [ServerChannel; 1] = [serde_json::from_value(json!({
+            "type": 0,
+            "id": "1",
+            "guild_id": "100",
+            "name": "announcements",
+            "position": 0,
+            "parent_id": null,
+            "permission_overwrites": [
+                {"id": "100", "type": 0, "allow": Permissions::empty(), "deny": Permissions::SEND_MESSAGES},
+                {"id": "7", "type": 1, "allow": Permissions::SEND_MESSAGES, "deny": Permissions::empty()},
+            ],
+        }))
+        .unwrap()];
+
+        let perms = |user: u64, roles_of: &[u64]| {
+            compute(
+                ServerId(100),
+                UserId(1),
+                &roles,
+                &channels,
+                ChannelId(1),
+                UserId(user),
+                Some(&member(user, roles_of)),
+            )
+        };
+        assert_eq!(
+            perms(7, &[2]),
+            Permissions::READ_MESSAGES | Permissions::SEND_MESSAGES | Permissions::MANAGE_MESSAGES
+        );
+        assert_eq!(perms(8, &[]), Permissions::READ_MESSAGES);
+    }
+}
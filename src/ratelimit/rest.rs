@@ -1,19 +1,38 @@
 //! Tracking for rate-limits on discord REST clients.
 
-use std::{collections::HashMap, sync::Mutex};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use chrono::{DateTime, Duration, NaiveDateTime, TimeZone, Utc};
 use rand::{distributions::Distribution, thread_rng};
-use reqwest::Response;
+use reqwest::header::HeaderMap;
+use reqwest::{Method, Response};
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
 
 use crate::Error;
 
+/// How many per-route counters are kept before those whose window has ended are dropped.
+const MAX_COUNTERS: usize = 1024;
+
 /// Tracking for rate-limits on discord REST clients.
 /// See the [module level documentation][self] for more.
+///
+/// Discord groups routes into buckets, which it names by a hash in the `X-RateLimit-Bucket`
+/// header, and limits each bucket separately for every value of the route's major parameter,
+/// which is the channel, server or webhook the route is under. Counters are kept per bucket
+/// and major parameter, and per route until the route's bucket is known.
 #[derive(Debug, Default)]
 pub struct RateLimits {
     global: Mutex<GlobalLimitCounter>,
+    /// The bucket learned for each route, by the route with its IDs left out.
+    buckets: Mutex<HashMap<String, Bucket>>,
     routes: Mutex<HashMap<String, LimitCounter>>,
+    /// The requests waiting on each counter, in the order they were made.
+    queues: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
 }
 
 impl RateLimits {
@@ -22,82 +41,121 @@ impl RateLimits {
     ///
     /// The url parameter is the path part of the discord query URL.
     /// For example, `/channels/012345678910/messages` is a query URL.
+    /// Discord limits each method of a route separately, so the method is needed too.
     ///
     /// This function optimistically increments all affected counters.
+    /// The returned [`Reservation`] rolls those increments back when dropped,
+    /// unless the request was sent or it is completed once a response has been received.
     #[inline]
-    pub fn check(&self, url: &str) -> Option<tokio::time::Sleep> {
+    pub fn check(
+        &self,
+        method: &Method,
+        url: &str,
+    ) -> (Reservation<'_>, Option<tokio::time::Sleep>) {
+        let (key, limit) = self.counter_key(method, url);
+        let mut reservation = Reservation {
+            limits: self,
+            key,
+            route: false,
+            sent: false,
+            completed: false,
+        };
+
         let mut global = self.global.lock().expect("poisoned global counter");
         match global.increment_and_check() {
             Ok(_) => {}
-            Err(sleep_until) => {
-                let now = Utc::now();
-                let time_between = sleep_until - now;
-
-                let random_offset = Duration::milliseconds(
-                    rand::distributions::Uniform::new(0, 10).sample(&mut thread_rng()),
-                );
-                let time_to_sleep = time_between + random_offset;
-
-                return Some(tokio::time::sleep(time_to_sleep.to_std().unwrap()));
-            }
+            Err(sleep_until) => return (reservation, Some(sleep_with_offset(sleep_until))),
         }
 
         drop(global);
 
         let mut routes = self.routes.lock().expect("poisoned per-route counters");
         let route = routes
-            .entry(url.to_string())
-            .or_insert(LimitCounter::default());
+            .entry(reservation.key.clone())
+            .or_insert_with(|| LimitCounter::new(limit));
+        reservation.route = true;
 
         match route.decrement_and_check() {
-            Ok(_) => None,
-            Err(sleep_until) => {
-                let now = Utc::now();
-                let time_between = sleep_until - now;
+            Ok(_) => (reservation, None),
+            Err(sleep_until) => (reservation, Some(sleep_with_offset(sleep_until))),
+        }
+    }
 
-                let random_offset = Duration::milliseconds(
-                    rand::distributions::Uniform::new(0, 10).sample(&mut thread_rng()),
-                );
-                let time_to_sleep = time_between + random_offset;
+    /// Wait until a request can be sent without hitting the rate limits, and reserve it.
+    ///
+    /// Requests which share a counter wait in a queue, so they are sent in the order
+    /// they were made, and only the one at the front sleeps until the limit is reset,
+    /// instead of every waiting request waking at once and racing for it.
+    /// Dropping the future gives up the request's place in the queue.
+    pub async fn acquire(&self, method: &Method, url: &str) -> Reservation<'_> {
+        let queue = self.queue(method, url);
+        let _turn = queue.lock().await;
+
+        loop {
+            let (reservation, sleep) = self.check(method, url);
+            let Some(sleep) = sleep else {
+                return reservation;
+            };
+            // give the counters back while sleeping, so they are checked again afterwards
+            drop(reservation);
+            sleep.await;
+        }
+    }
 
-                Some(tokio::time::sleep(time_to_sleep.to_std().unwrap()))
-            }
+    /// The queue of requests waiting on the counter for a URL.
+    fn queue(&self, method: &Method, url: &str) -> Arc<tokio::sync::Mutex<()>> {
+        let (key, _) = self.counter_key(method, url);
+        let mut queues = self.queues.lock().expect("poisoned request queues");
+        if queues.len() >= MAX_COUNTERS {
+            // queues nobody is waiting in are recreated when needed
+            queues.retain(|_, queue| Arc::strong_count(queue) > 1);
         }
+        queues.entry(key).or_default().clone()
     }
 
     /// Update the limit counters held in `self` from the headers in a given response.
     /// This method exists to correct any false optimistic updates set in the `check` method.
     #[inline]
-    pub fn update(&self, url: &str, response: &Response) -> Result<(), Error> {
-        let limit: u32 = response
-            .headers()
+    pub fn update(&self, method: &Method, url: &str, response: &Response) -> Result<(), Error> {
+        self.update_from_headers(method, url, response.headers())
+    }
+
+    /// Update the limit counters held in `self` from the rate limit headers of a response.
+    pub fn update_from_headers(
+        &self,
+        method: &Method,
+        url: &str,
+        headers: &HeaderMap,
+    ) -> Result<(), Error> {
+        let limit: u32 = headers
             .get("X-RateLimit-Limit")
             .ok_or(Error::Other("missing X-RateLimit-Limit header"))?
             .to_str()
             .map_err(|_| Error::Other("non-string X-RateLimit-Limit header"))?
             .parse()
             .map_err(|_| Error::Other("non-number X-RateLimit-Limit header"))?;
-        let remaining: u32 = response
-            .headers()
+        let remaining: u32 = headers
             .get("X-RateLimit-Remaining")
             .ok_or(Error::Other("missing X-RateLimit-Remaining header"))?
             .to_str()
             .map_err(|_| Error::Other("non-string X-RateLimit-Remaining header"))?
             .parse()
             .map_err(|_| Error::Other("non-number X-RateLimit-Remaining header"))?;
-        let reset: u64 = response
-            .headers()
-            .get("X-RateLimit-Reset")
-            .ok_or(Error::Other("missing X-RateLimit-Reset header"))?
+        // the relative reset is used rather than `X-RateLimit-Reset`,
+        // so that the local clock being off doesn't matter
+        let reset_after: f64 = headers
+            .get("X-RateLimit-Reset-After")
+            .ok_or(Error::Other("missing X-RateLimit-Reset-After header"))?
             .to_str()
-            .map_err(|_| Error::Other("non-string X-RateLimit-Reset header"))?
+            .map_err(|_| Error::Other("non-string X-RateLimit-Reset-After header"))?
             .parse()
-            .map_err(|_| Error::Other("non-number X-RateLimit-Reset header"))?;
+            .map_err(|_| Error::Other("non-number X-RateLimit-Reset-After header"))?;
+        let reset_after = Duration::try_from_secs_f64(reset_after)
+            .map_err(|_| Error::Other("invalid X-RateLimit-Reset-After header"))?;
 
-        let reset =
-            Utc.from_utc_datetime(&NaiveDateTime::from_timestamp_opt(reset as i64, 0).unwrap());
+        let reset = Instant::now() + reset_after;
 
-        let is_global_limit: bool = response.headers().get("X-RateLimit-Global").is_some();
+        let is_global_limit: bool = headers.get("X-RateLimit-Global").is_some();
         if is_global_limit {
             let mut global = self.global.lock().expect("poisoned global counter");
             global.requests_made = global.limit;
@@ -108,10 +166,29 @@ impl RateLimits {
             return Ok(());
         }
 
+        if let Some(hash) = headers.get("X-RateLimit-Bucket") {
+            let hash = hash
+                .to_str()
+                .map_err(|_| Error::Other("non-string X-RateLimit-Bucket header"))?;
+            let mut buckets = self.buckets.lock().expect("poisoned route buckets");
+            buckets.insert(
+                route_template(method, url),
+                Bucket {
+                    hash: hash.to_owned(),
+                    limit,
+                },
+            );
+        }
+
+        let (key, _) = self.counter_key(method, url);
         let mut routes = self.routes.lock().expect("poisoned per-route counters");
+        if routes.len() >= MAX_COUNTERS {
+            let now = Instant::now();
+            routes.retain(|_, route| route.window > now);
+        }
         let route = routes
-            .entry(url.to_string())
-            .or_insert(LimitCounter::default());
+            .entry(key)
+            .or_insert_with(|| LimitCounter::new(Some(limit)));
 
         route.limit = limit;
         route.remaining = remaining as i32;
@@ -119,10 +196,243 @@ impl RateLimits {
 
         Ok(())
     }
+
+    /// Hold back every request until `delay` has passed, after Discord reported that the
+    /// global rate limit was hit.
+    pub fn limit_globally(&self, delay: Duration) {
+        let mut global = self.global.lock().expect("poisoned global counter");
+        global.requests_made = global.limit;
+        // requests are held back until a second after counting started
+        let until = Instant::now() + delay;
+        global.started_counting = until.checked_sub(Duration::from_secs(1)).unwrap_or(until);
+    }
+
+    /// Serialize the limits learned for each route, to be loaded by a later run with `load`.
+    ///
+    /// Limits are learned from responses, so a new client assumes every route allows
+    /// a few requests until it has been told otherwise. Short-lived programs can save
+    /// and load the limits to avoid tripping the tighter ones on every run.
+    pub fn save(&self) -> Result<Vec<u8>, Error> {
+        let now = Instant::now();
+        let unix_now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+
+        let buckets = self.buckets.lock().expect("poisoned route buckets").clone();
+        let routes = self.routes.lock().expect("poisoned per-route counters");
+        let routes = routes
+            .iter()
+            .map(|(key, route)| {
+                let reset = unix_now + route.window.saturating_duration_since(now);
+                let saved = SavedRoute {
+                    limit: route.limit,
+                    remaining: route.remaining,
+                    reset_ms: reset.as_millis() as u64,
+                };
+                (key.clone(), saved)
+            })
+            .collect();
+        Ok(serde_json::to_vec(&SavedLimits { buckets, routes })?)
+    }
+
+    /// Load limits saved with `save`, replacing those learned for the same routes.
+    ///
+    /// Windows which have ended since the limits were saved start out reset.
+    pub fn load(&self, bytes: &[u8]) -> Result<(), Error> {
+        let saved: SavedLimits = serde_json::from_slice(bytes)?;
+
+        let now = Instant::now();
+        let unix_now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+
+        let mut buckets = self.buckets.lock().expect("poisoned route buckets");
+        buckets.extend(saved.buckets);
+        drop(buckets);
+
+        let mut routes = self.routes.lock().expect("poisoned per-route counters");
+        for (key, route) in saved.routes {
+            let reset = Duration::from_millis(route.reset_ms);
+            let counter = match reset.checked_sub(unix_now) {
+                Some(left) => LimitCounter {
+                    limit: route.limit,
+                    remaining: route.remaining,
+                    window: now + left,
+                },
+                None => LimitCounter {
+                    limit: route.limit,
+                    remaining: route.limit as i32,
+                    window: now,
+                },
+            };
+            routes.insert(key, counter);
+        }
+        Ok(())
+    }
+
+    /// Find the key of the counter limiting a URL, by its bucket if known or else its route,
+    /// along with the limit of the bucket if known.
+    fn counter_key(&self, method: &Method, url: &str) -> (String, Option<u32>) {
+        let template = route_template(method, url);
+        let major = major_parameter(url);
+        let buckets = self.buckets.lock().expect("poisoned route buckets");
+        match buckets.get(&template) {
+            Some(bucket) => (format!("{}:{}", bucket.hash, major), Some(bucket.limit)),
+            None => (format!("{}:{}", template, major), None),
+        }
+    }
+}
+
+/// A rate limit bucket, as learned from the headers of a response.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Bucket {
+    /// The hash Discord names the bucket by.
+    hash: String,
+    /// How many requests the bucket allows per window.
+    limit: u32,
+}
+
+/// Leave the IDs and tokens out of a URL, so that every URL of the same route is the same,
+/// and add the method, which routes are limited by too, e.g. `GET /channels/{id}/messages/{id}`.
+///
+/// Latencies are reported by the same routes, so they line up with the rate limits.
+pub(crate) fn route_template(method: &Method, url: &str) -> String {
+    let path = url.split('?').next().unwrap_or(url);
+    let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+
+    let mut template = format!("{method} ");
+    for (i, segment) in segments.iter().enumerate() {
+        template.push('/');
+        let tokened = matches!(segments[0], "webhooks" | "interactions");
+        if i > 0 && segments[i - 1] == "reactions" {
+            template.push_str("{emoji}");
+        } else if i == 2 && tokened {
+            template.push_str("{token}");
+        } else if segment.bytes().all(|b| b.is_ascii_digit()) {
+            template.push_str("{id}");
+        } else {
+            template.push_str(segment);
+        }
+    }
+    template
+}
+
+/// Find the major parameter of a URL, which is the channel, server or webhook it is under,
+/// e.g. `channels/123`, or an empty string for routes without one.
+///
+/// Webhook tokens are hashed, so that they aren't kept in keys which may be logged or saved.
+fn major_parameter(url: &str) -> String {
+    let path = url.split('?').next().unwrap_or(url);
+    let mut segments = path.split('/').filter(|s| !s.is_empty());
+    match (segments.next(), segments.next()) {
+        (Some(kind @ ("channels" | "guilds")), Some(id)) => format!("{kind}/{id}"),
+        (Some("webhooks"), Some(id)) => match segments.next() {
+            Some(token) => format!("webhooks/{id}/{:016x}", hash_token(token)),
+            None => format!("webhooks/{id}"),
+        },
+        _ => String::new(),
+    }
+}
+
+/// Hash a token with 64-bit FNV-1a, which unlike `DefaultHasher` is the same in every run,
+/// so that saved limits still apply.
+fn hash_token(token: &str) -> u64 {
+    token.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// The limits saved by [`RateLimits::save`].
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct SavedLimits {
+    buckets: HashMap<String, Bucket>,
+    routes: HashMap<String, SavedRoute>,
+}
+
+/// A route's limit as saved by [`RateLimits::save`].
+#[derive(Debug, Serialize, Deserialize)]
+struct SavedRoute {
+    limit: u32,
+    remaining: i32,
+    /// When the window ends, in milliseconds since the Unix epoch.
+    reset_ms: u64,
+}
+
+/// Sleep until the given instant, plus a small random offset
+/// so that requests waiting on the same limit don't all wake at once.
+fn sleep_with_offset(sleep_until: Instant) -> tokio::time::Sleep {
+    let random_offset =
+        Duration::from_millis(rand::distributions::Uniform::new(0, 10).sample(&mut thread_rng()));
+    tokio::time::sleep_until(sleep_until + random_offset)
+}
+
+/// The counters optimistically incremented by [`RateLimits::check`] for a single request.
+///
+/// Dropping the reservation gives the counters back, unless it was marked as
+/// [`sent`](Reservation::mark_sent) first. Once the request is on its way it may be counted
+/// by Discord, even if the request future is then dropped or the request times out, so only
+/// requests which provably never reached Discord are rolled back by
+/// [`settle`](Reservation::settle).
+#[derive(Debug)]
+pub struct Reservation<'a> {
+    limits: &'a RateLimits,
+    /// The key of the per-route counter.
+    key: String,
+    /// Whether the per-route counter was decremented, in addition to the global one.
+    route: bool,
+    /// Whether the request may have been sent, so the counters are kept when dropped.
+    sent: bool,
+    completed: bool,
+}
+
+impl Reservation<'_> {
+    /// Note that the request is about to be sent, so the counters are kept from then on,
+    /// even if the request future is dropped before a response is received.
+    pub fn mark_sent(&mut self) {
+        self.sent = true;
+    }
+
+    /// Keep the counters as they are, since the request reached Discord.
+    pub fn complete(mut self) {
+        self.completed = true;
+    }
+
+    /// Complete the reservation, or roll it back if sending the request failed before
+    /// a connection to Discord was made.
+    pub fn settle<T>(mut self, sent: &reqwest::Result<T>) {
+        match sent {
+            // rolled back once dropped, as the request never reached Discord
+            Err(err) if err.is_connect() => self.sent = false,
+            _ => self.complete(),
+        }
+    }
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        if self.completed || self.sent {
+            return;
+        }
+
+        let mut global = self.limits.global.lock().expect("poisoned global counter");
+        global.requests_made = global.requests_made.saturating_sub(1);
+        drop(global);
+
+        if self.route {
+            let mut routes = self
+                .limits
+                .routes
+                .lock()
+                .expect("poisoned per-route counters");
+            if let Some(route) = routes.get_mut(&self.key) {
+                route.remaining = (route.remaining + 1).min(route.limit as i32);
+            }
+        }
+    }
 }
 
 /// A per query path rate limit counter.
-/// This counter implements an inefficient reset timer due to needing to consume unix timestamps.
 #[derive(Debug)]
 struct LimitCounter {
     /// The absolute limit on how many requests can be sent.
@@ -132,49 +442,48 @@ struct LimitCounter {
     /// This value is affected by the value in the `window` field.
     remaining: i32,
     /// When the limit held in this counter will be cleared by discord.
-    window: DateTime<Utc>,
+    window: Instant,
 }
 
 impl LimitCounter {
-    pub fn decrement_and_check(&mut self) -> Result<(), DateTime<Utc>> {
+    /// Start counting for a limit, which is assumed to be 5 if it isn't known yet.
+    fn new(limit: Option<u32>) -> Self {
+        // The initial limit is assumed to be 5,
+        // so that there is ample space for requests to fetch the real limit.
+        let limit = limit.unwrap_or(5);
+        Self {
+            limit,
+            remaining: limit as i32,
+            // The limit is said to have already expired because idk
+            window: Instant::now(),
+        }
+    }
+
+    pub fn decrement_and_check(&mut self) -> Result<(), Instant> {
         self.remaining -= 1;
 
         if self.remaining > 0 {
             return Ok(());
         }
 
-        let now = Utc::now();
+        let now = Instant::now();
         if now < self.window {
             // window is in the future
 
-            Err(self.window.clone())
+            Err(self.window)
         } else {
             // window elapsed, and the limit is reset
 
             self.remaining = self.limit as i32;
             // this value is replaced once the discord servers respond with a real reset time
             // reset time is set to now plus one second in order to avoid race conditions, which lead to liberal limit application
-            self.window = now + Duration::seconds(1);
+            self.window = now + Duration::from_secs(1);
 
             Ok(())
         }
     }
 }
 
-impl Default for LimitCounter {
-    fn default() -> Self {
-        Self {
-            // The initial limit is assumed to be 5,
-            // so that there is ample space for requests to fetch the real limit.
-            limit: 5,
-
-            remaining: 5,
-            // The limit is said to have already expired because idk
-            window: Utc::now(),
-        }
-    }
-}
-
 /// A counter for the global rate limit (50 requests per second).
 ///
 /// This counter counts up until it hits 49 requests, at which time,
@@ -198,7 +507,7 @@ struct GlobalLimitCounter {
     /// but not enough to start counting close enough to the *real* **discord accounted** reset is very slim.
     ///
     /// If the above assumption is proven false, the penalty is a lot of small sleeps being executed.
-    started_counting: DateTime<Utc>,
+    started_counting: Instant,
 }
 
 impl GlobalLimitCounter {
@@ -206,17 +515,16 @@ impl GlobalLimitCounter {
     ///
     /// `Ok` is returned of the limit has not been reached, and `Err` is returned if it has been.
     /// The value held in `Err` is when the limit is estimated to be reset.
-    pub fn increment_and_check(&mut self) -> Result<(), DateTime<Utc>> {
+    pub fn increment_and_check(&mut self) -> Result<(), Instant> {
         self.requests_made += 1;
 
         if self.requests_made < self.limit {
             return Ok(());
         }
 
-        let mut start_plus_one = self.started_counting.clone();
-        start_plus_one += Duration::seconds(1);
+        let start_plus_one = self.started_counting + Duration::from_secs(1);
 
-        let now = Utc::now();
+        let now = Instant::now();
 
         if start_plus_one > now {
             // start_plus_one is in the future
@@ -238,7 +546,219 @@ impl Default for GlobalLimitCounter {
         Self {
             limit: 50,
             requests_made: 0,
-            started_counting: Utc::now(),
+            started_counting: Instant::now(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use tokio::time::Instant;
+
+    use reqwest::header::{HeaderMap, HeaderValue};
+    use reqwest::Method;
+
+    use super::{hash_token, major_parameter, route_template, RateLimits};
+
+    #[test]
+    fn dropped_reservation_rolls_back() {
+        let limits = RateLimits::default();
+
+        let (reservation, _) = limits.check(&Method::GET, "/channels/1/messages");
+        reservation.complete();
+        drop(limits.check(&Method::GET, "/channels/1/messages"));
+
+        let global = limits.global.lock().unwrap();
+        assert_eq!(global.requests_made, 1);
+        let key = limits.counter_key(&Method::GET, "/channels/1/messages").0;
+        let routes = limits.routes.lock().unwrap();
+        assert_eq!(routes[&key].remaining, 4);
+    }
+
+    #[tokio::test]
+    async fn timed_out_requests_stay_counted() {
+        let limits = RateLimits::default();
+        let remaining = || {
+            let key = limits.counter_key(&Method::GET, "/channels/1/messages").0;
+            limits.routes.lock().unwrap()[&key].remaining
+        };
+        let client = reqwest::Client::new();
+
+        // nothing listens on the port once the listener is dropped
+        let closed = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", closed.local_addr().unwrap());
+        drop(closed);
+        let (mut reservation, _) = limits.check(&Method::GET, "/channels/1/messages");
+        reservation.mark_sent();
+        reservation.settle(&client.get(&url).send().await);
+        assert_eq!(remaining(), 5);
+
+        // while a request which times out may have reached Discord
+        let silent = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", silent.local_addr().unwrap());
+        let (mut reservation, _) = limits.check(&Method::GET, "/channels/1/messages");
+        reservation.mark_sent();
+        let sent = client
+            .get(&url)
+            .timeout(Duration::from_millis(100))
+            .send()
+            .await;
+        assert!(sent.as_ref().unwrap_err().is_timeout());
+        reservation.settle(&sent);
+        assert_eq!(remaining(), 4);
+
+        // as may one whose future was dropped while it was in flight
+        let (mut reservation, _) = limits.check(&Method::GET, "/channels/1/messages");
+        reservation.mark_sent();
+        let abandoned = tokio::time::timeout(Duration::from_millis(100), client.get(&url).send());
+        assert!(abandoned.await.is_err());
+        drop(reservation);
+        assert_eq!(remaining(), 3);
+    }
+
+    #[tokio::test]
+    async fn sleeps_for_fractional_window() {
+        let limits = RateLimits::default();
+        let window = Instant::now() + Duration::from_millis(250);
+        limits
+            .check(&Method::GET, "/channels/1/messages")
+            .0
+            .complete();
+        {
+            let key = limits.counter_key(&Method::GET, "/channels/1/messages").0;
+            let mut routes = limits.routes.lock().unwrap();
+            let route = routes.get_mut(&key).unwrap();
+            route.remaining = 1;
+            route.window = window;
+        }
+
+        let (_reservation, sleep) = limits.check(&Method::GET, "/channels/1/messages");
+        let deadline = sleep.expect("route is limited").deadline();
+        assert!(deadline >= window && deadline < window + Duration::from_millis(10));
+    }
+
+    #[test]
+    fn saved_limits_load() {
+        let limits = RateLimits::default();
+        limits.check(&Method::GET, "/guilds/1/emojis").0.complete();
+        limits
+            .check(&Method::GET, "/channels/1/messages")
+            .0
+            .complete();
+        let emojis_key = limits.counter_key(&Method::GET, "/guilds/1/emojis").0;
+        let messages_key = limits.counter_key(&Method::GET, "/channels/1/messages").0;
+        {
+            let mut routes = limits.routes.lock().unwrap();
+            let emojis = routes.get_mut(&emojis_key).unwrap();
+            emojis.limit = 1;
+            emojis.remaining = 0;
+            emojis.window = Instant::now() + Duration::from_secs(60);
+        }
+        let saved = limits.save().unwrap();
+
+        let loaded = RateLimits::default();
+        loaded.load(&saved).unwrap();
+        let routes = loaded.routes.lock().unwrap();
+        let emojis = &routes[&emojis_key];
+        assert_eq!((emojis.limit, emojis.remaining), (1, 0));
+        assert!(emojis.window > Instant::now() + Duration::from_secs(55));
+        // the window of this route had already ended
+        let messages = &routes[&messages_key];
+        assert_eq!((messages.limit, messages.remaining), (5, 5));
+    }
+
+    #[tokio::test]
+    async fn routes_share_buckets() {
+        assert_eq!(
+            route_template(
+                &Method::PUT,
+                "/channels/1/messages/2/reactions/%F0%9F%91%8C/@me"
+            ),
+            "PUT /channels/{id}/messages/{id}/reactions/{emoji}/@me"
+        );
+        assert_eq!(
+            route_template(&Method::POST, "/webhooks/1/tok?wait=true"),
+            "POST /webhooks/{id}/{token}"
+        );
+        assert_eq!(major_parameter("/guilds/5/members/6"), "guilds/5");
+        assert_eq!(
+            major_parameter("/webhooks/1/tok/messages/2"),
+            format!("webhooks/1/{:016x}", hash_token("tok"))
+        );
+        assert_eq!(major_parameter("/users/@me"), "");
+
+        let limits = RateLimits::default();
+        let mut headers = HeaderMap::new();
+        headers.insert("X-RateLimit-Limit", HeaderValue::from_static("1"));
+        headers.insert("X-RateLimit-Remaining", HeaderValue::from_static("0"));
+        headers.insert("X-RateLimit-Reset-After", HeaderValue::from_static("60"));
+        headers.insert("X-RateLimit-Bucket", HeaderValue::from_static("abcd"));
+        limits
+            .check(&Method::GET, "/channels/1/messages/2")
+            .0
+            .complete();
+        limits
+            .update_from_headers(&Method::GET, "/channels/1/messages/2", &headers)
+            .unwrap();
+
+        // another message of the same channel is in the same bucket
+        let (_reservation, sleep) = limits.check(&Method::GET, "/channels/1/messages/3");
+        assert!(sleep.is_some());
+        // while the same route in another channel is limited separately
+        let (_reservation, sleep) = limits.check(&Method::GET, "/channels/2/messages/3");
+        assert!(sleep.is_none());
+        assert_eq!(
+            limits.counter_key(&Method::GET, "/channels/2/messages/3"),
+            ("abcd:channels/2".into(), Some(1))
+        );
+        // and sending messages to the channel is limited apart from reading them
+        let (_reservation, sleep) = limits.check(&Method::POST, "/channels/1/messages/3");
+        assert!(sleep.is_none());
+    }
+
+    #[tokio::test]
+    async fn queued_requests_keep_their_order() {
+        let limits = Arc::new(RateLimits::default());
+        let window = Instant::now() + Duration::from_millis(100);
+        limits
+            .check(&Method::GET, "/channels/1/messages")
+            .0
+            .complete();
+        {
+            let key = limits.counter_key(&Method::GET, "/channels/1/messages").0;
+            let mut routes = limits.routes.lock().unwrap();
+            let route = routes.get_mut(&key).unwrap();
+            route.limit = 10;
+            route.remaining = 1;
+            route.window = window;
+        }
+
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let tasks: Vec<_> = (0..5)
+            .map(|i| {
+                let limits = limits.clone();
+                let order = order.clone();
+                tokio::spawn(async move {
+                    limits
+                        .acquire(&Method::GET, "/channels/1/messages")
+                        .await
+                        .complete();
+                    order.lock().unwrap().push((i, Instant::now()));
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        let order = order.lock().unwrap();
+        assert_eq!(
+            order.iter().map(|&(i, _)| i).collect::<Vec<_>>(),
+            [0, 1, 2, 3, 4]
+        );
+        assert!(order.iter().all(|&(_, sent)| sent >= window));
+    }
+}
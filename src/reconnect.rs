@@ -0,0 +1,213 @@
+//! Deciding whether, and how soon, to reconnect to the gateway.
+
+use std::time::Duration;
+
+use rand::Rng;
+
+/// The reason the gateway gave for closing a connection.
+///
+/// Some of these can't be fixed by reconnecting, such as an invalid token,
+/// and a [`Connection`](crate::Connection) gives up on them instead of retrying.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GatewayCloseCode {
+    /// Something went wrong on Discord's side. (4000)
+    UnknownError,
+    /// An invalid opcode or payload for an opcode was sent. (4001)
+    UnknownOpcode,
+    /// A payload which couldn't be decoded was sent. (4002)
+    DecodeError,
+    /// A payload was sent before identifying. (4003)
+    NotAuthenticated,
+    /// The token sent when identifying was invalid. (4004)
+    AuthenticationFailed,
+    /// More than one identify payload was sent. (4005)
+    AlreadyAuthenticated,
+    /// The session is no longer valid. (4006)
+    SessionNoLongerValid,
+    /// An invalid sequence number was sent when resuming. (4007)
+    InvalidSequence,
+    /// Payloads are being sent too quickly. (4008)
+    RateLimited,
+    /// The session timed out. (4009)
+    SessionTimedOut,
+    /// An invalid shard was sent when identifying. (4010)
+    InvalidShard,
+    /// The bot is in too many servers to connect without sharding. (4011)
+    ShardingRequired,
+    /// An invalid version of the gateway was requested. (4012)
+    InvalidApiVersion,
+    /// Invalid intents were sent when identifying. (4013)
+    InvalidIntents,
+    /// Intents the bot isn't approved or enabled for were sent when identifying. (4014)
+    DisallowedIntents,
+    /// Any other code, including those of the websocket protocol itself.
+    Other(u16),
+}
+
+impl GatewayCloseCode {
+    /// The numeric close code.
+    pub fn code(self) -> u16 {
+        match self {
+            GatewayCloseCode::UnknownError => 4000,
+            GatewayCloseCode::UnknownOpcode => 4001,
+            GatewayCloseCode::DecodeError => 4002,
+            GatewayCloseCode::NotAuthenticated => 4003,
+            GatewayCloseCode::AuthenticationFailed => 4004,
+            GatewayCloseCode::AlreadyAuthenticated => 4005,
+            GatewayCloseCode::SessionNoLongerValid => 4006,
+            GatewayCloseCode::InvalidSequence => 4007,
+            GatewayCloseCode::RateLimited => 4008,
+            GatewayCloseCode::SessionTimedOut => 4009,
+            GatewayCloseCode::InvalidShard => 4010,
+            GatewayCloseCode::ShardingRequired => 4011,
+            GatewayCloseCode::InvalidApiVersion => 4012,
+            GatewayCloseCode::InvalidIntents => 4013,
+            GatewayCloseCode::DisallowedIntents => 4014,
+            GatewayCloseCode::Other(code) => code,
+        }
+    }
+
+    /// Whether reconnecting would only be closed again for the same reason,
+    /// because the token, shard or intents need to be fixed first.
+    pub fn is_fatal(self) -> bool {
+        matches!(
+            self,
+            GatewayCloseCode::AuthenticationFailed
+                | GatewayCloseCode::InvalidShard
+                | GatewayCloseCode::ShardingRequired
+                | GatewayCloseCode::InvalidApiVersion
+                | GatewayCloseCode::InvalidIntents
+                | GatewayCloseCode::DisallowedIntents
+        )
+    }
+
+    /// Whether the session can't be resumed, and must be identified again.
+    pub fn invalidates_session(self) -> bool {
+        matches!(
+            self,
+            GatewayCloseCode::SessionNoLongerValid
+                | GatewayCloseCode::InvalidSequence
+                | GatewayCloseCode::SessionTimedOut
+        )
+    }
+}
+
+impl From<u16> for GatewayCloseCode {
+    fn from(code: u16) -> GatewayCloseCode {
+        match code {
+            4000 => GatewayCloseCode::UnknownError,
+            4001 => GatewayCloseCode::UnknownOpcode,
+            4002 => GatewayCloseCode::DecodeError,
+            4003 => GatewayCloseCode::NotAuthenticated,
+            4004 => GatewayCloseCode::AuthenticationFailed,
+            4005 => GatewayCloseCode::AlreadyAuthenticated,
+            4006 => GatewayCloseCode::SessionNoLongerValid,
+            4007 => GatewayCloseCode::InvalidSequence,
+            4008 => GatewayCloseCode::RateLimited,
+            4009 => GatewayCloseCode::SessionTimedOut,
+            4010 => GatewayCloseCode::InvalidShard,
+            4011 => GatewayCloseCode::ShardingRequired,
+            4012 => GatewayCloseCode::InvalidApiVersion,
+            4013 => GatewayCloseCode::InvalidIntents,
+            4014 => GatewayCloseCode::DisallowedIntents,
+            other => GatewayCloseCode::Other(other),
+        }
+    }
+}
+
+/// How a [`Connection`](crate::Connection) retries reconnecting to the gateway.
+///
+/// The first attempt is made straight away. After each failed attempt, the wait doubles
+/// from the base delay up to the maximum delay, and is randomly shortened by up to half
+/// so that many shards disconnected at once don't all reconnect at the same moment.
+/// The count of attempts starts over once a session is resumed or identified.
+///
+/// By default, the base delay is one second, the maximum delay is one minute,
+/// and the connection gives up after 10 attempts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReconnectPolicy {
+    base_delay: Duration,
+    max_delay: Duration,
+    max_attempts: Option<u32>,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> ReconnectPolicy {
+        ReconnectPolicy {
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+            max_attempts: Some(10),
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// The default policy.
+    pub fn new() -> ReconnectPolicy {
+        ReconnectPolicy::default()
+    }
+
+    /// Wait this long after the first failed attempt.
+    pub fn base_delay(mut self, delay: Duration) -> Self {
+        self.base_delay = delay;
+        self
+    }
+
+    /// Never wait longer than this between attempts.
+    pub fn max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = delay;
+        self
+    }
+
+    /// Give up after this many attempts in a row, or never give up if `None`.
+    pub fn max_attempts(mut self, attempts: Option<u32>) -> Self {
+        self.max_attempts = attempts;
+        self
+    }
+
+    /// How long to wait before an attempt, counting from zero,
+    /// or `None` if the connection should give up instead.
+    pub fn delay(&self, attempt: u32) -> Option<Duration> {
+        if self.max_attempts.is_some_and(|max| attempt >= max) {
+            return None;
+        }
+        if attempt == 0 {
+            return Some(Duration::ZERO);
+        }
+
+        let factor = 1u32 << (attempt - 1).min(31);
+        let delay = self
+            .base_delay
+            .checked_mul(factor)
+            .map_or(self.max_delay, |delay| delay.min(self.max_delay));
+        let jitter = rand::thread_rng().gen_range(0.0..=0.5);
+        Some(delay.mul_f64(1.0 - jitter))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{GatewayCloseCode, ReconnectPolicy};
+
+    #[test]
+    fn backs_off_and_gives_up() {
+        let policy = ReconnectPolicy::new()
+            .base_delay(Duration::from_secs(2))
+            .max_delay(Duration::from_secs(5))
+            .max_attempts(Some(4));
+
+        assert_eq!(policy.delay(0), Some(Duration::ZERO));
+        let second = policy.delay(2).unwrap();
+        assert!(second >= Duration::from_secs(2) && second <= Duration::from_secs(4));
+        // capped at the maximum delay
+        let third = policy.delay(3).unwrap();
+        assert!(third >= Duration::from_millis(2500) && third <= Duration::from_secs(5));
+        assert_eq!(policy.delay(4), None);
+
+        assert!(GatewayCloseCode::from(4004).is_fatal());
+        assert!(!GatewayCloseCode::from(4008).is_fatal());
+        assert_eq!(GatewayCloseCode::from(1001), GatewayCloseCode::Other(1001));
+    }
+}
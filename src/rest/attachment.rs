@@ -0,0 +1,161 @@
+//! Download the files attached to messages from Discord's CDN.
+
+use bytes::Bytes;
+use chrono::{Duration, Utc};
+use futures::{stream, Stream};
+use reqwest::Method;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{
+    error::{Error, Result},
+    model::Attachment,
+};
+
+use super::Discord;
+
+/// How long before its expiry an attachment URL is refreshed, so it doesn't expire mid-download.
+const EXPIRY_MARGIN: i64 = 60;
+
+impl Discord {
+    /// Download the whole file of an attachment.
+    ///
+    /// Attachment URLs are signed and only work for a limited time, so an expired URL
+    /// is refreshed through the API first. The download itself is made without the
+    /// client's token, which the CDN doesn't need. The client's timeout applies only
+    /// until the CDN responds, not to the download of the file.
+    pub async fn download_attachment(&self, attachment: &Attachment) -> Result<Bytes> {
+        let bytes = self.attachment_response(attachment).await?.bytes().await?;
+        Ok(bytes)
+    }
+
+    /// Download the file of an attachment in chunks as they arrive, rather than all at once.
+    ///
+    /// Useful for large files, which can be written out without being held in memory.
+    /// See `download_attachment` for how URLs are handled.
+    pub async fn download_attachment_stream(
+        &self,
+        attachment: &Attachment,
+    ) -> Result<impl Stream<Item = Result<Bytes>> + Send + 'static> {
+        let response = self.attachment_response(attachment).await?;
+
+        Ok(stream::try_unfold(response, |mut response| async move {
+            Ok(response.chunk().await?.map(|chunk| (chunk, response)))
+        }))
+    }
+
+    /// Get new signed URLs for attachment URLs which have expired, in the same order.
+    pub async fn refresh_attachment_urls(&self, urls: &[&str]) -> Result<Vec<String>> {
+        #[derive(Deserialize)]
+        struct Refreshed {
+            refreshed_urls: Vec<RefreshedUrl>,
+        }
+
+        #[derive(Deserialize)]
+        struct RefreshedUrl {
+            original: String,
+            refreshed: String,
+        }
+
+        let map = json! {{ "attachment_urls": urls }};
+
+        let refreshed: Refreshed = self
+            .request("/attachments/refresh-urls", Method::POST, |req| {
+                req.json(&map)
+            })
+            .await?
+            .json()
+            .await?;
+
+        urls.iter()
+            .map(|&url| {
+                refreshed
+                    .refreshed_urls
+                    .iter()
+                    .find(|refreshed| refreshed.original == url)
+                    .map(|refreshed| refreshed.refreshed.clone())
+                    .ok_or(Error::Other("Discord didn't refresh an attachment URL"))
+            })
+            .collect()
+    }
+
+    /// Request the file of an attachment from the CDN, refreshing its URL if needed.
+    async fn attachment_response(&self, attachment: &Attachment) -> Result<reqwest::Response> {
+        let expired = attachment
+            .expires_at()
+            .is_some_and(|expiry| expiry <= Utc::now() + Duration::seconds(EXPIRY_MARGIN));
+        let url = if expired {
+            let mut urls = self.refresh_attachment_urls(&[&attachment.url]).await?;
+            urls.remove(0)
+        } else {
+            attachment.url.clone()
+        };
+
+        // The client's timeout only bounds the wait for the headers here, as
+        // a whole file can take far longer to arrive than an API response.
+        let request = self.client.get(url).send();
+        let response = match self.timeout {
+            Some(timeout) => tokio::time::timeout(timeout, request)
+                .await
+                .map_err(|_| Error::Other("Timed out waiting for the CDN to respond"))??,
+            None => request.await?,
+        };
+        self.connections.record_request();
+
+        if !response.status().is_success() {
+            return Err(Error::from_response(response).await);
+        }
+        Ok(response)
+    }
+}
+
+impl Attachment {
+    /// Download the whole file of the attachment, as with `Discord::download_attachment`.
+    pub async fn download(&self, discord: &Discord) -> Result<Bytes> {
+        discord.download_attachment(self).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use serde_json::json;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use crate::model::Attachment;
+    use crate::{Discord, LoginExt};
+
+    #[tokio::test]
+    async fn timeout_spares_slow_downloads() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = [0; 1024];
+            let _ = socket.read(&mut request).await.unwrap();
+            socket
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 4\r\n\r\nab")
+                .await
+                .unwrap();
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            socket.write_all(b"cd").await.unwrap();
+        });
+
+        let mut discord = Discord::from_bot_token("token").unwrap();
+        // the real client only speaks https
+        discord.client = reqwest::Client::new();
+        discord.set_timeout(Some(Duration::from_millis(100)));
+        let attachment: Attachment = serde_json::from_value(json!({
+            "id": "1",
+            "filename": "file.txt",
+            "url": format!("http://{}/file.txt", addr),
+            "proxy_url": format!("http://{}/file.txt", addr),
+            "size": 4,
+        }))
+        .unwrap();
+
+        let bytes = discord.download_attachment(&attachment).await.unwrap();
+        assert_eq!(&bytes[..], b"abcd");
+    }
+}
@@ -3,11 +3,11 @@ use reqwest::Method;
 use serde_json::json;
 
 use crate::{
-    builders::EditChannel,
+    builders::{EditChannel, SendMessage},
     error::{Error, Result, StatusChecks},
     model::{
         Channel, ChannelId, MessageId, PermissionOverwrite, PermissionOverwriteId, ServerId,
-        UserId, VoiceRegion,
+        ServerThread, TagId, UserId, VoiceRegion,
     },
 };
 
@@ -34,6 +34,34 @@ pub trait ChannelExt {
     where
         F: Send + FnOnce(EditChannel) -> EditChannel;
 
+    /// Change a text channel's topic.
+    ///
+    /// Shorthand for `edit_channel` with only `topic` set.
+    fn set_topic(
+        &self,
+        channel: ChannelId,
+        topic: &str,
+    ) -> impl Future<Output = Result<Channel>> + Send;
+
+    /// Change how many seconds users must wait between sending messages in a channel.
+    /// Zero (`0`) disables slowmode.
+    ///
+    /// Shorthand for `edit_channel` with only `slowmode` set.
+    fn set_slowmode(
+        &self,
+        channel: ChannelId,
+        seconds: u64,
+    ) -> impl Future<Output = Result<Channel>> + Send;
+
+    /// Change whether a channel is marked as NSFW.
+    ///
+    /// Shorthand for `edit_channel` with only `nsfw` set.
+    fn set_nsfw(
+        &self,
+        channel: ChannelId,
+        nsfw: bool,
+    ) -> impl Future<Output = Result<Channel>> + Send;
+
     /// Delete a channel, or close a private message.
     ///
     /// Requires the `MANAGE_CHANNELS` permission for the server,
@@ -44,6 +72,25 @@ pub trait ChannelExt {
     /// In contrast, when used with a private message, it is possible to undo the action by opening a private message with the recipient again.
     fn delete_channel(&self, channel: ChannelId) -> impl Future<Output = Result<Channel>> + Send;
 
+    /// Create a post in a forum channel, which is a thread starting with the built message.
+    ///
+    /// `tags` are the IDs of the forum's available tags to apply to the post (up to 5).
+    ///
+    /// ```ignore
+    /// let post = discord.create_forum_post(forum_id, "Release notes", &[announcement_tag], |m|
+    ///     m.content("Version 1.0 is out!")
+    /// ).await?;
+    /// ```
+    fn create_forum_post<F>(
+        &self,
+        channel: ChannelId,
+        name: &str,
+        tags: &[TagId],
+        f: F,
+    ) -> impl Future<Output = Result<ServerThread>> + Send
+    where
+        F: Send + FnOnce(SendMessage) -> SendMessage;
+
     /// Create permissions for a `Channel` for a `Member` or `Role`.
     ///
     /// # Examples
@@ -170,6 +217,18 @@ impl ChannelExt for Discord {
         Ok(channel)
     }
 
+    async fn set_topic(&self, channel: ChannelId, topic: &str) -> Result<Channel> {
+        self.edit_channel(channel, |ch| ch.topic(topic)).await
+    }
+
+    async fn set_slowmode(&self, channel: ChannelId, seconds: u64) -> Result<Channel> {
+        self.edit_channel(channel, |ch| ch.slowmode(seconds)).await
+    }
+
+    async fn set_nsfw(&self, channel: ChannelId, nsfw: bool) -> Result<Channel> {
+        self.edit_channel(channel, |ch| ch.nsfw(nsfw)).await
+    }
+
     async fn delete_channel(&self, channel: ChannelId) -> Result<Channel> {
         let channel = self
             .empty_request(&format!("/channels/{channel}"), Method::DELETE)
@@ -180,6 +239,37 @@ impl ChannelExt for Discord {
         Ok(channel)
     }
 
+    async fn create_forum_post<F>(
+        &self,
+        channel: ChannelId,
+        name: &str,
+        tags: &[TagId],
+        f: F,
+    ) -> Result<ServerThread>
+    where
+        F: Send + FnOnce(SendMessage) -> SendMessage,
+    {
+        let mut message = SendMessage::build(f);
+        self.message_defaults(&mut message);
+        let map = json! {{
+            "name": name,
+            "applied_tags": tags,
+            "message": message,
+        }};
+
+        let thread = self
+            .request(
+                &format!("/channels/{channel}/threads"),
+                Method::POST,
+                |req| req.json(&map),
+            )
+            .await?
+            .json()
+            .await?;
+
+        Ok(thread)
+    }
+
     async fn create_permission(
         &self,
         channel: ChannelId,
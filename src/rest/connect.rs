@@ -3,7 +3,11 @@ use std::collections::BTreeMap;
 use futures::Future;
 use reqwest::Method;
 
-use crate::{connection::Connection, model::ReadyEvent, Discord, Error, Object, Result};
+use crate::{
+    connection::{Connection, ConnectionBuilder},
+    model::{GatewayBot, IdentifyConnection, IdentifyPayload, ReadyEvent},
+    Discord, Error, Object, Result, ShardId,
+};
 
 /// Discord Rest API methods for preparing and establishing a gateway connection.
 ///
@@ -24,32 +28,44 @@ pub trait ConnectExt {
     /// This method waits for and returns the `ReadyEvent` sent by Discord upon establishing the
     /// connection, which contains the initial state as seen by the client.
     ///
-    /// See `connect` if you do not want to use guild sharding.
+    /// See `connect` if you do not want to use guild sharding,
+    /// or `ShardManager` to run every shard at once.
     fn connect_sharded(
         &self,
-        shard_id: u8,
-        total_shards: u8,
+        shard_id: ShardId,
+        total_shards: u32,
     ) -> impl Future<Output = Result<(Connection, ReadyEvent)>> + Send;
 
     /// Retrieves the number of guild shards Discord suggests to use based on the number of guilds.
     /// This endpoint is only available for bots.
-    fn suggested_shard_count(&self) -> impl Future<Output = Result<u8>> + Send;
+    fn suggested_shard_count(&self) -> impl Future<Output = Result<u32>> + Send;
+
+    /// Retrieves the gateway URL along with the recommended number of shards
+    /// and the limits on starting sessions. This endpoint is only available for bots.
+    fn get_gateway_bot(&self) -> impl Future<Output = Result<GatewayBot>> + Send;
 }
 
 impl ConnectExt for Discord {
     async fn connect(&self) -> Result<(Connection, ReadyEvent)> {
-        todo!()
+        let url = get_gateway_url(self).await?;
+        open_connection(self, &url, identify_payload(self, None)).await
     }
 
     async fn connect_sharded(
         &self,
-        _shard_id: u8,
-        _total_shards: u8,
+        shard_id: ShardId,
+        total_shards: u32,
     ) -> Result<(Connection, ReadyEvent)> {
-        todo!()
+        let url = get_gateway_url(self).await?;
+        open_connection(
+            self,
+            &url,
+            identify_payload(self, Some((shard_id, total_shards))),
+        )
+        .await
     }
 
-    async fn suggested_shard_count(&self) -> Result<u8> {
+    async fn suggested_shard_count(&self) -> Result<u32> {
         let mut response = self
             .empty_request("/gateway/bot", Method::GET)
             .await?
@@ -58,7 +74,7 @@ impl ConnectExt for Discord {
 
         match response.remove("shards") {
             Some(value) => match value.as_u64() {
-                Some(shards) => Ok(shards as u8),
+                Some(shards) => Ok(shards as u32),
                 None => Err(Error::Decode("Invalid \"shards\"", value)),
             },
             None => Err(Error::Decode(
@@ -67,6 +83,16 @@ impl ConnectExt for Discord {
             )),
         }
     }
+
+    async fn get_gateway_bot(&self) -> Result<GatewayBot> {
+        let gateway = self
+            .empty_request("/gateway/bot", Method::GET)
+            .await?
+            .json()
+            .await?;
+
+        Ok(gateway)
+    }
 }
 
 /// Fetch the gateway URL to connect to.
@@ -84,3 +110,34 @@ async fn get_gateway_url(client: &Discord) -> Result<String> {
         )),
     }
 }
+
+/// Connect to the gateway, with the transport compression, reconnect policy
+/// and signal handling of the client.
+async fn open_connection(
+    client: &Discord,
+    url: &str,
+    identify: IdentifyPayload,
+) -> Result<(Connection, ReadyEvent)> {
+    let builder = ConnectionBuilder::new(url, identify)
+        .transport_compression(client.transport_compression)
+        .reconnect_policy(client.reconnect_policy);
+    #[cfg(feature = "signal")]
+    let builder = builder.shutdown_on_signal(client.shutdown_on_signal);
+    builder.connect().await
+}
+
+/// Build the payload used to identify with the gateway.
+fn identify_payload(client: &Discord, shard: Option<(ShardId, u32)>) -> IdentifyPayload {
+    IdentifyPayload {
+        token: client.token.clone(),
+        shard,
+        intents: client.intents,
+        compress: Some(true),
+        large_threshold: Some(250),
+        fingerprint: IdentifyConnection {
+            os: std::env::consts::OS.to_owned(),
+            browser: "Discord library for Rust".to_owned(),
+            device: "discord-tokio".to_owned(),
+        },
+    }
+}
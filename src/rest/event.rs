@@ -0,0 +1,286 @@
+use futures::{stream, Future, Stream, TryStreamExt};
+use reqwest::Method;
+
+use crate::{
+    builders::{CreateScheduledEvent, EditScheduledEvent},
+    error::{Result, StatusChecks},
+    model::{EventId, ScheduledEvent, ScheduledEventUser, ServerId, UserId},
+};
+
+use super::{Cursor, Discord};
+
+/// How many subscribed users can be fetched at once.
+const EVENT_USERS_PAGE: u64 = 100;
+
+/// Discord REST API methods for managing and taking part in scheduled server events.
+pub trait EventExt {
+    /// Get the scheduled events of a server.
+    ///
+    /// If `with_user_count` is set, each event's `user_count` is filled in.
+    fn list_scheduled_events(
+        &self,
+        server: ServerId,
+        with_user_count: bool,
+    ) -> impl Future<Output = Result<Vec<ScheduledEvent>>> + Send;
+
+    /// Get a single scheduled event of a server.
+    fn get_scheduled_event(
+        &self,
+        server: ServerId,
+        event: EventId,
+    ) -> impl Future<Output = Result<ScheduledEvent>> + Send;
+
+    /// Schedule an event on a server.
+    ///
+    /// Requires the `MANAGE_EVENTS` permission. The event's name, start time, privacy
+    /// and where it is hosted must be set.
+    ///
+    /// ```ignore
+    /// let event = discord.create_scheduled_event(server_id, |e| e
+    ///     .name("Movie night")
+    ///     .start_time(start)
+    ///     .privacy(ScheduledEventPrivacy::ServerOnly)
+    ///     .voice(cinema_channel)
+    /// ).await?;
+    /// ```
+    fn create_scheduled_event<F>(
+        &self,
+        server: ServerId,
+        f: F,
+    ) -> impl Future<Output = Result<ScheduledEvent>> + Send
+    where
+        F: Send + FnOnce(CreateScheduledEvent) -> CreateScheduledEvent;
+
+    /// Edit a scheduled event. See `EditScheduledEvent` for the editable fields.
+    ///
+    /// Requires the `MANAGE_EVENTS` permission.
+    fn edit_scheduled_event<F>(
+        &self,
+        server: ServerId,
+        event: EventId,
+        f: F,
+    ) -> impl Future<Output = Result<ScheduledEvent>> + Send
+    where
+        F: Send + FnOnce(EditScheduledEvent) -> EditScheduledEvent;
+
+    /// Delete a scheduled event.
+    ///
+    /// Requires the `MANAGE_EVENTS` permission.
+    fn delete_scheduled_event(
+        &self,
+        server: ServerId,
+        event: EventId,
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    /// Mark the current user as interested in a scheduled event,
+    /// to be notified when it starts.
+    ///
+    /// This method may only be used by user accounts.
+    fn subscribe(
+        &self,
+        server: ServerId,
+        event: EventId,
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    /// Remove the current user's interest in a scheduled event.
+    ///
+    /// This method may only be used by user accounts.
+    fn unsubscribe(
+        &self,
+        server: ServerId,
+        event: EventId,
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    /// Get a page of the users subscribed to a scheduled event, ordered by their ID.
+    ///
+    /// `limit` can be up to 100, and defaults to 100.
+    /// If `with_member` is set, each user's server member data is included where available.
+    fn get_event_users(
+        &self,
+        server: ServerId,
+        event: EventId,
+        cursor: Cursor<UserId>,
+        limit: Option<u64>,
+        with_member: bool,
+    ) -> impl Future<Output = Result<Vec<ScheduledEventUser>>> + Send;
+
+    /// Stream every user subscribed to a scheduled event, fetching them a page at a time.
+    ///
+    /// The stream ends after yielding an error.
+    ///
+    /// ```ignore
+    /// let mut users = pin!(discord.event_users(server_id, event_id, false));
+    /// while let Some(user) = users.try_next().await? {
+    ///     println!("{} is interested", user.user.name);
+    /// }
+    /// ```
+    fn event_users(
+        &self,
+        server: ServerId,
+        event: EventId,
+        with_member: bool,
+    ) -> impl Stream<Item = Result<ScheduledEventUser>> + Send + '_;
+}
+
+impl EventExt for Discord {
+    async fn list_scheduled_events(
+        &self,
+        server: ServerId,
+        with_user_count: bool,
+    ) -> Result<Vec<ScheduledEvent>> {
+        let events = self
+            .empty_request(
+                &format!("/guilds/{server}/scheduled-events?with_user_count={with_user_count}"),
+                Method::GET,
+            )
+            .await?
+            .json()
+            .await?;
+
+        Ok(events)
+    }
+
+    async fn get_scheduled_event(
+        &self,
+        server: ServerId,
+        event: EventId,
+    ) -> Result<ScheduledEvent> {
+        let event = self
+            .empty_request(
+                &format!("/guilds/{server}/scheduled-events/{event}"),
+                Method::GET,
+            )
+            .await?
+            .json()
+            .await?;
+
+        Ok(event)
+    }
+
+    async fn create_scheduled_event<F>(&self, server: ServerId, f: F) -> Result<ScheduledEvent>
+    where
+        F: Send + FnOnce(CreateScheduledEvent) -> CreateScheduledEvent,
+    {
+        let map = CreateScheduledEvent::build(f);
+
+        let event = self
+            .request(
+                &format!("/guilds/{server}/scheduled-events"),
+                Method::POST,
+                |req| req.json(&map),
+            )
+            .await?
+            .json()
+            .await?;
+
+        Ok(event)
+    }
+
+    async fn edit_scheduled_event<F>(
+        &self,
+        server: ServerId,
+        event: EventId,
+        f: F,
+    ) -> Result<ScheduledEvent>
+    where
+        F: Send + FnOnce(EditScheduledEvent) -> EditScheduledEvent,
+    {
+        let map = EditScheduledEvent::build(f);
+
+        let event = self
+            .request(
+                &format!("/guilds/{server}/scheduled-events/{event}"),
+                Method::PATCH,
+                |req| req.json(&map),
+            )
+            .await?
+            .json()
+            .await?;
+
+        Ok(event)
+    }
+
+    async fn delete_scheduled_event(&self, server: ServerId, event: EventId) -> Result<()> {
+        self.empty_request(
+            &format!("/guilds/{server}/scheduled-events/{event}"),
+            Method::DELETE,
+        )
+        .await?
+        .insure_no_content()
+        .await
+    }
+
+    async fn subscribe(&self, server: ServerId, event: EventId) -> Result<()> {
+        self.empty_request(
+            &format!("/guilds/{server}/scheduled-events/{event}/users/@me"),
+            Method::PUT,
+        )
+        .await?
+        .insure_no_content()
+        .await
+    }
+
+    async fn unsubscribe(&self, server: ServerId, event: EventId) -> Result<()> {
+        self.empty_request(
+            &format!("/guilds/{server}/scheduled-events/{event}/users/@me"),
+            Method::DELETE,
+        )
+        .await?
+        .insure_no_content()
+        .await
+    }
+
+    async fn get_event_users(
+        &self,
+        server: ServerId,
+        event: EventId,
+        cursor: Cursor<UserId>,
+        limit: Option<u64>,
+        with_member: bool,
+    ) -> Result<Vec<ScheduledEventUser>> {
+        let mut query = cursor.checked_query(limit)?;
+        if with_member {
+            if !query.is_empty() {
+                query.push('&');
+            }
+            query.push_str("with_member=true");
+        }
+
+        let users = self
+            .empty_request(
+                &format!("/guilds/{server}/scheduled-events/{event}/users?{query}"),
+                Method::GET,
+            )
+            .await?
+            .json()
+            .await?;
+
+        Ok(users)
+    }
+
+    fn event_users(
+        &self,
+        server: ServerId,
+        event: EventId,
+        with_member: bool,
+    ) -> impl Stream<Item = Result<ScheduledEventUser>> + Send + '_ {
+        let pages = stream::try_unfold(Some(Cursor::Start), move |cursor| async move {
+            let Some(cursor) = cursor else {
+                return Ok(None);
+            };
+            let users = self
+                .get_event_users(server, event, cursor, Some(EVENT_USERS_PAGE), with_member)
+                .await?;
+
+            let next = match users.last() {
+                Some(last) if users.len() as u64 == EVENT_USERS_PAGE => {
+                    Some(Cursor::After(last.user.id))
+                }
+                _ => None,
+            };
+            Result::Ok(Some((stream::iter(users.into_iter().map(Ok)), next)))
+        });
+
+        pages.try_flatten()
+    }
+}
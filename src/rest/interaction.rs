@@ -0,0 +1,723 @@
+//! Register and manage application commands, and respond to interactions.
+//!
+//! Global commands are available in every server the application is in,
+//! and in DMs, while server commands are only available in the server they are registered in.
+//!
+//! Interactions must be responded to within 3 seconds, either directly or by deferring
+//! the response, after which the interaction token can be used for 15 minutes
+//! to edit the original response and send followup messages.
+//! An [`InteractionHandle`] keeps the token for doing so from another task.
+
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use futures::Future;
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::warn;
+
+use crate::{
+    builders::{CreateCommand, SendMessage},
+    error::{Error, Result, StatusChecks},
+    model::{
+        ApplicationCommand, ApplicationId, CommandId, Interaction, InteractionCallbackType,
+        InteractionId, Message, MessageFlags, MessageId, ServerId,
+    },
+};
+
+use super::Discord;
+
+/// How long an interaction token can be used for, from when the interaction was created.
+const TOKEN_LIFETIME: Duration = Duration::from_secs(15 * 60);
+
+/// How long before its token expires an interaction handle warns about it being used.
+const EXPIRY_WARNING: Duration = Duration::from_secs(60);
+
+/// Discord REST API methods for working with application (slash) commands and interactions.
+///
+/// This trait is not meant to be implemented by any type
+/// except the [`Discord`] Rest API client provided by this crate.
+pub trait InteractionExt {
+    /// Get the global commands of an application.
+    fn get_global_commands(
+        &self,
+        application: ApplicationId,
+    ) -> impl Future<Output = Result<Vec<ApplicationCommand>>> + Send;
+
+    /// Create a global command.
+    ///
+    /// Creating a command with the same name as an existing one replaces it.
+    fn create_global_command<F>(
+        &self,
+        application: ApplicationId,
+        f: F,
+    ) -> impl Future<Output = Result<ApplicationCommand>> + Send
+    where
+        F: Send + FnOnce(CreateCommand) -> CreateCommand;
+
+    /// Edit a global command. Only the given fields are changed.
+    fn edit_global_command<F>(
+        &self,
+        application: ApplicationId,
+        command: CommandId,
+        f: F,
+    ) -> impl Future<Output = Result<ApplicationCommand>> + Send
+    where
+        F: Send + FnOnce(CreateCommand) -> CreateCommand;
+
+    /// Delete a global command.
+    fn delete_global_command(
+        &self,
+        application: ApplicationId,
+        command: CommandId,
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    /// Get the commands an application has registered in a server.
+    fn get_server_commands(
+        &self,
+        application: ApplicationId,
+        server: ServerId,
+    ) -> impl Future<Output = Result<Vec<ApplicationCommand>>> + Send;
+
+    /// Create a command in a server.
+    ///
+    /// Creating a command with the same name as an existing one replaces it.
+    fn create_server_command<F>(
+        &self,
+        application: ApplicationId,
+        server: ServerId,
+        f: F,
+    ) -> impl Future<Output = Result<ApplicationCommand>> + Send
+    where
+        F: Send + FnOnce(CreateCommand) -> CreateCommand;
+
+    /// Edit a command in a server. Only the given fields are changed.
+    fn edit_server_command<F>(
+        &self,
+        application: ApplicationId,
+        server: ServerId,
+        command: CommandId,
+        f: F,
+    ) -> impl Future<Output = Result<ApplicationCommand>> + Send
+    where
+        F: Send + FnOnce(CreateCommand) -> CreateCommand;
+
+    /// Delete a command in a server.
+    fn delete_server_command(
+        &self,
+        application: ApplicationId,
+        server: ServerId,
+        command: CommandId,
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    /// Respond to an interaction. The message built by `f` is sent as the response data,
+    /// and omitted if nothing is set, as for deferred responses.
+    fn respond_to_interaction<F>(
+        &self,
+        interaction: InteractionId,
+        token: &str,
+        kind: InteractionCallbackType,
+        f: F,
+    ) -> impl Future<Output = Result<()>> + Send
+    where
+        F: Send + FnOnce(SendMessage) -> SendMessage;
+
+    /// Acknowledge an interaction, showing a loading state until the original
+    /// response is edited with `edit_original_response`.
+    fn defer_interaction(
+        &self,
+        interaction: InteractionId,
+        token: &str,
+        ephemeral: bool,
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    /// Get the original response to an interaction.
+    fn get_original_response(
+        &self,
+        application: ApplicationId,
+        token: &str,
+    ) -> impl Future<Output = Result<Message>> + Send;
+
+    /// Edit the original response to an interaction.
+    fn edit_original_response<F>(
+        &self,
+        application: ApplicationId,
+        token: &str,
+        f: F,
+    ) -> impl Future<Output = Result<Message>> + Send
+    where
+        F: Send + FnOnce(SendMessage) -> SendMessage;
+
+    /// Delete the original response to an interaction.
+    fn delete_original_response(
+        &self,
+        application: ApplicationId,
+        token: &str,
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    /// Send a followup message for an interaction.
+    fn create_followup_message<F>(
+        &self,
+        application: ApplicationId,
+        token: &str,
+        f: F,
+    ) -> impl Future<Output = Result<Message>> + Send
+    where
+        F: Send + FnOnce(SendMessage) -> SendMessage;
+
+    /// Edit a followup message of an interaction.
+    fn edit_followup_message<F>(
+        &self,
+        application: ApplicationId,
+        token: &str,
+        message: MessageId,
+        f: F,
+    ) -> impl Future<Output = Result<Message>> + Send
+    where
+        F: Send + FnOnce(SendMessage) -> SendMessage;
+
+    /// Delete a followup message of an interaction.
+    fn delete_followup_message(
+        &self,
+        application: ApplicationId,
+        token: &str,
+        message: MessageId,
+    ) -> impl Future<Output = Result<()>> + Send;
+}
+
+impl InteractionExt for Discord {
+    async fn get_global_commands(
+        &self,
+        application: ApplicationId,
+    ) -> Result<Vec<ApplicationCommand>> {
+        let commands = self
+            .empty_request(
+                &format!("/applications/{application}/commands"),
+                Method::GET,
+            )
+            .await?
+            .json()
+            .await?;
+
+        Ok(commands)
+    }
+
+    async fn create_global_command<F>(
+        &self,
+        application: ApplicationId,
+        f: F,
+    ) -> Result<ApplicationCommand>
+    where
+        F: Send + FnOnce(CreateCommand) -> CreateCommand,
+    {
+        let map = CreateCommand::build(f);
+
+        let command = self
+            .request(
+                &format!("/applications/{application}/commands"),
+                Method::POST,
+                |req| req.json(&map),
+            )
+            .await?
+            .json()
+            .await?;
+
+        Ok(command)
+    }
+
+    async fn edit_global_command<F>(
+        &self,
+        application: ApplicationId,
+        command: CommandId,
+        f: F,
+    ) -> Result<ApplicationCommand>
+    where
+        F: Send + FnOnce(CreateCommand) -> CreateCommand,
+    {
+        let map = CreateCommand::build(f);
+
+        let command = self
+            .request(
+                &format!("/applications/{application}/commands/{command}"),
+                Method::PATCH,
+                |req| req.json(&map),
+            )
+            .await?
+            .json()
+            .await?;
+
+        Ok(command)
+    }
+
+    async fn delete_global_command(
+        &self,
+        application: ApplicationId,
+        command: CommandId,
+    ) -> Result<()> {
+        self.empty_request(
+            &format!("/applications/{application}/commands/{command}"),
+            Method::DELETE,
+        )
+        .await?
+        .insure_no_content()
+        .await
+    }
+
+    async fn get_server_commands(
+        &self,
+        application: ApplicationId,
+        server: ServerId,
+    ) -> Result<Vec<ApplicationCommand>> {
+        let commands = self
+            .empty_request(
+                &format!("/applications/{application}/guilds/{server}/commands"),
+                Method::GET,
+            )
+            .await?
+            .json()
+            .await?;
+
+        Ok(commands)
+    }
+
+    async fn create_server_command<F>(
+        &self,
+        application: ApplicationId,
+        server: ServerId,
+        f: F,
+    ) -> Result<ApplicationCommand>
+    where
+        F: Send + FnOnce(CreateCommand) -> CreateCommand,
+    {
+        let map = CreateCommand::build(f);
+
+        let command = self
+            .request(
+                &format!("/applications/{application}/guilds/{server}/commands"),
+                Method::POST,
+                |req| req.json(&map),
+            )
+            .await?
+            .json()
+            .await?;
+
+        Ok(command)
+    }
+
+    async fn edit_server_command<F>(
+        &self,
+        application: ApplicationId,
+        server: ServerId,
+        command: CommandId,
+        f: F,
+    ) -> Result<ApplicationCommand>
+    where
+        F: Send + FnOnce(CreateCommand) -> CreateCommand,
+    {
+        let map = CreateCommand::build(f);
+
+        let command = self
+            .request(
+                &format!("/applications/{application}/guilds/{server}/commands/{command}"),
+                Method::PATCH,
+                |req| req.json(&map),
+            )
+            .await?
+            .json()
+            .await?;
+
+        Ok(command)
+    }
+
+    async fn delete_server_command(
+        &self,
+        application: ApplicationId,
+        server: ServerId,
+        command: CommandId,
+    ) -> Result<()> {
+        self.empty_request(
+            &format!("/applications/{application}/guilds/{server}/commands/{command}"),
+            Method::DELETE,
+        )
+        .await?
+        .insure_no_content()
+        .await
+    }
+
+    async fn respond_to_interaction<F>(
+        &self,
+        interaction: InteractionId,
+        token: &str,
+        kind: InteractionCallbackType,
+        f: F,
+    ) -> Result<()>
+    where
+        F: Send + FnOnce(SendMessage) -> SendMessage,
+    {
+        let mut data = SendMessage::build(f);
+        let map = if data.is_empty() {
+            json! {{ "type": kind }}
+        } else {
+            self.message_defaults(&mut data);
+            json! {{ "type": kind, "data": data }}
+        };
+
+        self.request(
+            &format!("/interactions/{interaction}/{token}/callback"),
+            Method::POST,
+            |req| req.json(&map),
+        )
+        .await?
+        .insure_no_content()
+        .await
+    }
+
+    async fn defer_interaction(
+        &self,
+        interaction: InteractionId,
+        token: &str,
+        ephemeral: bool,
+    ) -> Result<()> {
+        self.respond_to_interaction(
+            interaction,
+            token,
+            InteractionCallbackType::DeferredChannelMessageWithSource,
+            |m| {
+                if ephemeral {
+                    m.flags(MessageFlags::EPHEMERAL)
+                } else {
+                    m
+                }
+            },
+        )
+        .await
+    }
+
+    async fn get_original_response(
+        &self,
+        application: ApplicationId,
+        token: &str,
+    ) -> Result<Message> {
+        let message = self
+            .empty_request(
+                &format!("/webhooks/{application}/{token}/messages/@original"),
+                Method::GET,
+            )
+            .await?
+            .json()
+            .await?;
+
+        Ok(message)
+    }
+
+    async fn edit_original_response<F>(
+        &self,
+        application: ApplicationId,
+        token: &str,
+        f: F,
+    ) -> Result<Message>
+    where
+        F: Send + FnOnce(SendMessage) -> SendMessage,
+    {
+        let map = SendMessage::build(f);
+
+        let message = self
+            .request(
+                &format!("/webhooks/{application}/{token}/messages/@original"),
+                Method::PATCH,
+                |req| req.json(&map),
+            )
+            .await?
+            .json()
+            .await?;
+
+        Ok(message)
+    }
+
+    async fn delete_original_response(
+        &self,
+        application: ApplicationId,
+        token: &str,
+    ) -> Result<()> {
+        self.empty_request(
+            &format!("/webhooks/{application}/{token}/messages/@original"),
+            Method::DELETE,
+        )
+        .await?
+        .insure_no_content()
+        .await
+    }
+
+    async fn create_followup_message<F>(
+        &self,
+        application: ApplicationId,
+        token: &str,
+        f: F,
+    ) -> Result<Message>
+    where
+        F: Send + FnOnce(SendMessage) -> SendMessage,
+    {
+        let mut map = SendMessage::build(f);
+        self.message_defaults(&mut map);
+
+        let message = self
+            .request(
+                &format!("/webhooks/{application}/{token}"),
+                Method::POST,
+                |req| req.json(&map),
+            )
+            .await?
+            .json()
+            .await?;
+
+        Ok(message)
+    }
+
+    async fn edit_followup_message<F>(
+        &self,
+        application: ApplicationId,
+        token: &str,
+        message: MessageId,
+        f: F,
+    ) -> Result<Message>
+    where
+        F: Send + FnOnce(SendMessage) -> SendMessage,
+    {
+        let map = SendMessage::build(f);
+
+        let message = self
+            .request(
+                &format!("/webhooks/{application}/{token}/messages/{message}"),
+                Method::PATCH,
+                |req| req.json(&map),
+            )
+            .await?
+            .json()
+            .await?;
+
+        Ok(message)
+    }
+
+    async fn delete_followup_message(
+        &self,
+        application: ApplicationId,
+        token: &str,
+        message: MessageId,
+    ) -> Result<()> {
+        self.empty_request(
+            &format!("/webhooks/{application}/{token}/messages/{message}"),
+            Method::DELETE,
+        )
+        .await?
+        .insure_no_content()
+        .await
+    }
+}
+
+/// What's needed to respond to an interaction and manage its responses, kept apart from the
+/// rest of the interaction.
+///
+/// The handle owns its token, so it can be moved to another task, or stored and used
+/// later, to edit the original response and send, edit and delete followup messages
+/// for as long as the token is valid, which is 15 minutes after the interaction was created.
+/// Calls made with less than a minute left are logged as warnings, and calls made after
+/// the token has expired fail without making a request.
+///
+/// ```ignore
+/// let handle = InteractionHandle::new(&interaction);
+/// handle.defer(&discord, false).await?;
+/// tokio::spawn(async move {
+///     let report = build_report().await;
+///     handle.edit_original(&discord, |m| m.content(&report)).await
+/// });
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InteractionHandle {
+    /// The ID of the interaction.
+    pub id: InteractionId,
+    /// The application the interaction is for.
+    pub application_id: ApplicationId,
+    /// The token for responding to the interaction.
+    pub token: String,
+}
+
+impl InteractionHandle {
+    /// Keep what's needed to respond to an interaction.
+    pub fn new(interaction: &Interaction) -> InteractionHandle {
+        InteractionHandle {
+            id: interaction.id,
+            application_id: interaction.application_id,
+            token: interaction.token.clone(),
+        }
+    }
+
+    /// Get when the interaction token stops working.
+    pub fn expires_at(&self) -> DateTime<Utc> {
+        self.id.creation_date()
+            + chrono::Duration::from_std(TOKEN_LIFETIME).expect("lifetime is in range")
+    }
+
+    /// Get how much longer the interaction token can be used for, or `None` if it has expired.
+    pub fn remaining(&self) -> Option<Duration> {
+        (self.expires_at() - Utc::now())
+            .to_std()
+            .ok()
+            .filter(|remaining| !remaining.is_zero())
+    }
+
+    /// Whether the interaction token has expired.
+    pub fn is_expired(&self) -> bool {
+        self.remaining().is_none()
+    }
+
+    /// Whether the interaction token expires within a minute, and should be used quickly
+    /// if at all.
+    pub fn is_near_expiry(&self) -> bool {
+        self.remaining()
+            .is_none_or(|remaining| remaining < EXPIRY_WARNING)
+    }
+
+    /// Respond to the interaction, as with `InteractionExt::respond_to_interaction`.
+    pub async fn respond<F>(
+        &self,
+        discord: &Discord,
+        kind: InteractionCallbackType,
+        f: F,
+    ) -> Result<()>
+    where
+        F: Send + FnOnce(SendMessage) -> SendMessage,
+    {
+        self.check_expiry()?;
+        discord
+            .respond_to_interaction(self.id, &self.token, kind, f)
+            .await
+    }
+
+    /// Acknowledge the interaction, as with `InteractionExt::defer_interaction`.
+    pub async fn defer(&self, discord: &Discord, ephemeral: bool) -> Result<()> {
+        self.check_expiry()?;
+        discord
+            .defer_interaction(self.id, &self.token, ephemeral)
+            .await
+    }
+
+    /// Get the original response to the interaction.
+    pub async fn get_original(&self, discord: &Discord) -> Result<Message> {
+        self.check_expiry()?;
+        discord
+            .get_original_response(self.application_id, &self.token)
+            .await
+    }
+
+    /// Edit the original response to the interaction.
+    pub async fn edit_original<F>(&self, discord: &Discord, f: F) -> Result<Message>
+    where
+        F: Send + FnOnce(SendMessage) -> SendMessage,
+    {
+        self.check_expiry()?;
+        discord
+            .edit_original_response(self.application_id, &self.token, f)
+            .await
+    }
+
+    /// Delete the original response to the interaction.
+    pub async fn delete_original(&self, discord: &Discord) -> Result<()> {
+        self.check_expiry()?;
+        discord
+            .delete_original_response(self.application_id, &self.token)
+            .await
+    }
+
+    /// Send a followup message for the interaction.
+    pub async fn followup<F>(&self, discord: &Discord, f: F) -> Result<Message>
+    where
+        F: Send + FnOnce(SendMessage) -> SendMessage,
+    {
+        self.check_expiry()?;
+        discord
+            .create_followup_message(self.application_id, &self.token, f)
+            .await
+    }
+
+    /// Edit a followup message of the interaction.
+    pub async fn edit_followup<F>(
+        &self,
+        discord: &Discord,
+        message: MessageId,
+        f: F,
+    ) -> Result<Message>
+    where
+        F: Send + FnOnce(SendMessage) -> SendMessage,
+    {
+        self.check_expiry()?;
+        discord
+            .edit_followup_message(self.application_id, &self.token, message, f)
+            .await
+    }
+
+    /// Delete a followup message of the interaction.
+    pub async fn delete_followup(&self, discord: &Discord, message: MessageId) -> Result<()> {
+        self.check_expiry()?;
+        discord
+            .delete_followup_message(self.application_id, &self.token, message)
+            .await
+    }
+
+    /// Fail if the token has expired, and warn if it is about to.
+    fn check_expiry(&self) -> Result<()> {
+        match self.remaining() {
+            None => Err(Error::Other("The interaction token has expired")),
+            Some(remaining) if remaining < EXPIRY_WARNING => {
+                warn!(
+                    "The token of interaction {} expires in {}s",
+                    self.id,
+                    remaining.as_secs()
+                );
+                Ok(())
+            }
+            Some(_) => Ok(()),
+        }
+    }
+}
+
+impl From<&Interaction> for InteractionHandle {
+    fn from(interaction: &Interaction) -> InteractionHandle {
+        InteractionHandle::new(interaction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::InteractionHandle;
+    use crate::model::{ApplicationId, InteractionId};
+
+    #[test]
+    fn interaction_tokens_expire() {
+        // snowflakes count milliseconds since the start of 2015 from bit 22
+        let created = |minutes_ago: i64| {
+            let millis = Utc::now().timestamp_millis() - minutes_ago * 60 * 1000;
+            InteractionId(((millis - 1_420_070_400_000) as u64) << 22)
+        };
+        let handle = |id| InteractionHandle {
+            id,
+            application_id: ApplicationId(1),
+            token: "token".into(),
+        };
+
+        let fresh = handle(created(1));
+        assert!(!fresh.is_expired());
+        assert!(!fresh.is_near_expiry());
+        assert!(fresh.remaining().unwrap().as_secs() > 13 * 60);
+
+        let old = handle(created(14));
+        assert!(!old.is_expired());
+        assert!(old.is_near_expiry());
+
+        let expired = handle(created(16));
+        assert!(expired.is_expired());
+        assert!(expired.check_expiry().is_err());
+    }
+}
@@ -0,0 +1,108 @@
+//! Timing of REST requests, for finding slow endpoints.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use reqwest::Method;
+
+use crate::ratelimit::rest::route_template;
+
+/// How many of the most recent requests are kept for each route.
+const SAMPLES: usize = 64;
+
+/// Latency statistics for one route of the REST API, over its most recent requests.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RouteLatency {
+    /// The method and path of the route, with IDs replaced by placeholders,
+    /// e.g. `GET /channels/{id}/messages`.
+    pub route: String,
+    /// How many requests the statistics are taken over.
+    pub samples: usize,
+    /// The mean time taken.
+    pub mean: Duration,
+    /// The median time taken.
+    pub median: Duration,
+    /// The longest time taken.
+    pub max: Duration,
+}
+
+/// The times taken by the most recent requests to each route.
+#[derive(Debug, Default)]
+pub(crate) struct Latencies {
+    routes: Mutex<HashMap<String, VecDeque<Duration>>>,
+}
+
+impl Latencies {
+    /// Record the time taken by a request.
+    pub fn record(&self, method: &Method, url: &str, elapsed: Duration) {
+        let mut routes = self.routes.lock().expect("poisoned latencies");
+        let samples = routes.entry(route_template(method, url)).or_default();
+        if samples.len() == SAMPLES {
+            samples.pop_front();
+        }
+        samples.push_back(elapsed);
+    }
+
+    /// Summarize the recorded times of every route, slowest first.
+    pub fn report(&self) -> Vec<RouteLatency> {
+        let routes = self.routes.lock().expect("poisoned latencies");
+        let mut report: Vec<_> = routes
+            .iter()
+            .filter(|(_, samples)| !samples.is_empty())
+            .map(|(route, samples)| {
+                let mut sorted: Vec<_> = samples.iter().copied().collect();
+                sorted.sort();
+                RouteLatency {
+                    route: route.clone(),
+                    samples: sorted.len(),
+                    mean: sorted.iter().sum::<Duration>() / sorted.len() as u32,
+                    median: sorted[sorted.len() / 2],
+                    max: sorted[sorted.len() - 1],
+                }
+            })
+            .collect();
+        report.sort_by(|a, b| b.mean.cmp(&a.mean).then_with(|| a.route.cmp(&b.route)));
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use reqwest::Method;
+
+    use super::Latencies;
+    use crate::ratelimit::rest::route_template;
+
+    #[test]
+    fn groups_by_route() {
+        assert_eq!(
+            route_template(
+                &Method::PUT,
+                "/channels/1/messages/2/reactions/%F0%9F%91%8D/@me"
+            ),
+            "PUT /channels/{id}/messages/{id}/reactions/{emoji}/@me"
+        );
+        assert_eq!(
+            route_template(&Method::PATCH, "/webhooks/3/abc.def/messages/@original"),
+            "PATCH /webhooks/{id}/{token}/messages/@original"
+        );
+
+        let latencies = Latencies::default();
+        for ms in [10, 30, 20] {
+            let url = format!("/channels/{}/messages?limit=50", ms);
+            latencies.record(&Method::GET, &url, Duration::from_millis(ms));
+        }
+        latencies.record(&Method::GET, "/gateway", Duration::from_millis(5));
+
+        let report = latencies.report();
+        assert_eq!(report[0].route, "GET /channels/{id}/messages");
+        assert_eq!(report[0].samples, 3);
+        assert_eq!(report[0].mean, Duration::from_millis(20));
+        assert_eq!(report[0].median, Duration::from_millis(20));
+        assert_eq!(report[0].max, Duration::from_millis(30));
+        assert_eq!(report[1].route, "GET /gateway");
+    }
+}
@@ -1,9 +1,20 @@
-use crate::{ratelimit::rest::RateLimits, Result};
+use std::sync::Arc;
+use std::time::Duration;
 
-use super::Discord;
+use reqwest::header::HeaderValue;
+
+use crate::{
+    builders::AllowedMentions,
+    model::{Intents, Token},
+    ratelimit::rest::RateLimits,
+    Error, Object, ReconnectPolicy, Result,
+};
+
+use super::pool::{Connections, CountingResolver};
+use super::{AutoPublish, Discord, Latencies, RetryPolicy};
 
 /// User agent to use when logging into a bot account.
-const BOT_USER_AGENT: &'static str = concat!(
+pub(super) const BOT_USER_AGENT: &'static str = concat!(
     "DiscordBot (https://github.com/spiceswag/discord-tokio, ",
     env!("CARGO_PKG_VERSION"),
     ")"
@@ -20,33 +31,204 @@ pub trait LoginExt {
 
     /// Log in as a user account using the given user authentication token.
     fn from_user_token(token: &str) -> Result<Discord>;
+
+    /// Log in as whichever kind of account the token is for.
+    fn from_token(token: Token) -> Result<Discord>;
 }
 
 impl LoginExt for Discord {
     /// Log in as a bot account using the given bot authentication token.
     /// The token will automatically be prefixed with `Bot `.
     fn from_bot_token(token: &str) -> Result<Discord> {
-        Ok(Discord {
-            rate_limits: RateLimits::default(),
-            client: reqwest::Client::builder()
-                .https_only(true)
-                .user_agent(BOT_USER_AGENT)
-                .build()
-                .expect("Couldn't build HTTPS reqwest client"),
-            token: format!("Bot {}", token.trim()),
-        })
+        DiscordBuilder::new().bot_token(token)
     }
 
     /// Log in as a user account using the given user authentication token.
     fn from_user_token(token: &str) -> Result<Discord> {
-        Ok(Discord {
-            rate_limits: RateLimits::default(),
-            client: reqwest::Client::builder()
-                .https_only(true)
-                .user_agent(USERBOT_USER_AGENT)
-                .build()
-                .expect("Couldn't build HTTPS reqwest client"),
-            token: token.trim().to_string(),
-        })
+        DiscordBuilder::new().user_token(token)
+    }
+
+    /// Log in as whichever kind of account the token is for.
+    fn from_token(token: Token) -> Result<Discord> {
+        DiscordBuilder::new().token(token)
+    }
+}
+
+/// Configure client-wide defaults for a [`Discord`] client before logging in.
+///
+/// ```ignore
+/// let discord = DiscordBuilder::new()
+///     .pool_max_idle_per_host(8)
+///     .http2_keep_alive_interval(Duration::from_secs(30))
+///     .default_allowed_mentions(|m| m.replied_user(false))
+///     .default_audit_log_reason("moderation bot")
+///     .default_request_timeout(Duration::from_secs(10))
+///     .bot_token(&token)?;
+/// ```
+#[derive(Debug, Default)]
+pub struct DiscordBuilder {
+    allowed_mentions: Option<Object>,
+    audit_log_reason: Option<String>,
+    timeout: Option<Duration>,
+    transport_compression: bool,
+    intents: Option<Intents>,
+    reconnect_policy: ReconnectPolicy,
+    retry_policy: RetryPolicy,
+    #[cfg(feature = "signal")]
+    shutdown_on_signal: bool,
+    pool_max_idle_per_host: Option<usize>,
+    pool_idle_timeout: Option<Duration>,
+    http2_keep_alive_interval: Option<Duration>,
+}
+
+impl DiscordBuilder {
+    /// Start configuring a client with no defaults.
+    pub fn new() -> DiscordBuilder {
+        DiscordBuilder::default()
+    }
+
+    /// Restrict the mentions of every new message, unless the message sets its own.
+    pub fn default_allowed_mentions<F: FnOnce(AllowedMentions) -> AllowedMentions>(
+        mut self,
+        f: F,
+    ) -> Self {
+        self.allowed_mentions = Some(AllowedMentions::build(f));
+        self
+    }
+
+    /// Give a reason, shown in the audit log, for every request which modifies something.
+    ///
+    /// Reasons given with `Discord::with_audit_log_reason` are added after it,
+    /// as in `default: reason`.
+    pub fn default_audit_log_reason(mut self, reason: &str) -> Self {
+        self.audit_log_reason = Some(reason.to_owned());
+        self
+    }
+
+    /// Set how long each request may take before failing, as with `Discord::set_timeout`.
+    pub fn default_request_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Compress everything sent by the gateway over connections made with `ConnectExt`,
+    /// as with `Connection::with_transport_compression`. Off by default.
+    pub fn transport_compression(mut self, transport_compression: bool) -> Self {
+        self.transport_compression = transport_compression;
+        self
+    }
+
+    /// Request only these intents over connections made with `ConnectExt`.
+    /// Every event is received by default.
+    pub fn intents(mut self, intents: Intents) -> Self {
+        self.intents = Some(intents);
+        self
+    }
+
+    /// Retry reconnecting gateway connections made with `ConnectExt` as the policy allows.
+    pub fn reconnect_policy(mut self, policy: ReconnectPolicy) -> Self {
+        self.reconnect_policy = policy;
+        self
+    }
+
+    /// Choose how requests which were rate limited or hit a temporary server error are
+    /// retried. See `RetryPolicy` for the defaults, or `RetryPolicy::none` to never retry.
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = policy;
+        self
+    }
+
+    /// Receive a final `Event::Shutdown` on Ctrl-C or `SIGTERM` over connections made with
+    /// `ConnectExt`, as with `ConnectionBuilder::shutdown_on_signal`. Off by default.
+    #[cfg(feature = "signal")]
+    pub fn shutdown_on_signal(mut self, shutdown_on_signal: bool) -> Self {
+        self.shutdown_on_signal = shutdown_on_signal;
+        self
+    }
+
+    /// Keep at most this many idle connections open to Discord. Unlimited by default.
+    pub fn pool_max_idle_per_host(mut self, max: usize) -> Self {
+        self.pool_max_idle_per_host = Some(max);
+        self
+    }
+
+    /// Close connections which have been idle for this long. 90 seconds by default.
+    ///
+    /// Bots which send bursts of requests with quiet periods in between can raise this
+    /// to avoid doing a new TLS handshake at the start of every burst.
+    pub fn pool_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.pool_idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Ping HTTP/2 connections at this interval, even while idle, to keep them open.
+    /// Off by default.
+    pub fn http2_keep_alive_interval(mut self, interval: Duration) -> Self {
+        self.http2_keep_alive_interval = Some(interval);
+        self
+    }
+
+    /// Log in as a bot account using the given bot authentication token.
+    /// The token will automatically be prefixed with `Bot `.
+    pub fn bot_token(self, token: &str) -> Result<Discord> {
+        self.token(Token::bot(token))
+    }
+
+    /// Log in as a user account using the given user authentication token.
+    pub fn user_token(self, token: &str) -> Result<Discord> {
+        self.token(Token::user(token))
+    }
+
+    /// Log in as whichever kind of account the token is for.
+    ///
+    /// Fails if the token is empty or contains characters which can't be sent in a header.
+    pub fn token(self, token: Token) -> Result<Discord> {
+        if token.secret().is_empty() || HeaderValue::from_str(&token.authorization()).is_err() {
+            return Err(Error::Other("Invalid token"));
+        }
+        let user_agent = if token.is_bot() {
+            BOT_USER_AGENT
+        } else {
+            USERBOT_USER_AGENT
+        };
+        Ok(self.login(user_agent, token))
+    }
+
+    fn login(self, user_agent: &str, token: Token) -> Discord {
+        let connections = Arc::new(Connections::default());
+
+        let mut client = super::https_client()
+            .user_agent(user_agent)
+            .dns_resolver(Arc::new(CountingResolver(connections.clone())));
+        if let Some(max) = self.pool_max_idle_per_host {
+            client = client.pool_max_idle_per_host(max);
+        }
+        if let Some(timeout) = self.pool_idle_timeout {
+            client = client.pool_idle_timeout(timeout);
+        }
+        if let Some(interval) = self.http2_keep_alive_interval {
+            client = client
+                .http2_keep_alive_interval(interval)
+                .http2_keep_alive_while_idle(true);
+        }
+
+        Discord {
+            rate_limits: Arc::new(RateLimits::default()),
+            auto_publish: Arc::new(AutoPublish::default()),
+            timeout: self.timeout,
+            latencies: Arc::new(Latencies::default()),
+            connections,
+            allowed_mentions: self.allowed_mentions,
+            audit_log_reason: self.audit_log_reason,
+            request_reason: None,
+            transport_compression: self.transport_compression,
+            intents: self.intents,
+            reconnect_policy: self.reconnect_policy,
+            retry_policy: self.retry_policy,
+            #[cfg(feature = "signal")]
+            shutdown_on_signal: self.shutdown_on_signal,
+            client: client.build().expect("Couldn't build HTTPS reqwest client"),
+            token,
+        }
     }
 }
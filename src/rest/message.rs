@@ -1,15 +1,47 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
 use futures::Future;
 use reqwest::Method;
-use serde_json::json;
+use serde_json::{json, Value};
 use tokio::io::{AsyncRead, AsyncReadExt};
+use tracing::{debug, warn};
 
 use crate::{
     builders::SendMessage,
     error::{Error, Result, StatusChecks},
-    model::{ChannelId, Message, MessageId, ReactionEmoji, User, UserId},
+    model::{ChannelId, Message, MessageId, ReactionEmoji, ReactionType, User, UserId},
+    Object,
 };
 
-use super::Discord;
+use super::{Cursor, Discord};
+
+/// How many messages may be crossposted per channel within `CROSSPOST_WINDOW`.
+const CROSSPOSTS_PER_WINDOW: usize = 10;
+
+/// The window over which crossposts are limited.
+const CROSSPOST_WINDOW: Duration = Duration::from_secs(60 * 60);
+
+/// The oldest a message can be for the bulk delete endpoint to accept it,
+/// with a minute to spare for clock differences and slow purges.
+const BULK_DELETE_MAX_AGE: chrono::Duration = chrono::Duration::minutes(14 * 24 * 60 - 1);
+
+/// The fewest pages of history `purge` scans before giving up on finding more matches,
+/// so that a filter which rejects most messages doesn't walk the whole channel.
+const PURGE_MIN_PAGES: u64 = 10;
+
+/// The pause between deleting messages one by one,
+/// as old message deletion is limited more strictly than the route reports.
+const INDIVIDUAL_DELETE_DELAY: Duration = Duration::from_millis(250);
+
+/// The pause between adding reactions one by one, as reactions are limited per message
+/// rather than per emoji, which the rate limits tracked per route don't catch.
+const REACTION_DELAY: Duration = Duration::from_millis(250);
+
+/// How many times a reaction is retried after being rate limited.
+const REACTION_RETRIES: usize = 3;
 
 /// Discord REST API methods for sending, editing, pining and otherwise interact with sent messages.
 pub trait MessageExt {
@@ -22,17 +54,29 @@ pub trait MessageExt {
 
     /// Get messages in the backlog for a given channel.
     ///
-    /// The `what` argument should be one of the options in the `GetMessages`
-    /// enum, and will determine which messages will be returned. A message
-    /// limit can also be specified, and defaults to 50. More recent messages
-    /// will appear first in the list.
+    /// The `what` argument is a `Cursor` which will determine which messages
+    /// will be returned. A message limit can also be specified, and defaults
+    /// to 50. More recent messages will appear first in the list.
     fn get_messages(
         &self,
         channel: ChannelId,
-        what: GetMessages,
+        what: Cursor<MessageId>,
         limit: Option<u64>,
     ) -> impl Future<Output = Result<Vec<Message>>> + Send;
 
+    /// Get the messages surrounding a given message, for showing it in context.
+    ///
+    /// Up to `before` messages sent before it and `after` messages sent after it
+    /// are returned along with the message itself, oldest first.
+    /// At most 100 messages can be fetched around a message.
+    fn get_context(
+        &self,
+        channel: ChannelId,
+        message: MessageId,
+        before: u64,
+        after: u64,
+    ) -> impl Future<Output = Result<MessageContext>> + Send;
+
     /// Gets a list of the pinned messages for a given channel.
     fn get_pins(&self, channel: ChannelId) -> impl Future<Output = Result<Vec<Message>>> + Send;
 
@@ -55,6 +99,10 @@ pub trait MessageExt {
     ) -> impl Future<Output = Result<()>> + Send;
 
     /// Build and send a message to a given channel.
+    ///
+    /// If `enforce_nonce` is set on the message, a send which fails ambiguously is retried
+    /// once with the same nonce, which Discord answers with the message it already posted
+    /// instead of posting it twice: see [`SendMessage::enforce_nonce`].
     fn send_message<F>(
         &self,
         channel: ChannelId,
@@ -128,6 +176,30 @@ pub trait MessageExt {
         R: AsyncRead + Unpin,
         F: Send + FnOnce(SendMessage) -> SendMessage;
 
+    /// Publish a message in an announcement channel to the channels following it.
+    ///
+    /// Discord only allows 10 messages to be crossposted per channel per hour.
+    fn crosspost_message(
+        &self,
+        channel: ChannelId,
+        message: MessageId,
+    ) -> impl Future<Output = Result<Message>> + Send;
+
+    /// Automatically crosspost every message sent to an announcement channel through this client.
+    ///
+    /// Messages are crossposted in the background once sent, and failures are logged.
+    /// Messages sent once the hourly crosspost limit is reached are queued, and published
+    /// in the background once the limit is reset, or earlier by calling `publish_pending`.
+    fn auto_publish(&self, channel: ChannelId);
+
+    /// Stop crossposting messages sent to a channel, discarding any queued messages.
+    fn stop_auto_publish(&self, channel: ChannelId);
+
+    /// Crosspost queued messages in auto-published channels, as far as the hourly limit allows.
+    ///
+    /// Returns how many messages are still queued.
+    fn publish_pending(&self) -> impl Future<Output = usize> + Send;
+
     /// Delete a previously posted message.
     ///
     /// Requires that either the message was posted by this user, or this user
@@ -157,6 +229,26 @@ pub trait MessageExt {
         messages: &[MessageId],
     ) -> impl Future<Output = Result<()>> + Send;
 
+    /// Delete up to `limit` messages matching `filter` from the recent history of a channel.
+    ///
+    /// Messages younger than 14 days are deleted in bulk, while older messages,
+    /// which the bulk delete endpoint rejects, are deleted one by one with a
+    /// short pause in between. Returns how many messages were deleted each way.
+    ///
+    /// Only the most recent 1,000 messages, or `limit` if that is more, are scanned,
+    /// so fewer than `limit` messages may be deleted even if older ones match.
+    ///
+    /// Requires the `MANAGE_MESSAGES` and `READ_MESSAGE_HISTORY` permissions,
+    /// and like `delete_messages`, may only be used by bots.
+    fn purge<F>(
+        &self,
+        channel: ChannelId,
+        filter: F,
+        limit: u64,
+    ) -> impl Future<Output = Result<PurgeReport>> + Send
+    where
+        F: Send + FnMut(&Message) -> bool;
+
     /// Add a `Reaction` to a `Message`.
     ///
     /// # Examples
@@ -184,6 +276,9 @@ pub trait MessageExt {
     /// ```
     ///
     /// Requires the `ADD_REACTIONS` permission to add a new reaction.
+    ///
+    /// With the `emoji` feature, unicode reactions which are not emojis
+    /// are rejected without making a request.
     fn add_reaction(
         &self,
         channel: ChannelId,
@@ -191,6 +286,25 @@ pub trait MessageExt {
         emoji: ReactionEmoji,
     ) -> impl Future<Output = Result<()>> + Send;
 
+    /// Add several reactions to a `Message`, one at a time so that they appear in order.
+    ///
+    /// Reactions are paced to stay under Discord's limit on reacting to a message, and
+    /// retried if rate limited anyway. The first failure stops the rest from being added,
+    /// so that the reactions which were added are still in order.
+    ///
+    /// ```ignore
+    /// let options = ["1️⃣", "2️⃣", "3️⃣"].map(|name| ReactionEmoji::Unicode { name: name.into() });
+    /// discord.add_reactions_ordered(channel, poll.id, options.to_vec()).await?;
+    /// ```
+    ///
+    /// Requires the `ADD_REACTIONS` permission to add new reactions.
+    fn add_reactions_ordered(
+        &self,
+        channel: ChannelId,
+        message: MessageId,
+        emojis: Vec<ReactionEmoji>,
+    ) -> impl Future<Output = Result<()>> + Send;
+
     /// Delete a `Reaction` from a `Message`.
     ///
     /// # Examples
@@ -241,15 +355,17 @@ pub trait MessageExt {
 
     /// Get users that have reacted with a given `Emoji` in a `Message`.
     ///
-    /// The default `limit` is 50. The optional value of `after` is the ID of
-    /// the user to retrieve the next reactions after.
+    /// Normal reactions and super reactions are listed separately, as selected by `kind`.
+    /// Reacting users are ordered by ID, so pass `Cursor::After` with the last
+    /// user of a page to get the next one. The default `limit` is 25.
     fn get_reactions(
         &self,
         channel: ChannelId,
         message: MessageId,
         emoji: ReactionEmoji,
-        limit: Option<i32>,
-        after: Option<UserId>,
+        kind: ReactionType,
+        after: Cursor<UserId>,
+        limit: Option<u64>,
     ) -> impl Future<Output = Result<Vec<User>>> + Send;
 }
 
@@ -270,27 +386,43 @@ impl MessageExt for Discord {
     async fn get_messages(
         &self,
         channel: ChannelId,
-        what: GetMessages,
+        what: Cursor<MessageId>,
         limit: Option<u64>,
     ) -> Result<Vec<Message>> {
-        use std::fmt::Write;
-        let mut url = format!("/channels/{channel}/messages?limit={}", limit.unwrap_or(50));
-        match what {
-            GetMessages::MostRecent => {}
-            GetMessages::Before(id) => {
-                let _ = write!(url, "&before={}", id);
-            }
-            GetMessages::After(id) => {
-                let _ = write!(url, "&after={}", id);
-            }
-            GetMessages::Around(id) => {
-                let _ = write!(url, "&around={}", id);
-            }
-        }
+        let url = format!("/channels/{channel}/messages?{}", what.query(limit));
 
         Ok(self.empty_request(&url, Method::GET).await?.json().await?)
     }
 
+    async fn get_context(
+        &self,
+        channel: ChannelId,
+        message: MessageId,
+        before: u64,
+        after: u64,
+    ) -> Result<MessageContext> {
+        // `around` splits the limit evenly on both sides of the message
+        let limit = (2 * before.max(after) + 1).min(100);
+        let mut messages = self
+            .get_messages(channel, Cursor::Around(message), Some(limit))
+            .await?;
+        messages.sort_by_key(|m| m.id);
+
+        // the anchor may have been deleted, so split around its ID instead of its position
+        let split = messages.partition_point(|m| m.id < message);
+        let anchor_found = messages.get(split).map_or(false, |m| m.id == message);
+
+        let start = split.saturating_sub(before as usize);
+        let end = (split + anchor_found as usize + after as usize).min(messages.len());
+        messages.truncate(end);
+        messages.drain(..start);
+
+        Ok(MessageContext {
+            messages,
+            anchor: anchor_found.then(|| split - start),
+        })
+    }
+
     async fn get_pins(&self, channel: ChannelId) -> Result<Vec<Message>> {
         let messages = self
             .empty_request(&format!("/channels/{channel}/pins"), Method::GET)
@@ -322,19 +454,28 @@ impl MessageExt for Discord {
     where
         F: Send + FnOnce(SendMessage) -> SendMessage,
     {
-        let map = SendMessage::build(builder);
+        let mut map = SendMessage::build(builder);
+        self.message_defaults(&mut map);
 
-        let message = self
-            .request(
-                &format!("/channels/{channel}/messages"),
-                Method::POST,
-                |req| req.json(&map),
-            )
-            .await?
-            .json()
-            .await?;
+        let enforce_nonce = map.get("enforce_nonce") == Some(&Value::Bool(true));
+        if !enforce_nonce {
+            return self.post_message(channel, &map).await;
+        }
 
-        Ok(message)
+        if !map.contains_key("nonce") {
+            let nonce = rand::random::<u64>().to_string();
+            map.insert("nonce".into(), Value::String(nonce));
+        }
+
+        match self.post_message(channel, &map).await {
+            Err(err) if is_ambiguous(&err) => {
+                warn!("Sending message failed ambiguously, sending it again: {err}");
+                // with the nonce enforced, Discord answers a send repeated within a few
+                // minutes with the message it already posted, instead of posting it twice
+                self.post_message(channel, &map).await
+            }
+            result => result,
+        }
     }
 
     async fn edit_message<F>(
@@ -393,7 +534,8 @@ impl MessageExt for Discord {
     {
         let url = format!("/channels/{channel}/messages");
 
-        let message_data = SendMessage::build(message);
+        let mut message_data = SendMessage::build(message);
+        self.message_defaults(&mut message_data);
         let json_part = reqwest::multipart::Part::bytes(serde_json::to_vec(&message_data)?)
             .mime_str("application/json")?;
 
@@ -453,6 +595,40 @@ impl MessageExt for Discord {
             .await
     }
 
+    async fn crosspost_message(&self, channel: ChannelId, message: MessageId) -> Result<Message> {
+        let message = self
+            .empty_request(
+                &format!("/channels/{channel}/messages/{message}/crosspost"),
+                Method::POST,
+            )
+            .await?
+            .json()
+            .await?;
+
+        Ok(message)
+    }
+
+    fn auto_publish(&self, channel: ChannelId) {
+        self.auto_publish.lock().entry(channel).or_default();
+    }
+
+    fn stop_auto_publish(&self, channel: ChannelId) {
+        self.auto_publish.lock().remove(&channel);
+    }
+
+    async fn publish_pending(&self) -> usize {
+        let channels: Vec<_> = self.auto_publish.lock().keys().copied().collect();
+        for channel in channels {
+            self.publish_queued(channel).await;
+        }
+
+        self.auto_publish
+            .lock()
+            .values()
+            .map(|queue| queue.pending.len())
+            .sum()
+    }
+
     async fn delete_message(&self, channel: ChannelId, message: MessageId) -> Result<()> {
         self.empty_request(
             &format!("/channels/{channel}/messages/{message}"),
@@ -488,12 +664,76 @@ impl MessageExt for Discord {
         .await
     }
 
+    async fn purge<F>(&self, channel: ChannelId, mut filter: F, limit: u64) -> Result<PurgeReport>
+    where
+        F: Send + FnMut(&Message) -> bool,
+    {
+        let bulk_cutoff = Utc::now() - BULK_DELETE_MAX_AGE;
+        let mut bulk = Vec::new();
+        let mut individual = Vec::new();
+
+        let max_pages = PURGE_MIN_PAGES.max(limit.div_ceil(100));
+        let mut what = Cursor::Start;
+        for _ in 0..max_pages {
+            if (bulk.len() + individual.len()) as u64 >= limit {
+                break;
+            }
+            let page = self.get_messages(channel, what, Some(100)).await?;
+            let last = match page.last() {
+                Some(message) => message.id,
+                None => break,
+            };
+
+            for message in page.iter().filter(|message| filter(message)) {
+                if ((bulk.len() + individual.len()) as u64) == limit {
+                    break;
+                }
+                if message.id.creation_date() > bulk_cutoff {
+                    bulk.push(message.id);
+                } else {
+                    individual.push(message.id);
+                }
+            }
+
+            if page.len() < 100 {
+                break;
+            }
+            what = Cursor::Before(last);
+        }
+
+        let mut report = PurgeReport::default();
+
+        for chunk in bulk.chunks(100) {
+            match chunk {
+                // the bulk delete endpoint wants at least two messages
+                [single] => self.delete_message(channel, *single).await?,
+                _ => self.delete_messages(channel, chunk).await?,
+            }
+            report.bulk_deleted += chunk.len() as u64;
+        }
+
+        for (i, message) in individual.into_iter().enumerate() {
+            if i > 0 {
+                tokio::time::sleep(INDIVIDUAL_DELETE_DELAY).await;
+            }
+            self.delete_message(channel, message).await?;
+            report.individually_deleted += 1;
+        }
+
+        Ok(report)
+    }
+
     async fn add_reaction(
         &self,
         channel: ChannelId,
         message: MessageId,
         emoji: ReactionEmoji,
     ) -> Result<()> {
+        #[cfg(feature = "emoji")]
+        if !emoji.is_valid() {
+            return Err(Error::Other("reaction is not a unicode emoji"));
+        }
+
         let emoji = match emoji {
             ReactionEmoji::Custom { name, id, .. } => format!("{}:{}", name, id.0),
             ReactionEmoji::Unicode { name } => name,
@@ -508,6 +748,39 @@ impl MessageExt for Discord {
         .await
     }
 
+    async fn add_reactions_ordered(
+        &self,
+        channel: ChannelId,
+        message: MessageId,
+        emojis: Vec<ReactionEmoji>,
+    ) -> Result<()> {
+        // check every emoji up front, rather than stopping partway through
+        #[cfg(feature = "emoji")]
+        if !emojis.iter().all(ReactionEmoji::is_valid) {
+            return Err(Error::Other("reaction is not a unicode emoji"));
+        }
+
+        for (i, emoji) in emojis.into_iter().enumerate() {
+            if i > 0 {
+                tokio::time::sleep(REACTION_DELAY).await;
+            }
+
+            let mut retries = 0;
+            loop {
+                match self.add_reaction(channel, message, emoji.clone()).await {
+                    Err(Error::RateLimited(millis)) if retries < REACTION_RETRIES => {
+                        debug!("Rate limited adding reactions, waiting {}ms", millis);
+                        tokio::time::sleep(Duration::from_millis(millis)).await;
+                        retries += 1;
+                    }
+                    result => break result?,
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     async fn delete_reaction(
         &self,
         channel: ChannelId,
@@ -541,24 +814,22 @@ impl MessageExt for Discord {
         channel: ChannelId,
         message: MessageId,
         emoji: ReactionEmoji,
-        limit: Option<i32>,
-        after: Option<UserId>,
+        kind: ReactionType,
+        after: Cursor<UserId>,
+        limit: Option<u64>,
     ) -> Result<Vec<User>> {
         let emoji = match emoji {
             ReactionEmoji::Custom { name, id, .. } => format!("{}:{}", name, id.0),
             ReactionEmoji::Unicode { name } => name,
         };
         let mut endpoint = format!(
-            "/channels/{}/messages/{}/reactions/{}?limit={}",
-            channel,
-            message,
-            emoji,
-            limit.unwrap_or(50)
+            "/channels/{}/messages/{}/reactions/{}?type={}",
+            channel, message, emoji, kind as u8
         );
-
-        if let Some(amount) = after {
-            use std::fmt::Write;
-            let _ = write!(endpoint, "&after={}", amount);
+        let cursor = after.checked_query(limit)?;
+        if !cursor.is_empty() {
+            endpoint.push('&');
+            endpoint.push_str(&cursor);
         }
 
         let users = self
@@ -572,14 +843,198 @@ impl MessageExt for Discord {
 }
 
 /// Argument to `get_messages` to specify the desired message retrieval.
-#[derive(Debug)]
-pub enum GetMessages {
-    /// Get the N most recent messages.
-    MostRecent,
-    /// Get the first N messages before the specified message.
-    Before(MessageId),
-    /// Get the first N messages after the specified message.
-    After(MessageId),
-    /// Get N/2 messages before, N/2 messages after, and the specified message.
-    Around(MessageId),
+pub type GetMessages = Cursor<MessageId>;
+
+/// Messages surrounding a message, returned by `get_context`.
+#[derive(Debug, Clone)]
+pub struct MessageContext {
+    /// The messages, oldest first.
+    pub messages: Vec<Message>,
+    /// The index of the requested message in `messages`, or `None` if it was deleted.
+    pub anchor: Option<usize>,
+}
+
+impl MessageContext {
+    /// Get the requested message, if it still exists.
+    pub fn anchor(&self) -> Option<&Message> {
+        self.anchor.map(|i| &self.messages[i])
+    }
+}
+
+/// The outcome of a `purge` call.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PurgeReport {
+    /// How many messages were young enough to be deleted with the bulk delete endpoint.
+    ///
+    /// This includes a single leftover message, which the endpoint doesn't accept on its own.
+    pub bulk_deleted: u64,
+    /// How many messages were too old to be bulk deleted, and were deleted one by one.
+    pub individually_deleted: u64,
+}
+
+/// The announcement channels being auto-published, and their crosspost queues.
+#[derive(Debug, Default)]
+pub(crate) struct AutoPublish(Mutex<HashMap<ChannelId, PublishQueue>>);
+
+#[derive(Debug, Default)]
+struct PublishQueue {
+    /// Messages waiting to be crossposted, oldest first.
+    pending: VecDeque<MessageId>,
+    /// When messages were crossposted within the last hour, oldest first.
+    published: VecDeque<Instant>,
+    /// Whether a task is waiting for the limit to reset to publish the queued messages.
+    scheduled: bool,
+}
+
+impl AutoPublish {
+    fn lock(&self) -> MutexGuard<'_, HashMap<ChannelId, PublishQueue>> {
+        self.0.lock().expect("auto publish queues poisoned")
+    }
+
+    /// Queue a message if its channel is being auto-published.
+    fn enqueue(&self, channel: ChannelId, message: MessageId) -> bool {
+        match self.lock().get_mut(&channel) {
+            Some(queue) => {
+                queue.pending.push_back(message);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Take as many queued messages as may be crossposted right now.
+    ///
+    /// If messages are left queued and no task is waiting to publish them yet, the caller
+    /// becomes that task, and is given how long to wait until the limit allows more.
+    /// `woken` is set by the waiting task itself, once it has waited.
+    fn take_ready(&self, channel: ChannelId, woken: bool) -> (Vec<MessageId>, Option<Duration>) {
+        let mut queues = self.lock();
+        let queue = match queues.get_mut(&channel) {
+            Some(queue) => queue,
+            None => return (Vec::new(), None),
+        };
+        if woken {
+            queue.scheduled = false;
+        }
+
+        while let Some(&at) = queue.published.front() {
+            if at.elapsed() < CROSSPOST_WINDOW {
+                break;
+            }
+            queue.published.pop_front();
+        }
+
+        let available = CROSSPOSTS_PER_WINDOW.saturating_sub(queue.published.len());
+        let ready: Vec<_> = (0..available)
+            .map_while(|_| queue.pending.pop_front())
+            .collect();
+        queue.published.extend(ready.iter().map(|_| Instant::now()));
+
+        if queue.pending.is_empty() || queue.scheduled {
+            return (ready, None);
+        }
+
+        let delay = queue.published.front().map_or(Duration::ZERO, |at| {
+            CROSSPOST_WINDOW.saturating_sub(at.elapsed())
+        });
+        warn!(
+            "Crosspost limit reached in {}, {} message(s) delayed by up to {:?}",
+            channel,
+            queue.pending.len(),
+            delay
+        );
+        queue.scheduled = true;
+        (ready, Some(delay))
+    }
+}
+
+impl Discord {
+    async fn post_message(&self, channel: ChannelId, map: &Object) -> Result<Message> {
+        let message: Message = self
+            .request(
+                &format!("/channels/{channel}/messages"),
+                Method::POST,
+                |req| req.json(map),
+            )
+            .await?
+            .json()
+            .await?;
+
+        // crosspost in the background, so the send doesn't wait on a second request
+        if self.auto_publish.enqueue(channel, message.id) {
+            let discord = self.clone();
+            tokio::spawn(async move { discord.publish_queued(channel).await });
+        }
+        Ok(message)
+    }
+
+    /// Crosspost the queued messages of a channel, as far as the hourly limit allows,
+    /// and have the rest published in the background once the limit is reset.
+    async fn publish_queued(&self, channel: ChannelId) {
+        let Some(delay) = self.publish_ready(channel, false).await else {
+            return;
+        };
+
+        let discord = self.clone();
+        tokio::spawn(async move {
+            let mut delay = delay;
+            loop {
+                tokio::time::sleep(delay).await;
+                match discord.publish_ready(channel, true).await {
+                    Some(next) => delay = next,
+                    None => break,
+                }
+            }
+        });
+    }
+
+    /// Crosspost the messages of a channel which may be crossposted right now,
+    /// returning how long to wait before publishing the rest, as with `take_ready`.
+    async fn publish_ready(&self, channel: ChannelId, woken: bool) -> Option<Duration> {
+        let (ready, delay) = self.auto_publish.take_ready(channel, woken);
+        for message in ready {
+            if let Err(err) = self.crosspost_message(channel, message).await {
+                warn!("Failed to crosspost {} in {}: {:?}", message, channel, err);
+            }
+        }
+        delay
+    }
+}
+
+/// Whether a request may or may not have reached Discord before failing.
+fn is_ambiguous(err: &Error) -> bool {
+    match err {
+        Error::Reqwest(err) => !err.is_connect() && !err.is_builder(),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AutoPublish, CROSSPOSTS_PER_WINDOW, CROSSPOST_WINDOW};
+    use crate::model::{ChannelId, MessageId};
+
+    #[test]
+    fn schedules_one_publisher_for_the_queue() {
+        let channel = ChannelId(1);
+        let auto_publish = AutoPublish::default();
+        auto_publish.lock().entry(channel).or_default();
+        for id in 0..CROSSPOSTS_PER_WINDOW as u64 + 2 {
+            assert!(auto_publish.enqueue(channel, MessageId(id)));
+        }
+
+        let (ready, delay) = auto_publish.take_ready(channel, false);
+        assert_eq!(ready.len(), CROSSPOSTS_PER_WINDOW);
+        assert!(delay.is_some_and(|delay| delay <= CROSSPOST_WINDOW));
+
+        // while a publisher waits for the limit, nobody else is asked to
+        assert_eq!(auto_publish.take_ready(channel, false), (vec![], None));
+        // and it keeps waiting once woken, as long as the limit allows nothing
+        let (ready, delay) = auto_publish.take_ready(channel, true);
+        assert!(ready.is_empty() && delay.is_some());
+
+        // stopping discards the queue, which ends the publisher
+        auto_publish.lock().remove(&channel);
+        assert_eq!(auto_publish.take_ready(channel, true), (vec![], None));
+    }
 }
@@ -7,33 +7,82 @@
 //! extension traits for the purposes of not importing dozens of methods
 //! all at once.
 
+mod attachment;
+
 mod channel;
 pub use channel::*;
 
 mod connect;
 pub use connect::*;
 
+mod event;
+pub use event::*;
+
+mod interaction;
+pub use interaction::*;
+
+mod latency;
+pub use latency::RouteLatency;
+
 mod login;
-use futures::future::OptionFuture;
 pub use login::*;
 
 mod message;
 pub use message::*;
 
+mod pool;
+pub use pool::PoolStats;
+
+mod retry;
+pub use retry::RetryPolicy;
+
 mod server;
 pub use server::*;
 
+mod soundboard;
+pub use soundboard::*;
+
+mod stage;
+pub use stage::*;
+
+mod status;
+pub use status::*;
+
+mod sticker;
+pub use sticker::*;
+
 mod user;
 pub use user::*;
 
+mod webhook;
+pub use webhook::WebhookClient;
+
+mod widget;
+pub use widget::get_widget;
+
 use crate::{
-    error::{CheckStatus, Result},
-    model::{Incident, Maintenance},
+    builders::AllowedMentions,
+    error::{Error, Result},
+    model::{Intents, Token},
     ratelimit::rest::RateLimits,
-    Object,
+    Object, ReconnectPolicy,
 };
 
+use latency::Latencies;
+use message::AutoPublish;
+use pool::Connections;
+
+use std::fmt::{self, Write};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use reqwest::header::{HeaderValue, AUTHORIZATION};
 use reqwest::{Method, RequestBuilder};
+use serde_json::Value;
+use tracing::debug;
+
+/// The header giving the reason for a request, shown in the audit log.
+const AUDIT_LOG_REASON: &str = "X-Audit-Log-Reason";
 
 /// Where the Discord API is mounted on the web.
 const API_BASE: &'static str = "https://discord.com/api/v6";
@@ -49,9 +98,14 @@ const API_BASE: &'static str = "https://discord.com/api/v6";
 ///
 /// Here is a brief guide to those extension traits, in alphabetical order:
 /// - `ChannelExt`: Interact with any sort of channel, in a server or outside of one.
+/// - `EventExt`: Schedule, edit and cancel server events, and subscribe to them.
+/// - `InteractionExt`: Register slash commands, and respond to interactions with them.
 /// - `LoginExt`: Login into the discord API from a bot or user token, or use the automated login system to generate a token.
-/// - `MessageExt`: Send, edit, pin, and react to messages in channels.
+/// - `MessageExt`: Send, edit, pin, crosspost, and react to messages in channels.
 /// - `ServerExt`: Create, fetch, update and delete servers, their invites and so on.
+/// - `SoundboardExt`: Fetch, upload and play the sounds of the soundboard.
+/// - `StageExt`: Start, edit and end live stages in stage channels.
+/// - `StickerExt`: Fetch standard stickers, and manage the custom stickers of servers.
 /// - `UserExt`: Fetch other users, or update the currently logged in one.
 ///
 /// # Multiple Clients
@@ -59,31 +113,62 @@ const API_BASE: &'static str = "https://discord.com/api/v6";
 /// Using multiple [`Discord`] clients is not advisable,
 /// as each one of them will be tracking individual detached rate-limiting counters.
 ///
-/// As 99.9% of operations require only immutable access (`&self`) to the client,
-/// courtesy of the rate-limits being held behind a [`Mutex`][std::sync::Mutex],
-/// it is best to hold the client behind an [`Rc`][std::rc::Rc] or [`Arc`][std::sync::Arc]
-#[derive(Debug)]
+/// Instead, the client can be cloned cheaply to share it between tasks.
+/// Clones share the connection pool and its statistics, rate limits, automatic crossposting and latency statistics
+/// of the client they were cloned from, while settings like [`set_timeout`][Discord::set_timeout]
+/// only apply to the clone they are made on. Clones made by [`with_timeout`][Discord::with_timeout]
+/// and [`with_audit_log_reason`][Discord::with_audit_log_reason] apply a setting to single calls.
+///
+/// # Cancellation
+///
+/// All request futures are cancellation safe, in that dropping one before it completes
+/// (e.g. in a `select!` or under a timeout) leaves the client in a consistent state.
+/// A request which was already sent may still take effect on Discord's side.
+#[derive(Debug, Clone)]
 pub struct Discord {
     /// Configured `reqwest` client for making request.
     client: reqwest::Client,
     /// The used token for making authorized requests.
-    token: String,
+    token: Token,
     /// Keeping track of rate limits for this client.
-    rate_limits: RateLimits,
+    rate_limits: Arc<RateLimits>,
+    /// Announcement channels whose messages are crossposted automatically.
+    auto_publish: Arc<AutoPublish>,
+    /// How long each request may take before failing.
+    timeout: Option<Duration>,
+    /// How long the most recent requests to each route took.
+    latencies: Arc<Latencies>,
+    /// How many requests were sent, and how many connections were opened for them.
+    connections: Arc<Connections>,
+    /// The `allowed_mentions` of new messages which don't set their own.
+    allowed_mentions: Option<Object>,
+    /// The audit log reason given before the reason of each modifying request.
+    audit_log_reason: Option<String>,
+    /// The audit log reason of modifying requests made through this clone.
+    request_reason: Option<String>,
+    /// Whether gateway connections use `zlib-stream` transport compression.
+    transport_compression: bool,
+    /// The intents requested by gateway connections made with `ConnectExt`.
+    intents: Option<Intents>,
+    /// How gateway connections made with `ConnectExt` retry reconnecting.
+    reconnect_policy: ReconnectPolicy,
+    /// How failed requests are retried.
+    retry_policy: RetryPolicy,
+    /// Whether gateway connections made with `ConnectExt` shut down on signals.
+    #[cfg(feature = "signal")]
+    shutdown_on_signal: bool,
 }
 
 impl Discord {
-    /// Make a request while having rate limits and authorization taken care of.
+    /// Make a request while having rate limits, retries, and authorization taken care of.
     async fn request<F: FnOnce(RequestBuilder) -> RequestBuilder>(
         &self,
         url: &str,
         method: Method,
         builder: F,
     ) -> Result<reqwest::Response> {
-        OptionFuture::from(self.rate_limits.check(url)).await;
-
         let request = self.client.request(
-            method,
+            method.clone(),
             &format!(
                 "{API_BASE}{}{}",
                 if url.starts_with('/') { "" } else { "/" },
@@ -91,13 +176,161 @@ impl Discord {
             ),
         );
 
-        let request = builder(request);
+        let request = match self.timeout {
+            Some(timeout) => request.timeout(timeout),
+            None => request,
+        };
+        let mut request = builder(request).build()?;
+
+        let mut authorization = HeaderValue::from_str(&self.token.authorization())
+            .expect("token was checked to be a valid header when logging in");
+        authorization.set_sensitive(true);
+        request.headers_mut().insert(AUTHORIZATION, authorization);
+
+        if request.method() != Method::GET {
+            if let Some(reason) = self.audit_log_header() {
+                request.headers_mut().insert(AUDIT_LOG_REASON, reason);
+            }
+        }
+
+        let mut retries = 0;
+        loop {
+            let mut reservation = self.rate_limits.acquire(&method, url).await;
+
+            // keep a copy to send again, unless the body is streamed
+            let copy = request.try_clone();
+            let started = Instant::now();
+            // from here on the request may reach Discord, even if this future is dropped
+            reservation.mark_sent();
+            let response = self.client.execute(request).await;
+            self.latencies.record(&method, url, started.elapsed());
+            reservation.settle(&response);
+            let response = response?;
+            self.connections.record_request();
+            let _ = self.rate_limits.update(&method, url, &response);
+
+            if response.status().is_success() {
+                return Ok(response);
+            }
+
+            let retry = self.retry_policy.retry(&response, retries);
+            match (retry, copy) {
+                (Some(retry), Some(copy)) => {
+                    debug!(
+                        "Retrying {} {} after {}, waiting {:?}",
+                        method,
+                        url,
+                        response.status(),
+                        retry.delay
+                    );
+                    if retry.global {
+                        self.rate_limits.limit_globally(retry.delay);
+                    }
+                    tokio::time::sleep(retry.delay).await;
+                    request = copy;
+                    retries += 1;
+                }
+                _ => return Err(Error::from_response(response).await),
+            }
+        }
+    }
+
+    /// Get a clone of the client which gives a reason in the audit log for every request
+    /// which modifies something, after the default reason if one was set.
+    ///
+    /// ```ignore
+    /// discord.with_audit_log_reason("spam").add_ban(server, user, 0).await?;
+    /// ```
+    pub fn with_audit_log_reason(&self, reason: &str) -> Discord {
+        Discord {
+            request_reason: Some(reason.to_owned()),
+            ..self.clone()
+        }
+    }
+
+    /// The `X-Audit-Log-Reason` header of modifying requests, joining the default reason
+    /// and the reason of this clone as `default: reason`.
+    fn audit_log_header(&self) -> Option<HeaderValue> {
+        let reason = match (&self.audit_log_reason, &self.request_reason) {
+            (Some(default), Some(reason)) => format!("{}: {}", default, reason),
+            (Some(reason), None) | (None, Some(reason)) => reason.clone(),
+            (None, None) => return None,
+        };
+        let value = HeaderValue::from_str(&percent_encode(&reason))
+            .expect("percent-encoded reason is a valid header");
+        Some(value)
+    }
+
+    /// Set how long each request may take, from sending it until the response
+    /// headers are received, before failing with a timeout error.
+    ///
+    /// By default requests never time out. To give only some requests a different
+    /// timeout, make them through [`with_timeout`](Discord::with_timeout) instead.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Get a clone of the client whose requests time out after the given duration,
+    /// overriding the timeout set with `set_timeout` for the requests made through it.
+    ///
+    /// ```ignore
+    /// let messages = discord
+    ///     .with_timeout(Duration::from_secs(2))
+    ///     .get_messages(channel, Cursor::Start, None)
+    ///     .await?;
+    /// ```
+    pub fn with_timeout(&self, timeout: Duration) -> Discord {
+        Discord {
+            timeout: Some(timeout),
+            ..self.clone()
+        }
+    }
+
+    /// Summarize how long the most recent requests to each route of the API took, slowest first.
+    ///
+    /// Times are measured from sending a request until its response headers are received,
+    /// so they exclude waiting on rate limits and reading the response body. Requests which
+    /// fail or time out count with the time until they did.
+    pub fn latency_report(&self) -> Vec<RouteLatency> {
+        self.latencies.report()
+    }
+
+    /// Count the requests sent by this client and the connections opened for them,
+    /// to check how well connections are being reused.
+    pub fn pool_stats(&self) -> PoolStats {
+        self.connections.stats()
+    }
+
+    /// Serialize the rate limits learned by this client, as with `RateLimits::save`.
+    pub fn save_rate_limits(&self) -> Result<Vec<u8>> {
+        self.rate_limits.save()
+    }
 
-        // todo retries
-        let response = request.send().await?;
-        self.rate_limits.update(url, &response);
+    /// Load rate limits saved by an earlier client, as with `RateLimits::load`.
+    pub fn load_rate_limits(&self, bytes: &[u8]) -> Result<()> {
+        self.rate_limits.load(bytes)
+    }
 
-        Ok(response)
+    /// Fill in the client-wide defaults of a new message, where it doesn't set its own.
+    fn message_defaults(&self, message: &mut Object) {
+        match message.get_mut("allowed_mentions") {
+            None => {
+                if let Some(allowed_mentions) = &self.allowed_mentions {
+                    message.insert("allowed_mentions".into(), allowed_mentions.clone().into());
+                }
+            }
+            // only whether to mention the replied user was chosen, e.g. by `SendMessage::reply`,
+            // which on its own would keep every other mention from notifying
+            Some(Value::Object(own)) if own.keys().all(|key| key == "replied_user") => {
+                let mut allowed_mentions = self
+                    .allowed_mentions
+                    .clone()
+                    .unwrap_or_else(|| AllowedMentions::all().into_inner());
+                allowed_mentions.append(own);
+                *own = allowed_mentions;
+            }
+            Some(_) => {}
+        }
     }
 
     /// Make a request while having rate limits, retries, and authorization taken care of.
@@ -108,81 +341,175 @@ impl Discord {
     }
 }
 
-macro_rules! status_concat {
-    ($e:expr) => {
-        concat!("https://status.discord.com/api/v2", $e)
-    };
+/// Start building an HTTPS-only client, with the TLS backend chosen by the crate features.
+pub(crate) fn https_client() -> reqwest::ClientBuilder {
+    reqwest::Client::builder().https_only(true)
 }
 
-/// Retrieves the current unresolved incidents from the status page.
-pub async fn get_unresolved_incidents() -> Result<Vec<Incident>> {
-    let client = tls_client();
-    let mut response: Object = client
-        .execute(
-            client
-                .get(status_concat!("/incidents/unresolved.json"))
-                .build()
-                .unwrap(),
-        )
-        .await
-        .check_status()
-        .await?
-        .json()
-        .await?;
-
-    match response.remove("incidents") {
-        Some(incidents) => Ok(serde_json::from_value(incidents)?),
-        None => Ok(vec![]),
+/// Percent-encode a header value, as Discord expects for non-ASCII audit log reasons.
+fn percent_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => {
+                let _ = write!(encoded, "%{:02X}", byte);
+            }
+        }
     }
+    encoded
 }
 
-/// Retrieves the active maintenances from the status page.
-pub async fn get_active_maintenances() -> Result<Vec<Maintenance>> {
-    let client = tls_client();
-    let mut response: Object = client
-        .execute(
-            client
-                .get(status_concat!("/scheduled-maintenances/active.json"))
-                .build()
-                .unwrap(),
-        )
-        .await
-        .check_status()
-        .await?
-        .json()
-        .await?;
-
-    match response.remove("scheduled_maintenances") {
-        Some(scheduled_maintenances) => Ok(serde_json::from_value(scheduled_maintenances)?),
-        None => Ok(vec![]),
+/// A position in a paginated list, used by every endpoint which returns results in pages.
+///
+/// Paginated endpoints also take a separate `limit` on the number of results,
+/// which falls back to Discord's default for the endpoint when not given.
+///
+/// The type parameter is the kind of ID the list is ordered by,
+/// e.g. `Cursor<UserId>` for server members and `Cursor<MessageId>` for messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cursor<Id> {
+    /// Start at the beginning of the list.
+    ///
+    /// For messages and the audit log this is the most recent entry,
+    /// while lists like server members and bans start from the lowest ID.
+    Start,
+    /// Get the entries before the given ID.
+    Before(Id),
+    /// Get the entries after the given ID.
+    After(Id),
+    /// Get the entries around the given ID.
+    ///
+    /// Only supported when getting messages, and other endpoints fail with `Error::Other`.
+    Around(Id),
+}
+
+impl<Id: fmt::Display> Cursor<Id> {
+    /// Format the cursor and limit as query string parameters, without a leading `?`.
+    fn query(&self, limit: Option<u64>) -> String {
+        let mut query = String::new();
+        if let Some(limit) = limit {
+            let _ = write!(query, "limit={}&", limit);
+        }
+        let _ = match self {
+            Cursor::Start => Ok(()),
+            Cursor::Before(id) => write!(query, "before={}", id),
+            Cursor::After(id) => write!(query, "after={}", id),
+            Cursor::Around(id) => write!(query, "around={}", id),
+        };
+        query.trim_end_matches('&').to_owned()
+    }
+
+    /// Format the cursor and limit as with `query`, for the endpoints which can only page
+    /// before or after an ID.
+    fn checked_query(&self, limit: Option<u64>) -> Result<String> {
+        if let Cursor::Around(_) = self {
+            return Err(Error::Other(
+                "Cursor::Around is only supported when getting messages",
+            ));
+        }
+        Ok(self.query(limit))
     }
 }
 
-/// Retrieves the upcoming maintenances from the status page.
-pub async fn get_upcoming_maintenances() -> Result<Vec<Maintenance>> {
-    let client = tls_client();
-    let mut response: Object = client
-        .execute(
-            client
-                .get(status_concat!("/scheduled-maintenances/upcoming.json"))
-                .build()
-                .unwrap(),
-        )
-        .await
-        .check_status()
-        .await?
-        .json()
-        .await?;
-
-    match response.remove("scheduled_maintenances") {
-        Some(scheduled_maintenances) => Ok(serde_json::from_value(scheduled_maintenances)?),
-        None => Ok(vec![]),
+impl<Id> Default for Cursor<Id> {
+    fn default() -> Self {
+        Cursor::Start
     }
 }
 
-fn tls_client() -> reqwest::Client {
-    reqwest::Client::builder()
-        .https_only(true)
-        .build()
-        .expect("Couldn't build HTTPS reqwest client")
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use serde_json::json;
+
+    use super::{percent_encode, Cursor};
+    use crate::builders::SendMessage;
+    use crate::model::{MessageId, UserId};
+    use crate::{DiscordBuilder, LoginExt};
+
+    #[test]
+    fn replies_keep_default_mentions() {
+        let reply = || SendMessage::build(|m| m.reply(MessageId(1), false));
+
+        let discord = DiscordBuilder::new()
+            .default_allowed_mentions(|m| m.users(&[]))
+            .bot_token("token")
+            .unwrap();
+        let mut message = reply();
+        discord.message_defaults(&mut message);
+        assert_eq!(
+            message["allowed_mentions"],
+            json!({ "users": [], "replied_user": false })
+        );
+
+        // without defaults, every other mention is still allowed
+        let discord = DiscordBuilder::new().bot_token("token").unwrap();
+        let mut message = reply();
+        discord.message_defaults(&mut message);
+        assert_eq!(
+            message["allowed_mentions"],
+            json!({ "parse": ["users", "roles", "everyone"], "replied_user": false })
+        );
+
+        // while mentions the message restricts itself are left alone
+        let mut message = SendMessage::build(|m| m.allowed_mentions(|m| m.parse(&[])));
+        discord.message_defaults(&mut message);
+        assert_eq!(message["allowed_mentions"], json!({ "parse": [] }));
+    }
+
+    #[test]
+    fn overrides_timeouts_per_clone() {
+        let mut discord = crate::Discord::from_bot_token("token").unwrap();
+        discord.set_timeout(Some(Duration::from_secs(10)));
+
+        let quick = discord.with_timeout(Duration::from_secs(1));
+        assert_eq!(quick.timeout, Some(Duration::from_secs(1)));
+        assert_eq!(discord.timeout, Some(Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn rejects_around_outside_messages() {
+        assert_eq!(
+            Cursor::Before(UserId(1)).checked_query(Some(5)).unwrap(),
+            "limit=5&before=1"
+        );
+        assert_eq!(Cursor::<UserId>::Start.checked_query(None).unwrap(), "");
+        assert!(Cursor::Around(UserId(1)).checked_query(None).is_err());
+    }
+
+    #[test]
+    fn percent_encodes_reasons() {
+        assert_eq!(percent_encode("Spam-bot_1.0~"), "Spam-bot_1.0~");
+        assert_eq!(percent_encode("ban: spam ü"), "ban%3A%20spam%20%C3%BC");
+    }
+
+    #[test]
+    fn prefixes_reasons_with_the_default() {
+        let header = |discord: &crate::Discord| {
+            discord
+                .audit_log_header()
+                .map(|value| value.to_str().unwrap().to_owned())
+        };
+
+        let discord = crate::Discord::from_bot_token("token").unwrap();
+        assert_eq!(header(&discord), None);
+        assert_eq!(
+            header(&discord.with_audit_log_reason("spam")).as_deref(),
+            Some("spam")
+        );
+
+        let discord = DiscordBuilder::new()
+            .default_audit_log_reason("mod bot")
+            .bot_token("token")
+            .unwrap();
+        assert_eq!(header(&discord).as_deref(), Some("mod%20bot"));
+        assert_eq!(
+            header(&discord.with_audit_log_reason("spam")).as_deref(),
+            Some("mod%20bot%3A%20spam")
+        );
+    }
 }
@@ -0,0 +1,98 @@
+//! Statistics on how often the REST client reuses its connections to Discord.
+
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use hyper::client::connect::dns::Name;
+use reqwest::dns::{Addrs, Resolve, Resolving};
+
+/// How well the connection pool of a [`Discord`](super::Discord) client is being reused.
+///
+/// A low share of reused connections means most requests pay for a new TLS handshake,
+/// which can be improved with the pool settings of `DiscordBuilder`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolStats {
+    /// How many requests were sent.
+    pub requests: u64,
+    /// How many new connections were opened for them.
+    pub connections: u64,
+}
+
+impl PoolStats {
+    /// How many requests were sent over a connection which was already open.
+    pub fn reused(&self) -> u64 {
+        self.requests.saturating_sub(self.connections)
+    }
+}
+
+/// Counters of requests sent and connections opened, shared between clones of a client.
+#[derive(Debug, Default)]
+pub(crate) struct Connections {
+    requests: AtomicU64,
+    opened: AtomicU64,
+}
+
+impl Connections {
+    /// Record that a request was sent.
+    pub fn record_request(&self) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Read the counters.
+    pub fn stats(&self) -> PoolStats {
+        PoolStats {
+            requests: self.requests.load(Ordering::Relaxed),
+            connections: self.opened.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// A DNS resolver which counts the connections being opened.
+///
+/// The pool only resolves a host when it has no idle connection to it,
+/// so every lookup is a new connection about to be opened.
+#[derive(Debug)]
+pub(crate) struct CountingResolver(pub Arc<Connections>);
+
+impl Resolve for CountingResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        self.0.opened.fetch_add(1, Ordering::Relaxed);
+        Box::pin(async move {
+            // the port is filled in by the connector
+            let addrs: Vec<SocketAddr> =
+                tokio::net::lookup_host((name.as_str(), 0)).await?.collect();
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+    use std::sync::Arc;
+
+    use hyper::client::connect::dns::Name;
+    use reqwest::dns::Resolve;
+
+    use super::{Connections, CountingResolver};
+
+    #[tokio::test]
+    async fn counts_lookups_as_connections() {
+        let connections = Arc::new(Connections::default());
+        let resolver = CountingResolver(connections.clone());
+
+        for _ in 0..3 {
+            connections.record_request();
+        }
+        let addrs = resolver
+            .resolve(Name::from_str("localhost").unwrap())
+            .await
+            .unwrap();
+        assert!(addrs.count() > 0);
+
+        let stats = connections.stats();
+        assert_eq!((stats.requests, stats.connections), (3, 1));
+        assert_eq!(stats.reused(), 2);
+    }
+}
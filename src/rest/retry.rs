@@ -0,0 +1,154 @@
+//! Retrying REST requests which were rate limited or hit a temporary server error.
+
+use std::time::Duration;
+
+use rand::Rng;
+use reqwest::{Response, StatusCode};
+
+/// How the [`Discord`](super::Discord) client retries requests which failed,
+/// but may succeed if sent again.
+///
+/// Requests which were rate limited anyway, answered with `429 Too Many Requests`,
+/// are sent again once the limit resets, as long as that is within the maximum delay.
+/// A global rate limit holds back every other request of the client until then too.
+/// Requests answered with `502 Bad Gateway`, `503 Service Unavailable` or
+/// `504 Gateway Timeout` are sent again after waiting, with the wait doubling
+/// from the base delay up to the maximum delay, and randomly shortened by up to half.
+///
+/// Requests with a streamed body, like file uploads, can't be sent again and are never
+/// retried.
+///
+/// By default, requests are retried up to 3 times, the base delay is half a second,
+/// and the maximum delay is 30 seconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    base_delay: Duration,
+    max_delay: Duration,
+    max_retries: u32,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> RetryPolicy {
+        RetryPolicy {
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            max_retries: 3,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// The default policy.
+    pub fn new() -> RetryPolicy {
+        RetryPolicy::default()
+    }
+
+    /// A policy which never retries, returning every failure straight away.
+    pub fn none() -> RetryPolicy {
+        RetryPolicy::default().max_retries(0)
+    }
+
+    /// Wait this long before the first retry after a server error.
+    pub fn base_delay(mut self, delay: Duration) -> Self {
+        self.base_delay = delay;
+        self
+    }
+
+    /// Never wait longer than this before a retry, and give up on rate limits which
+    /// reset later than this.
+    pub fn max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = delay;
+        self
+    }
+
+    /// Retry each request at most this many times.
+    pub fn max_retries(mut self, retries: u32) -> Self {
+        self.max_retries = retries;
+        self
+    }
+
+    /// How long to wait before retrying after a server error, counting retries from zero,
+    /// or `None` if the request should fail instead.
+    pub fn delay(&self, retry: u32) -> Option<Duration> {
+        if retry >= self.max_retries {
+            return None;
+        }
+
+        let factor = 1u32 << retry.min(31);
+        let delay = self
+            .base_delay
+            .checked_mul(factor)
+            .map_or(self.max_delay, |delay| delay.min(self.max_delay));
+        let jitter = rand::thread_rng().gen_range(0.0..=0.5);
+        Some(delay.mul_f64(1.0 - jitter))
+    }
+
+    /// Decide whether to retry a request which got a non-success response, and how long
+    /// to wait first.
+    pub(crate) fn retry(&self, response: &Response, retry: u32) -> Option<Retry> {
+        match response.status() {
+            StatusCode::TOO_MANY_REQUESTS if retry < self.max_retries => {
+                let delay = rate_limit_reset(response)?;
+                if delay > self.max_delay {
+                    return None;
+                }
+                Some(Retry {
+                    delay,
+                    global: response.headers().contains_key("X-RateLimit-Global"),
+                })
+            }
+            StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT => Some(Retry {
+                delay: self.delay(retry)?,
+                global: false,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// A decision to send a request again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Retry {
+    /// How long to wait first.
+    pub delay: Duration,
+    /// Whether every request of the client is rate limited, rather than only the route.
+    pub global: bool,
+}
+
+/// Read how long until a rate limit resets from the headers of a `429` response.
+///
+/// The body's `retry_after` is given by the `Retry-After` header too, rounded up to
+/// whole seconds, so the precise `X-RateLimit-Reset-After` is preferred when present.
+fn rate_limit_reset(response: &Response) -> Option<Duration> {
+    let header = |name: &str| {
+        let value = response.headers().get(name)?.to_str().ok()?;
+        Duration::try_from_secs_f64(value.parse().ok()?).ok()
+    };
+    header("X-RateLimit-Reset-After").or_else(|| header("Retry-After"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::RetryPolicy;
+
+    #[test]
+    fn backs_off_and_gives_up() {
+        let policy = RetryPolicy::new()
+            .base_delay(Duration::from_secs(1))
+            .max_delay(Duration::from_secs(3))
+            .max_retries(3);
+
+        let first = policy.delay(0).unwrap();
+        assert!(first >= Duration::from_millis(500) && first <= Duration::from_secs(1));
+        // capped at the maximum delay
+        let third = policy.delay(2).unwrap();
+        assert!(third >= Duration::from_millis(1500) && third <= Duration::from_secs(3));
+        assert_eq!(policy.delay(3), None);
+
+        assert_eq!(RetryPolicy::none().delay(0), None);
+    }
+}
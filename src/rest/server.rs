@@ -5,39 +5,62 @@
 //! Other text-based channels such as Direct Messages, group chats
 //! and `relationships` can be found in the `AtMeExt` extension trait.
 
-use futures::Future;
+use std::time::Duration;
+
+use futures::{stream, Future, Stream, TryStreamExt};
 use reqwest::Method;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
 
 use crate::{
     builders::{EditMember, EditRole, EditServer},
     error::{Result, StatusChecks},
     model::{
-        trim_invite_code, Ban, ChannelId, ChannelType, Emoji, EmojiId, Image, Invite,
-        ManagedInvite, Member, Permissions, Role, RoleId, Server, ServerChannel, ServerId,
-        ServerPreview, ServerPrune, UserId,
+        trim_invite_code, AuditLog, AuditLogAction, AuditLogEntryId, Ban, BanInfo, BulkBan,
+        Channel, ChannelId, ChannelType, Emoji, EmojiId, Image, Invite, JoinRequestId,
+        JoinRequestStatus, ManagedInvite, Member, MfaLevel, Permissions, Role, RoleId, Server,
+        ServerChannel, ServerId, ServerJoinRequest, ServerPreview, ServerPrune, User, UserId,
     },
+    ChannelRef, Error, Object, State,
 };
 
-use super::Discord;
+use super::{ChannelExt, Cursor, Discord};
+
+/// How many times the audit log is checked for a ban before giving up.
+const BAN_INFO_ATTEMPTS: u32 = 4;
+
+/// How many bans can be fetched at once.
+const BANS_PAGE: u64 = 1000;
+
+/// How many users can be banned with one bulk ban.
+const BULK_BAN_LIMIT: usize = 200;
 
 /// Discord Rest API methods for working with servers (guilds) and their channels
 ///
 /// This trait is not meant to be implemented by any type
 /// except the [`Discord`] Rest API client provided by this crate.
 pub trait ServerExt {
-    /// Get the list of servers this user knows about.
-    fn get_servers(&self) -> impl Future<Output = Result<Vec<ServerPreview>>> + Send;
+    /// Get the list of servers this user knows about, ordered by ID.
+    ///
+    /// The default `limit` is 200.
+    fn get_servers(
+        &self,
+        cursor: Cursor<ServerId>,
+        limit: Option<u64>,
+    ) -> impl Future<Output = Result<Vec<ServerPreview>>> + Send;
 
-    /// Gets a specific server.
+    /// Gets a specific server, including its approximate member and presence counts.
     fn get_server(&self, server_id: ServerId) -> impl Future<Output = Result<Server>> + Send;
 
-    /// Gets the list of a specific server's members.
+    /// Gets the list of a specific server's members, ordered by user ID.
+    ///
+    /// Pass `Cursor::After` with the last member of a page to get the next one.
+    /// The default `limit` is 1, and the maximum 1000.
     fn get_server_members(
         &self,
         server_id: ServerId,
-        limit: Option<u32>,
-        after: Option<u32>,
+        after: Cursor<UserId>,
+        limit: Option<u64>,
     ) -> impl Future<Output = Result<Vec<Member>>> + Send;
 
     /// Get the list of channels in a server.
@@ -94,8 +117,33 @@ pub trait ServerExt {
     /// Leave the given server.
     fn leave_server(&self, server: ServerId) -> impl Future<Output = Result<Server>> + Send;
 
-    /// Get the ban list for the given server.
-    fn get_bans(&self, server: ServerId) -> impl Future<Output = Result<Vec<Ban>>> + Send;
+    /// Get the ban list for the given server, ordered by user ID.
+    ///
+    /// The default `limit` is 1000.
+    fn get_bans(
+        &self,
+        server: ServerId,
+        cursor: Cursor<UserId>,
+        limit: Option<u64>,
+    ) -> impl Future<Output = Result<Vec<Ban>>> + Send;
+
+    /// Stream every ban of the given server, fetching them a page at a time.
+    ///
+    /// The stream ends after yielding an error.
+    fn bans(&self, server: ServerId) -> impl Stream<Item = Result<Ban>> + Send + '_;
+
+    /// Ban up to 200 users at once, optionally deleting their recent messages.
+    ///
+    /// Users who could not be banned, for example because they are already banned,
+    /// are listed in the result's `failed_users` rather than failing the request.
+    ///
+    /// Requires the `BAN_MEMBERS` and `MANAGE_GUILD` permissions.
+    fn bulk_ban(
+        &self,
+        server: ServerId,
+        users: &[UserId],
+        delete_message_seconds: u32,
+    ) -> impl Future<Output = Result<BulkBan>> + Send;
 
     /// Ban a user from the server, optionally deleting their recent messages.
     ///
@@ -111,6 +159,35 @@ pub trait ServerExt {
     fn remove_ban(&self, server: ServerId, user: UserId)
         -> impl Future<Output = Result<()>> + Send;
 
+    /// Get entries of a server's audit log, optionally only of one type of action,
+    /// or of the actions performed by one user.
+    ///
+    /// Entries are returned newest first, so pass `Cursor::Before` with the
+    /// last entry of a page to get the next one. The default `limit` is 50.
+    ///
+    /// Requires the `VIEW_AUDIT_LOG` permission.
+    fn get_audit_log(
+        &self,
+        server: ServerId,
+        action: Option<AuditLogAction>,
+        user: Option<UserId>,
+        cursor: Cursor<AuditLogEntryId>,
+        limit: Option<u64>,
+    ) -> impl Future<Output = Result<AuditLog>> + Send;
+
+    /// Find out who banned a user and why, for example upon receiving a `ServerBanAdd` event.
+    ///
+    /// Audit log entries can appear a short while after the ban itself, so the
+    /// audit log is polled a few times with increasing delays before giving up,
+    /// in which case the actor and reason are left empty.
+    ///
+    /// Requires the `VIEW_AUDIT_LOG` permission.
+    fn get_ban_info(
+        &self,
+        server: ServerId,
+        user: User,
+    ) -> impl Future<Output = Result<BanInfo>> + Send;
+
     /// Kick a member from a server.
     fn kick_member(
         &self,
@@ -118,6 +195,34 @@ pub trait ServerExt {
         user: UserId,
     ) -> impl Future<Output = Result<()>> + Send;
 
+    /// Get the join requests of a server with Membership Screening, filtered by their status.
+    ///
+    /// This method may only be used by user accounts.
+    fn get_join_requests(
+        &self,
+        server: ServerId,
+        status: JoinRequestStatus,
+    ) -> impl Future<Output = Result<Vec<ServerJoinRequest>>> + Send;
+
+    /// Approve a submitted join request, letting the user into the server.
+    ///
+    /// This method may only be used by user accounts.
+    fn approve_join_request(
+        &self,
+        server: ServerId,
+        request: JoinRequestId,
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    /// Reject a submitted join request, optionally giving the user a reason.
+    ///
+    /// This method may only be used by user accounts.
+    fn reject_join_request(
+        &self,
+        server: ServerId,
+        request: JoinRequestId,
+        reason: Option<&str>,
+    ) -> impl Future<Output = Result<()>> + Send;
+
     /// Extract information from an invite.
     ///
     /// The invite should either be a URL of the form `http://discord.gg/CODE`,
@@ -281,6 +386,25 @@ pub trait ServerExt {
     where
         F: Send + FnOnce(EditRole) -> EditRole;
 
+    /// Move channels around a server, changing their positions and categories.
+    ///
+    /// Use [`ChannelMove::plan`] to compute the moves needed to reach a given layout.
+    fn reorder_channels(
+        &self,
+        server: ServerId,
+        moves: &[ChannelMove],
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    /// Create a copy of a server channel with a new name, keeping its topic, permission overwrites,
+    /// slowmode, category, and other settings, but not its messages.
+    ///
+    /// The copy is placed right below the original. Threads can't be cloned.
+    fn clone_channel(
+        &self,
+        channel: ChannelId,
+        name: &str,
+    ) -> impl Future<Output = Result<ServerChannel>> + Send;
+
     /// Reorder the roles on a server.
     fn reorder_roles(
         &self,
@@ -312,12 +436,30 @@ pub trait ServerExt {
         server: ServerId,
         days: u16,
     ) -> impl Future<Output = Result<ServerPrune>> + Send;
+
+    /// Change whether moderators of a server need two-factor authentication to take
+    /// moderation actions, returning the new level.
+    ///
+    /// Only the server's owner may do this, and only with two-factor authentication enabled
+    /// on their own account.
+    fn set_mfa_level(
+        &self,
+        server: ServerId,
+        level: MfaLevel,
+    ) -> impl Future<Output = Result<MfaLevel>> + Send;
 }
 
 impl ServerExt for Discord {
-    async fn get_servers(&self) -> Result<Vec<ServerPreview>> {
+    async fn get_servers(
+        &self,
+        cursor: Cursor<ServerId>,
+        limit: Option<u64>,
+    ) -> Result<Vec<ServerPreview>> {
         let servers = self
-            .empty_request("/users/@me/guilds", Method::GET)
+            .empty_request(
+                &format!("/users/@me/guilds?{}", cursor.checked_query(limit)?),
+                Method::GET,
+            )
             .await?
             .json()
             .await?;
@@ -327,7 +469,10 @@ impl ServerExt for Discord {
 
     async fn get_server(&self, server_id: ServerId) -> Result<Server> {
         let server = self
-            .empty_request(&format!("/guilds/{server_id}"), Method::GET)
+            .empty_request(
+                &format!("/guilds/{server_id}?with_counts=true"),
+                Method::GET,
+            )
             .await?
             .json()
             .await?;
@@ -338,17 +483,14 @@ impl ServerExt for Discord {
     async fn get_server_members(
         &self,
         server_id: ServerId,
-        limit: Option<u32>,
-        after: Option<u32>,
+        after: Cursor<UserId>,
+        limit: Option<u64>,
     ) -> Result<Vec<Member>> {
-        let limit = limit.unwrap_or(1);
-        let after = after.unwrap_or(0);
-
         let members = self
             .empty_request(
                 &format!(
-                    "/guilds/{server_id}/members?limit={}&after={}",
-                    limit, after
+                    "/guilds/{server_id}/members?{}",
+                    after.checked_query(limit)?
                 ),
                 Method::GET,
             )
@@ -444,9 +586,17 @@ impl ServerExt for Discord {
         Ok(server)
     }
 
-    async fn get_bans(&self, server: ServerId) -> Result<Vec<Ban>> {
+    async fn get_bans(
+        &self,
+        server: ServerId,
+        cursor: Cursor<UserId>,
+        limit: Option<u64>,
+    ) -> Result<Vec<Ban>> {
         let bans = self
-            .empty_request(&format!("/guilds/{server}/bans"), Method::GET)
+            .empty_request(
+                &format!("/guilds/{server}/bans?{}", cursor.checked_query(limit)?),
+                Method::GET,
+            )
             .await?
             .json()
             .await?;
@@ -454,6 +604,120 @@ impl ServerExt for Discord {
         Ok(bans)
     }
 
+    fn bans(&self, server: ServerId) -> impl Stream<Item = Result<Ban>> + Send + '_ {
+        let pages = stream::try_unfold(Some(Cursor::Start), move |cursor| async move {
+            let Some(cursor) = cursor else {
+                return Ok(None);
+            };
+            let bans = self.get_bans(server, cursor, Some(BANS_PAGE)).await?;
+
+            let next = match bans.last() {
+                Some(last) if bans.len() as u64 == BANS_PAGE => Some(Cursor::After(last.user.id)),
+                _ => None,
+            };
+            Result::Ok(Some((stream::iter(bans.into_iter().map(Ok)), next)))
+        });
+        pages.try_flatten()
+    }
+
+    async fn bulk_ban(
+        &self,
+        server: ServerId,
+        users: &[UserId],
+        delete_message_seconds: u32,
+    ) -> Result<BulkBan> {
+        let mut users = users.to_vec();
+        users.sort();
+        users.dedup();
+
+        if users.is_empty() {
+            return Err(Error::Other("A minimum of 1 user id must be supplied"));
+        } else if users.len() > BULK_BAN_LIMIT {
+            return Err(Error::Other("A maximum of 200 user ids may be supplied"));
+        }
+
+        let map = json! {{
+            "user_ids": users,
+            "delete_message_seconds": delete_message_seconds,
+        }};
+        let result = self
+            .request(&format!("/guilds/{server}/bulk-ban"), Method::POST, |req| {
+                req.json(&map)
+            })
+            .await?
+            .json()
+            .await?;
+
+        Ok(result)
+    }
+
+    async fn get_audit_log(
+        &self,
+        server: ServerId,
+        action: Option<AuditLogAction>,
+        user: Option<UserId>,
+        cursor: Cursor<AuditLogEntryId>,
+        limit: Option<u64>,
+    ) -> Result<AuditLog> {
+        let mut query = vec![cursor.checked_query(limit)?];
+        if let Some(action) = action {
+            query.push(format!("action_type={}", action.0));
+        }
+        if let Some(user) = user {
+            query.push(format!("user_id={}", user));
+        }
+        query.retain(|param| !param.is_empty());
+
+        let url = format!("/guilds/{server}/audit-logs?{}", query.join("&"));
+        Ok(self.empty_request(&url, Method::GET).await?.json().await?)
+    }
+
+    async fn get_ban_info(&self, server: ServerId, user: User) -> Result<BanInfo> {
+        let target = user.id.to_string();
+        let mut delay = Duration::from_millis(500);
+
+        for attempt in 1..=BAN_INFO_ATTEMPTS {
+            let log = self
+                .get_audit_log(
+                    server,
+                    Some(AuditLogAction::MEMBER_BAN_ADD),
+                    None,
+                    Cursor::Start,
+                    Some(10),
+                )
+                .await?;
+
+            let entry = log
+                .entries
+                .into_iter()
+                .find(|entry| entry.target_id.as_deref() == Some(&*target));
+            if let Some(entry) = entry {
+                let actor = entry
+                    .user_id
+                    .and_then(|id| log.users.into_iter().find(|user| user.id == id));
+                return Ok(BanInfo {
+                    server_id: server,
+                    user,
+                    actor,
+                    reason: entry.reason,
+                });
+            }
+
+            // the entry may be logged shortly after the ban, so wait before trying again
+            if attempt < BAN_INFO_ATTEMPTS {
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+            }
+        }
+
+        Ok(BanInfo {
+            server_id: server,
+            user,
+            actor: None,
+            reason: None,
+        })
+    }
+
     async fn add_ban(
         &self,
         server: ServerId,
@@ -486,6 +750,63 @@ impl ServerExt for Discord {
             .await
     }
 
+    async fn get_join_requests(
+        &self,
+        server: ServerId,
+        status: JoinRequestStatus,
+    ) -> Result<Vec<ServerJoinRequest>> {
+        let mut response: Object = self
+            .empty_request(
+                &format!("/guilds/{server}/requests?status={}", status.name()),
+                Method::GET,
+            )
+            .await?
+            .json()
+            .await?;
+
+        match response.remove("guild_join_requests") {
+            Some(requests) => Ok(serde_json::from_value(requests)?),
+            None => Err(Error::Decode(
+                "get_join_requests missing \"guild_join_requests\"",
+                serde_json::Value::Object(response),
+            )),
+        }
+    }
+
+    async fn approve_join_request(&self, server: ServerId, request: JoinRequestId) -> Result<()> {
+        let map = json! {{ "action": JoinRequestStatus::Approved }};
+
+        self.request(
+            &format!("/guilds/{server}/requests/id/{request}"),
+            Method::PATCH,
+            |req| req.json(&map),
+        )
+        .await?
+        .insure_no_content()
+        .await
+    }
+
+    async fn reject_join_request(
+        &self,
+        server: ServerId,
+        request: JoinRequestId,
+        reason: Option<&str>,
+    ) -> Result<()> {
+        let map = json! {{
+            "action": JoinRequestStatus::Rejected,
+            "rejection_reason": reason,
+        }};
+
+        self.request(
+            &format!("/guilds/{server}/requests/id/{request}"),
+            Method::PATCH,
+            |req| req.json(&map),
+        )
+        .await?
+        .insure_no_content()
+        .await
+    }
+
     async fn get_invite(&self, invite: &str) -> Result<Invite> {
         let invite = trim_invite_code(invite).unwrap_or(invite);
 
@@ -572,7 +893,7 @@ impl ServerExt for Discord {
     async fn create_emoji(&self, server: ServerId, name: &str, image: Image) -> Result<Emoji> {
         let map = json! {{
             "name": name,
-            "image": image.data,
+            "image": image,
         }};
 
         let emoji = self
@@ -758,6 +1079,37 @@ impl ServerExt for Discord {
         Ok(role)
     }
 
+    async fn reorder_channels(&self, server: ServerId, moves: &[ChannelMove]) -> Result<()> {
+        self.request(
+            &format!("/guilds/{server}/channels"),
+            Method::PATCH,
+            |req| req.json(moves),
+        )
+        .await?
+        .insure_no_content()
+        .await
+    }
+
+    async fn clone_channel(&self, channel: ChannelId, name: &str) -> Result<ServerChannel> {
+        let channel = match self.get_channel(channel).await? {
+            Channel::Server(channel) => channel,
+            _ => return Err(Error::Other("only server channels can be cloned")),
+        };
+        let map = clone_payload(&channel, name)?;
+
+        let clone = self
+            .request(
+                &format!("/guilds/{}/channels", channel.server_id()),
+                Method::POST,
+                |req| req.json(&map),
+            )
+            .await?
+            .json()
+            .await?;
+
+        Ok(clone)
+    }
+
     async fn reorder_roles(
         &self,
         server: ServerId,
@@ -818,4 +1170,296 @@ impl ServerExt for Discord {
 
         Ok(prune)
     }
+
+    async fn set_mfa_level(&self, server: ServerId, level: MfaLevel) -> Result<MfaLevel> {
+        #[derive(Deserialize)]
+        struct Level {
+            level: MfaLevel,
+        }
+
+        let map = json! {{ "level": level }};
+
+        let level: Level = self
+            .request(&format!("/guilds/{server}/mfa"), Method::POST, |req| {
+                req.json(&map)
+            })
+            .await?
+            .json()
+            .await?;
+
+        Ok(level.level)
+    }
+}
+
+impl Discord {
+    /// Find which of the `needed` permissions the bot is missing in a server channel,
+    /// so that a command can be refused up front rather than failing midway.
+    ///
+    /// The bot's member is looked up in `state`, and fetched if it isn't there,
+    /// while the server's roles and the channel's overwrites are taken from `state`.
+    /// Returns an empty set if nothing is missing.
+    ///
+    /// ```ignore
+    /// let missing = discord
+    ///     .check_bot_permissions(&state, channel, Permissions::MANAGE_MESSAGES)
+    ///     .await?;
+    /// if !missing.is_empty() {
+    ///     discord.send_message(channel, |m| m.content(&format!("I need {:?}", missing))).await?;
+    /// }
+    /// ```
+    pub async fn check_bot_permissions(
+        &self,
+        state: &State,
+        channel: ChannelId,
+        needed: Permissions,
+    ) -> Result<Permissions> {
+        let server = match state.find_channel(channel) {
+            Some(ChannelRef::Server(server, _)) => server,
+            _ => return Err(Error::Other("Channel is not a known server channel")),
+        };
+
+        let bot = state.user().id;
+        let member = match state.member(server.id, bot) {
+            Some(member) => member,
+            None => self.get_member(server.id, bot).await?,
+        };
+
+        let granted = server.member_permissions(channel, bot, Some(&member));
+        Ok(needed - granted)
+    }
+}
+
+/// A change to a channel's position or category, for use with `reorder_channels`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ChannelMove {
+    /// The channel to move.
+    pub id: ChannelId,
+    /// The new sorting position of the channel, or `None` to leave it as is.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub position: Option<i64>,
+    /// The new category of the channel, or `None` to leave it as is.
+    ///
+    /// `Some(None)` takes the channel out of its category.
+    #[serde(rename = "parent_id", skip_serializing_if = "Option::is_none")]
+    pub category: Option<Option<ChannelId>>,
+    /// Whether to sync the channel's permission overwrites with its new category.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lock_permissions: Option<bool>,
+}
+
+impl ChannelMove {
+    /// Compute the moves needed to go from the current channel layout to the desired one.
+    ///
+    /// `desired` lists channels in the order they should appear, each with the category
+    /// it should be in, and `current` should be every channel of the server. In each category
+    /// given, the listed channels come first, followed by the channels already in it which
+    /// are not listed, in their current order, so no two channels share a position.
+    /// Only the channels whose position or category changes are moved.
+    pub fn plan(
+        current: &[ServerChannel],
+        desired: &[(ChannelId, Option<ChannelId>)],
+    ) -> Vec<ChannelMove> {
+        let find = |id: ChannelId| current.iter().find(|c| *c.id() == id);
+        let listed = |id: &ChannelId| desired.iter().any(|(other, _)| other == id);
+
+        let mut categories = Vec::new();
+        for &(_, category) in desired {
+            if !categories.contains(&category) {
+                categories.push(category);
+            }
+        }
+
+        let mut moves = Vec::new();
+        for category in categories {
+            // positions only matter relative to the other channels with the same parent
+            let mut unlisted: Vec<&ServerChannel> = current
+                .iter()
+                .filter(|c| c.category_id() == category && !listed(c.id()))
+                .collect();
+            unlisted.sort_by_key(|c| (c.position(), *c.id()));
+            let layout = desired
+                .iter()
+                .filter(|&&(_, other)| other == category)
+                .filter_map(|&(id, _)| find(id))
+                .chain(unlisted);
+
+            for (position, channel) in layout.enumerate() {
+                let position = Some(position as i64).filter(|&p| channel.position() != Some(p));
+                let category = Some(category).filter(|&c| channel.category_id() != c);
+
+                if position.is_some() || category.is_some() {
+                    moves.push(ChannelMove {
+                        id: *channel.id(),
+                        position,
+                        category,
+                        lock_permissions: None,
+                    });
+                }
+            }
+        }
+        moves
+    }
+}
+
+/// The settings of a channel which are copied when cloning it.
+const CLONED_FIELDS: &[&str] = &[
+    "type",
+    "topic",
+    "bitrate",
+    "user_limit",
+    "rate_limit_per_user",
+    "position",
+    "permission_overwrites",
+    "parent_id",
+    "nsfw",
+    "rtc_region",
+    "video_quality_mode",
+    "default_auto_archive_duration",
+    "default_reaction_emoji",
+    "available_tags",
+    "default_sort_order",
+    "default_forum_layout",
+    "default_thread_rate_limit_per_user",
+];
+
+/// Build the body creating a copy of a channel with the given name.
+fn clone_payload(channel: &ServerChannel, name: &str) -> Result<Object> {
+    if channel.permission_overwrites().is_none() {
+        return Err(Error::Other("threads can't be cloned"));
+    }
+
+    let mut map = match serde_json::to_value(channel)? {
+        serde_json::Value::Object(map) => map,
+        other => return Err(Error::Decode("channel is not an object", other)),
+    };
+    map.retain(|key, value| CLONED_FIELDS.contains(&key.as_str()) && !value.is_null());
+    map.insert("name".into(), name.into());
+    Ok(map)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{clone_payload, ChannelMove};
+    use crate::model::{ChannelId, RoleId, ServerChannel, UserId};
+
+    fn channel(id: u64, position: i64, category: Option<u64>) -> ServerChannel {
+        serde_json::from_value(json!({
+            "type": 0,
+            "id": id.to_string(),
+            "guild_id": "100",
+            "name": "channel",
+            "position": position,
+            "parent_id": category.map(|c| c.to_string()),
+            "permission_overwrites": [],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn plans_minimal_moves() {
+        let current = [
+            channel(1, 0, Some(10)),
+            channel(2, 1, Some(10)),
+            channel(3, 0, Some(20)),
+        ];
+        let cat = |id| Some(ChannelId(id));
+
+        // move channel 3 to the top of category 10
+        let moves = ChannelMove::plan(
+            &current,
+            &[
+                (ChannelId(3), cat(10)),
+                (ChannelId(1), cat(10)),
+                (ChannelId(2), cat(10)),
+            ],
+        );
+        assert_eq!(
+            moves,
+            vec![
+                ChannelMove {
+                    id: ChannelId(3),
+                    position: None,
+                    category: Some(cat(10)),
+                    lock_permissions: None,
+                },
+                ChannelMove {
+                    id: ChannelId(1),
+                    position: Some(1),
+                    category: None,
+                    lock_permissions: None,
+                },
+                ChannelMove {
+                    id: ChannelId(2),
+                    position: Some(2),
+                    category: None,
+                    lock_permissions: None,
+                },
+            ]
+        );
+
+        // moving only channel 2 to the top shifts channel 1 down, rather than sharing its place
+        let moves = ChannelMove::plan(&current, &[(ChannelId(2), cat(10))]);
+        assert_eq!(
+            moves.iter().map(|m| (m.id, m.position)).collect::<Vec<_>>(),
+            [(ChannelId(2), Some(0)), (ChannelId(1), Some(1))]
+        );
+
+        // as does moving channel 3 into category 10 without listing the others
+        let moves = ChannelMove::plan(&current, &[(ChannelId(3), cat(10))]);
+        let mut layout: Vec<_> = current
+            .iter()
+            .map(|c| {
+                let moved = moves.iter().find(|m| m.id == *c.id());
+                (
+                    moved.and_then(|m| m.category).unwrap_or(c.category_id()),
+                    moved.and_then(|m| m.position).or(c.position()),
+                )
+            })
+            .collect();
+        layout.sort();
+        layout.dedup();
+        assert_eq!(layout.len(), current.len());
+        assert_eq!(moves.len(), 3);
+    }
+
+    #[test]
+    fn clones_channel_settings() {
+        let map = clone_payload(&channel(1, 3, Some(10)), "general-2").unwrap();
+        assert_eq!(
+            serde_json::Value::Object(map),
+            json!({
+                "type": 0,
+                "name": "general-2",
+                "position": 3,
+                "parent_id": ChannelId(10),
+                "permission_overwrites": [],
+                "nsfw": false,
+            })
+        );
+
+        // permissions are sent as strings, as Discord sends them
+        let channel: ServerChannel = serde_json::from_value(json!({
+            "type": 0,
+            "id": "1",
+            "guild_id": "100",
+            "name": "channel",
+            "position": 0,
+            "parent_id": null,
+            "permission_overwrites": [
+                {"id": "100", "type": 0, "allow": "1024", "deny": "2048"},
+                {"id": "7", "type": 1, "allow": "0", "deny": "2199023255552"},
+            ],
+        }))
+        .unwrap();
+        let map = clone_payload(&channel, "general-2").unwrap();
+        assert_eq!(
+            map["permission_overwrites"],
+            json!([
+                {"id": RoleId(100), "type": 0, "allow": "1024", "deny": "2048"},
+                {"id": UserId(7), "type": 1, "allow": "0", "deny": "2199023255552"},
+            ])
+        );
+    }
 }
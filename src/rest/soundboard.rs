@@ -0,0 +1,232 @@
+use futures::Future;
+use reqwest::Method;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{
+    builders::EditSoundboardSound,
+    error::{Result, StatusChecks},
+    model::{ChannelId, ServerId, SoundId, SoundboardSound},
+};
+
+use super::Discord;
+
+/// Discord REST API methods for the default and server soundboard sounds.
+pub trait SoundboardExt {
+    /// Get the sounds every user can play from the soundboard.
+    fn list_default_soundboard_sounds(
+        &self,
+    ) -> impl Future<Output = Result<Vec<SoundboardSound>>> + Send;
+
+    /// Get the custom soundboard sounds of a server.
+    fn list_server_soundboard_sounds(
+        &self,
+        server: ServerId,
+    ) -> impl Future<Output = Result<Vec<SoundboardSound>>> + Send;
+
+    /// Get a single custom soundboard sound of a server.
+    fn get_server_soundboard_sound(
+        &self,
+        server: ServerId,
+        sound: SoundId,
+    ) -> impl Future<Output = Result<SoundboardSound>> + Send;
+
+    /// Upload a custom soundboard sound to a server.
+    ///
+    /// The file must be an MP3 or Ogg file of at most 512 KB and 5.2 seconds.
+    /// See `EditSoundboardSound` for the optional fields.
+    ///
+    /// Requires the `MANAGE_EMOJIS` permission.
+    fn create_server_soundboard_sound<F>(
+        &self,
+        server: ServerId,
+        name: &str,
+        sound: Vec<u8>,
+        f: F,
+    ) -> impl Future<Output = Result<SoundboardSound>> + Send
+    where
+        F: Send + FnOnce(EditSoundboardSound) -> EditSoundboardSound;
+
+    /// Edit a custom soundboard sound of a server. See `EditSoundboardSound` for the editable
+    /// fields.
+    ///
+    /// Requires the `MANAGE_EMOJIS` permission.
+    fn edit_server_soundboard_sound<F>(
+        &self,
+        server: ServerId,
+        sound: SoundId,
+        f: F,
+    ) -> impl Future<Output = Result<SoundboardSound>> + Send
+    where
+        F: Send + FnOnce(EditSoundboardSound) -> EditSoundboardSound;
+
+    /// Delete a custom soundboard sound of a server.
+    ///
+    /// Requires the `MANAGE_EMOJIS` permission.
+    fn delete_server_soundboard_sound(
+        &self,
+        server: ServerId,
+        sound: SoundId,
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    /// Play a soundboard sound in the voice channel the current user is connected to.
+    ///
+    /// `source` is the server the sound is from, which must be given to play a custom sound
+    /// of another server than the channel's. Default sounds have no server.
+    ///
+    /// Requires the `SPEAK` permission, and the current user must not be muted or deafened.
+    fn send_soundboard_sound(
+        &self,
+        channel: ChannelId,
+        sound: SoundId,
+        source: Option<ServerId>,
+    ) -> impl Future<Output = Result<()>> + Send;
+}
+
+impl SoundboardExt for Discord {
+    async fn list_default_soundboard_sounds(&self) -> Result<Vec<SoundboardSound>> {
+        let sounds = self
+            .empty_request("/soundboard-default-sounds", Method::GET)
+            .await?
+            .json()
+            .await?;
+
+        Ok(sounds)
+    }
+
+    async fn list_server_soundboard_sounds(
+        &self,
+        server: ServerId,
+    ) -> Result<Vec<SoundboardSound>> {
+        #[derive(Deserialize)]
+        struct Sounds {
+            items: Vec<SoundboardSound>,
+        }
+
+        let sounds: Sounds = self
+            .empty_request(&format!("/guilds/{server}/soundboard-sounds"), Method::GET)
+            .await?
+            .json()
+            .await?;
+
+        Ok(sounds.items)
+    }
+
+    async fn get_server_soundboard_sound(
+        &self,
+        server: ServerId,
+        sound: SoundId,
+    ) -> Result<SoundboardSound> {
+        let sound = self
+            .empty_request(
+                &format!("/guilds/{server}/soundboard-sounds/{sound}"),
+                Method::GET,
+            )
+            .await?
+            .json()
+            .await?;
+
+        Ok(sound)
+    }
+
+    async fn create_server_soundboard_sound<F>(
+        &self,
+        server: ServerId,
+        name: &str,
+        sound: Vec<u8>,
+        f: F,
+    ) -> Result<SoundboardSound>
+    where
+        F: Send + FnOnce(EditSoundboardSound) -> EditSoundboardSound,
+    {
+        let mut map = EditSoundboardSound::build(|b| f(b.name(name)));
+        map.insert("sound".into(), sound_data_uri(&sound).into());
+
+        let sound = self
+            .request(
+                &format!("/guilds/{server}/soundboard-sounds"),
+                Method::POST,
+                |req| req.json(&map),
+            )
+            .await?
+            .json()
+            .await?;
+
+        Ok(sound)
+    }
+
+    async fn edit_server_soundboard_sound<F>(
+        &self,
+        server: ServerId,
+        sound: SoundId,
+        f: F,
+    ) -> Result<SoundboardSound>
+    where
+        F: Send + FnOnce(EditSoundboardSound) -> EditSoundboardSound,
+    {
+        let map = EditSoundboardSound::build(f);
+
+        let sound = self
+            .request(
+                &format!("/guilds/{server}/soundboard-sounds/{sound}"),
+                Method::PATCH,
+                |req| req.json(&map),
+            )
+            .await?
+            .json()
+            .await?;
+
+        Ok(sound)
+    }
+
+    async fn delete_server_soundboard_sound(&self, server: ServerId, sound: SoundId) -> Result<()> {
+        self.empty_request(
+            &format!("/guilds/{server}/soundboard-sounds/{sound}"),
+            Method::DELETE,
+        )
+        .await?
+        .insure_no_content()
+        .await
+    }
+
+    async fn send_soundboard_sound(
+        &self,
+        channel: ChannelId,
+        sound: SoundId,
+        source: Option<ServerId>,
+    ) -> Result<()> {
+        let map = json! {{
+            "sound_id": sound,
+            "source_guild_id": source,
+        }};
+
+        self.request(
+            &format!("/channels/{channel}/send-soundboard-sound"),
+            Method::POST,
+            |req| req.json(&map),
+        )
+        .await?
+        .insure_no_content()
+        .await
+    }
+}
+
+/// Encode a sound file as a data URI, detecting Ogg files by their magic number.
+fn sound_data_uri(sound: &[u8]) -> String {
+    let media_type = match sound {
+        [b'O', b'g', b'g', b'S', ..] => "audio/ogg",
+        _ => "audio/mpeg",
+    };
+    format!("data:{};base64,{}", media_type, base64::encode(sound))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::sound_data_uri;
+
+    #[test]
+    fn detects_sound_formats() {
+        assert_eq!(sound_data_uri(b"OggS\0"), "data:audio/ogg;base64,T2dnUwA=");
+        assert_eq!(sound_data_uri(b"ID3"), "data:audio/mpeg;base64,SUQz");
+    }
+}
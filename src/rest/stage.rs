@@ -0,0 +1,109 @@
+use futures::Future;
+use reqwest::Method;
+use serde_json::json;
+
+use crate::{
+    error::{Result, StatusChecks},
+    model::{ActiveStage, ChannelId, StagePrivacyLevel},
+};
+
+use super::Discord;
+
+/// Discord REST API methods for starting and managing live stages.
+pub trait StageExt {
+    /// Get the live stage instance of a stage channel.
+    fn get_stage_instance(
+        &self,
+        channel: ChannelId,
+    ) -> impl Future<Output = Result<ActiveStage>> + Send;
+
+    /// Make a stage channel live with the given topic (1-120 characters).
+    ///
+    /// Requires the current user to be a moderator of the stage, having the
+    /// `MANAGE_CHANNELS`, `MUTE_MEMBERS` and `MOVE_MEMBERS` permissions.
+    fn create_stage_instance(
+        &self,
+        channel: ChannelId,
+        topic: &str,
+        privacy: StagePrivacyLevel,
+    ) -> impl Future<Output = Result<ActiveStage>> + Send;
+
+    /// Change the topic and privacy of a live stage.
+    ///
+    /// Requires the current user to be a moderator of the stage.
+    fn edit_stage_instance(
+        &self,
+        channel: ChannelId,
+        topic: &str,
+        privacy: StagePrivacyLevel,
+    ) -> impl Future<Output = Result<ActiveStage>> + Send;
+
+    /// End a live stage.
+    ///
+    /// Requires the current user to be a moderator of the stage.
+    fn delete_stage_instance(&self, channel: ChannelId) -> impl Future<Output = Result<()>> + Send;
+}
+
+impl StageExt for Discord {
+    async fn get_stage_instance(&self, channel: ChannelId) -> Result<ActiveStage> {
+        let stage = self
+            .empty_request(&format!("/stage-instances/{channel}"), Method::GET)
+            .await?
+            .json()
+            .await?;
+
+        Ok(stage)
+    }
+
+    async fn create_stage_instance(
+        &self,
+        channel: ChannelId,
+        topic: &str,
+        privacy: StagePrivacyLevel,
+    ) -> Result<ActiveStage> {
+        let map = json! {{
+            "channel_id": channel,
+            "topic": topic,
+            "privacy_level": privacy,
+        }};
+
+        let stage = self
+            .request("/stage-instances", Method::POST, |req| req.json(&map))
+            .await?
+            .json()
+            .await?;
+
+        Ok(stage)
+    }
+
+    async fn edit_stage_instance(
+        &self,
+        channel: ChannelId,
+        topic: &str,
+        privacy: StagePrivacyLevel,
+    ) -> Result<ActiveStage> {
+        let map = json! {{
+            "topic": topic,
+            "privacy_level": privacy,
+        }};
+
+        let stage = self
+            .request(
+                &format!("/stage-instances/{channel}"),
+                Method::PATCH,
+                |req| req.json(&map),
+            )
+            .await?
+            .json()
+            .await?;
+
+        Ok(stage)
+    }
+
+    async fn delete_stage_instance(&self, channel: ChannelId) -> Result<()> {
+        self.empty_request(&format!("/stage-instances/{channel}"), Method::DELETE)
+            .await?
+            .insure_no_content()
+            .await
+    }
+}
@@ -0,0 +1,276 @@
+//! Access to the Discord status page.
+
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+use futures::Stream;
+
+use crate::{
+    error::{CheckStatus, Result},
+    model::{Incident, IncidentUpdate, Maintenance},
+    Object,
+};
+
+macro_rules! status_concat {
+    ($e:expr) => {
+        concat!("https://status.discord.com/api/v2", $e)
+    };
+}
+
+/// Retrieves the current unresolved incidents from the status page.
+pub async fn get_unresolved_incidents() -> Result<Vec<Incident>> {
+    let client = tls_client();
+    let mut response: Object = client
+        .execute(
+            client
+                .get(status_concat!("/incidents/unresolved.json"))
+                .build()
+                .unwrap(),
+        )
+        .await
+        .check_status()
+        .await?
+        .json()
+        .await?;
+
+    match response.remove("incidents") {
+        Some(incidents) => Ok(serde_json::from_value(incidents)?),
+        None => Ok(vec![]),
+    }
+}
+
+/// Retrieves the active maintenances from the status page.
+pub async fn get_active_maintenances() -> Result<Vec<Maintenance>> {
+    let client = tls_client();
+    let mut response: Object = client
+        .execute(
+            client
+                .get(status_concat!("/scheduled-maintenances/active.json"))
+                .build()
+                .unwrap(),
+        )
+        .await
+        .check_status()
+        .await?
+        .json()
+        .await?;
+
+    match response.remove("scheduled_maintenances") {
+        Some(scheduled_maintenances) => Ok(serde_json::from_value(scheduled_maintenances)?),
+        None => Ok(vec![]),
+    }
+}
+
+/// Retrieves the upcoming maintenances from the status page.
+pub async fn get_upcoming_maintenances() -> Result<Vec<Maintenance>> {
+    let client = tls_client();
+    let mut response: Object = client
+        .execute(
+            client
+                .get(status_concat!("/scheduled-maintenances/upcoming.json"))
+                .build()
+                .unwrap(),
+        )
+        .await
+        .check_status()
+        .await?
+        .json()
+        .await?;
+
+    match response.remove("scheduled_maintenances") {
+        Some(scheduled_maintenances) => Ok(serde_json::from_value(scheduled_maintenances)?),
+        None => Ok(vec![]),
+    }
+}
+
+fn tls_client() -> reqwest::Client {
+    super::https_client()
+        .build()
+        .expect("Couldn't build HTTPS reqwest client")
+}
+
+/// The interval at which a `StatusMonitor` polls the status page by default.
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+/// A change on the status page detected by a `StatusMonitor`.
+#[derive(Debug, Clone)]
+pub enum StatusChange {
+    /// A new incident was opened.
+    IncidentCreated(Incident),
+    /// An existing incident received an update.
+    IncidentUpdated(Incident, IncidentUpdate),
+    /// An incident was resolved, and is no longer listed as unresolved.
+    IncidentResolved(Incident),
+    /// A scheduled maintenance has started.
+    MaintenanceStarted(Maintenance),
+    /// A maintenance has finished, and is no longer listed as active.
+    MaintenanceEnded(Maintenance),
+}
+
+/// Polls the status page and reports what changed between polls,
+/// so that outages can be announced as they happen.
+///
+/// The first poll only records the current state of the status page,
+/// so incidents which were already ongoing are not reported.
+#[derive(Debug, Clone)]
+pub struct StatusMonitor {
+    interval: Duration,
+    baseline: bool,
+    incidents: HashMap<String, Incident>,
+    maintenances: HashMap<String, Maintenance>,
+}
+
+impl StatusMonitor {
+    /// Create a monitor which polls every minute.
+    pub fn new() -> StatusMonitor {
+        StatusMonitor {
+            interval: DEFAULT_POLL_INTERVAL,
+            baseline: false,
+            incidents: HashMap::new(),
+            maintenances: HashMap::new(),
+        }
+    }
+
+    /// Set the interval between polls when used as a stream.
+    pub fn interval(mut self, interval: Duration) -> StatusMonitor {
+        self.interval = interval;
+        self
+    }
+
+    /// Poll the status page once, returning the changes since the last poll.
+    pub async fn poll(&mut self) -> Result<Vec<StatusChange>> {
+        let incidents = get_unresolved_incidents().await?;
+        let maintenances = get_active_maintenances().await?;
+        Ok(self.update(incidents, maintenances))
+    }
+
+    /// Turn the monitor into a stream of changes, polling at the configured interval.
+    ///
+    /// Errors while polling are yielded without ending the stream.
+    pub fn changes(self) -> impl Stream<Item = Result<StatusChange>> {
+        let mut ticker = tokio::time::interval(self.interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        futures::stream::unfold(
+            (self, ticker, Vec::new()),
+            |(mut monitor, mut ticker, mut pending)| async move {
+                loop {
+                    if let Some(change) = pending.pop() {
+                        return Some((Ok(change), (monitor, ticker, pending)));
+                    }
+
+                    ticker.tick().await;
+                    match monitor.poll().await {
+                        Ok(mut changes) => {
+                            changes.reverse();
+                            pending = changes;
+                        }
+                        Err(err) => return Some((Err(err), (monitor, ticker, pending))),
+                    }
+                }
+            },
+        )
+    }
+
+    fn update(
+        &mut self,
+        incidents: Vec<Incident>,
+        maintenances: Vec<Maintenance>,
+    ) -> Vec<StatusChange> {
+        let mut changes = Vec::new();
+
+        let mut previous = std::mem::take(&mut self.incidents);
+        for incident in incidents {
+            match previous.remove(&incident.id) {
+                Some(old) => {
+                    let seen: HashSet<&str> = old
+                        .incident_updates
+                        .iter()
+                        .map(|update| &*update.id)
+                        .collect();
+                    // updates are listed newest first
+                    for update in incident.incident_updates.iter().rev() {
+                        if !seen.contains(&*update.id) {
+                            changes.push(StatusChange::IncidentUpdated(
+                                incident.clone(),
+                                update.clone(),
+                            ));
+                        }
+                    }
+                }
+                None => changes.push(StatusChange::IncidentCreated(incident.clone())),
+            }
+            self.incidents.insert(incident.id.clone(), incident);
+        }
+        changes.extend(previous.into_values().map(StatusChange::IncidentResolved));
+
+        let mut previous = std::mem::take(&mut self.maintenances);
+        for maintenance in maintenances {
+            if previous.remove(&maintenance.id).is_none() {
+                changes.push(StatusChange::MaintenanceStarted(maintenance.clone()));
+            }
+            self.maintenances
+                .insert(maintenance.id.clone(), maintenance);
+        }
+        changes.extend(previous.into_values().map(StatusChange::MaintenanceEnded));
+
+        if !self.baseline {
+            self.baseline = true;
+            changes.clear();
+        }
+        changes
+    }
+}
+
+impl Default for StatusMonitor {
+    fn default() -> Self {
+        StatusMonitor::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{StatusChange, StatusMonitor};
+    use crate::model::Incident;
+
+    fn incident(id: &str, updates: &[&str]) -> Incident {
+        let updates: Vec<_> = updates
+            .iter()
+            .map(|update| {
+                json!({
+                    "body": "", "id": update, "incident_id": id, "status": "investigating",
+                    "affected_components": [], "created_at": "", "display_at": "", "updated_at": "",
+                })
+            })
+            .collect();
+        serde_json::from_value(json!({
+            "id": id, "impact": "minor", "monitoring_at": null, "name": id, "page_id": "",
+            "shortlink": "", "status": "investigating", "incident_updates": updates,
+            "created_at": "", "resolved_at": null, "updated_at": "",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn reports_changes_after_baseline() {
+        let mut monitor = StatusMonitor::new();
+        assert!(monitor
+            .update(vec![incident("a", &["1"])], vec![])
+            .is_empty());
+
+        let changes = monitor.update(vec![incident("a", &["2", "1"]), incident("b", &[])], vec![]);
+        assert!(matches!(
+            &changes[..],
+            [StatusChange::IncidentUpdated(a, update), StatusChange::IncidentCreated(b)]
+                if a.id == "a" && update.id == "2" && b.id == "b"
+        ));
+
+        let changes = monitor.update(vec![incident("b", &[])], vec![]);
+        assert!(matches!(
+            &changes[..],
+            [StatusChange::IncidentResolved(a)] if a.id == "a"
+        ));
+    }
+}
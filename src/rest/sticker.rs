@@ -0,0 +1,188 @@
+use futures::Future;
+use reqwest::multipart::{Form, Part};
+use reqwest::Method;
+use serde::Deserialize;
+
+use crate::{
+    builders::EditSticker,
+    error::{Result, StatusChecks},
+    model::{Image, ServerId, Sticker, StickerId, StickerPack},
+};
+
+use super::Discord;
+
+/// Discord REST API methods for standard and server stickers.
+pub trait StickerExt {
+    /// Get a sticker by its ID, be it a standard or a server sticker.
+    fn get_sticker(&self, sticker: StickerId) -> impl Future<Output = Result<Sticker>> + Send;
+
+    /// Get the list of packs of standard stickers.
+    fn list_sticker_packs(&self) -> impl Future<Output = Result<Vec<StickerPack>>> + Send;
+
+    /// Get the custom stickers of a server.
+    ///
+    /// The uploader of each sticker is only included with the `MANAGE_EMOJIS_AND_STICKERS` permission.
+    fn list_server_stickers(
+        &self,
+        server: ServerId,
+    ) -> impl Future<Output = Result<Vec<Sticker>>> + Send;
+
+    /// Get a single custom sticker of a server.
+    fn get_server_sticker(
+        &self,
+        server: ServerId,
+        sticker: StickerId,
+    ) -> impl Future<Output = Result<Sticker>> + Send;
+
+    /// Upload a custom sticker to a server.
+    ///
+    /// The file must be a PNG, APNG or GIF image, or a Lottie JSON animation, of at most 512 KB.
+    /// `tags` is the name of a standard emoji related to the sticker, used for autocomplete.
+    ///
+    /// Requires the `MANAGE_EMOJIS_AND_STICKERS` permission.
+    fn create_server_sticker(
+        &self,
+        server: ServerId,
+        name: &str,
+        description: &str,
+        tags: &str,
+        file: Vec<u8>,
+    ) -> impl Future<Output = Result<Sticker>> + Send;
+
+    /// Edit a custom sticker of a server. See `EditSticker` for the editable fields.
+    ///
+    /// Requires the `MANAGE_EMOJIS_AND_STICKERS` permission.
+    fn edit_server_sticker<F>(
+        &self,
+        server: ServerId,
+        sticker: StickerId,
+        f: F,
+    ) -> impl Future<Output = Result<Sticker>> + Send
+    where
+        F: Send + FnOnce(EditSticker) -> EditSticker;
+
+    /// Delete a custom sticker of a server.
+    ///
+    /// Requires the `MANAGE_EMOJIS_AND_STICKERS` permission.
+    fn delete_server_sticker(
+        &self,
+        server: ServerId,
+        sticker: StickerId,
+    ) -> impl Future<Output = Result<()>> + Send;
+}
+
+impl StickerExt for Discord {
+    async fn get_sticker(&self, sticker: StickerId) -> Result<Sticker> {
+        let sticker = self
+            .empty_request(&format!("/stickers/{sticker}"), Method::GET)
+            .await?
+            .json()
+            .await?;
+
+        Ok(sticker)
+    }
+
+    async fn list_sticker_packs(&self) -> Result<Vec<StickerPack>> {
+        #[derive(Deserialize)]
+        struct StickerPacks {
+            sticker_packs: Vec<StickerPack>,
+        }
+
+        let packs: StickerPacks = self
+            .empty_request("/sticker-packs", Method::GET)
+            .await?
+            .json()
+            .await?;
+
+        Ok(packs.sticker_packs)
+    }
+
+    async fn list_server_stickers(&self, server: ServerId) -> Result<Vec<Sticker>> {
+        let stickers = self
+            .empty_request(&format!("/guilds/{server}/stickers"), Method::GET)
+            .await?
+            .json()
+            .await?;
+
+        Ok(stickers)
+    }
+
+    async fn get_server_sticker(&self, server: ServerId, sticker: StickerId) -> Result<Sticker> {
+        let sticker = self
+            .empty_request(&format!("/guilds/{server}/stickers/{sticker}"), Method::GET)
+            .await?
+            .json()
+            .await?;
+
+        Ok(sticker)
+    }
+
+    async fn create_server_sticker(
+        &self,
+        server: ServerId,
+        name: &str,
+        description: &str,
+        tags: &str,
+        file: Vec<u8>,
+    ) -> Result<Sticker> {
+        // Lottie animations are JSON, which can't be mistaken for an image
+        let image = Image::new(file);
+        let media_type = match image.data.first() {
+            Some(b'{') => "application/json",
+            _ => image.media_type(),
+        };
+        let file = Part::bytes(image.data)
+            .file_name("sticker")
+            .mime_str(media_type)?;
+
+        let form = Form::new()
+            .text("name", name.to_owned())
+            .text("description", description.to_owned())
+            .text("tags", tags.to_owned())
+            .part("file", file);
+
+        let sticker = self
+            .request(&format!("/guilds/{server}/stickers"), Method::POST, |req| {
+                req.multipart(form)
+            })
+            .await?
+            .json()
+            .await?;
+
+        Ok(sticker)
+    }
+
+    async fn edit_server_sticker<F>(
+        &self,
+        server: ServerId,
+        sticker: StickerId,
+        f: F,
+    ) -> Result<Sticker>
+    where
+        F: Send + FnOnce(EditSticker) -> EditSticker,
+    {
+        let map = EditSticker::build(f);
+
+        let sticker = self
+            .request(
+                &format!("/guilds/{server}/stickers/{sticker}"),
+                Method::PATCH,
+                |req| req.json(&map),
+            )
+            .await?
+            .json()
+            .await?;
+
+        Ok(sticker)
+    }
+
+    async fn delete_server_sticker(&self, server: ServerId, sticker: StickerId) -> Result<()> {
+        self.empty_request(
+            &format!("/guilds/{server}/stickers/{sticker}"),
+            Method::DELETE,
+        )
+        .await?
+        .insure_no_content()
+        .await
+    }
+}
@@ -5,7 +5,7 @@ use serde_json::json;
 use crate::{
     builders::{EditProfile, EditUserProfile},
     error::{Error, Result, StatusChecks},
-    model::{ApplicationInfo, CurrentUser, DirectMessage, User, UserId},
+    model::{ApplicationInfo, CurrentUser, DirectMessage, Token, User, UserId},
     Object,
 };
 
@@ -131,7 +131,7 @@ impl UserExt for Discord {
         // If a token was included in the response, switch to it. Important because if the
         // password was changed, the old token is invalidated.
         if let Some(serde_json::Value::String(token)) = json.remove("token") {
-            self.token = token;
+            self.token = Token::User(token);
         }
 
         Ok(serde_json::from_value(serde_json::to_value(json)?)?)
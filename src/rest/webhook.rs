@@ -0,0 +1,221 @@
+use std::sync::Arc;
+
+use reqwest::{Method, RequestBuilder};
+use serde_json::Value;
+
+use crate::{
+    builders::SendMessage,
+    error::{Error, Result, StatusChecks},
+    model::{Message, MessageId, WebhookId},
+    ratelimit::rest::RateLimits,
+    Object,
+};
+
+use super::{API_BASE, BOT_USER_AGENT};
+
+/// A client which posts messages through a single webhook.
+///
+/// Webhooks are authenticated by their own token, which is part of their URL,
+/// so services which only post notifications don't need a bot account at all.
+/// The client keeps its own rate limits, shared between its clones.
+///
+/// ```ignore
+/// let webhook = WebhookClient::from_url(&url)?.username("Build bot");
+/// webhook.execute(|m| m.content("The build passed")).await?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct WebhookClient {
+    client: reqwest::Client,
+    id: WebhookId,
+    token: String,
+    rate_limits: Arc<RateLimits>,
+    /// The name and avatar of messages which don't set their own.
+    username: Option<String>,
+    avatar_url: Option<String>,
+}
+
+impl WebhookClient {
+    /// Create a client for the webhook with the given ID and token.
+    pub fn new(id: WebhookId, token: &str) -> WebhookClient {
+        WebhookClient {
+            client: super::https_client()
+                .user_agent(BOT_USER_AGENT)
+                .build()
+                .expect("Couldn't build HTTPS reqwest client"),
+            id,
+            token: token.trim().to_owned(),
+            rate_limits: Arc::new(RateLimits::default()),
+            username: None,
+            avatar_url: None,
+        }
+    }
+
+    /// Create a client from a webhook URL, as copied from a channel's integration settings.
+    ///
+    /// The URL looks like `https://discord.com/api/webhooks/{id}/{token}`.
+    pub fn from_url(url: &str) -> Result<WebhookClient> {
+        let (id, token) = parse_webhook_url(url).ok_or(Error::Other("Invalid webhook URL"))?;
+        Ok(WebhookClient::new(id, token))
+    }
+
+    /// Post messages under this name, unless a message sets its own.
+    pub fn username(mut self, username: &str) -> Self {
+        self.username = Some(username.to_owned());
+        self
+    }
+
+    /// Post messages with this avatar, unless a message sets its own.
+    pub fn avatar_url(mut self, avatar_url: &str) -> Self {
+        self.avatar_url = Some(avatar_url.to_owned());
+        self
+    }
+
+    /// The ID of the webhook.
+    pub fn id(&self) -> WebhookId {
+        self.id
+    }
+
+    /// Post a message through the webhook, under the client's username and avatar if set.
+    pub async fn execute<F>(&self, f: F) -> Result<Message>
+    where
+        F: FnOnce(SendMessage) -> SendMessage,
+    {
+        let mut map = webhook_payload(SendMessage::build(f));
+        if let Some(username) = &self.username {
+            map.entry("username")
+                .or_insert_with(|| username.as_str().into());
+        }
+        if let Some(avatar_url) = &self.avatar_url {
+            map.entry("avatar_url")
+                .or_insert_with(|| avatar_url.as_str().into());
+        }
+
+        let message = self
+            .request("?wait=true", Method::POST, |req| req.json(&map))
+            .await?
+            .json()
+            .await?;
+
+        Ok(message)
+    }
+
+    /// Get a message previously posted through the webhook.
+    pub async fn get_message(&self, message: MessageId) -> Result<Message> {
+        let message = self
+            .request(&format!("/messages/{message}"), Method::GET, |req| req)
+            .await?
+            .json()
+            .await?;
+
+        Ok(message)
+    }
+
+    /// Edit a message previously posted through the webhook.
+    pub async fn edit_message<F>(&self, message: MessageId, f: F) -> Result<Message>
+    where
+        F: FnOnce(SendMessage) -> SendMessage,
+    {
+        let map = webhook_payload(SendMessage::build(f));
+
+        let message = self
+            .request(&format!("/messages/{message}"), Method::PATCH, |req| {
+                req.json(&map)
+            })
+            .await?
+            .json()
+            .await?;
+
+        Ok(message)
+    }
+
+    /// Delete a message previously posted through the webhook.
+    pub async fn delete_message(&self, message: MessageId) -> Result<()> {
+        self.request(&format!("/messages/{message}"), Method::DELETE, |req| req)
+            .await?
+            .insure_no_content()
+            .await
+    }
+
+    /// Make a request to a route of the webhook, waiting out its rate limits.
+    async fn request<F: FnOnce(RequestBuilder) -> RequestBuilder>(
+        &self,
+        route: &str,
+        method: Method,
+        builder: F,
+    ) -> Result<reqwest::Response> {
+        let url = format!("/webhooks/{}/{}{}", self.id, self.token, route);
+        // the query doesn't change which limit applies
+        let path = url.split('?').next().unwrap_or(&url);
+
+        let mut reservation = self.rate_limits.acquire(&method, path).await;
+
+        let request = builder(
+            self.client
+                .request(method.clone(), format!("{API_BASE}{url}")),
+        );
+        reservation.mark_sent();
+        let response = request.send().await;
+        reservation.settle(&response);
+        let response = response?;
+        let _ = self.rate_limits.update(&method, path, &response);
+
+        if !response.status().is_success() {
+            return Err(Error::from_response(response).await);
+        }
+        Ok(response)
+    }
+}
+
+/// Webhooks only accept a list of embeds, rather than the single `embed` of channel messages.
+fn webhook_payload(mut map: Object) -> Object {
+    if let Some(embed) = map.remove("embed") {
+        map.insert("embeds".into(), Value::Array(vec![embed]));
+    }
+    map
+}
+
+/// Split a webhook URL into the webhook's ID and token.
+fn parse_webhook_url(url: &str) -> Option<(WebhookId, &str)> {
+    let rest = url.split_once("/webhooks/")?.1;
+    let (id, token) = rest.split_once('/')?;
+    let token = token.split(['/', '?']).next()?;
+    if token.is_empty() {
+        return None;
+    }
+    Some((WebhookId(id.parse().ok()?), token))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{parse_webhook_url, webhook_payload};
+    use crate::builders::SendMessage;
+    use crate::model::WebhookId;
+
+    #[test]
+    fn parses_webhook_urls() {
+        assert_eq!(
+            parse_webhook_url("https://discord.com/api/webhooks/123/abc-DEF_4"),
+            Some((WebhookId(123), "abc-DEF_4"))
+        );
+        assert_eq!(
+            parse_webhook_url("https://discordapp.com/api/v10/webhooks/5/tok?wait=true"),
+            Some((WebhookId(5), "tok"))
+        );
+        assert_eq!(
+            parse_webhook_url("https://discord.com/api/webhooks/123"),
+            None
+        );
+        assert_eq!(
+            parse_webhook_url("https://example.com/webhooks/x/tok"),
+            None
+        );
+
+        let payload = webhook_payload(SendMessage::build(|m| {
+            m.content("hi").embed(|e| e.title("Build"))
+        }));
+        assert_eq!(payload["embeds"], json!([{"title": "Build"}]));
+        assert!(!payload.contains_key("embed"));
+    }
+}
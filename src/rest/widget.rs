@@ -0,0 +1,90 @@
+//! Fetch the public widget of a server, without a token.
+
+use std::sync::OnceLock;
+
+use crate::{
+    error::{CheckStatus, Result},
+    model::{ServerId, ServerWidget},
+};
+
+use super::{API_BASE, BOT_USER_AGENT};
+
+/// Retrieves the widget of a server, with its online member count, a sample of its online
+/// members, and its invite.
+///
+/// No token is needed, so status pages and other tools can show a server's activity
+/// without holding a bot token. Fails with `DiscordErrorCode::WidgetDisabled` unless
+/// the server has enabled its widget.
+pub async fn get_widget(server: ServerId) -> Result<ServerWidget> {
+    let widget = client()
+        .get(format!("{API_BASE}/guilds/{server}/widget.json"))
+        .send()
+        .await
+        .check_status()
+        .await?
+        .json()
+        .await?;
+
+    Ok(widget)
+}
+
+/// The client shared by widget requests, built on the first one so later requests reuse
+/// its connection pool.
+fn client() -> &'static reqwest::Client {
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+    CLIENT.get_or_init(|| {
+        super::https_client()
+            .user_agent(BOT_USER_AGENT)
+            .build()
+            .expect("Couldn't build HTTPS reqwest client")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::model::{ChannelId, OnlineStatus, ServerWidget};
+
+    #[test]
+    fn decodes_widgets() {
+        let widget: ServerWidget = serde_json::from_value(json!({
+            "id": "290926798626357999",
+            "name": "Test Server",
+            "instant_invite": "https://discord.com/invite/abcdef",
+            "channels": [{ "id": "705216630279993882", "name": "Lounge", "position": 2 }],
+            "members": [{
+                "id": "0",
+                "username": "1234",
+                "discriminator": "0000",
+                "avatar": null,
+                "status": "online",
+                "avatar_url": "https://cdn.discordapp.com/widget-avatars/abc/def",
+                "activity": { "name": "Rust" },
+                "channel_id": "705216630279993882"
+            }],
+            "presence_count": 1
+        }))
+        .unwrap();
+
+        assert_eq!(widget.name, "Test Server");
+        assert_eq!(widget.channels[0].id, ChannelId(705216630279993882));
+        assert_eq!(widget.members[0].status, OnlineStatus::Online);
+        assert_eq!(widget.members[0].activity.as_ref().unwrap().name, "Rust");
+        assert_eq!(widget.presence_count, 1);
+    }
+
+    #[test]
+    fn decodes_widgets_without_members() {
+        let widget: ServerWidget = serde_json::from_value(json!({
+            "id": "290926798626357999",
+            "name": "Test Server",
+            "instant_invite": null,
+            "presence_count": 0
+        }))
+        .unwrap();
+
+        assert!(widget.instant_invite.is_none());
+        assert!(widget.channels.is_empty() && widget.members.is_empty());
+    }
+}
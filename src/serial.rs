@@ -23,6 +23,16 @@ pub fn ignore_errors<'d, T: Deserialize<'d> + Default, D: Deserializer<'d>>(
     Ok(T::deserialize(v).ok().unwrap_or_default())
 }
 
+/// Deserialize a present field into `Some`, even if it is `null`.
+///
+/// Used with `#[serde(default)]` on `Option<Option<T>>` fields, to tell apart
+/// a missing field (`None`) from one explicitly set to `null` (`Some(None)`).
+pub fn deserialize_some<'d, T: Deserialize<'d>, D: Deserializer<'d>>(
+    d: D,
+) -> Result<Option<T>, D::Error> {
+    T::deserialize(d).map(Some)
+}
+
 /// Deserialize a maybe-string ID into a u64.
 pub fn deserialize_id<'d, D: Deserializer<'d>>(d: D) -> Result<u64, D::Error> {
     struct IdVisitor;
@@ -50,6 +60,19 @@ pub fn deserialize_id<'d, D: Deserializer<'d>>(d: D) -> Result<u64, D::Error> {
     d.deserialize_any(IdVisitor)
 }
 
+/// Serialize an ID as a string, the way Discord sends them.
+///
+/// Large IDs lose precision when read as numbers by JavaScript consumers,
+/// and some newer endpoints reject numeric IDs outright.
+/// With the `numeric-ids` feature, IDs are serialized as numbers instead.
+pub fn serialize_id<S: Serializer>(id: &u64, s: S) -> Result<S::Ok, S::Error> {
+    if cfg!(feature = "numeric-ids") {
+        s.serialize_u64(*id)
+    } else {
+        s.collect_str(id)
+    }
+}
+
 /// Deserialize a maybe-string discriminator into a u16.
 /// Also enforces 0 <= N <= 9999.
 #[allow(unused_comparisons)]
@@ -98,8 +121,28 @@ pub fn deserialize_discrim<'d, D: Deserializer<'d>>(d: D) -> Result<u16, D::Erro
     }
 }
 
-/// Make sure a field holds a certain numeric value, or fail otherwise.
-#[derive(Debug, Clone)]
+/// A field which must hold a certain number, such as an opcode or a type discriminant.
+///
+/// Deserialization fails if the field holds any other number, which makes it
+/// possible to tell apart the variants of an untagged enum by a numeric tag:
+///
+/// ```
+/// use discord_tokio::model::OpCode;
+/// use serde::Deserialize;
+///
+/// #[derive(Deserialize)]
+/// #[serde(untagged)]
+/// enum Custom {
+///     Ping { op: OpCode<100> },
+///     Pong { op: OpCode<101>, d: u64 },
+/// }
+///
+/// let pong: Custom = serde_json::from_str(r#"{ "op": 101, "d": 5 }"#).unwrap();
+/// assert!(matches!(pong, Custom::Pong { d: 5, .. }));
+/// ```
+///
+/// Always serializes to `N`. Exported publicly as `model::OpCode`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
 pub struct Eq<const N: u64>;
 
 impl<'de, const N: u64> Deserialize<'de> for Eq<N> {
@@ -222,3 +265,102 @@ impl<const N: u64> Serialize for Eq<N> {
         serializer.serialize_u64(N)
     }
 }
+
+/// Like `Eq`, but also accepts the field being missing or null.
+///
+/// Useful for tags which Discord may add to a payload in the future,
+/// or only sends some of the time, while still rejecting other numbers.
+/// Always serializes to `N`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Kind<const N: u64>;
+
+impl<'de, const N: u64> Deserialize<'de> for Kind<N> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        // a missing field deserializes as `None`, so there is no need for `#[serde(default)]`
+        Option::<Eq<N>>::deserialize(deserializer)?;
+        Ok(Self)
+    }
+}
+
+impl<const N: u64> Serialize for Kind<N> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_u64(N)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::Kind;
+
+    #[derive(Deserialize)]
+    struct Tagged {
+        #[allow(dead_code)]
+        kind: Kind<2>,
+    }
+
+    #[test]
+    fn kind_tolerates_missing() {
+        assert!(serde_json::from_str::<Tagged>(r#"{ "kind": 2 }"#).is_ok());
+        assert!(serde_json::from_str::<Tagged>(r#"{ "kind": null }"#).is_ok());
+        assert!(serde_json::from_str::<Tagged>(r#"{}"#).is_ok());
+        assert!(serde_json::from_str::<Tagged>(r#"{ "kind": 3 }"#).is_err());
+    }
+
+    #[test]
+    fn ids_round_trip() {
+        use crate::model::UserId;
+
+        let json = serde_json::to_value(UserId(80351110224678912)).unwrap();
+        if cfg!(feature = "numeric-ids") {
+            assert_eq!(json, serde_json::json!(80351110224678912u64));
+        } else {
+            assert_eq!(json, serde_json::json!("80351110224678912"));
+        }
+
+        let from_str: UserId = serde_json::from_str(r#""80351110224678912""#).unwrap();
+        let from_num: UserId = serde_json::from_str("80351110224678912").unwrap();
+        assert_eq!(from_str, from_num);
+    }
+
+    #[test]
+    fn partial_presence_user() {
+        use crate::model::PresenceUser;
+
+        let id_only: PresenceUser = serde_json::from_str(r#"{ "id": "1" }"#).unwrap();
+        assert!(id_only.name.is_none() && id_only.avatar.is_none());
+
+        let removed: PresenceUser =
+            serde_json::from_str(r#"{ "id": "1", "avatar": null, "discriminator": "0042" }"#)
+                .unwrap();
+        assert_eq!(removed.avatar, Some(None));
+        assert_eq!(removed.discriminator, Some(42));
+    }
+
+    #[cfg(feature = "extra-fields")]
+    #[test]
+    fn keeps_extra_fields() {
+        use crate::model::Member;
+
+        let mut json = crate::fixtures::member(1, &[]);
+        json["communication_disabled_until"] = "2024-02-01T00:00:00+00:00".into();
+        let member: Member = serde_json::from_value(json).unwrap();
+        assert_eq!(
+            member.extra["communication_disabled_until"],
+            "2024-02-01T00:00:00+00:00"
+        );
+
+        let back = serde_json::to_value(&member).unwrap();
+        assert_eq!(
+            back["communication_disabled_until"],
+            "2024-02-01T00:00:00+00:00"
+        );
+    }
+}
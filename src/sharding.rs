@@ -0,0 +1,325 @@
+//! Running every shard of a bot from a single place.
+
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use futures::Stream;
+use tokio::sync::{mpsc, Mutex};
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use tracing::{debug, warn};
+
+use crate::model::{Event, ReadyEvent};
+use crate::reconnect::GatewayCloseCode;
+use crate::{ConnectExt, Connection, Discord, Result};
+
+/// The index of a shard, starting at 0.
+pub type ShardId = u32;
+
+/// How long a shard waits after identifying before another shard in its bucket may identify.
+const IDENTIFY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long a shard waits before trying to connect again after failing to.
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// How many events are buffered before the shards wait for them to be received.
+const EVENT_BUFFER: usize = 256;
+
+/// Opens a new gateway session for the shard with the given ID.
+type Connect =
+    Arc<dyn Fn(ShardId) -> BoxFuture<'static, Result<(Connection, ReadyEvent)>> + Send + Sync>;
+
+/// Runs all the shards of a bot, and receives their events as a single stream.
+///
+/// Each shard is connected and kept running in a background task. Shards identify
+/// no faster than the gateway allows, one at a time within each of the
+/// `max_concurrency` buckets, and are restarted with a new session if their connection fails.
+///
+/// The stream yields every event along with the shard it was received on,
+/// starting with each shard's `Ready` event. A shard closed for a reason reconnecting
+/// can't fix, such as an invalid token, shard or intents, is not restarted, as that would
+/// only use up session starts: its error is yielded instead and the shard stops.
+/// The stream ends once every shard has stopped.
+///
+/// ```ignore
+/// let discord = Discord::from_bot_token(&token)?;
+/// let mut shards = ShardManager::new(discord.clone()).await?;
+/// while let Some((shard, event)) = shards.next().await {
+///     let event = event?;
+///     // handle the event
+/// }
+/// ```
+#[derive(Debug)]
+pub struct ShardManager {
+    events: mpsc::Receiver<(ShardId, Result<Event>)>,
+    shards: Vec<JoinHandle<()>>,
+}
+
+impl ShardManager {
+    /// Start the number of shards recommended by Discord.
+    pub async fn new(discord: Discord) -> Result<ShardManager> {
+        let gateway = discord.get_gateway_bot().await?;
+        if gateway.session_start_limit.remaining < gateway.shards.into() {
+            warn!(
+                "Only {} session starts remaining for {} shards",
+                gateway.session_start_limit.remaining, gateway.shards
+            );
+        }
+
+        Ok(ShardManager::with_shards(
+            discord,
+            gateway.shards,
+            gateway.session_start_limit.max_concurrency,
+        ))
+    }
+
+    /// Start the given number of shards, identifying up to `max_concurrency` at a time.
+    pub fn with_shards(discord: Discord, total_shards: u32, max_concurrency: u32) -> ShardManager {
+        let connect: Connect = Arc::new(move |id| {
+            let discord = discord.clone();
+            Box::pin(async move { discord.connect_sharded(id, total_shards).await })
+        });
+        ShardManager::start(
+            connect,
+            total_shards,
+            max_concurrency,
+            IDENTIFY_INTERVAL,
+            RETRY_DELAY,
+        )
+    }
+
+    fn start(
+        connect: Connect,
+        total_shards: u32,
+        max_concurrency: u32,
+        identify_interval: Duration,
+        retry_delay: Duration,
+    ) -> ShardManager {
+        let (sender, events) = mpsc::channel(EVENT_BUFFER);
+        let buckets: Arc<[Mutex<Option<Instant>>]> = (0..max_concurrency.max(1))
+            .map(|_| Mutex::new(None))
+            .collect();
+
+        let shards = (0..total_shards)
+            .map(|id| {
+                tokio::spawn(run_shard(
+                    connect.clone(),
+                    id,
+                    buckets.clone(),
+                    identify_interval,
+                    retry_delay,
+                    sender.clone(),
+                ))
+            })
+            .collect();
+
+        ShardManager { events, shards }
+    }
+
+    /// The number of shards being run.
+    pub fn shard_count(&self) -> u32 {
+        self.shards.len() as u32
+    }
+}
+
+impl Stream for ShardManager {
+    type Item = (ShardId, Result<Event>);
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.events.poll_recv(cx)
+    }
+}
+
+impl Drop for ShardManager {
+    fn drop(&mut self) {
+        for shard in &self.shards {
+            shard.abort();
+        }
+    }
+}
+
+/// Keep a shard connected, forwarding its events until the manager is dropped
+/// or the shard is closed for good.
+///
+/// `buckets` holds when a shard of each identify bucket last identified,
+/// and is locked for the duration of the identify.
+async fn run_shard(
+    connect: Connect,
+    id: ShardId,
+    buckets: Arc<[Mutex<Option<Instant>>]>,
+    identify_interval: Duration,
+    retry_delay: Duration,
+    events: mpsc::Sender<(ShardId, Result<Event>)>,
+) {
+    loop {
+        let connected = {
+            let mut last_identify = buckets[id as usize % buckets.len()].lock().await;
+            if let Some(last_identify) = *last_identify {
+                tokio::time::sleep_until(last_identify + identify_interval).await;
+            }
+            let connected = connect(id).await;
+            *last_identify = Some(Instant::now());
+            connected
+        };
+
+        let (mut connection, ready) = match connected {
+            Ok(connected) => connected,
+            Err(err) if err.close_code().is_some_and(GatewayCloseCode::is_fatal) => {
+                warn!("Shard {} was refused, stopping: {:?}", id, err);
+                let _ = events.send((id, Err(err))).await;
+                return;
+            }
+            Err(err) => {
+                warn!("Shard {} failed to connect, retrying: {:?}", id, err);
+                tokio::time::sleep(retry_delay).await;
+                continue;
+            }
+        };
+        debug!("Shard {} is ready", id);
+        if events.send((id, Ok(Event::Ready(ready)))).await.is_err() {
+            return;
+        }
+
+        loop {
+            match connection.recv_event().await {
+                Ok(event) => {
+                    if events.send((id, Ok(event))).await.is_err() {
+                        return;
+                    }
+                }
+                Err(err) if err.close_code().is_some_and(GatewayCloseCode::is_fatal) => {
+                    warn!("Shard {} was closed for good, stopping: {:?}", id, err);
+                    let _ = events.send((id, Err(err))).await;
+                    return;
+                }
+                Err(err) => {
+                    warn!("Shard {} failed, restarting: {:?}", id, err);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use futures::StreamExt;
+    use tokio::time::Instant;
+
+    use super::{Connect, ShardManager};
+    use crate::model::{Event, IdentifyConnection, IdentifyPayload, Token};
+    use crate::test_gateway::TestGateway;
+    use crate::{Connection, GatewayCloseCode};
+
+    fn connect_to(gateway: &TestGateway, total_shards: u32) -> Connect {
+        let url = gateway.url();
+        Arc::new(move |id| {
+            let identify = IdentifyPayload {
+                token: Token::bot("Bot token"),
+                shard: Some((id, total_shards)),
+                intents: None,
+                compress: None,
+                large_threshold: None,
+                fingerprint: IdentifyConnection {
+                    os: "test".to_owned(),
+                    browser: "test".to_owned(),
+                    device: "test".to_owned(),
+                },
+            };
+            let url = url.clone();
+            Box::pin(async move { Connection::new(&url, identify).await })
+        })
+    }
+
+    #[tokio::test]
+    async fn identifies_in_buckets_and_restarts() {
+        let gateway = TestGateway::bind().await.unwrap();
+        let connect = connect_to(&gateway, 3);
+
+        tokio::spawn(async move {
+            let mut sessions = Vec::new();
+            let mut restarted = false;
+            loop {
+                let mut session = gateway.accept().await.unwrap();
+                session.hello(45000).await.unwrap();
+                let identify = session.expect_identify().await.unwrap();
+                let shard = identify["shard"][0].as_u64().unwrap();
+                if shard == 1 && !restarted {
+                    // a close that is worth trying again after
+                    restarted = true;
+                    session.close(4000, "Unknown error").await.unwrap();
+                } else {
+                    session.ready(&format!("shard {}", shard)).await.unwrap();
+                    sessions.push(session);
+                }
+            }
+        });
+
+        let interval = Duration::from_millis(300);
+        let started = Instant::now();
+        let mut shards = ShardManager::start(connect, 3, 2, interval, Duration::from_millis(10));
+        assert_eq!(shards.shard_count(), 3);
+
+        let mut ready = Vec::new();
+        while ready.len() < 3 {
+            let (shard, event) = shards.next().await.unwrap();
+            if let Event::Ready(_) = event.unwrap() {
+                ready.push((shard, started.elapsed()));
+            }
+        }
+        ready.sort();
+
+        // shards 0 and 1 are in separate buckets, while 2 waits for 0 to have identified,
+        // and 1 waits for its own first attempt before identifying again
+        let [(0, first), (1, restart), (2, third)] = ready[..] else {
+            panic!("unexpected ready events: {:?}", ready);
+        };
+        assert!(first < interval);
+        assert!(third >= interval);
+        assert!(restart >= interval);
+    }
+
+    #[tokio::test]
+    async fn stops_shards_closed_for_good() {
+        let gateway = TestGateway::bind().await.unwrap();
+        let connect = connect_to(&gateway, 1);
+
+        let identified = Arc::new(AtomicUsize::new(0));
+        let counter = identified.clone();
+        tokio::spawn(async move {
+            loop {
+                let mut session = gateway.accept().await.unwrap();
+                session.hello(45000).await.unwrap();
+                session.expect_identify().await.unwrap();
+                counter.fetch_add(1, Ordering::SeqCst);
+                session.ready("shard 0").await.unwrap();
+                session.close(4004, "Authentication failed").await.unwrap();
+            }
+        });
+
+        let retry_delay = Duration::from_millis(10);
+        let mut shards = ShardManager::start(connect, 1, 1, Duration::ZERO, retry_delay);
+
+        let (shard, event) = shards.next().await.unwrap();
+        assert!(shard == 0 && matches!(event, Ok(Event::Ready(_))));
+        let (shard, event) = shards.next().await.unwrap();
+        let err = event.unwrap_err();
+        assert_eq!(shard, 0);
+        assert_eq!(
+            err.close_code(),
+            Some(GatewayCloseCode::AuthenticationFailed)
+        );
+
+        // the stream ends with its only shard, which never identified again
+        assert!(shards.next().await.is_none());
+        tokio::time::sleep(retry_delay * 5).await;
+        assert_eq!(identified.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,81 @@
+//! Turning Ctrl-C and `SIGTERM` into a final `Event::Shutdown`.
+
+use tokio::sync::watch;
+use tracing::{debug, warn};
+
+/// Whether the process has been asked to stop, shared by a connection and the task
+/// listening for signals.
+#[derive(Debug)]
+pub(crate) struct ShutdownSignal {
+    received: watch::Receiver<bool>,
+    /// Whether `Event::Shutdown` has been received from the connection.
+    pub(crate) delivered: bool,
+}
+
+impl ShutdownSignal {
+    /// Start listening for Ctrl-C, and `SIGTERM` on Unix, in a background task.
+    pub(crate) fn listen() -> ShutdownSignal {
+        let (sender, received) = watch::channel(false);
+        tokio::spawn(async move {
+            wait_for_signal().await;
+            debug!("Received a signal to shut down");
+            let _ = sender.send(true);
+        });
+        ShutdownSignal::new(received)
+    }
+
+    pub(crate) fn new(received: watch::Receiver<bool>) -> ShutdownSignal {
+        ShutdownSignal {
+            received,
+            delivered: false,
+        }
+    }
+
+    /// Wait until a signal has been received.
+    ///
+    /// The future doesn't borrow the signal, so it can be raced against the connection,
+    /// and nothing is lost if it is dropped.
+    pub(crate) fn wait(&self) -> impl std::future::Future<Output = ()> + Send + 'static {
+        let mut received = self.received.clone();
+        async move {
+            if received.wait_for(|&received| received).await.is_err() {
+                // the listener failed to register, so no signal will come
+                std::future::pending::<()>().await;
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+async fn wait_for_signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    match signal(SignalKind::terminate()) {
+        Ok(mut terminate) => {
+            tokio::select! {
+                result = tokio::signal::ctrl_c() => {
+                    if let Err(err) = result {
+                        warn!("Failed to listen for Ctrl-C: {:?}", err);
+                        terminate.recv().await;
+                    }
+                }
+                _ = terminate.recv() => {}
+            }
+        }
+        Err(err) => {
+            warn!("Failed to listen for SIGTERM: {:?}", err);
+            if let Err(err) = tokio::signal::ctrl_c().await {
+                warn!("Failed to listen for Ctrl-C: {:?}", err);
+                std::future::pending::<()>().await;
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_signal() {
+    if let Err(err) = tokio::signal::ctrl_c().await {
+        warn!("Failed to listen for Ctrl-C: {:?}", err);
+        std::future::pending::<()>().await;
+    }
+}
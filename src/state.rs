@@ -1,14 +1,26 @@
+use serde::{Deserialize, Serialize};
 use tracing::warn;
 
 use super::model::*;
+use crate::cache::{CacheBackend, InMemoryCache};
+use crate::{Connection, Discord, Error, Result, ServerExt};
+use std::borrow::Cow;
 use std::collections::BTreeMap;
 
+/// The version of the layout written by `State::to_bytes`.
+///
+/// Bumped whenever the snapshot or the models it contains change in a way
+/// older snapshots can't be read with, so that they are rejected instead of misread.
+pub const SNAPSHOT_VERSION: u32 = 2;
+
 /// Known state composed from received events.
 #[derive(Debug, Clone)]
 pub struct State {
     user: CurrentUser,
     servers: Vec<LiveServer>,
     unavailable_servers: Vec<ServerId>,
+    /// Servers which were unavailable in the `ReadyEvent` and have not been created since.
+    pending_servers: Vec<ServerId>,
     private_channels: Vec<DirectMessage>,
     groups: BTreeMap<ChannelId, Group>,
     calls: BTreeMap<ChannelId, Call>,
@@ -18,17 +30,183 @@ pub struct State {
     settings: Option<UserSettings>,
     server_settings: Option<Vec<UserServerSettings>>,
     notes: Option<BTreeMap<UserId, Option<String>>>,
+    /// The members of every server, which are kept apart from `servers`,
+    /// and the most recent messages of each channel, if messages are cached.
+    cache: Box<dyn CacheBackend>,
+    /// Which resources are cached, with the backend already moved into `cache`.
+    config: StateConfig,
+    /// Whether the state was restored from a snapshot and has yet to see a `ReadyEvent`.
+    restored: bool,
+}
+
+/// Choose which resources a [`State`] caches, to cut its memory usage on large bots.
+///
+/// Everything but messages is cached by default, with members kept in memory.
+///
+/// ```ignore
+/// let config = StateConfig::new().cache_presences(false).max_messages_per_channel(100);
+/// let mut state = State::with_config(ready, config);
+/// ```
+#[derive(Debug, Clone)]
+pub struct StateConfig {
+    members: bool,
+    presences: bool,
+    voice_states: bool,
+    messages: usize,
+    backend: Option<Box<dyn CacheBackend>>,
+}
+
+impl Default for StateConfig {
+    fn default() -> StateConfig {
+        StateConfig {
+            members: true,
+            presences: true,
+            voice_states: true,
+            messages: 0,
+            backend: None,
+        }
+    }
+}
+
+impl StateConfig {
+    /// Start configuring a state which caches everything.
+    pub fn new() -> StateConfig {
+        StateConfig::default()
+    }
+
+    /// Whether to cache server members.
+    ///
+    /// Without members, `unknown_members` counts every member as unknown.
+    pub fn cache_members(mut self, enabled: bool) -> Self {
+        self.members = enabled;
+        self
+    }
+
+    /// Whether to cache the presences of server members and friends.
+    pub fn cache_presences(mut self, enabled: bool) -> Self {
+        self.presences = enabled;
+        self
+    }
+
+    /// Whether to cache who is connected to voice channels and calls.
+    pub fn cache_voice_states(mut self, enabled: bool) -> Self {
+        self.voice_states = enabled;
+        self
+    }
+
+    /// Cache up to this many of the most recent messages of each channel,
+    /// to be found with `State::message` and `State::recent_messages`.
+    ///
+    /// Messages aren't cached by default.
+    pub fn max_messages_per_channel(mut self, max: usize) -> Self {
+        self.messages = max;
+        self
+    }
+
+    /// Keep members, and messages if they are cached, in the given backend rather than
+    /// in memory.
+    pub fn member_backend(mut self, backend: Box<dyn CacheBackend>) -> Self {
+        self.backend = Some(backend);
+        self
+    }
+
+    /// Take the backend members and messages are stored in, which discards members
+    /// if they aren't cached.
+    fn take_backend(&mut self) -> Box<dyn CacheBackend> {
+        let backend = self
+            .backend
+            .take()
+            .unwrap_or_else(|| Box::new(InMemoryCache::new()));
+        if self.members {
+            backend
+        } else {
+            Box::new(MembersUncached(backend))
+        }
+    }
+
+    /// Drop the parts of a server received from the gateway which aren't cached.
+    fn trim(&self, server: &mut LiveServer) {
+        if !self.presences {
+            server.presences = Vec::new();
+        }
+        if !self.voice_states {
+            server.voice_states = Vec::new();
+        }
+    }
+}
+
+/// The backend used when members aren't cached, which stores only messages.
+#[derive(Debug, Clone)]
+struct MembersUncached(Box<dyn CacheBackend>);
+
+impl CacheBackend for MembersUncached {
+    fn get_member(&self, _: ServerId, _: UserId) -> Option<Member> {
+        None
+    }
+
+    fn put_member(&mut self, _: ServerId, _: UserId, _: Member) {}
+
+    fn remove_member(&mut self, _: ServerId, _: UserId) {}
+
+    fn members(&self, _: ServerId) -> Vec<Member> {
+        Vec::new()
+    }
+
+    fn clear_members(&mut self, _: ServerId) {}
+
+    fn get_message(&self, channel: ChannelId, message: MessageId) -> Option<Message> {
+        self.0.get_message(channel, message)
+    }
+
+    fn push_message(&mut self, channel: ChannelId, message: Message, max: usize) {
+        self.0.push_message(channel, message, max)
+    }
+
+    fn replace_message(&mut self, channel: ChannelId, message: Message) {
+        self.0.replace_message(channel, message)
+    }
+
+    fn remove_messages(&mut self, channel: ChannelId, messages: &[MessageId]) {
+        self.0.remove_messages(channel, messages)
+    }
+
+    fn messages(&self, channel: ChannelId) -> Vec<Message> {
+        self.0.messages(channel)
+    }
+
+    fn clear_messages(&mut self, channel: ChannelId) {
+        self.0.clear_messages(channel)
+    }
+
+    fn boxed_clone(&self) -> Box<dyn CacheBackend> {
+        Box::new(self.clone())
+    }
 }
 
 impl State {
-    /// Create a new state from an initial `ReadyEvent`.
+    /// Create a new state from an initial `ReadyEvent`, keeping members in memory.
     pub fn new(ready: ReadyEvent) -> State {
+        State::with_config(ready, StateConfig::new())
+    }
+
+    /// Create a new state from an initial `ReadyEvent`, keeping members in the given backend.
+    pub fn with_backend(ready: ReadyEvent, members: Box<dyn CacheBackend>) -> State {
+        State::with_config(ready, StateConfig::new().member_backend(members))
+    }
+
+    /// Create a new state from an initial `ReadyEvent`, caching only what `config` allows.
+    pub fn with_config(ready: ReadyEvent, mut config: StateConfig) -> State {
+        let mut members = config.take_backend();
         let mut servers = Vec::new();
         let mut unavailable = Vec::new();
         for server in ready.servers {
             match server {
                 PossibleServer::Offline { id, .. } => unavailable.push(id),
-                PossibleServer::Online(id) => servers.push(id),
+                PossibleServer::Online(mut server) => {
+                    store_members(&mut *members, server.id, take_members(&mut server));
+                    config.trim(&mut server);
+                    servers.push(server)
+                }
             }
         }
         let mut groups: BTreeMap<ChannelId, Group> = BTreeMap::new();
@@ -48,6 +226,7 @@ impl State {
         State {
             user: ready.user,
             servers: servers,
+            pending_servers: unavailable.clone(),
             unavailable_servers: unavailable,
             private_channels: private_channels,
             groups: groups,
@@ -57,14 +236,199 @@ impl State {
             settings: ready.user_settings,
             server_settings: ready.user_server_settings,
             notes: ready.notes,
+            cache: members,
+            config,
+            restored: false,
         }
     }
 
+    /// Save the state, including the members of every server, to be restored with `from_bytes`.
+    ///
+    /// This lets a bot snapshot its cache at shutdown, and warm-start from it rather than
+    /// downloading every member again. When the first `ReadyEvent` after restoring is received,
+    /// the members of the servers the user is still in are kept, and are refreshed by the events
+    /// which follow. Any later `ReadyEvent` starts over with no members.
+    ///
+    /// Snapshots are tagged with [`SNAPSHOT_VERSION`], and can only be restored by a version
+    /// of this crate with the same snapshot version.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let members = self
+            .all_servers()
+            .into_iter()
+            .map(|id| (id, self.cache.members(id)))
+            .filter(|(_, members)| !members.is_empty())
+            .collect();
+        let snapshot = Snapshot {
+            version: SNAPSHOT_VERSION,
+            user: Cow::Borrowed(&self.user),
+            servers: Cow::Borrowed(&self.servers),
+            unavailable_servers: Cow::Borrowed(&self.unavailable_servers),
+            pending_servers: Cow::Borrowed(&self.pending_servers),
+            private_channels: Cow::Borrowed(&self.private_channels),
+            groups: Cow::Borrowed(&self.groups),
+            calls: Cow::Borrowed(&self.calls),
+            presences: Cow::Borrowed(&self.presences),
+            relationships: Cow::Borrowed(&self.relationships),
+            settings: Cow::Borrowed(&self.settings),
+            server_settings: Cow::Borrowed(&self.server_settings),
+            notes: Cow::Borrowed(&self.notes),
+            members,
+        };
+        Ok(serde_json::to_vec(&snapshot)?)
+    }
+
+    /// Restore a state saved with `to_bytes`, keeping members in memory.
+    ///
+    /// Fails if the snapshot was written with a different [`SNAPSHOT_VERSION`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<State> {
+        State::from_bytes_with_config(bytes, StateConfig::new())
+    }
+
+    /// Restore a state saved with `to_bytes`, storing its members in the given backend.
+    pub fn from_bytes_with_backend(bytes: &[u8], members: Box<dyn CacheBackend>) -> Result<State> {
+        State::from_bytes_with_config(bytes, StateConfig::new().member_backend(members))
+    }
+
+    /// Restore a state saved with `to_bytes`, caching only what `config` allows from then on.
+    pub fn from_bytes_with_config(bytes: &[u8], mut config: StateConfig) -> Result<State> {
+        #[derive(Deserialize)]
+        struct Version {
+            version: u32,
+        }
+        if serde_json::from_slice::<Version>(bytes)?.version != SNAPSHOT_VERSION {
+            return Err(Error::Other(
+                "State snapshot was saved by an incompatible version",
+            ));
+        }
+
+        let snapshot: Snapshot = serde_json::from_slice(bytes)?;
+        let mut members = config.take_backend();
+        for (server_id, server_members) in snapshot.members {
+            store_members(&mut *members, server_id, server_members);
+        }
+        Ok(State {
+            user: snapshot.user.into_owned(),
+            servers: snapshot.servers.into_owned(),
+            unavailable_servers: snapshot.unavailable_servers.into_owned(),
+            pending_servers: snapshot.pending_servers.into_owned(),
+            private_channels: snapshot.private_channels.into_owned(),
+            groups: snapshot.groups.into_owned(),
+            calls: snapshot.calls.into_owned(),
+            presences: snapshot.presences.into_owned(),
+            relationships: snapshot.relationships.into_owned(),
+            settings: snapshot.settings.into_owned(),
+            server_settings: snapshot.server_settings.into_owned(),
+            notes: snapshot.notes.into_owned(),
+            cache: members,
+            config,
+            restored: true,
+        })
+    }
+
+    /// Look up a cached message.
+    ///
+    /// To see a message as it was before being edited or deleted, look it up
+    /// before passing the `MessageUpdate` or `MessageDelete` event to `update`.
+    pub fn message(&self, channel_id: ChannelId, message_id: MessageId) -> Option<Message> {
+        self.cache.get_message(channel_id, message_id)
+    }
+
+    /// Get the cached messages of a channel, oldest first.
+    pub fn recent_messages(&self, channel_id: ChannelId) -> Vec<Message> {
+        self.cache.messages(channel_id)
+    }
+
+    /// Keep the message cache up to date with an event.
+    fn update_messages(&mut self, event: &Event) {
+        match *event {
+            Event::MessageCreate(ref message) => {
+                self.cache
+                    .push_message(message.channel_id, message.clone(), self.config.messages);
+            }
+            Event::MessageUpdate {
+                id,
+                channel_id,
+                ref kind,
+                ref content,
+                ref nonce,
+                ref tts,
+                ref pinned,
+                ref timestamp,
+                ref edited_timestamp,
+                ref author,
+                ref mention_everyone,
+                ref mentions,
+                ref mention_roles,
+                ref attachments,
+                ref embeds,
+            } => {
+                if let Some(mut message) = self.cache.get_message(channel_id, id) {
+                    opt_modify(&mut message.kind, kind);
+                    opt_modify(&mut message.content, content);
+                    if nonce.is_some() {
+                        message.nonce.clone_from(nonce);
+                    }
+                    opt_modify(&mut message.tts, tts);
+                    opt_modify(&mut message.pinned, pinned);
+                    opt_modify(&mut message.timestamp, timestamp);
+                    if edited_timestamp.is_some() {
+                        message.edited_timestamp.clone_from(edited_timestamp);
+                    }
+                    opt_modify(&mut message.author, author);
+                    opt_modify(&mut message.mention_everyone, mention_everyone);
+                    opt_modify(&mut message.mentions, mentions);
+                    opt_modify(&mut message.mention_roles, mention_roles);
+                    opt_modify(&mut message.attachments, attachments);
+                    if let Some(embeds) = embeds {
+                        message.embeds = embeds
+                            .iter()
+                            .filter_map(|embed| serde_json::from_value(embed.clone()).ok())
+                            .collect();
+                    }
+                    self.cache.replace_message(channel_id, message);
+                }
+            }
+            Event::MessageDelete {
+                channel_id,
+                message_id,
+            } => {
+                self.cache.remove_messages(channel_id, &[message_id]);
+            }
+            Event::MessageDeleteBulk {
+                channel_id,
+                ref ids,
+            } => {
+                self.cache.remove_messages(channel_id, ids);
+            }
+            Event::ChannelDelete(ref channel) => {
+                let channel_id = match *channel {
+                    Channel::Group(ref group) => group.id,
+                    Channel::DirectMessage(ref channel) => channel.id,
+                    Channel::Server(ref channel) => *channel.id(),
+                };
+                self.cache.clear_messages(channel_id);
+            }
+            Event::ThreadDelete { id, .. } => {
+                self.cache.clear_messages(id);
+            }
+            _ => {}
+        }
+    }
+
+    /// Forget a server, along with its members and the messages of its channels.
+    fn remove_server(&mut self, id: ServerId) {
+        if let Some(server) = self.servers.iter().find(|s| s.id == id) {
+            clear_messages(&mut *self.cache, server);
+        }
+        self.servers.retain(|s| s.id != id);
+        self.cache.clear_members(id);
+    }
+
     /// Count the total number of server members not yet downloaded.
     pub fn unknown_members(&self) -> u64 {
         let mut total = 0;
         for srv in &self.servers {
-            let members = srv.members.len() as u64;
+            let members = self.cache.member_count(srv.id) as u64;
             if srv.member_count > members {
                 total += srv.member_count - members;
             } else if srv.member_count < members {
@@ -77,6 +441,20 @@ impl State {
         total
     }
 
+    /// Re-synchronize a server's member and presence counts with the approximate
+    /// counts reported by the REST API, correcting any drift from missed events.
+    ///
+    /// Does nothing if the server is not known.
+    pub async fn refresh_counts(&mut self, discord: &Discord, server_id: ServerId) -> Result<()> {
+        let server = discord.get_server(server_id).await?;
+        if let Some(srv) = self.servers.iter_mut().find(|s| s.id == server_id) {
+            srv.member_count = server.approximate_member_count;
+            srv.approximate_member_count = server.approximate_member_count;
+            srv.approximate_presence_count = server.approximate_presence_count;
+        }
+        Ok(())
+    }
+
     /// Build a list of all known private and group channel IDs.
     pub fn all_private_channels(&self) -> Vec<ChannelId> {
         self.groups
@@ -97,21 +475,52 @@ impl State {
 
     #[doc(hidden)]
     pub fn __download_members(&mut self) -> Vec<ServerId> {
-        self.servers
-            .iter_mut()
+        let large: Vec<_> = self
+            .servers
+            .iter()
             .filter(|s| s.large)
-            .map(|s| {
-                s.members.clear();
-                s.id
-            })
-            .collect::<Vec<_>>()
+            .map(|s| s.id)
+            .collect();
+        for &server_id in &large {
+            self.cache.clear_members(server_id);
+        }
+        large
     }
 
     /// Update the state according to the changes described in the given event.
     #[allow(deprecated)]
     pub fn update(&mut self, event: &Event) {
+        if self.config.messages > 0 {
+            self.update_messages(event);
+        }
+
         match *event {
-            Event::Ready(ref ready) => *self = State::new(ready.clone()),
+            Event::Ready(ref ready) => {
+                let mut members =
+                    std::mem::replace(&mut self.cache, Box::new(InMemoryCache::new()));
+                let in_ready = |id: &ServerId| {
+                    ready.servers.iter().any(|server| match server {
+                        PossibleServer::Online(server) => server.id == *id,
+                        PossibleServer::Offline { id: offline, .. } => offline == id,
+                    })
+                };
+                for server in &self.servers {
+                    if !in_ready(&server.id) {
+                        clear_messages(&mut *members, server);
+                    }
+                }
+                // on a warm start from a snapshot, members of servers the user is still in are kept
+                for server_id in self.all_servers() {
+                    if !(self.restored && in_ready(&server_id)) {
+                        members.clear_members(server_id);
+                    }
+                }
+                let config = StateConfig {
+                    backend: Some(members),
+                    ..self.config.clone()
+                };
+                *self = State::with_config(ready.clone(), config);
+            }
             Event::UserUpdate(ref user) => {
                 self.user.update_from(user);
             }
@@ -176,6 +585,7 @@ impl State {
                         });
                 }
             }
+            Event::VoiceStateUpdate(..) if !self.config.voice_states => {}
             Event::VoiceStateUpdate(None, ref state) => {
                 if let Some(channel) = state.channel_id {
                     // channel id available, insert voice state
@@ -221,12 +631,16 @@ impl State {
             }
             Event::CallCreate(ref call) => {
                 use std::collections::btree_map::Entry;
+                let mut call = call.clone();
+                if !self.config.voice_states {
+                    call.voice_states = Vec::new();
+                }
                 match self.calls.entry(call.channel_id) {
                     Entry::Vacant(e) => {
-                        e.insert(call.clone());
+                        e.insert(call);
                     }
                     Entry::Occupied(mut e) => {
-                        e.get_mut().clone_from(call);
+                        *e.get_mut() = call;
                     }
                 }
             }
@@ -259,6 +673,10 @@ impl State {
                 ref presence,
                 ..
             } => {
+                self.merge_user(&presence.user);
+                if !self.config.presences {
+                    return;
+                }
                 if let Some(server_id) = server_id {
                     self.servers
                         .iter_mut()
@@ -270,7 +688,7 @@ impl State {
                     update_presence(&mut self.presences, presence);
                 }
             }
-            Event::PresencesReplace(ref presences) => {
+            Event::PresencesReplace(ref presences) if self.config.presences => {
                 self.presences.clone_from(presences);
             }
             Event::RelationshipAdd(ref relationship) => {
@@ -289,17 +707,28 @@ impl State {
             }
             Event::ServerCreate(PossibleServer::Offline { id, .. })
             | Event::ServerDelete(PossibleServer::Offline { id, .. }) => {
-                self.servers.retain(|s| s.id != id);
+                self.pending_servers.retain(|&pending| pending != id);
+                self.remove_server(id);
                 if !self.unavailable_servers.contains(&id) {
                     self.unavailable_servers.push(id);
                 }
             }
             Event::ServerCreate(PossibleServer::Online(ref server)) => {
+                self.pending_servers.retain(|&id| id != server.id);
                 self.unavailable_servers.retain(|&id| id != server.id);
-                self.servers.push(server.clone())
+
+                let mut server = server.clone();
+                // small servers come with every member, while large ones are downloaded in chunks
+                if !server.large {
+                    self.cache.clear_members(server.id);
+                }
+                store_members(&mut *self.cache, server.id, take_members(&mut server));
+                self.config.trim(&mut server);
+                self.servers.push(server)
             }
             Event::ServerDelete(PossibleServer::Online(ref server)) => {
-                self.servers.retain(|s| s.id != server.id);
+                self.pending_servers.retain(|&id| id != server.id);
+                self.remove_server(server.id);
             }
             Event::ServerUpdate(ref server) => {
                 self.servers
@@ -322,8 +751,20 @@ impl State {
                     .iter_mut()
                     .find(|s| s.id == *server_id)
                     .map(|srv| {
-                        srv.member_count += 1;
-                        srv.members.push(member.clone());
+                        // a member already cached, e.g. from a chunk or an event replayed
+                        // after resuming, was counted when it was first seen
+                        let known = member
+                            .user
+                            .as_ref()
+                            .is_some_and(|user| self.cache.get_member(srv.id, user.id).is_some());
+                        // the count is kept even if the members list is not fully downloaded
+                        if !known {
+                            srv.member_count += 1;
+                        }
+
+                        if let Some(ref user) = member.user {
+                            self.cache.put_member(srv.id, user.id, member.clone());
+                        }
                     });
             }
             Event::ServerMemberUpdate {
@@ -331,37 +772,35 @@ impl State {
                 ref roles,
                 ref user,
                 ref nick,
+                pending,
             } => {
-                self.servers
-                    .iter_mut()
-                    .find(|s| s.id == *server_id)
-                    .map(|srv| {
-                        srv.members
-                            .iter_mut()
-                            .find(|m| m.user.as_ref().unwrap().id == user.id)
-                            .map(|member| {
-                                member.user.clone_from(&Some(user).cloned());
-                                member.roles.clone_from(roles);
-                                member.nick.clone_from(nick);
-                            })
-                    });
+                if let Some(mut member) = self.cache.get_member(*server_id, user.id) {
+                    member.user = Some(user.clone());
+                    member.roles.clone_from(roles);
+                    member.nick.clone_from(nick);
+                    member.pending = pending;
+                    self.cache.put_member(*server_id, user.id, member);
+                }
             }
             Event::ServerMemberRemove(ref server_id, ref user) => {
                 self.servers
                     .iter_mut()
                     .find(|s| s.id == *server_id)
                     .map(|srv| {
-                        srv.member_count -= 1;
-                        srv.members
-                            .retain(|m| m.user.as_ref().unwrap().id != user.id);
+                        srv.member_count = srv.member_count.saturating_sub(1);
+                        self.cache.remove_member(srv.id, user.id);
                     });
             }
-            Event::ServerMembersChunk(server_id, ref members) => {
+            Event::ServerMembersChunk {
+                server_id,
+                ref members,
+                ..
+            } => {
                 self.servers
                     .iter_mut()
                     .find(|s| s.id == server_id)
                     .map(|srv| {
-                        srv.members.extend_from_slice(members);
+                        store_members(&mut *self.cache, srv.id, members.iter().cloned());
                     });
             }
             Event::ServerSync {
@@ -375,8 +814,11 @@ impl State {
                     .find(|s| s.id == server_id)
                     .map(|srv| {
                         srv.large = large;
-                        srv.members.clone_from(members);
-                        srv.presences.clone_from(presences);
+                        self.cache.clear_members(srv.id);
+                        store_members(&mut *self.cache, srv.id, members.iter().cloned());
+                        if self.config.presences {
+                            srv.presences.clone_from(presences);
+                        }
                     });
             }
             Event::ServerRoleCreate(ref server_id, ref role) => {
@@ -408,6 +850,74 @@ impl State {
                         srv.roles.retain(|r| r.id != *role_id);
                     });
             }
+            Event::ServerStickersUpdate {
+                server_id,
+                ref stickers,
+            } => {
+                self.servers
+                    .iter_mut()
+                    .find(|s| s.id == server_id)
+                    .map(|srv| {
+                        srv.stickers = Some(stickers.clone());
+                    });
+            }
+            Event::ThreadCreate(ref thread) | Event::ThreadUpdate(ref thread) => {
+                let info = thread.thread();
+                if let Some(srv) = self.servers.iter_mut().find(|s| s.id == info.server_id) {
+                    srv.threads.retain(|t| t.thread().id != info.id);
+                    // only active threads are tracked, as in `ServerCreate`
+                    if !info.thread_info.archived {
+                        srv.threads.push(thread.clone());
+                    }
+                }
+            }
+            Event::ThreadDelete { id, server_id, .. } => {
+                if let Some(srv) = self.servers.iter_mut().find(|s| s.id == server_id) {
+                    srv.threads.retain(|t| t.thread().id != id);
+                }
+            }
+            Event::ThreadListSync {
+                server_id,
+                ref channel_ids,
+                ref threads,
+                ..
+            } => {
+                if let Some(srv) = self.servers.iter_mut().find(|s| s.id == server_id) {
+                    match *channel_ids {
+                        Some(ref channel_ids) => srv
+                            .threads
+                            .retain(|t| !channel_ids.contains(&t.thread().parent_id)),
+                        None => srv.threads.clear(),
+                    }
+                    srv.threads.extend(threads.iter().cloned());
+                }
+            }
+            Event::ThreadMembersUpdate {
+                id,
+                server_id,
+                member_count,
+                ..
+            } => {
+                if let Some(thread) = self
+                    .servers
+                    .iter_mut()
+                    .find(|s| s.id == server_id)
+                    .and_then(|srv| srv.threads.iter_mut().find(|t| t.thread().id == id))
+                {
+                    thread.thread_mut().member_count = member_count;
+                }
+            }
+            Event::StageInstanceCreate(ref stage) | Event::StageInstanceUpdate(ref stage) => {
+                if let Some(srv) = self.servers.iter_mut().find(|s| s.id == stage.server_id) {
+                    srv.active_stages.retain(|s| s.id != stage.id);
+                    srv.active_stages.push(stage.clone());
+                }
+            }
+            Event::StageInstanceDelete(ref stage) => {
+                if let Some(srv) = self.servers.iter_mut().find(|s| s.id == stage.server_id) {
+                    srv.active_stages.retain(|s| s.id != stage.id);
+                }
+            }
             Event::ChannelCreate(ref channel) => match *channel {
                 Channel::Group(ref group) => {
                     self.groups.insert(group.id, group.clone());
@@ -519,16 +1029,52 @@ impl State {
     }
 
     /// Get the servers this user has access to.
+    ///
+    /// The members of the servers are kept separately, and are found with `members` instead.
     #[inline]
     pub fn servers(&self) -> &[LiveServer] {
         &self.servers
     }
 
+    /// Summarize each of the servers this user has access to.
+    pub fn summaries(&self) -> impl Iterator<Item = ServerSummary> + '_ {
+        self.servers.iter().map(LiveServer::summary)
+    }
+
     /// Get the currently unavailable servers.
     pub fn unavailable_servers(&self) -> &[ServerId] {
         &self.unavailable_servers
     }
 
+    /// Get the servers from the `ReadyEvent` which have not been received yet.
+    ///
+    /// After connecting, servers are sent as `ServerCreate` events following the `ReadyEvent`.
+    pub fn pending_servers(&self) -> &[ServerId] {
+        &self.pending_servers
+    }
+
+    /// Check whether every server from the `ReadyEvent` has been received.
+    ///
+    /// Until then, the state only knows the IDs of the pending servers.
+    pub fn is_ready(&self) -> bool {
+        self.pending_servers.is_empty()
+    }
+
+    /// Receive events and update the state until every server from the `ReadyEvent` has been
+    /// received, returning the events received meanwhile so they can still be handled.
+    ///
+    /// Servers in an outage are only received once they become available again,
+    /// so consider wrapping this in a timeout.
+    pub async fn wait_until_hydrated(&mut self, connection: &mut Connection) -> Result<Vec<Event>> {
+        let mut events = Vec::new();
+        while !self.is_ready() {
+            let event = connection.recv_event().await?;
+            self.update(&event);
+            events.push(event);
+        }
+        Ok(events)
+    }
+
     /// Get the active 1-on-1 private channels with other users.
     #[inline]
     pub fn private_channels(&self) -> &[DirectMessage] {
@@ -600,6 +1146,107 @@ impl State {
         None
     }
 
+    /// Look up a member of a server.
+    pub fn member(&self, server_id: ServerId, user_id: UserId) -> Option<Member> {
+        self.cache.get_member(server_id, user_id)
+    }
+
+    /// Get the known members of a server.
+    ///
+    /// For large servers, this is only a part of the members unless they have been downloaded.
+    pub fn members(&self, server_id: ServerId) -> Vec<Member> {
+        self.cache.members(server_id)
+    }
+
+    /// Calculate the effective permissions of a user in a channel of a server.
+    ///
+    /// Returns `None` if the server is not known.
+    pub fn permissions_for(
+        &self,
+        server_id: ServerId,
+        channel_id: ChannelId,
+        user_id: UserId,
+    ) -> Option<Permissions> {
+        let server = self.find_server(server_id)?;
+        let member = self.member(server_id, user_id);
+        Some(server.member_permissions(channel_id, user_id, member.as_ref()))
+    }
+
+    /// Iterate over the channels of a server in which the current user's permissions pass
+    /// `filter`, such as every channel the bot can post in.
+    ///
+    /// Categories are included. Nothing is yielded if the server is not known, or if the
+    /// user's member isn't cached, as their roles can't be told apart from having none.
+    /// This is always the case when members aren't cached (see `StateConfig::cache_members`).
+    ///
+    /// ```ignore
+    /// let needed = Permissions::VIEW_CHANNEL | Permissions::SEND_MESSAGES;
+    /// for channel in state.channels_where(server, |p| p.contains(needed)) {
+    ///     // ...
+    /// }
+    /// ```
+    pub fn channels_where<'a, F>(
+        &'a self,
+        server_id: ServerId,
+        filter: F,
+    ) -> impl Iterator<Item = &'a ServerChannel> + 'a
+    where
+        F: FnMut(Permissions) -> bool + 'a,
+    {
+        self.user_channels_where(server_id, self.user.id, filter)
+    }
+
+    /// Iterate over the channels of a server in which a user's permissions pass `filter`,
+    /// as with `channels_where`. Nothing is yielded for users whose member isn't cached.
+    pub fn user_channels_where<'a, F>(
+        &'a self,
+        server_id: ServerId,
+        user_id: UserId,
+        mut filter: F,
+    ) -> impl Iterator<Item = &'a ServerChannel> + 'a
+    where
+        F: FnMut(Permissions) -> bool + 'a,
+    {
+        let server = self.find_server(server_id);
+        let member = server.and_then(|_| self.member(server_id, user_id));
+        let channels = match (server, &member) {
+            (Some(server), Some(_)) => &server.channels[..],
+            _ => &[][..],
+        };
+        channels.iter().filter(move |channel| {
+            server.is_some_and(|server| {
+                filter(server.member_permissions(*channel.id(), user_id, member.as_ref()))
+            })
+        })
+    }
+
+    /// Iterate over the roles of a server whose permissions pass `filter`,
+    /// such as every role which grants `ADMINISTRATOR`.
+    ///
+    /// Nothing is yielded if the server is not known.
+    pub fn roles_where<'a, F>(
+        &'a self,
+        server_id: ServerId,
+        mut filter: F,
+    ) -> impl Iterator<Item = &'a Role> + 'a
+    where
+        F: FnMut(Permissions) -> bool + 'a,
+    {
+        let roles = self
+            .find_server(server_id)
+            .map_or(&[][..], |server| &server.roles[..]);
+        roles.iter().filter(move |role| filter(role.permissions))
+    }
+
+    /// Look up one of a server's custom stickers by its name.
+    pub fn find_sticker(&self, server_id: ServerId, name: &str) -> Option<&Sticker> {
+        self.find_server(server_id)?
+            .stickers
+            .as_ref()?
+            .iter()
+            .find(|sticker| sticker.name == name)
+    }
+
     /// Look up a private or public channel by its ID.
     pub fn find_channel(&self, id: ChannelId) -> Option<ChannelRef> {
         for server in &self.servers {
@@ -620,6 +1267,111 @@ impl State {
         None
     }
 
+    /// Check the state for references to objects which it does not contain.
+    ///
+    /// A consistent state returns an empty list. Anything else points to an event
+    /// which was missed or handled incorrectly, so this is mostly useful for debugging.
+    pub fn verify(&self) -> Vec<Inconsistency> {
+        let mut found = Vec::new();
+        for server in &self.servers {
+            let has_channel = |id: &ChannelId| server.channels.iter().any(|c| c.id() == id);
+
+            for member in &self.cache.members(server.id) {
+                let user_id = match member.user {
+                    Some(ref user) => user.id,
+                    None => continue,
+                };
+                for role_id in &member.roles {
+                    if !server.roles.iter().any(|r| r.id == *role_id) {
+                        found.push(Inconsistency::MissingRole {
+                            server_id: server.id,
+                            user_id,
+                            role_id: *role_id,
+                        });
+                    }
+                }
+            }
+
+            let threads = server.threads.iter().map(ServerThread::thread).chain(
+                server.channels.iter().filter_map(|channel| match channel {
+                    ServerChannel::AnnouncementThread { thread, .. }
+                    | ServerChannel::PublicThread { thread, .. }
+                    | ServerChannel::PrivateThread { thread, .. } => Some(thread),
+                    _ => None,
+                }),
+            );
+            for thread in threads {
+                if !has_channel(&thread.parent_id) {
+                    found.push(Inconsistency::MissingParent {
+                        server_id: server.id,
+                        thread_id: thread.id,
+                        parent_id: thread.parent_id,
+                    });
+                }
+            }
+
+            for voice_state in &server.voice_states {
+                if let Some(channel_id) = voice_state.channel_id {
+                    if !has_channel(&channel_id) {
+                        found.push(Inconsistency::MissingVoiceChannel {
+                            server_id: Some(server.id),
+                            user_id: voice_state.user_id,
+                            channel_id,
+                        });
+                    }
+                }
+            }
+        }
+
+        for call in self.calls.values() {
+            let known = self.groups.contains_key(&call.channel_id)
+                || self
+                    .private_channels
+                    .iter()
+                    .any(|c| c.id == call.channel_id);
+            if !known {
+                for voice_state in &call.voice_states {
+                    found.push(Inconsistency::MissingVoiceChannel {
+                        server_id: None,
+                        user_id: voice_state.user_id,
+                        channel_id: call.channel_id,
+                    });
+                }
+            }
+        }
+        found
+    }
+
+    /// Look up a user by their ID among server members, friends and private channel recipients.
+    pub fn find_user(&self, id: UserId) -> Option<User> {
+        if let Some(user) = self.known_users().find(|user| user.id == id) {
+            return Some(user.clone());
+        }
+        self.servers
+            .iter()
+            .find_map(|srv| self.cache.get_member(srv.id, id)?.user)
+    }
+
+    /// Check whether an event reveals that a known user changed their username,
+    /// discriminator or avatar, comparing it with the currently known user.
+    ///
+    /// Discord does not send an event for such changes to other users,
+    /// but they show up in presence updates. Call this before `update`,
+    /// which applies the changes to the state.
+    pub fn profile_change(&self, event: &Event) -> Option<ProfileChange> {
+        let presence = match *event {
+            Event::PresenceUpdate { ref presence, .. } => presence,
+            _ => return None,
+        };
+        let before = self.find_user(presence.user.id)?;
+        let mut after = before.clone();
+        if presence.user.update(&mut after) {
+            Some(ProfileChange { before, after })
+        } else {
+            None
+        }
+    }
+
     /// Look up the voice channel or group call a user is in, if any.
     ///
     /// For bot users which may be in multiple voice channels, the first found is returned.
@@ -644,6 +1396,89 @@ impl State {
     }
 }
 
+impl State {
+    /// Iterate over the users known outside of server members.
+    fn known_users(&self) -> impl Iterator<Item = &User> {
+        let friends = self.relationships.iter().map(|r| &r.user);
+        let private = self.private_channels.iter().map(|c| &c.recipient[0]);
+        let groups = self.groups.values().flat_map(|g| g.recipients.iter());
+        friends.chain(private).chain(groups)
+    }
+
+    /// Copy the fields of a partial user onto every copy of that user in the state.
+    fn merge_user(&mut self, partial: &PresenceUser) {
+        for srv in &self.servers {
+            if let Some(mut member) = self.cache.get_member(srv.id, partial.id) {
+                if let Some(user) = member.user.as_mut() {
+                    partial.update(user);
+                }
+                self.cache.put_member(srv.id, partial.id, member);
+            }
+        }
+
+        let friends = self.relationships.iter_mut().map(|r| &mut r.user);
+        let private = self
+            .private_channels
+            .iter_mut()
+            .map(|c| &mut c.recipient[0]);
+        let groups = self
+            .groups
+            .values_mut()
+            .flat_map(|g| g.recipients.iter_mut());
+        for user in friends.chain(private).chain(groups) {
+            if user.id == partial.id {
+                partial.update(user);
+            }
+        }
+    }
+}
+
+/// The layout written by `State::to_bytes`, borrowing from the state when saving it.
+#[derive(Serialize, Deserialize)]
+struct Snapshot<'a> {
+    version: u32,
+    user: Cow<'a, CurrentUser>,
+    servers: Cow<'a, [LiveServer]>,
+    unavailable_servers: Cow<'a, [ServerId]>,
+    pending_servers: Cow<'a, [ServerId]>,
+    private_channels: Cow<'a, [DirectMessage]>,
+    groups: Cow<'a, BTreeMap<ChannelId, Group>>,
+    calls: Cow<'a, BTreeMap<ChannelId, Call>>,
+    presences: Cow<'a, [Presence]>,
+    relationships: Cow<'a, [Relationship]>,
+    settings: Cow<'a, Option<UserSettings>>,
+    server_settings: Cow<'a, Option<Vec<UserServerSettings>>>,
+    notes: Cow<'a, Option<BTreeMap<UserId, Option<String>>>>,
+    members: Vec<(ServerId, Vec<Member>)>,
+}
+
+/// Move the members out of a server received from the gateway, to be stored in a backend.
+fn take_members(server: &mut LiveServer) -> Vec<Member> {
+    std::mem::take(&mut server.members)
+}
+
+/// Store members of a server in a backend, skipping those without a user.
+fn store_members<I>(backend: &mut dyn CacheBackend, server_id: ServerId, members: I)
+where
+    I: IntoIterator<Item = Member>,
+{
+    for member in members {
+        if let Some(user_id) = member.user.as_ref().map(|user| user.id) {
+            backend.put_member(server_id, user_id, member);
+        }
+    }
+}
+
+/// Drop the cached messages of every channel and thread of a server.
+fn clear_messages(backend: &mut dyn CacheBackend, server: &LiveServer) {
+    for channel in &server.channels {
+        backend.clear_messages(*channel.id());
+    }
+    for thread in &server.threads {
+        backend.clear_messages(thread.thread().id);
+    }
+}
+
 fn update_presence(vec: &mut Vec<Presence>, presence: &Presence) {
     // Remove the user from the presence list, if they've gone offline
     if presence.status == OnlineStatus::Offline {
@@ -659,6 +1494,47 @@ fn update_presence(vec: &mut Vec<Presence>, presence: &Presence) {
     }
 }
 
+/// A dangling reference found by [`State::verify`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Inconsistency {
+    /// A member has a role which is not in the server's role list.
+    MissingRole {
+        /// The server the member belongs to.
+        server_id: ServerId,
+        /// The member with the role.
+        user_id: UserId,
+        /// The role which could not be found.
+        role_id: RoleId,
+    },
+    /// A thread's parent channel is not in the server's channel list.
+    MissingParent {
+        /// The server the thread belongs to.
+        server_id: ServerId,
+        /// The thread itself.
+        thread_id: ChannelId,
+        /// The parent channel which could not be found.
+        parent_id: ChannelId,
+    },
+    /// A user's voice state refers to a channel which is not known.
+    MissingVoiceChannel {
+        /// The server the voice state belongs to, or `None` for group and private calls.
+        server_id: Option<ServerId>,
+        /// The user in the voice channel.
+        user_id: UserId,
+        /// The channel which could not be found.
+        channel_id: ChannelId,
+    },
+}
+
+/// A change to a user's profile, found by [`State::profile_change`].
+#[derive(Debug, Clone)]
+pub struct ProfileChange {
+    /// The user as they were known before the change.
+    pub before: User,
+    /// The user after the change.
+    pub after: User,
+}
+
 /// A reference to a private or public channel.
 #[derive(Debug, Clone, Copy)]
 pub enum ChannelRef<'a> {
@@ -676,3 +1552,288 @@ fn opt_modify<T: Clone>(dest: &mut T, src: &Option<T>) {
         dest.clone_from(val);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{State, StateConfig};
+    use crate::model::{
+        ChannelId, Event, Member, MessageId, Permissions, PossibleServer, ReadyEvent, RoleId,
+        ServerId, UserId,
+    };
+
+    fn ready() -> ReadyEvent {
+        serde_json::from_value(json!({
+            "v": 6,
+            "session_id": "session",
+            "guilds": [{ "id": "5", "unavailable": true }],
+            "user": {
+                "id": "1",
+                "username": "test",
+                "discriminator": "0001",
+                "avatar": null,
+                "email": null,
+                "verified": true,
+                "bot": true,
+                "mfa_enabled": false,
+            },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn snapshot_round_trip() {
+        let mut member = crate::fixtures::member(2, &[]);
+        member["nick"] = "nick".into();
+        let member: Member = serde_json::from_value(member).unwrap();
+
+        let mut state = State::new(ready());
+        state.cache.put_member(ServerId(5), UserId(2), member);
+
+        let bytes = state.to_bytes().unwrap();
+        let restored = State::from_bytes(&bytes).unwrap();
+        assert_eq!(restored.user().id, UserId(1));
+        assert_eq!(restored.all_servers(), [ServerId(5)]);
+        let member = restored.member(ServerId(5), UserId(2)).unwrap();
+        assert_eq!(member.nick.as_deref(), Some("nick"));
+
+        let mut snapshot: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        snapshot["version"] = json!(0);
+        assert!(State::from_bytes(&serde_json::to_vec(&snapshot).unwrap()).is_err());
+    }
+
+    #[test]
+    fn keeps_members_only_on_warm_starts() {
+        let server_create = |members: &[u64]| {
+            let members = members
+                .iter()
+                .map(|&id| crate::fixtures::member(id, &[]))
+                .collect();
+            let server = serde_json::from_value(crate::fixtures::live_server(5, members)).unwrap();
+            Event::ServerCreate(PossibleServer::Online(server))
+        };
+
+        let mut state = State::new(ready());
+        state.update(&server_create(&[2, 3]));
+        assert_eq!(state.members(ServerId(5)).len(), 2);
+
+        let restored = State::from_bytes(&state.to_bytes().unwrap()).unwrap();
+        assert_eq!(restored.find_server(ServerId(5)).unwrap().name, "server");
+        assert!(restored.member(ServerId(5), UserId(3)).is_some());
+
+        // the first ready after restoring keeps the members, while later ones don't
+        let mut state = restored;
+        state.update(&Event::Ready(ready()));
+        assert_eq!(state.members(ServerId(5)).len(), 2);
+        state.update(&Event::Ready(ready()));
+        assert!(state.members(ServerId(5)).is_empty());
+
+        // a small server lists every member, so those who left are dropped
+        state.update(&server_create(&[2, 3]));
+        state.update(&server_create(&[3]));
+        assert!(state.member(ServerId(5), UserId(2)).is_none());
+        assert!(state.member(ServerId(5), UserId(3)).is_some());
+    }
+
+    #[test]
+    fn counts_each_member_once() {
+        let server = crate::fixtures::live_server(5, vec![crate::fixtures::member(2, &[])]);
+        let mut state = State::new(ready());
+        state.update(&Event::ServerCreate(PossibleServer::Online(
+            serde_json::from_value(server).unwrap(),
+        )));
+        let add = |user: u64| {
+            let member = serde_json::from_value(crate::fixtures::member(user, &[])).unwrap();
+            Event::ServerMemberAdd(ServerId(5), member)
+        };
+        let count = |state: &State| state.find_server(ServerId(5)).unwrap().member_count;
+
+        state.update(&add(3));
+        assert_eq!(count(&state), 2);
+        // the same add replayed after resuming, or of a member received in a chunk
+        state.update(&add(3));
+        state.update(&add(2));
+        assert_eq!(count(&state), 2);
+    }
+
+    #[test]
+    fn drops_messages_of_departed_servers() {
+        let server = || {
+            let mut server = crate::fixtures::live_server(5, vec![]);
+            server["channels"] = json!([crate::fixtures::text_channel(10, 5)]);
+            server["stickers"] = json!([]);
+            server
+        };
+        let create = || {
+            Event::ServerCreate(PossibleServer::Online(
+                serde_json::from_value(server()).unwrap(),
+            ))
+        };
+        let message = |id: u64| {
+            Event::MessageCreate(serde_json::from_value(crate::fixtures::message(id, 10)).unwrap())
+        };
+        let cached = |state: &State| state.recent_messages(ChannelId(10)).len();
+
+        let config = StateConfig::new().max_messages_per_channel(10);
+        let mut state = State::with_config(ready(), config);
+        state.update(&create());
+        state.update(&message(1));
+
+        // a ready which still lists the server keeps its messages
+        state.update(&Event::Ready(ready()));
+        assert_eq!(cached(&state), 1);
+
+        // while one without it drops them
+        let mut left = ready();
+        left.servers.clear();
+        state.update(&create());
+        state.update(&Event::Ready(left));
+        assert_eq!(cached(&state), 0);
+
+        // as do servers being deleted or becoming unavailable
+        for delete in [
+            Event::ServerDelete(PossibleServer::Online(
+                serde_json::from_value(server()).unwrap(),
+            )),
+            Event::ServerDelete(PossibleServer::Offline {
+                id: ServerId(5),
+                unavailable: false,
+            }),
+            Event::ServerCreate(PossibleServer::Offline {
+                id: ServerId(5),
+                unavailable: true,
+            }),
+        ] {
+            state.update(&create());
+            state.update(&message(2));
+            assert_eq!(cached(&state), 1);
+            state.update(&delete);
+            assert_eq!(cached(&state), 0);
+        }
+    }
+
+    #[test]
+    fn filters_channels_and_roles_by_permissions() {
+        let mut server = crate::fixtures::live_server(
+            5,
+            vec![
+                crate::fixtures::member(1, &[7]),
+                crate::fixtures::member(2, &[]),
+            ],
+        );
+        let mut hidden = crate::fixtures::text_channel(11, 5);
+        hidden["permission_overwrites"] = json!([
+            {"id": "5", "type": 0, "allow": "0", "deny": Permissions::READ_MESSAGES},
+            {"id": "7", "type": 0, "allow": Permissions::READ_MESSAGES, "deny": "0"},
+        ]);
+        server["channels"] = json!([crate::fixtures::text_channel(10, 5), hidden]);
+        let mut moderator = server["roles"][0].clone();
+        moderator["id"] = "7".into();
+        moderator["name"] = "moderator".into();
+        moderator["permissions"] = json!(Permissions::MANAGE_MESSAGES);
+        server["roles"].as_array_mut().unwrap().push(moderator);
+        let create = Event::ServerCreate(PossibleServer::Online(
+            serde_json::from_value(server).unwrap(),
+        ));
+
+        let mut state = State::new(ready());
+        state.update(&create);
+        let readable = |user: u64| -> Vec<_> {
+            state
+                .user_channels_where(ServerId(5), UserId(user), |p| {
+                    p.contains(Permissions::READ_MESSAGES)
+                })
+                .map(|channel| *channel.id())
+                .collect()
+        };
+        assert_eq!(readable(1), [ChannelId(10), ChannelId(11)]);
+        assert_eq!(readable(2), [ChannelId(10)]);
+        // users whose roles aren't known are left out, rather than given only @everyone's
+        assert_eq!(readable(3), []);
+
+        let mine: Vec<_> = state
+            .channels_where(ServerId(5), |p| p.contains(Permissions::READ_MESSAGES))
+            .map(|channel| *channel.id())
+            .collect();
+        assert_eq!(mine, readable(1));
+        assert_eq!(state.channels_where(ServerId(6), |_| true).count(), 0);
+
+        let moderators: Vec<_> = state
+            .roles_where(ServerId(5), |p| p.contains(Permissions::MANAGE_MESSAGES))
+            .map(|role| role.id)
+            .collect();
+        assert_eq!(moderators, [RoleId(7)]);
+        assert_eq!(state.roles_where(ServerId(6), |_| true).count(), 0);
+
+        // without cached members, no user's channels can be worked out
+        let mut state = State::with_config(ready(), StateConfig::new().cache_members(false));
+        state.update(&create);
+        assert_eq!(state.channels_where(ServerId(5), |_| true).count(), 0);
+    }
+
+    #[test]
+    fn skips_disabled_resources() {
+        let presence = serde_json::from_value(json!({
+            "user": { "id": "2" },
+            "status": "online",
+            "activities": [],
+        }))
+        .unwrap();
+        let update = Event::PresenceUpdate {
+            presence,
+            server_id: None,
+            roles: None,
+        };
+
+        let mut state = State::new(ready());
+        state.update(&update);
+        assert_eq!(state.presences().len(), 1);
+
+        let mut state = State::with_config(ready(), StateConfig::new().cache_presences(false));
+        state.update(&update);
+        assert!(state.presences().is_empty());
+    }
+
+    #[test]
+    fn caches_recent_messages() {
+        let create = |id: u64| {
+            Event::MessageCreate(serde_json::from_value(crate::fixtures::message(id, 10)).unwrap())
+        };
+
+        let config = StateConfig::new().max_messages_per_channel(2);
+        let mut state = State::with_config(ready(), config);
+        for id in 1..=3 {
+            state.update(&create(id));
+        }
+        let ids: Vec<_> = state
+            .recent_messages(ChannelId(10))
+            .iter()
+            .map(|m| m.id)
+            .collect();
+        assert_eq!(ids, [MessageId(2), MessageId(3)]);
+
+        let edit: Event = serde_json::from_value(json!({
+            "t": "MESSAGE_UPDATE",
+            "d": { "id": "3", "channel_id": "10", "content": "edited" },
+        }))
+        .unwrap();
+        state.update(&edit);
+        let edited = state.message(ChannelId(10), MessageId(3)).unwrap();
+        assert_eq!(edited.content, "edited");
+
+        state.update(&Event::MessageDelete {
+            channel_id: ChannelId(10),
+            message_id: MessageId(2),
+        });
+        assert!(state.message(ChannelId(10), MessageId(2)).is_none());
+
+        // messages are kept in the backend even when members aren't cached
+        let config = StateConfig::new()
+            .cache_members(false)
+            .max_messages_per_channel(2);
+        let mut state = State::with_config(ready(), config);
+        state.update(&create(1));
+        assert!(state.message(ChannelId(10), MessageId(1)).is_some());
+    }
+}
@@ -0,0 +1,289 @@
+//! An in-process stand-in for the Discord gateway, for testing.
+//!
+//! The [`TestGateway`] speaks just enough of the gateway protocol to drive a
+//! [`Connection`](crate::Connection) through identifying, heartbeating, dispatching events,
+//! and reconnecting or resuming, so that bots can test their event handling deterministically.
+//!
+//! The server is scripted from the test itself: accept a session, then send and expect
+//! messages in the order the test calls for.
+//!
+//! ```no_run
+//! # async fn example() -> std::io::Result<()> {
+//! use discord_tokio::test_gateway::TestGateway;
+//! use serde_json::json;
+//!
+//! let gateway = TestGateway::bind().await?;
+//! // point a connection at `gateway.url()`, then:
+//! let mut session = gateway.accept().await?;
+//! session.hello(45000).await?;
+//! session.expect_identify().await?;
+//! session.ready("session").await?;
+//! session.dispatch("TYPING_START", json!({ /* ... */ })).await?;
+//! session.reconnect().await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Only available with the `test-gateway` feature.
+
+use std::io::{self, ErrorKind};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use serde_json::{json, Value};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// The GUID appended to the client's key during the websocket handshake.
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// A websocket server listening on localhost, accepting gateway sessions.
+#[derive(Debug)]
+pub struct TestGateway {
+    listener: TcpListener,
+    /// The dispatch sequence number, shared between sessions so that resumes continue it.
+    sequence: Arc<AtomicU64>,
+}
+
+impl TestGateway {
+    /// Start listening on a random local port.
+    pub async fn bind() -> io::Result<TestGateway> {
+        Ok(TestGateway {
+            listener: TcpListener::bind("127.0.0.1:0").await?,
+            sequence: Arc::new(AtomicU64::new(0)),
+        })
+    }
+
+    /// The URL a connection should use to reach this gateway.
+    pub fn url(&self) -> String {
+        format!(
+            "ws://{}",
+            self.listener
+                .local_addr()
+                .expect("listener has a local address")
+        )
+    }
+
+    /// Wait for a client to connect, and complete the websocket handshake.
+    pub async fn accept(&self) -> io::Result<TestSession> {
+        let (mut stream, _) = self.listener.accept().await?;
+
+        let mut request = Vec::new();
+        while !request.ends_with(b"\r\n\r\n") {
+            request.push(stream.read_u8().await?);
+        }
+        let request = String::from_utf8_lossy(&request);
+        let key = request
+            .lines()
+            .find_map(|line| {
+                let (name, value) = line.split_once(':')?;
+                name.eq_ignore_ascii_case("Sec-WebSocket-Key")
+                    .then(|| value.trim())
+            })
+            .ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "missing websocket key"))?;
+
+        let accept = sha1_smol::Sha1::from(format!("{}{}", key, WEBSOCKET_GUID)).digest();
+        let response = format!(
+            "HTTP/1.1 101 Switching Protocols\r\n\
+             Upgrade: websocket\r\n\
+             Connection: Upgrade\r\n\
+             Sec-WebSocket-Accept: {}\r\n\r\n",
+            base64::encode(accept.bytes())
+        );
+        stream.write_all(response.as_bytes()).await?;
+
+        Ok(TestSession {
+            stream,
+            sequence: self.sequence.clone(),
+        })
+    }
+}
+
+/// A single websocket connection accepted by a [`TestGateway`].
+#[derive(Debug)]
+pub struct TestSession {
+    stream: TcpStream,
+    sequence: Arc<AtomicU64>,
+}
+
+impl TestSession {
+    /// Send the `Hello` message which opens every gateway session.
+    pub async fn hello(&mut self, heartbeat_interval: u64) -> io::Result<()> {
+        self.send(json!({ "op": 10, "d": { "heartbeat_interval": heartbeat_interval } }))
+            .await
+    }
+
+    /// Wait for the client to identify, returning the identify payload.
+    pub async fn expect_identify(&mut self) -> io::Result<Value> {
+        self.expect_op(2).await
+    }
+
+    /// Wait for the client to resume, returning the resume payload.
+    pub async fn expect_resume(&mut self) -> io::Result<Value> {
+        self.expect_op(6).await
+    }
+
+    /// Wait for a heartbeat, returning the sequence number it carries.
+    ///
+    /// The heartbeat is acknowledged before returning.
+    pub async fn expect_heartbeat(&mut self) -> io::Result<Option<u64>> {
+        loop {
+            let message = self.recv().await?;
+            if message["op"] == 1 {
+                self.send(json!({ "op": 11 })).await?;
+                return Ok(message["d"].as_u64());
+            }
+        }
+    }
+
+    /// Wait for the client to close the websocket, returning the close code it gave.
+    ///
+    /// Messages received in the meantime are skipped.
+    pub async fn expect_close(&mut self) -> io::Result<Option<u16>> {
+        loop {
+            let (opcode, payload) = self.read_frame().await?;
+            if opcode == 0x8 {
+                return Ok(payload
+                    .get(..2)
+                    .map(|code| u16::from_be_bytes([code[0], code[1]])));
+            }
+        }
+    }
+
+    /// Wait for a message with the given opcode, returning its payload.
+    ///
+    /// Heartbeats received in the meantime are acknowledged, and other messages are skipped.
+    pub async fn expect_op(&mut self, op: u64) -> io::Result<Value> {
+        loop {
+            let mut message = self.recv().await?;
+            if message["op"] == op {
+                return Ok(message["d"].take());
+            }
+            if message["op"] == 1 {
+                self.send(json!({ "op": 11 })).await?;
+            }
+        }
+    }
+
+    /// Dispatch a `READY` event for a bot user, starting a session with the given ID.
+    pub async fn ready(&mut self, session_id: &str) -> io::Result<u64> {
+        self.dispatch(
+            "READY",
+            json!({
+                "v": 6,
+                "session_id": session_id,
+                "guilds": [],
+                "user": {
+                    "id": "1",
+                    "username": "test",
+                    "discriminator": "0001",
+                    "avatar": null,
+                    "email": null,
+                    "verified": true,
+                    "bot": true,
+                    "mfa_enabled": false,
+                },
+            }),
+        )
+        .await
+    }
+
+    /// Dispatch a `RESUMED` event, acknowledging a resume.
+    pub async fn resumed(&mut self) -> io::Result<u64> {
+        self.dispatch("RESUMED", json!({ "_trace": [] })).await
+    }
+
+    /// Dispatch an event with the given name and data, returning its sequence number.
+    pub async fn dispatch(&mut self, event: &str, data: Value) -> io::Result<u64> {
+        let sequence = self.sequence.fetch_add(1, Ordering::Relaxed) + 1;
+        self.send(json!({ "op": 0, "s": sequence, "t": event, "d": data }))
+            .await?;
+        Ok(sequence)
+    }
+
+    /// Ask the client to send a heartbeat immediately.
+    pub async fn request_heartbeat(&mut self) -> io::Result<()> {
+        self.send(json!({ "op": 1, "d": null })).await
+    }
+
+    /// Ask the client to reconnect and resume.
+    pub async fn reconnect(&mut self) -> io::Result<()> {
+        self.send(json!({ "op": 7, "d": null })).await
+    }
+
+    /// Tell the client its session is invalid.
+    pub async fn invalid_session(&mut self, resumable: bool) -> io::Result<()> {
+        self.send(json!({ "op": 9, "d": resumable })).await
+    }
+
+    /// Close the websocket with the given close code and reason.
+    pub async fn close(mut self, code: u16, reason: &str) -> io::Result<()> {
+        let mut payload = code.to_be_bytes().to_vec();
+        payload.extend_from_slice(reason.as_bytes());
+        self.write_frame(0x8, &payload).await
+    }
+
+    /// Send a raw JSON message to the client.
+    pub async fn send(&mut self, message: Value) -> io::Result<()> {
+        self.write_frame(0x1, message.to_string().as_bytes()).await
+    }
+
+    /// Receive a raw JSON message from the client.
+    pub async fn recv(&mut self) -> io::Result<Value> {
+        loop {
+            let (opcode, payload) = self.read_frame().await?;
+            match opcode {
+                0x1 | 0x2 => return Ok(serde_json::from_slice(&payload)?),
+                0x8 => return Err(io::Error::new(ErrorKind::ConnectionAborted, "closed")),
+                _ => {}
+            }
+        }
+    }
+
+    async fn write_frame(&mut self, opcode: u8, payload: &[u8]) -> io::Result<()> {
+        self.send_frame(true, opcode, payload).await
+    }
+
+    /// Send a single websocket frame, such as one fragment of a message split over several.
+    ///
+    /// Fragments after the first have the continuation opcode `0x0`, and the last sets `fin`.
+    pub async fn send_frame(&mut self, fin: bool, opcode: u8, payload: &[u8]) -> io::Result<()> {
+        let mut frame = vec![if fin { 0x80 } else { 0 } | opcode];
+        match payload.len() {
+            len if len < 126 => frame.push(len as u8),
+            len if len <= u16::MAX as usize => {
+                frame.push(126);
+                frame.extend_from_slice(&(len as u16).to_be_bytes());
+            }
+            len => {
+                frame.push(127);
+                frame.extend_from_slice(&(len as u64).to_be_bytes());
+            }
+        }
+        frame.extend_from_slice(payload);
+        self.stream.write_all(&frame).await
+    }
+
+    async fn read_frame(&mut self) -> io::Result<(u8, Vec<u8>)> {
+        let opcode = self.stream.read_u8().await? & 0x0F;
+        let header = self.stream.read_u8().await?;
+        let len = match header & 0x7F {
+            126 => self.stream.read_u16().await? as usize,
+            127 => self.stream.read_u64().await? as usize,
+            len => len as usize,
+        };
+
+        // frames sent by clients are always masked
+        let mut mask = [0; 4];
+        if header & 0x80 != 0 {
+            self.stream.read_exact(&mut mask).await?;
+        }
+        let mut payload = vec![0; len];
+        self.stream.read_exact(&mut payload).await?;
+        for (i, byte) in payload.iter_mut().enumerate() {
+            *byte ^= mask[i % 4];
+        }
+
+        Ok((opcode, payload))
+    }
+}
@@ -0,0 +1,217 @@
+//! Coalescing of typing indicator events.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use futures::{Stream, StreamExt};
+use tokio::time::Instant;
+
+use crate::model::{ChannelId, Event, UserId};
+
+/// How long a typing indicator lasts after the last `TypingStart`, as in the official client.
+const DEFAULT_EXPIRY: Duration = Duration::from_secs(10);
+
+/// A change in whether a user is typing in a channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TypingChange {
+    /// The user started typing.
+    Started {
+        /// The channel being typed in.
+        channel_id: ChannelId,
+        /// The user who is typing.
+        user_id: UserId,
+    },
+    /// The user stopped typing, either by sending a message or by letting the indicator expire.
+    Stopped {
+        /// The channel that was being typed in.
+        channel_id: ChannelId,
+        /// The user who was typing.
+        user_id: UserId,
+    },
+}
+
+/// Tracks who is typing where, turning the raw stream of `TypingStart` events
+/// into "started typing" and "stopped typing" transitions.
+///
+/// Discord sends `TypingStart` repeatedly while a user keeps typing, and nothing
+/// at all when they stop, so the tracker considers a user to have stopped once
+/// no event was received from them for the expiry duration, or once they send a message.
+#[derive(Debug, Clone)]
+pub struct TypingTracker {
+    expiry: Duration,
+    typing: BTreeMap<(ChannelId, UserId), Instant>,
+}
+
+impl TypingTracker {
+    /// Create a tracker using the default expiry of 10 seconds.
+    pub fn new() -> TypingTracker {
+        TypingTracker::with_expiry(DEFAULT_EXPIRY)
+    }
+
+    /// Create a tracker which considers users to have stopped typing
+    /// after not receiving a `TypingStart` from them for the given duration.
+    pub fn with_expiry(expiry: Duration) -> TypingTracker {
+        TypingTracker {
+            expiry,
+            typing: BTreeMap::new(),
+        }
+    }
+
+    /// Update the tracker according to the given event, returning a change if one occurred.
+    ///
+    /// Repeated `TypingStart` events for a user who is already typing only extend the indicator.
+    pub fn update(&mut self, event: &Event) -> Option<TypingChange> {
+        self.update_at(event, Instant::now())
+    }
+
+    /// Remove indicators which have expired, returning the resulting changes.
+    pub fn expire(&mut self) -> Vec<TypingChange> {
+        self.expire_at(Instant::now())
+    }
+
+    /// When the next indicator is due to expire, if anyone is typing.
+    pub fn next_expiry(&self) -> Option<Instant> {
+        self.typing.values().min().map(|&since| since + self.expiry)
+    }
+
+    /// Get the users currently typing in a channel.
+    pub fn typing_in(&self, channel_id: ChannelId) -> Vec<UserId> {
+        self.typing
+            .range((channel_id, UserId(0))..=(channel_id, UserId(u64::MAX)))
+            .map(|(&(_, user_id), _)| user_id)
+            .collect()
+    }
+
+    /// Check whether a user is typing in a channel.
+    pub fn is_typing(&self, channel_id: ChannelId, user_id: UserId) -> bool {
+        self.typing.contains_key(&(channel_id, user_id))
+    }
+
+    /// Turn a stream of events into a stream of typing changes,
+    /// including the expiry of indicators while no events are arriving.
+    ///
+    /// The returned stream ends when the event stream ends.
+    pub fn changes<S>(self, events: S) -> impl Stream<Item = TypingChange>
+    where
+        S: Stream<Item = Event> + Unpin,
+    {
+        futures::stream::unfold(
+            (self, events, Vec::new()),
+            |(mut tracker, mut events, mut pending)| async move {
+                loop {
+                    if let Some(change) = pending.pop() {
+                        return Some((change, (tracker, events, pending)));
+                    }
+
+                    let expiry = tracker.next_expiry();
+                    tokio::select! {
+                        event = events.next() => {
+                            let event = event?;
+                            pending.extend(tracker.update(&event));
+                        }
+                        _ = tokio::time::sleep_until(expiry.unwrap_or_else(Instant::now)), if expiry.is_some() => {
+                            pending = tracker.expire();
+                            pending.reverse();
+                        }
+                    }
+                }
+            },
+        )
+    }
+
+    fn update_at(&mut self, event: &Event, now: Instant) -> Option<TypingChange> {
+        match *event {
+            Event::TypingStart {
+                channel_id,
+                user_id,
+                ..
+            } => match self.typing.insert((channel_id, user_id), now) {
+                Some(_) => None,
+                None => Some(TypingChange::Started {
+                    channel_id,
+                    user_id,
+                }),
+            },
+            Event::MessageCreate(ref message) => {
+                let key = (message.channel_id, message.author.id);
+                self.typing.remove(&key).map(|_| TypingChange::Stopped {
+                    channel_id: key.0,
+                    user_id: key.1,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    fn expire_at(&mut self, now: Instant) -> Vec<TypingChange> {
+        let expiry = self.expiry;
+        let mut changes = Vec::new();
+        self.typing.retain(|&(channel_id, user_id), &mut since| {
+            let alive = now < since + expiry;
+            if !alive {
+                changes.push(TypingChange::Stopped {
+                    channel_id,
+                    user_id,
+                });
+            }
+            alive
+        });
+        changes
+    }
+}
+
+impl Default for TypingTracker {
+    fn default() -> Self {
+        TypingTracker::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chrono::Utc;
+    use tokio::time::Instant;
+
+    use super::{TypingChange, TypingTracker};
+    use crate::model::{ChannelId, Event, UserId};
+
+    fn typing(channel: u64, user: u64) -> Event {
+        Event::TypingStart {
+            channel_id: ChannelId(channel),
+            user_id: UserId(user),
+            timestamp: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn coalesces_and_expires() {
+        let mut tracker = TypingTracker::with_expiry(Duration::from_secs(10));
+        let start = Instant::now();
+
+        assert_eq!(
+            tracker.update_at(&typing(1, 2), start),
+            Some(TypingChange::Started {
+                channel_id: ChannelId(1),
+                user_id: UserId(2)
+            })
+        );
+        assert_eq!(
+            tracker.update_at(&typing(1, 2), start + Duration::from_secs(8)),
+            None
+        );
+        assert!(tracker
+            .expire_at(start + Duration::from_secs(12))
+            .is_empty());
+        assert_eq!(tracker.typing_in(ChannelId(1)), vec![UserId(2)]);
+
+        assert_eq!(
+            tracker.expire_at(start + Duration::from_secs(18)),
+            vec![TypingChange::Stopped {
+                channel_id: ChannelId(1),
+                user_id: UserId(2)
+            }]
+        );
+        assert!(!tracker.is_typing(ChannelId(1), UserId(2)));
+    }
+}
@@ -2,42 +2,109 @@
 //!
 //! A `VoiceConnection` for a server is obtained from a `Connection`. It can then be used to
 //! join a channel, change mute/deaf status, and play and receive audio.
-
-use std::collections::HashMap;
-use std::io::{self, Read, Write};
-use std::net::UdpSocket;
-use std::sync::mpsc;
+//!
+//! Each voice connection runs as a background task on the tokio runtime, which speaks to the
+//! voice gateway and sends and receives audio over UDP. Audio sources are read on the blocking
+//! thread pool, so they may block on files and child processes as they please.
+
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use byteorder::{BigEndian, LittleEndian, ReadBytesExt, WriteBytesExt};
-use opus;
-use serde_json;
-use sodiumoxide::crypto::secretbox as crypto;
-use websocket::client::{Client, Sender};
-use websocket::stream::WebSocketStream;
-
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::{interval_at, Interval, MissedTickBehavior};
+use tracing::{debug, error, info, warn};
+use websockets::{Frame, WebSocket, WebSocketReadHalf, WebSocketWriteHalf};
+
+use crate::connection::SharedSink;
 use crate::model::*;
+use crate::serial::Eq;
 use crate::{Error, Result};
 
+mod encryption;
+use self::encryption::{Cipher, EncryptionMode};
+
 /// An active or inactive voice connection, obtained from `Connection::voice`.
 #[derive(Debug)]
 pub struct VoiceConnection {
     // primary WS send control
-    server_id: Option<ServerId>, // None for group and private calls
-    user_id: UserId,
-    main_ws: mpsc::Sender<crate::internal::Status>,
+    server_id: ServerId,
+    main_ws: SharedSink,
     channel_id: Option<ChannelId>,
     mute: bool,
     deaf: bool,
 
     // main WS receive control
-    session_id: Option<String>,
+    session_id: Option<(UserId, String)>,
     endpoint_token: Option<(String, String)>,
 
-    // voice thread (voice WS + UDP) control
-    sender: mpsc::Sender<Status>,
+    // voice task (voice WS + UDP) control
+    sender: mpsc::UnboundedSender<Status>,
+    encoder_config: EncoderConfig,
+    stats: Arc<Mutex<VoiceStats>>,
+    queued: Arc<Mutex<Vec<TrackId>>>,
+    next_track: u64,
+}
+
+/// Identifies a track playing on a voice connection, returned by `VoiceConnection::add_track`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TrackId(u64);
+
+/// Statistics about the audio flowing over a voice connection, obtained from `VoiceConnection::stats`.
+///
+/// The counters are reset whenever a new voice channel is joined.
+#[derive(Debug, Clone, Default)]
+pub struct VoiceStats {
+    /// The number of voice packets sent.
+    pub packets_sent: u64,
+    /// The number of voice packets received from other users.
+    pub packets_received: u64,
+    /// The number of incoming voice packets estimated to be lost, from gaps in sequence numbers.
+    pub packets_lost: u64,
+    /// The number of RTCP reports received from the voice server.
+    pub rtcp_received: u64,
+    /// The interarrival jitter of incoming voice, as estimated per RFC 3550.
+    pub jitter: Duration,
+    /// The round-trip time of the last UDP keepalive, if one has been answered yet.
+    pub round_trip: Option<Duration>,
+    /// The bitrate the Opus encoder is currently set to, in bits per second.
+    pub bitrate: Option<i32>,
+}
+
+/// Settings for the Opus encoder used to send audio.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EncoderConfig {
+    /// The target bitrate; Discord channels accept between 8kbps and 128kbps, or more with boosts.
+    pub bitrate: opus::Bitrate,
+    /// Whether to embed forward error correction data so listeners can recover from lost packets.
+    pub fec: bool,
+    /// The packet loss percentage the encoder should expect, tuning the amount of FEC data.
+    pub expected_packet_loss: u8,
+}
+
+impl Default for EncoderConfig {
+    fn default() -> Self {
+        EncoderConfig {
+            bitrate: opus::Bitrate::Bits(64_000),
+            fec: false,
+            expected_packet_loss: 0,
+        }
+    }
 }
 
 /// A readable audio source.
+///
+/// Frames are read on tokio's blocking thread pool, so reading may block.
 pub trait AudioSource: Send {
     /// Called each frame to determine if the audio source is stereo.
     ///
@@ -57,9 +124,38 @@ pub trait AudioSource: Send {
     /// remain active. If `None` is returned, the audio source is considered to have ended, and
     /// `read_frame` will not be called again.
     fn read_frame(&mut self, buffer: &mut [i16]) -> Option<usize>;
+
+    /// Called to move playback to the given position from the start of the source.
+    ///
+    /// Returns whether the source was able to seek. By default sources can't.
+    fn seek(&mut self, position: Duration) -> bool {
+        let _ = position;
+        false
+    }
+
+    /// Called each frame to determine if the audio source supplies pre-encoded Opus audio.
+    ///
+    /// If so, and it is the only track playing, `read_opus_frame` is called instead of
+    /// `read_frame` and its frames are sent without being decoded and encoded again.
+    /// Otherwise, `read_frame` must still supply the decoded audio so it can be mixed.
+    fn is_opus(&mut self) -> bool {
+        false
+    }
+
+    /// Called each frame in place of `read_frame` when the audio source's frames are sent as
+    /// they are, returning the next 20ms frame of Opus audio at 48000Hz.
+    ///
+    /// If an empty frame is returned, no audio will be sent this frame. If `None` is returned,
+    /// the audio source is considered to have ended, as with `read_frame`.
+    fn read_opus_frame(&mut self) -> Option<Vec<u8>> {
+        None
+    }
 }
 
 /// A receiver for incoming audio.
+///
+/// The receiver is called from a thread of its own, so it may block, e.g. to write to a file,
+/// although incoming audio queues up in the meantime.
 pub trait AudioReceiver: Send {
     /// Called when a user's currently-speaking state has updated.
     ///
@@ -86,61 +182,67 @@ pub trait AudioReceiver: Send {
 }
 
 impl VoiceConnection {
-    #[doc(hidden)]
-    pub fn __new(
-        server_id: Option<ServerId>,
-        user_id: UserId,
-        main_ws: mpsc::Sender<crate::internal::Status>,
-    ) -> Self {
-        let (tx, rx) = mpsc::channel();
-        start_voice_thread(server_id, rx);
+    /// Create the voice connection for a server, starting its background task.
+    ///
+    /// Must be called from within a tokio runtime.
+    pub(crate) fn new(server_id: ServerId, main_ws: SharedSink) -> Self {
+        if sodiumoxide::init().is_err() {
+            warn!("Failed to initialize libsodium");
+        }
+        let stats = Arc::new(Mutex::new(VoiceStats::default()));
+        let queued = Arc::new(Mutex::new(Vec::new()));
+        let sender = start_voice_task(server_id, stats.clone(), queued.clone());
         VoiceConnection {
-            server_id: server_id,
-            user_id: user_id,
-            main_ws: main_ws,
+            server_id,
+            main_ws,
             channel_id: None,
             mute: false,
             deaf: false,
             session_id: None,
             endpoint_token: None,
-            sender: tx,
+            sender,
+            encoder_config: EncoderConfig::default(),
+            stats,
+            queued,
+            next_track: 0,
         }
     }
 
     /// Connect to the specified voice channel. Any previous channel on this server will be
     /// disconnected from.
-    #[inline]
-    pub fn connect(&mut self, channel_id: ChannelId) {
+    ///
+    /// This returns once the request has been sent to the gateway; the voice connection is
+    /// established in the background once the gateway responds.
+    pub async fn connect(&mut self, channel_id: ChannelId) -> Result<()> {
         self.channel_id = Some(channel_id);
-        self.send_connect();
+        self.send_connect().await
     }
 
     /// Disconnect from the current voice channel, if any.
-    #[inline]
-    pub fn disconnect(&mut self) {
+    pub async fn disconnect(&mut self) -> Result<()> {
         self.channel_id = None;
-        self.send_connect();
+        self.send_connect().await
     }
 
     /// Set the mute status of the voice connection.
     ///
     /// Note that enabling mute client-side is cosmetic and does not prevent the sending of audio;
     /// to fully mute, you must manually silence the audio source.
-    #[inline]
-    pub fn set_mute(&mut self, mute: bool) {
+    pub async fn set_mute(&mut self, mute: bool) -> Result<()> {
         self.mute = mute;
         if self.channel_id.is_some() {
-            self.send_connect()
+            self.send_connect().await?;
         }
+        Ok(())
     }
 
     /// Set the deaf status of the voice connection. Does not affect mute status.
-    #[inline]
-    pub fn set_deaf(&mut self, deaf: bool) {
+    pub async fn set_deaf(&mut self, deaf: bool) -> Result<()> {
         self.deaf = deaf;
         if self.channel_id.is_some() {
-            self.send_connect()
+            self.send_connect().await?;
         }
+        Ok(())
     }
 
     /// Get the current channel of this voice connection, if any.
@@ -149,45 +251,71 @@ impl VoiceConnection {
         self.channel_id
     }
 
+    /// Get a snapshot of the statistics of the current voice connection.
+    pub fn stats(&self) -> VoiceStats {
+        self.stats.lock().expect("voice stats poisoned").clone()
+    }
+
+    /// Change the settings of the Opus encoder, taking effect from the next audio frame.
+    ///
+    /// The settings are kept across reconnects and channel changes.
+    pub fn set_encoder_config(&mut self, config: EncoderConfig) {
+        self.encoder_config = config;
+        self.task_send(Status::SetEncoderConfig(config));
+    }
+
+    /// Get the current settings of the Opus encoder.
+    #[inline]
+    pub fn encoder_config(&self) -> EncoderConfig {
+        self.encoder_config
+    }
+
+    /// The voice state update requesting the current channel and mute/deaf status.
+    fn voice_state_message(&self) -> SentMessage {
+        SentMessage::UpdateVoiceState {
+            op: Eq,
+            payload: UpdateVoiceStatePayload {
+                guild_id: self.server_id,
+                channel_id: self.channel_id,
+                self_mute: self.mute,
+                self_deaf: self.deaf,
+            },
+        }
+    }
+
     /// Send the connect/disconnect command over the main websocket
-    fn send_connect(&self) {
-        let _ = self
-            .main_ws
-            .send(crate::internal::Status::SendMessage(json! {{
-                "op": 4,
-                "d": {
-                    "guild_id": self.server_id,
-                    "channel_id": self.channel_id,
-                    "self_mute": self.mute,
-                    "self_deaf": self.deaf,
-                }
-            }}));
+    async fn send_connect(&self) -> Result<()> {
+        self.main_ws.send(&self.voice_state_message()).await
     }
 
-    #[doc(hidden)]
-    pub fn __update_state(&mut self, voice_state: &VoiceState) {
-        if voice_state.user_id == self.user_id {
-            self.channel_id = voice_state.channel_id;
-            if voice_state.channel_id.is_some() {
-                let session_id = voice_state.session_id.clone();
-                if let Some((endpoint, token)) = self.endpoint_token.take() {
-                    self.internal_connect(session_id, endpoint, token);
-                } else {
-                    self.session_id = Some(session_id);
-                }
+    /// Handle a voice state update in this server, given the ID of the gateway session.
+    ///
+    /// Our own voice state is the one belonging to the gateway session.
+    pub(crate) fn update_state(&mut self, session_id: Option<&str>, voice_state: &VoiceState) {
+        if session_id != Some(voice_state.session_id.as_str()) {
+            return;
+        }
+        self.channel_id = voice_state.channel_id;
+        if voice_state.channel_id.is_some() {
+            let session = (voice_state.user_id, voice_state.session_id.clone());
+            if let Some((endpoint, token)) = self.endpoint_token.take() {
+                self.internal_connect(session, endpoint, token);
             } else {
-                self.internal_disconnect();
+                self.session_id = Some(session);
             }
+        } else {
+            self.session_id = None;
+            self.internal_disconnect();
         }
     }
 
-    #[doc(hidden)]
-    pub fn __update_server(&mut self, endpoint: &Option<String>, token: &str) {
+    /// Handle a voice server update in this server.
+    pub(crate) fn update_server(&mut self, endpoint: &Option<String>, token: &str) {
         if let Some(endpoint) = endpoint.clone() {
             let token = token.to_string();
             // nb: .take() is not used; in the event of server transfer, only this is called
-            if let Some(session_id) = self.session_id.clone() {
-                self.internal_connect(session_id, endpoint, token);
+            if let Some(session) = self.session_id.clone() {
+                self.internal_connect(session, endpoint, token);
             } else {
                 self.endpoint_token = Some((endpoint, token));
             }
@@ -196,73 +324,144 @@ impl VoiceConnection {
         }
     }
 
-    /// Play from the given audio source.
+    /// Play from the given audio source, stopping all other tracks.
     #[inline]
     pub fn play(&mut self, source: Box<dyn AudioSource>) {
-        self.thread_send(Status::SetSource(Some(source)));
+        self.stop();
+        self.add_track(source, 1.0);
     }
 
-    /// Stop the currently playing audio source.
+    /// Stop all currently playing tracks.
     #[inline]
     pub fn stop(&mut self) {
-        self.thread_send(Status::SetSource(None));
+        self.track_send(TrackCommand::Clear);
+    }
+
+    /// Play from the given audio source on top of any other playing tracks.
+    ///
+    /// The tracks are mixed by adding their samples together, after scaling them by their `gain`,
+    /// where `1.0` leaves a track's volume unchanged. A track is removed once its source ends.
+    pub fn add_track(&mut self, source: Box<dyn AudioSource>, gain: f32) -> TrackId {
+        let id = self.next_track_id();
+        self.track_send(TrackCommand::Add(id, source, gain));
+        id
+    }
+
+    /// Get a handle to the queue of tracks played one after another.
+    pub fn queue(&mut self) -> TrackQueue<'_> {
+        TrackQueue { connection: self }
+    }
+
+    fn next_track_id(&mut self) -> TrackId {
+        let id = TrackId(self.next_track);
+        self.next_track += 1;
+        id
+    }
+
+    /// Stop a single track, leaving the others playing.
+    #[inline]
+    pub fn remove_track(&mut self, track: TrackId) {
+        self.track_send(TrackCommand::Remove(track));
+    }
+
+    /// Change the gain of a playing track.
+    #[inline]
+    pub fn set_gain(&mut self, track: TrackId, gain: f32) {
+        self.track_send(TrackCommand::SetGain(track, gain));
+    }
+
+    /// Pause a track, keeping its place until it is resumed.
+    #[inline]
+    pub fn pause_track(&mut self, track: TrackId) {
+        self.track_send(TrackCommand::SetPaused(track, true));
+    }
+
+    /// Resume a paused track.
+    #[inline]
+    pub fn resume_track(&mut self, track: TrackId) {
+        self.track_send(TrackCommand::SetPaused(track, false));
+    }
+
+    /// Move a track to the given position from its start, if its source can seek.
+    ///
+    /// Sources opened with `open_ffmpeg_stream` and `open_ytdl_stream` can seek.
+    #[inline]
+    pub fn seek(&mut self, track: TrackId, position: Duration) {
+        self.track_send(TrackCommand::Seek(track, position));
+    }
+
+    /// Set a callback to be told when tracks start and end, replacing any previous one.
+    ///
+    /// The callback is called from the thread reading the audio sources, so it should not block.
+    pub fn on_track_event<F: FnMut(TrackEvent) + Send + 'static>(&mut self, callback: F) {
+        self.track_send(TrackCommand::SetCallback(Box::new(callback)));
     }
 
     /// Set the receiver to which incoming voice will be sent.
     #[inline]
     pub fn set_receiver(&mut self, receiver: Box<dyn AudioReceiver>) {
-        self.thread_send(Status::SetReceiver(Some(receiver)));
+        self.task_send(Status::SetReceiver(Some(receiver)));
     }
 
     /// Clear the voice receiver, discarding incoming voice.
     #[inline]
     pub fn clear_receiver(&mut self) {
-        self.thread_send(Status::SetReceiver(None));
+        self.task_send(Status::SetReceiver(None));
     }
 
-    fn thread_send(&mut self, status: Status) {
-        match self.sender.send(status) {
-            Ok(()) => {}
-            Err(mpsc::SendError(status)) => {
-                // voice thread has crashed... start it over again
-                let (tx, rx) = mpsc::channel();
-                self.sender = tx;
-                self.sender.send(status).unwrap(); // should be infallible
-                debug!("Restarting crashed voice thread...");
-                start_voice_thread(self.server_id, rx);
-                self.send_connect();
-            }
+    fn track_send(&mut self, command: TrackCommand) {
+        self.task_send(Status::Track(command));
+    }
+
+    fn task_send(&mut self, status: Status) {
+        if let Err(mpsc::error::SendError(status)) = self.sender.send(status) {
+            // voice task has crashed... start it over again
+            debug!("Restarting crashed voice task...");
+            self.sender = start_voice_task(self.server_id, self.stats.clone(), self.queued.clone());
+            let _ = self
+                .sender
+                .send(Status::SetEncoderConfig(self.encoder_config));
+            let _ = self.sender.send(status);
+
+            // rejoin the channel to be given a fresh voice server
+            let main_ws = self.main_ws.clone();
+            let message = self.voice_state_message();
+            tokio::spawn(async move {
+                if let Err(err) = main_ws.send(&message).await {
+                    warn!("Failed to rejoin voice after a crash: {:?}", err);
+                }
+            });
         }
     }
 
     #[inline]
     fn internal_disconnect(&mut self) {
-        self.thread_send(Status::Disconnect);
+        self.task_send(Status::Disconnect);
     }
 
     #[inline]
-    fn internal_connect(&mut self, session_id: String, endpoint: String, token: String) {
-        let user_id = self.user_id;
-        let server_id = match (&self.server_id, &self.channel_id) {
-            (&Some(ServerId(id)), _) | (&None, &Some(ChannelId(id))) => id,
-            _ => {
-                error!("no server_id or channel_id in internal_connect");
-                return;
-            }
-        };
-        self.thread_send(Status::Connect(ConnStartInfo {
-            server_id: server_id,
-            user_id: user_id,
-            session_id: session_id,
-            endpoint: endpoint,
-            token: token,
+    fn internal_connect(&mut self, session: (UserId, String), endpoint: String, token: String) {
+        let (user_id, session_id) = session;
+        self.task_send(Status::Connect(ConnStartInfo {
+            server_id: self.server_id,
+            user_id,
+            session_id,
+            endpoint,
+            token,
         }));
     }
 }
 
 impl Drop for VoiceConnection {
     fn drop(&mut self) {
-        self.disconnect();
+        // the voice task ends once its sender is dropped, but the channel must be left explicitly
+        if self.channel_id.take().is_some() {
+            if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+                let main_ws = self.main_ws.clone();
+                let message = self.voice_state_message();
+                runtime.spawn(async move { main_ws.send(&message).await });
+            }
+        }
     }
 }
 
@@ -298,18 +497,485 @@ impl<R: Read + Send> AudioSource for PcmSource<R> {
     }
 }
 
+/// Create an audio source from frames of pre-encoded Opus audio.
+///
+/// Each item should be one 20ms frame of Opus audio at 48000Hz, such as those read from a
+/// DCA file or received from another voice connection. When the source is the only track
+/// playing, its frames are sent as they are rather than being decoded and encoded again.
+/// An empty frame is treated as silence.
+pub fn create_opus_source<I>(stereo: bool, frames: I) -> Box<dyn AudioSource>
+where
+    I: Iterator<Item = Vec<u8>> + Send + 'static,
+{
+    Box::new(OpusSource {
+        stereo,
+        frames,
+        decoder: None,
+    })
+}
+
+/// Create an audio source from a stream in the DCA format, as used by many Discord bots to
+/// store pre-encoded audio.
+///
+/// Both the original format, which is only a series of Opus frames each preceded by its length
+/// as a signed 16-bit little-endian integer, and DCA1, which begins with a metadata header, are
+/// accepted. Audio in the original format is assumed to be in stereo.
+pub fn create_dca_source<R: Read + Send + 'static>(mut read: R) -> Result<Box<dyn AudioSource>> {
+    let mut magic = [0u8; 4];
+    read.read_exact(&mut magic)?;
+    if &magic != b"DCA1" {
+        // the original format has no header, so the bytes read begin the first frame
+        let read = io::Cursor::new(magic).chain(read);
+        return Ok(create_opus_source(true, DcaFrames(read)));
+    }
+
+    let len = read.read_i32::<LittleEndian>()?;
+    let mut metadata = vec![0; len.max(0) as usize];
+    read.read_exact(&mut metadata)?;
+    let stereo = serde_json::from_slice::<Value>(&metadata)
+        .ok()
+        .and_then(|metadata| metadata["opus"]["channels"].as_u64())
+        .is_none_or(|channels| channels == 2);
+    Ok(create_opus_source(stereo, DcaFrames(read)))
+}
+
+/// Open a DCA file as an audio source. See `create_dca_source`.
+pub fn open_dca_file<P: AsRef<Path>>(path: P) -> Result<Box<dyn AudioSource>> {
+    create_dca_source(io::BufReader::new(File::open(path)?))
+}
+
+/// The Opus frames of a DCA stream, each preceded by its length.
+struct DcaFrames<R: Read>(R);
+
+impl<R: Read> Iterator for DcaFrames<R> {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Vec<u8>> {
+        let len = self.0.read_i16::<LittleEndian>().ok()?;
+        let mut frame = vec![0; usize::try_from(len).ok()?];
+        self.0.read_exact(&mut frame).ok()?;
+        Some(frame)
+    }
+}
+
+struct OpusSource<I> {
+    stereo: bool,
+    frames: I,
+    /// Only created if the audio has to be mixed with other tracks.
+    decoder: Option<opus::Decoder>,
+}
+
+impl<I: Iterator<Item = Vec<u8>> + Send> AudioSource for OpusSource<I> {
+    fn is_stereo(&mut self) -> bool {
+        self.stereo
+    }
+
+    fn read_frame(&mut self, buffer: &mut [i16]) -> Option<usize> {
+        let frame = self.frames.next()?;
+        if frame.is_empty() {
+            buffer.iter_mut().for_each(|sample| *sample = 0);
+            return Some(buffer.len());
+        }
+        let decoder = match self.decoder {
+            Some(ref mut decoder) => decoder,
+            None => {
+                let channels = if self.stereo {
+                    opus::Channels::Stereo
+                } else {
+                    opus::Channels::Mono
+                };
+                match opus::Decoder::new(SAMPLE_RATE, channels) {
+                    Ok(decoder) => self.decoder.insert(decoder),
+                    Err(e) => {
+                        warn!("Failed to create an Opus decoder: {:?}", e);
+                        return None;
+                    }
+                }
+            }
+        };
+        let channels = if self.stereo { 2 } else { 1 };
+        match decoder.decode(&frame, buffer, false) {
+            Ok(len) => Some(len * channels),
+            Err(e) => {
+                warn!("Failed to decode an Opus frame: {:?}", e);
+                buffer.iter_mut().for_each(|sample| *sample = 0);
+                Some(buffer.len())
+            }
+        }
+    }
+
+    fn is_opus(&mut self) -> bool {
+        true
+    }
+
+    fn read_opus_frame(&mut self) -> Option<Vec<u8>> {
+        self.frames.next()
+    }
+}
+
+/// Something that happened to a track, passed to the callback set with
+/// `VoiceConnection::on_track_event`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrackEvent {
+    /// A track from the queue started playing.
+    Started(TrackId),
+    /// A track ended, because its source ended or it was removed, skipped or stopped.
+    Ended(TrackId),
+}
+
+/// A handle to the queue of a voice connection, obtained from `VoiceConnection::queue`.
+///
+/// Tracks in the queue play one after another, alongside any tracks added with `add_track`.
+///
+/// ```ignore
+/// let voice = connection.voice(server_id);
+/// voice.on_track_event(|event| println!("{:?}", event));
+/// let mut queue = voice.queue();
+/// queue.enqueue(voice::open_ffmpeg_stream("intro.mp3")?);
+/// queue.enqueue(voice::open_ytdl_stream(url)?);
+/// ```
+#[derive(Debug)]
+pub struct TrackQueue<'a> {
+    connection: &'a mut VoiceConnection,
+}
+
+impl TrackQueue<'_> {
+    /// Add a source to the end of the queue, starting it straight away if the queue is empty.
+    pub fn enqueue(&mut self, source: Box<dyn AudioSource>) -> TrackId {
+        let id = self.connection.next_track_id();
+        self.connection
+            .track_send(TrackCommand::Enqueue(id, source));
+        id
+    }
+
+    /// End the current track, starting the next one.
+    pub fn skip(&mut self) {
+        if let Some(current) = self.current() {
+            self.connection.remove_track(current);
+        }
+    }
+
+    /// Pause the current track, keeping its place.
+    pub fn pause(&mut self) {
+        if let Some(current) = self.current() {
+            self.connection.pause_track(current);
+        }
+    }
+
+    /// Resume the current track after pausing it.
+    pub fn resume(&mut self) {
+        if let Some(current) = self.current() {
+            self.connection.resume_track(current);
+        }
+    }
+
+    /// End every track in the queue, leaving other tracks playing.
+    pub fn clear(&mut self) {
+        self.connection.track_send(TrackCommand::ClearQueue);
+    }
+
+    /// The track playing from the queue, if any.
+    pub fn current(&self) -> Option<TrackId> {
+        self.tracks().first().copied()
+    }
+
+    /// The tracks in the queue, starting with the one playing.
+    ///
+    /// Tracks are only added to and removed from this list once the voice connection's task
+    /// has handled them, so it may briefly lag behind `enqueue` and `skip`.
+    pub fn tracks(&self) -> Vec<TrackId> {
+        self.connection
+            .queued
+            .lock()
+            .expect("voice queue poisoned")
+            .clone()
+    }
+}
+
+/// Mixes the tracks playing on a voice connection into a single stream of audio.
+struct Mixer {
+    tracks: Vec<Track>,
+    /// Tracks played one after another, the first of which is playing.
+    queue: VecDeque<Track>,
+    /// The IDs of the tracks in the queue, shared with the `VoiceConnection`.
+    queued: Arc<Mutex<Vec<TrackId>>>,
+    /// Called when tracks start and end.
+    on_event: Option<TrackEventCallback>,
+    /// Scratch space for reading each track's frame.
+    track_buffer: Vec<i16>,
+}
+
+type TrackEventCallback = Box<dyn FnMut(TrackEvent) + Send>;
+
+struct Track {
+    id: TrackId,
+    source: Box<dyn AudioSource>,
+    gain: f32,
+    paused: bool,
+}
+
+impl Track {
+    fn new(id: TrackId, source: Box<dyn AudioSource>, gain: f32) -> Track {
+        Track {
+            id,
+            source,
+            gain,
+            paused: false,
+        }
+    }
+}
+
+impl Mixer {
+    fn new(queued: Arc<Mutex<Vec<TrackId>>>) -> Mixer {
+        queued.lock().expect("voice queue poisoned").clear();
+        Mixer {
+            tracks: Vec::new(),
+            queue: VecDeque::new(),
+            queued,
+            on_event: None,
+            track_buffer: vec![0; 960 * 2],
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.tracks.is_empty() && self.queue.is_empty()
+    }
+
+    fn add(&mut self, id: TrackId, source: Box<dyn AudioSource>, gain: f32) {
+        self.tracks.push(Track::new(id, source, gain));
+    }
+
+    fn enqueue(&mut self, id: TrackId, source: Box<dyn AudioSource>) {
+        self.queue.push_back(Track::new(id, source, 1.0));
+        if self.queue.len() == 1 {
+            self.emit(TrackEvent::Started(id));
+        }
+        self.queue_changed();
+    }
+
+    fn remove(&mut self, id: TrackId) {
+        let before = self.tracks.len();
+        self.tracks.retain(|track| track.id != id);
+        if self.tracks.len() != before {
+            self.emit(TrackEvent::Ended(id));
+        }
+
+        if let Some(index) = self.queue.iter().position(|track| track.id == id) {
+            self.queue.remove(index);
+            self.emit(TrackEvent::Ended(id));
+            if index == 0 {
+                self.start_next();
+            }
+            self.queue_changed();
+        }
+    }
+
+    fn track_mut(&mut self, id: TrackId) -> Option<&mut Track> {
+        self.tracks
+            .iter_mut()
+            .chain(self.queue.iter_mut())
+            .find(|track| track.id == id)
+    }
+
+    fn set_gain(&mut self, id: TrackId, gain: f32) {
+        if let Some(track) = self.track_mut(id) {
+            track.gain = gain;
+        }
+    }
+
+    fn set_paused(&mut self, id: TrackId, paused: bool) {
+        if let Some(track) = self.track_mut(id) {
+            track.paused = paused;
+        }
+    }
+
+    fn seek(&mut self, id: TrackId, position: Duration) {
+        if let Some(track) = self.track_mut(id) {
+            if !track.source.seek(position) {
+                warn!("Track {:?} can't seek", id);
+            }
+        }
+    }
+
+    fn clear_queue(&mut self) {
+        for track in std::mem::take(&mut self.queue) {
+            self.emit(TrackEvent::Ended(track.id));
+        }
+        self.queue_changed();
+    }
+
+    fn clear(&mut self) {
+        for track in std::mem::take(&mut self.tracks) {
+            self.emit(TrackEvent::Ended(track.id));
+        }
+        self.clear_queue();
+    }
+
+    fn emit(&mut self, event: TrackEvent) {
+        if let Some(on_event) = self.on_event.as_mut() {
+            on_event(event);
+        }
+    }
+
+    fn start_next(&mut self) {
+        if let Some(next) = self.queue.front() {
+            let id = next.id;
+            self.emit(TrackEvent::Started(id));
+        }
+    }
+
+    /// The only track playing, if its pre-encoded audio can be sent without mixing it.
+    fn passthrough(&mut self) -> Option<TrackId> {
+        let mut playing = self
+            .tracks
+            .iter_mut()
+            .chain(self.queue.front_mut())
+            .filter(|track| !track.paused);
+        let track = playing.next()?;
+        let alone = playing.next().is_none();
+        (alone && track.gain == 1.0 && track.source.is_opus()).then_some(track.id)
+    }
+
+    fn queue_changed(&self) {
+        *self.queued.lock().expect("voice queue poisoned") =
+            self.queue.iter().map(|track| track.id).collect();
+    }
+}
+
+/// Read a track's frame and mix it into the buffer, returning the samples mixed,
+/// or `None` if the track's source has ended.
+fn mix_track(
+    track: &mut Track,
+    stereo: bool,
+    track_buffer: &mut [i16],
+    buffer: &mut [i16],
+) -> Option<usize> {
+    if track.paused {
+        return Some(0);
+    }
+    let track_stereo = track.source.is_stereo();
+    let frame = &mut track_buffer[..if track_stereo { 960 * 2 } else { 960 }];
+    for value in frame.iter_mut() {
+        *value = 0;
+    }
+
+    let len = track.source.read_frame(frame)?;
+
+    // upmix mono tracks by duplicating each sample into both channels
+    let upmix = stereo && !track_stereo;
+    for (i, &sample) in frame[..len].iter().enumerate() {
+        let scaled = (sample as f32 * track.gain) as i32;
+        let targets = if upmix { 2 * i..2 * i + 2 } else { i..i + 1 };
+        for out in &mut buffer[targets] {
+            *out = (*out as i32 + scaled).clamp(i16::MIN as i32, i16::MAX as i32) as i16;
+        }
+    }
+    Some(if upmix { len * 2 } else { len })
+}
+
+impl AudioSource for Mixer {
+    /// The mix is in stereo if any of the playing tracks are.
+    fn is_stereo(&mut self) -> bool {
+        self.tracks
+            .iter_mut()
+            .chain(self.queue.front_mut())
+            .any(|track| !track.paused && track.source.is_stereo())
+    }
+
+    fn read_frame(&mut self, buffer: &mut [i16]) -> Option<usize> {
+        let stereo = buffer.len() == 960 * 2;
+        for value in buffer.iter_mut() {
+            *value = 0;
+        }
+
+        let mut mixed = 0;
+        let mut ended = Vec::new();
+        let track_buffer = &mut self.track_buffer;
+        self.tracks.retain_mut(|track| {
+            match mix_track(track, stereo, track_buffer, buffer) {
+                Some(len) => {
+                    mixed = mixed.max(len);
+                    true
+                }
+                // the source has ended, so drop the track
+                None => {
+                    ended.push(track.id);
+                    false
+                }
+            }
+        });
+
+        let mut advanced = false;
+        if let Some(current) = self.queue.front_mut() {
+            match mix_track(current, stereo, track_buffer, buffer) {
+                Some(len) => mixed = mixed.max(len),
+                None => {
+                    ended.push(current.id);
+                    self.queue.pop_front();
+                    advanced = true;
+                }
+            }
+        }
+
+        for id in ended {
+            self.emit(TrackEvent::Ended(id));
+        }
+        if advanced {
+            self.start_next();
+            self.queue_changed();
+        }
+
+        Some(mixed)
+    }
+
+    /// The mix is pre-encoded when a single pre-encoded track is playing at its own volume.
+    fn is_opus(&mut self) -> bool {
+        self.passthrough().is_some()
+    }
+
+    fn read_opus_frame(&mut self) -> Option<Vec<u8>> {
+        let id = self.passthrough()?;
+        let track = self.track_mut(id)?;
+        match track.source.read_opus_frame() {
+            Some(frame) => Some(frame),
+            None => {
+                // the source has ended, so drop the track
+                self.remove(id);
+                Some(Vec::new())
+            }
+        }
+    }
+}
+
 /// Use `ffmpeg` to open an audio file as a PCM stream.
 ///
 /// Requires `ffmpeg` to be on the path and executable. If `ffprobe` is available and indicates
 /// that the input file is stereo, the returned audio source will be stereo.
+///
+/// The returned audio source can seek, by restarting `ffmpeg` from the new position.
 pub fn open_ffmpeg_stream<P: AsRef<::std::ffi::OsStr>>(path: P) -> Result<Box<dyn AudioSource>> {
+    let path = path.as_ref().to_owned();
+    let stereo = check_stereo(&path).unwrap_or(false);
+    let stream = spawn_ffmpeg(&path, stereo, Duration::ZERO)?;
+    Ok(Box::new(FfmpegSource {
+        path,
+        pcm: PcmSource(stereo, stream),
+    }))
+}
+
+/// Start `ffmpeg` decoding a file to PCM from the given position.
+fn spawn_ffmpeg(
+    path: &::std::ffi::OsStr,
+    stereo: bool,
+    start: Duration,
+) -> io::Result<ProcessStream> {
     use std::process::{Command, Stdio};
-    let path = path.as_ref();
-    let stereo = check_stereo(path).unwrap_or(false);
     let child = Command::new("ffmpeg")
+        .arg("-ss")
+        .arg(format!("{:.3}", start.as_secs_f64()))
         .arg("-i")
         .arg(path)
-        .args(&[
+        .args([
             "-f",
             "s16le",
             "-ac",
@@ -324,13 +990,42 @@ pub fn open_ffmpeg_stream<P: AsRef<::std::ffi::OsStr>>(path: P) -> Result<Box<dy
         .stdout(Stdio::piped())
         .stderr(Stdio::null())
         .spawn()?;
-    Ok(create_pcm_source(stereo, ProcessStream(child)))
+    Ok(ProcessStream(child))
+}
+
+/// An audio file decoded by `ffmpeg`, which seeks by restarting it.
+struct FfmpegSource {
+    path: ::std::ffi::OsString,
+    pcm: PcmSource<ProcessStream>,
+}
+
+impl AudioSource for FfmpegSource {
+    fn is_stereo(&mut self) -> bool {
+        self.pcm.is_stereo()
+    }
+
+    fn read_frame(&mut self, buffer: &mut [i16]) -> Option<usize> {
+        self.pcm.read_frame(buffer)
+    }
+
+    fn seek(&mut self, position: Duration) -> bool {
+        match spawn_ffmpeg(&self.path, self.pcm.0, position) {
+            Ok(stream) => {
+                self.pcm.1 = stream;
+                true
+            }
+            Err(err) => {
+                warn!("Failed to restart ffmpeg to seek: {:?}", err);
+                false
+            }
+        }
+    }
 }
 
 fn check_stereo(path: &::std::ffi::OsStr) -> Result<bool> {
     use std::process::{Command, Stdio};
     let output = Command::new("ffprobe")
-        .args(&["-v", "quiet", "-of", "json", "-show_streams", "-i"])
+        .args(["-v", "quiet", "-of", "json", "-show_streams", "-i"])
         .arg(path)
         .stdin(Stdio::null())
         .output()?;
@@ -348,127 +1043,623 @@ fn check_stereo(path: &::std::ffi::OsStr) -> Result<bool> {
     }))
 }
 
-/// A stream that reads from a child's stdout and kills it on drop.
-struct ProcessStream(::std::process::Child);
+/// A stream that reads from a child's stdout and kills it on drop.
+struct ProcessStream(::std::process::Child);
+
+impl Read for ProcessStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.stdout.as_mut().expect("missing stdout").read(buf)
+    }
+}
+
+impl Drop for ProcessStream {
+    fn drop(&mut self) {
+        // If we can't kill it, it's dead already or out of our hands
+        let _ = self.0.kill();
+        // To avoid zombie processes, we must also wait on it
+        let _ = self.0.wait();
+    }
+}
+
+/// Use `youtube-dl` and `ffmpeg` to stream from an internet source.
+///
+/// Requires both `youtube-dl` and `ffmpeg` to be on the path and executable.
+/// On Windows, this means the `.exe` version of `youtube-dl` must be used.
+///
+/// The audio download is streamed rather than downloaded in full; this may be desireable for
+/// longer audios but can introduce occasional brief interruptions.
+pub fn open_ytdl_stream(url: &str) -> Result<Box<dyn AudioSource>> {
+    use std::process::{Command, Stdio};
+    let output = Command::new("youtube-dl")
+        .args([
+            "-f",
+            "webm[abr>0]/bestaudio/best",
+            "--no-playlist",
+            "--print-json",
+            "--skip-download",
+            url,
+        ])
+        .stdin(Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        debug!("youtube-dl failed: {:?}", output);
+        return Err(Error::Other("youtube-dl failed"));
+    }
+
+    let json: serde_json::Value = serde_json::from_reader(&output.stdout[..])?;
+    let map = match json.as_object() {
+        Some(map) => map,
+        None => return Err(Error::Other("youtube-dl output could not be read")),
+    };
+    let url = match map.get("url").and_then(serde_json::Value::as_str) {
+        Some(url) => url,
+        None => {
+            return Err(Error::Other(
+                "youtube-dl output's \"url\" could not be read",
+            ))
+        }
+    };
+    open_ffmpeg_stream(url)
+}
+
+/// How many frames of audio a recording file holds before a new file is started (one hour).
+const ROTATE_FRAMES: u64 = 48000 * 60 * 60;
+
+/// How far behind the newest audio the mixed recording is written, to let late packets be mixed in.
+const MIX_DELAY: usize = 48000;
+
+/// Record everyone speaking in a voice channel to a 48kHz WAV file.
+///
+/// Each user's audio is placed according to when it was spoken, with silence filling the gaps.
+/// Speakers are always mixed together; if `mono` is true, the recording is also
+/// downmixed to a single channel, halving its size.
+///
+/// Every hour of audio, the recording moves on to a new file, numbered after the
+/// original path, e.g. `meeting.wav`, `meeting-1.wav`, `meeting-2.wav`, and so on.
+pub fn record_to_wav<P: AsRef<Path>>(path: P, mono: bool) -> Result<Box<dyn AudioReceiver>> {
+    let channels = if mono { 1 } else { 2 };
+    Ok(Box::new(MixedRecorder {
+        file: RotatingWav::create(path.as_ref().to_owned(), channels)?,
+        timeline: Timeline::new(),
+        buffer: VecDeque::new(),
+        buffer_start: 0,
+    }))
+}
+
+/// Record each user speaking in a voice channel to their own 48kHz stereo WAV file
+/// in the given directory, named after their user ID, e.g. `80351110224678912.wav`.
+///
+/// The files all start when recording started, so they line up when played together.
+/// Audio received before Discord says which user is speaking is dropped.
+/// Files are rotated every hour of audio as with `record_to_wav`.
+pub fn record_per_user<P: AsRef<Path>>(dir: P) -> Result<Box<dyn AudioReceiver>> {
+    let dir = dir.as_ref().to_owned();
+    std::fs::create_dir_all(&dir)?;
+    Ok(Box::new(UserRecorder {
+        dir,
+        timeline: Timeline::new(),
+        users: HashMap::new(),
+        files: HashMap::new(),
+    }))
+}
+
+/// Maps the RTP timestamps of each audio stream onto a shared recording timeline,
+/// measured in frames since the recording started.
+struct Timeline {
+    started: Instant,
+    /// The RTP timestamp and timeline position of the first packet of each stream.
+    anchors: HashMap<u32, (u32, u64)>,
+}
+
+impl Timeline {
+    fn new() -> Timeline {
+        Timeline {
+            started: Instant::now(),
+            anchors: HashMap::new(),
+        }
+    }
+
+    /// The frames elapsed since the recording started.
+    fn now(&self) -> u64 {
+        (self.started.elapsed().as_secs_f64() * 48000.0) as u64
+    }
+
+    /// Where a packet belongs on the timeline.
+    ///
+    /// Streams are anchored by their first packet, after which their timestamps
+    /// keep advancing through silence, so gaps are preserved.
+    fn position(&mut self, ssrc: u32, timestamp: u32) -> u64 {
+        let now = self.now();
+        let &mut (anchor_timestamp, anchor) = self.anchors.entry(ssrc).or_insert((timestamp, now));
+        // treat timestamps slightly before the anchor as reordering, rather than wrapping around
+        let offset = timestamp.wrapping_sub(anchor_timestamp) as i32 as i64;
+        (anchor as i64 + offset).max(0) as u64
+    }
+}
+
+/// Convert a packet's samples to stereo frames.
+fn stereo_frames(stereo: bool, data: &[i16]) -> Vec<[i16; 2]> {
+    if stereo {
+        data.chunks_exact(2).map(|s| [s[0], s[1]]).collect()
+    } else {
+        data.iter().map(|&s| [s, s]).collect()
+    }
+}
+
+/// Records every stream into a single file.
+struct MixedRecorder {
+    file: RotatingWav,
+    timeline: Timeline,
+    /// Frames not yet written, summed over every stream.
+    buffer: VecDeque<[i32; 2]>,
+    /// The timeline position of the first frame in `buffer`.
+    buffer_start: u64,
+}
+
+impl MixedRecorder {
+    /// Write out the frames which are old enough that no more audio is expected for them.
+    fn flush_until(&mut self, position: u64) -> io::Result<()> {
+        while self.buffer_start < position {
+            let [left, right] = match self.buffer.pop_front() {
+                Some(frame) => frame,
+                None => {
+                    // nobody spoke for the rest of the gap
+                    self.file.write_silence(position - self.buffer_start)?;
+                    self.buffer_start = position;
+                    break;
+                }
+            };
+            let clamp = |s: i32| s.clamp(i16::MIN as i32, i16::MAX as i32) as i16;
+            self.file.write_frame(clamp(left), clamp(right))?;
+            self.buffer_start += 1;
+        }
+        Ok(())
+    }
+}
+
+impl AudioReceiver for MixedRecorder {
+    fn speaking_update(&mut self, _ssrc: u32, _user_id: UserId, _speaking: bool) {}
+
+    fn voice_packet(
+        &mut self,
+        ssrc: u32,
+        _sequence: u16,
+        timestamp: u32,
+        stereo: bool,
+        data: &[i16],
+    ) {
+        let position = self.timeline.position(ssrc, timestamp);
+        let flush_until = self.timeline.now().saturating_sub(MIX_DELAY as u64);
+        if let Err(err) = self.flush_until(flush_until) {
+            warn!("Failed to write recording: {:?}", err);
+        }
+
+        // audio arriving after its place was written is dropped
+        let skip = self.buffer_start.saturating_sub(position) as usize;
+        let start = position.saturating_sub(self.buffer_start) as usize;
+        for (i, [left, right]) in stereo_frames(stereo, data)
+            .into_iter()
+            .enumerate()
+            .skip(skip)
+        {
+            let index = start + i - skip;
+            if self.buffer.len() <= index {
+                self.buffer.resize(index + 1, [0, 0]);
+            }
+            self.buffer[index][0] += left as i32;
+            self.buffer[index][1] += right as i32;
+        }
+    }
+}
+
+impl Drop for MixedRecorder {
+    fn drop(&mut self) {
+        let end = self.buffer_start + self.buffer.len() as u64;
+        if let Err(err) = self.flush_until(end) {
+            warn!("Failed to write recording: {:?}", err);
+        }
+    }
+}
+
+/// Records each user into their own file.
+struct UserRecorder {
+    dir: PathBuf,
+    timeline: Timeline,
+    users: HashMap<u32, UserId>,
+    files: HashMap<UserId, UserFile>,
+}
+
+struct UserFile {
+    file: RotatingWav,
+    /// The timeline position up to which the file has been written.
+    written: u64,
+}
+
+impl AudioReceiver for UserRecorder {
+    fn speaking_update(&mut self, ssrc: u32, user_id: UserId, _speaking: bool) {
+        self.users.insert(ssrc, user_id);
+    }
+
+    fn voice_packet(
+        &mut self,
+        ssrc: u32,
+        _sequence: u16,
+        timestamp: u32,
+        stereo: bool,
+        data: &[i16],
+    ) {
+        let user_id = match self.users.get(&ssrc) {
+            Some(&user_id) => user_id,
+            None => return,
+        };
+        let position = self.timeline.position(ssrc, timestamp);
+
+        let user = match self.files.entry(user_id) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let path = self.dir.join(format!("{}.wav", user_id.0));
+                match RotatingWav::create(path, 2) {
+                    Ok(file) => entry.insert(UserFile { file, written: 0 }),
+                    Err(err) => {
+                        warn!("Failed to create recording for {:?}: {:?}", user_id, err);
+                        return;
+                    }
+                }
+            }
+        };
+
+        let result = (|| {
+            // fill the gap since the user last spoke with silence
+            if user.written < position {
+                user.file.write_silence(position - user.written)?;
+                user.written = position;
+            }
+            // audio overlapping what was already written, e.g. when reordered, is dropped
+            let skip = (user.written - position) as usize;
+            for [left, right] in stereo_frames(stereo, data).into_iter().skip(skip) {
+                user.file.write_frame(left, right)?;
+                user.written += 1;
+            }
+            Ok::<(), io::Error>(())
+        })();
+        if let Err(err) = result {
+            warn!("Failed to write recording for {:?}: {:?}", user_id, err);
+        }
+    }
+}
+
+/// A 16-bit 48kHz WAV file which moves on to a new file every `ROTATE_FRAMES`.
+struct RotatingWav {
+    path: PathBuf,
+    channels: u16,
+    /// How many frames each file holds, which is only changed by tests.
+    max_frames: u64,
+    index: u32,
+    file: WavFile,
+}
+
+impl RotatingWav {
+    fn create(path: PathBuf, channels: u16) -> io::Result<RotatingWav> {
+        let file = WavFile::create(&path, channels)?;
+        Ok(RotatingWav {
+            path,
+            channels,
+            max_frames: ROTATE_FRAMES,
+            index: 0,
+            file,
+        })
+    }
+
+    /// Move on to the next file if the current one is full.
+    fn rotate(&mut self) -> io::Result<()> {
+        if self.file.frames >= self.max_frames {
+            self.index += 1;
+            let path = rotated_path(&self.path, self.index);
+            let file = WavFile::create(&path, self.channels)?;
+            std::mem::replace(&mut self.file, file).finish()?;
+        }
+        Ok(())
+    }
+
+    /// Write frames of silence, across as many files as they take.
+    fn write_silence(&mut self, mut frames: u64) -> io::Result<()> {
+        while frames > 0 {
+            self.rotate()?;
+            let count = frames.min(self.max_frames - self.file.frames);
+            self.file.write_silence(count)?;
+            frames -= count;
+        }
+        Ok(())
+    }
+
+    /// Write a frame, downmixing it if the file is mono.
+    fn write_frame(&mut self, left: i16, right: i16) -> io::Result<()> {
+        self.rotate()?;
+        if self.channels == 1 {
+            self.file
+                .write_samples(&[((left as i32 + right as i32) / 2) as i16])
+        } else {
+            self.file.write_samples(&[left, right])
+        }
+    }
+}
+
+/// The path of the `index`th file of a rotated recording.
+fn rotated_path(path: &Path, index: u32) -> PathBuf {
+    if index == 0 {
+        return path.to_owned();
+    }
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    match path.extension() {
+        Some(ext) => path.with_file_name(format!("{}-{}.{}", stem, index, ext.to_string_lossy())),
+        None => path.with_file_name(format!("{}-{}", stem, index)),
+    }
+}
 
-impl Read for ProcessStream {
-    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        self.0.stdout.as_mut().expect("missing stdout").read(buf)
+/// A 16-bit 48kHz PCM WAV file, whose header is completed once it is finished or dropped.
+struct WavFile {
+    writer: Option<BufWriter<File>>,
+    channels: u16,
+    frames: u64,
+}
+
+impl WavFile {
+    fn create(path: &Path, channels: u16) -> io::Result<WavFile> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        let block_align = channels * 2;
+
+        writer.write_all(b"RIFF")?;
+        // the RIFF and data chunk sizes are filled in when finishing
+        writer.write_u32::<LittleEndian>(0)?;
+        writer.write_all(b"WAVEfmt ")?;
+        writer.write_u32::<LittleEndian>(16)?;
+        writer.write_u16::<LittleEndian>(1)?;
+        writer.write_u16::<LittleEndian>(channels)?;
+        writer.write_u32::<LittleEndian>(48000)?;
+        writer.write_u32::<LittleEndian>(48000 * block_align as u32)?;
+        writer.write_u16::<LittleEndian>(block_align)?;
+        writer.write_u16::<LittleEndian>(16)?;
+        writer.write_all(b"data")?;
+        writer.write_u32::<LittleEndian>(0)?;
+
+        Ok(WavFile {
+            writer: Some(writer),
+            channels,
+            frames: 0,
+        })
+    }
+
+    fn write_samples(&mut self, samples: &[i16]) -> io::Result<()> {
+        let writer = self.writer.as_mut().expect("wav file already finished");
+        for &sample in samples {
+            writer.write_i16::<LittleEndian>(sample)?;
+        }
+        self.frames += 1;
+        Ok(())
+    }
+
+    fn write_silence(&mut self, frames: u64) -> io::Result<()> {
+        const ZEROS: [u8; 4096] = [0; 4096];
+        let writer = self.writer.as_mut().expect("wav file already finished");
+        let mut len = frames * self.channels as u64 * 2;
+        while len > 0 {
+            let chunk = len.min(ZEROS.len() as u64) as usize;
+            writer.write_all(&ZEROS[..chunk])?;
+            len -= chunk as u64;
+        }
+        self.frames += frames;
+        Ok(())
+    }
+
+    /// Fill in the chunk sizes of the header and close the file.
+    fn finish(mut self) -> io::Result<()> {
+        self.finish_mut()
+    }
+
+    fn finish_mut(&mut self) -> io::Result<()> {
+        let mut writer = match self.writer.take() {
+            Some(writer) => writer,
+            None => return Ok(()),
+        };
+        let data_len = (self.frames * self.channels as u64 * 2) as u32;
+
+        writer.seek(SeekFrom::Start(4))?;
+        writer.write_u32::<LittleEndian>(36 + data_len)?;
+        writer.seek(SeekFrom::Start(40))?;
+        writer.write_u32::<LittleEndian>(data_len)?;
+        writer.flush()
     }
 }
 
-impl Drop for ProcessStream {
+impl Drop for WavFile {
     fn drop(&mut self) {
-        // If we can't kill it, it's dead already or out of our hands
-        let _ = self.0.kill();
-        // To avoid zombie processes, we must also wait on it
-        let _ = self.0.wait();
+        if let Err(err) = self.finish_mut() {
+            warn!("Failed to finish recording: {:?}", err);
+        }
     }
 }
 
-/// Use `youtube-dl` and `ffmpeg` to stream from an internet source.
-///
-/// Requires both `youtube-dl` and `ffmpeg` to be on the path and executable.
-/// On Windows, this means the `.exe` version of `youtube-dl` must be used.
+/// Calls an `AudioReceiver` from a thread of its own, so that receivers which block,
+/// like the recorders writing to disk, don't hold up the voice connection.
 ///
-/// The audio download is streamed rather than downloaded in full; this may be desireable for
-/// longer audios but can introduce occasional brief interruptions.
-pub fn open_ytdl_stream(url: &str) -> Result<Box<dyn AudioSource>> {
-    use std::process::{Command, Stdio};
-    let output = Command::new("youtube-dl")
-        .args(&[
-            "-f",
-            "webm[abr>0]/bestaudio/best",
-            "--no-playlist",
-            "--print-json",
-            "--skip-download",
-            url,
-        ])
-        .stdin(Stdio::null())
-        .output()?;
-    if !output.status.success() {
-        return Err(Error::Command("youtube-dl", output));
+/// The receiver is dropped on that thread once the `ReceiverThread` is.
+struct ReceiverThread {
+    sender: std::sync::mpsc::Sender<Received>,
+}
+
+/// A call to make on an `AudioReceiver`.
+enum Received {
+    Speaking {
+        ssrc: u32,
+        user_id: UserId,
+        speaking: bool,
+    },
+    Packet {
+        ssrc: u32,
+        sequence: u16,
+        timestamp: u32,
+        stereo: bool,
+        data: Vec<i16>,
+    },
+}
+
+impl ReceiverThread {
+    fn spawn(mut receiver: Box<dyn AudioReceiver>) -> ReceiverThread {
+        let (sender, received) = std::sync::mpsc::channel();
+        std::thread::Builder::new()
+            .name("voice receiver".into())
+            .spawn(move || {
+                for call in received {
+                    match call {
+                        Received::Speaking {
+                            ssrc,
+                            user_id,
+                            speaking,
+                        } => receiver.speaking_update(ssrc, user_id, speaking),
+                        Received::Packet {
+                            ssrc,
+                            sequence,
+                            timestamp,
+                            stereo,
+                            data,
+                        } => receiver.voice_packet(ssrc, sequence, timestamp, stereo, &data),
+                    }
+                }
+            })
+            .expect("failed to spawn the voice receiver thread");
+        ReceiverThread { sender }
     }
 
-    let json: serde_json::Value = serde_json::from_reader(&output.stdout[..])?;
-    let map = match json.as_object() {
-        Some(map) => map,
-        None => return Err(Error::Other("youtube-dl output could not be read")),
-    };
-    let url = match map.get("url").and_then(serde_json::Value::as_str) {
-        Some(url) => url,
-        None => {
-            return Err(Error::Other(
-                "youtube-dl output's \"url\" could not be read",
-            ))
-        }
-    };
-    open_ffmpeg_stream(url)
+    fn send(&self, call: Received) {
+        // the thread only stops early if the receiver panicked
+        let _ = self.sender.send(call);
+    }
 }
 
 enum Status {
-    SetSource(Option<Box<dyn AudioSource>>),
+    Track(TrackCommand),
     SetReceiver(Option<Box<dyn AudioReceiver>>),
+    SetEncoderConfig(EncoderConfig),
     Connect(ConnStartInfo),
     Disconnect,
 }
 
-fn start_voice_thread(server_id: Option<ServerId>, rx: mpsc::Receiver<Status>) {
-    let name = match server_id {
-        Some(ServerId(id)) => format!("discord voice (server {})", id),
-        None => "discord voice (private/groups)".to_owned(),
-    };
-    ::std::thread::Builder::new()
-        .name(name)
-        .spawn(move || voice_thread(rx))
-        .expect("Failed to start voice thread");
+enum TrackCommand {
+    Add(TrackId, Box<dyn AudioSource>, f32),
+    Enqueue(TrackId, Box<dyn AudioSource>),
+    Remove(TrackId),
+    SetGain(TrackId, f32),
+    SetPaused(TrackId, bool),
+    Seek(TrackId, Duration),
+    SetCallback(TrackEventCallback),
+    ClearQueue,
+    Clear,
+}
+
+impl Mixer {
+    fn handle(&mut self, command: TrackCommand) {
+        match command {
+            TrackCommand::Add(id, source, gain) => self.add(id, source, gain),
+            TrackCommand::Enqueue(id, source) => self.enqueue(id, source),
+            TrackCommand::Remove(id) => self.remove(id),
+            TrackCommand::SetGain(id, gain) => self.set_gain(id, gain),
+            TrackCommand::SetPaused(id, paused) => self.set_paused(id, paused),
+            TrackCommand::Seek(id, position) => self.seek(id, position),
+            TrackCommand::SetCallback(callback) => self.on_event = Some(callback),
+            TrackCommand::ClearQueue => self.clear_queue(),
+            TrackCommand::Clear => self.clear(),
+        }
+    }
 }
 
-fn voice_thread(channel: mpsc::Receiver<Status>) {
-    let mut audio_source = None;
+fn start_voice_task(
+    server_id: ServerId,
+    stats: Arc<Mutex<VoiceStats>>,
+    queued: Arc<Mutex<Vec<TrackId>>>,
+) -> mpsc::UnboundedSender<Status> {
+    let (tx, rx) = mpsc::unbounded_channel();
+    debug!("Starting the voice task for {:?}", server_id);
+    tokio::spawn(voice_task(rx, stats, queued));
+    tx
+}
+
+async fn voice_task(
+    mut channel: mpsc::UnboundedReceiver<Status>,
+    stats: Arc<Mutex<VoiceStats>>,
+    queued: Arc<Mutex<Vec<TrackId>>>,
+) {
+    // the mixer is lent to the blocking thread pool while a frame is read,
+    // and lost along with the track callback if reading panics
+    let mut mixer = Some(Mixer::new(queued.clone()));
     let mut receiver = None;
-    let mut connection = None;
-    let mut audio_timer = crate::Timer::new(20);
+    let mut connection: Option<InternalConnection> = None;
+    let mut encoder_config = EncoderConfig::default();
+    let mut audio_timer = tokio::time::interval(FRAME_DURATION);
+    audio_timer.set_missed_tick_behavior(MissedTickBehavior::Delay);
 
     // start the main loop
-    'outer: loop {
-        // Check on the signalling channel
-        loop {
-            match channel.try_recv() {
-                Ok(Status::SetSource(s)) => audio_source = s,
-                Ok(Status::SetReceiver(r)) => receiver = r,
-                Ok(Status::Connect(info)) => {
-                    connection = InternalConnection::new(info)
+    loop {
+        tokio::select! {
+            // Check on the signalling channel
+            status = channel.recv() => match status {
+                Some(Status::Track(command)) => mixer
+                    .get_or_insert_with(|| Mixer::new(queued.clone()))
+                    .handle(command),
+                Some(Status::SetReceiver(r)) => receiver = r.map(ReceiverThread::spawn),
+                Some(Status::SetEncoderConfig(config)) => {
+                    encoder_config = config;
+                    if let Some(connection) = connection.as_mut() {
+                        if let Err(e) = connection.configure_encoder(config) {
+                            error!("Error configuring the voice encoder: {:?}", e);
+                        }
+                    }
+                }
+                Some(Status::Connect(info)) => {
+                    // close the old voice websocket before opening the new one
+                    drop(connection.take());
+                    connection = InternalConnection::new(info, encoder_config, stats.clone())
+                        .await
                         .map_err(|e| error!("Error connecting to voice: {:?}", e))
                         .ok();
                 }
-                Ok(Status::Disconnect) => connection = None,
-                Err(mpsc::TryRecvError::Empty) => break,
-                Err(mpsc::TryRecvError::Disconnected) => break 'outer,
+                Some(Status::Disconnect) => connection = None,
+                None => break,
+            },
+
+            // Send a frame of audio every 20ms
+            _ = audio_timer.tick(), if connection.is_some() => {
+                if let Some(conn) = connection.as_mut() {
+                    if let Err(e) = conn.send_audio(&mut mixer).await {
+                        error!("Error in voice connection: {:?}", e);
+                        connection = None;
+                    }
+                }
             }
-        }
 
-        // Update the voice connection, transmitting and receiving data as needed
-        let mut error = false;
-        if let Some(connection) = connection.as_mut() {
-            // update() will sleep using audio_timer as needed
-            if let Err(e) = connection.update(&mut audio_source, &mut receiver, &mut audio_timer) {
-                error!("Error in voice connection: {:?}", e);
-                error = true;
+            // Receive voice data and keep the connection alive
+            result = receive(&mut connection, &receiver) => {
+                if let Err(e) = result {
+                    error!("Error in voice connection: {:?}", e);
+                    connection = None;
+                }
             }
-        } else {
-            // no connection, so we sleep ourselves
-            audio_timer.sleep_until_tick();
-        }
-        if error {
-            connection = None;
         }
     }
 }
 
+/// Wait for and handle the next incoming message of the connection, if there is one.
+async fn receive(
+    connection: &mut Option<InternalConnection>,
+    receiver: &Option<ReceiverThread>,
+) -> Result<()> {
+    match connection.as_mut() {
+        Some(connection) => connection.receive(receiver).await,
+        None => std::future::pending().await,
+    }
+}
+
 struct ConnStartInfo {
-    // may have originally been a ServerId or ChannelId
-    server_id: u64,
+    server_id: ServerId,
     user_id: UserId,
     endpoint: String,
     session_id: String,
@@ -476,13 +1667,13 @@ struct ConnStartInfo {
 }
 
 struct InternalConnection {
-    sender: Sender<WebSocketStream>,
-    receive_chan: mpsc::Receiver<RecvStatus>,
-    ws_close: mpsc::Sender<()>,
-    udp_close: mpsc::Sender<()>,
-    encryption_key: crypto::Key,
+    sender: WebSocketWriteHalf,
+    receive_chan: mpsc::UnboundedReceiver<Result<VoiceEvent>>,
+    ws_task: JoinHandle<()>,
+    cipher: Cipher,
+    /// The counter from which the nonce of each packet sent is made.
+    nonce: u32,
     udp: UdpSocket,
-    destination: ::std::net::SocketAddr,
     ssrc: u32,
     sequence: u16,
     timestamp: u32,
@@ -491,17 +1682,30 @@ struct InternalConnection {
     decoder_map: HashMap<(u32, opus::Channels), opus::Decoder>,
     encoder: opus::Encoder,
     encoder_stereo: bool,
-    keepalive_timer: crate::Timer,
-    audio_keepalive_timer: crate::Timer,
-    ws_thread: Option<::std::thread::JoinHandle<()>>,
-    udp_thread: Option<::std::thread::JoinHandle<()>>,
+    encoder_config: EncoderConfig,
+    stats: Arc<Mutex<VoiceStats>>,
+    receive_stats: HashMap<u32, ReceiveStats>,
+    connected_at: Instant,
+    keepalive_counter: u64,
+    keepalive_sent: Option<(u64, Instant)>,
+    keepalive_timer: Interval,
+    audio_keepalive_timer: Interval,
 }
 
 const SAMPLE_RATE: u32 = 48000;
 const HEADER_LEN: usize = 12;
+const FRAME_DURATION: Duration = Duration::from_millis(20);
+/// After 5 minutes of us sending nothing, Discord will stop sending voice data to us.
+const AUDIO_KEEPALIVE_INTERVAL: Duration = Duration::from_secs(4 * 60);
+/// How long to wait for the voice server to answer IP discovery.
+const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(5);
 
 impl InternalConnection {
-    fn new(info: ConnStartInfo) -> Result<InternalConnection> {
+    async fn new(
+        info: ConnStartInfo,
+        encoder_config: EncoderConfig,
+        stats: Arc<Mutex<VoiceStats>>,
+    ) -> Result<InternalConnection> {
         let ConnStartInfo {
             server_id,
             user_id,
@@ -517,14 +1721,8 @@ impl InternalConnection {
         }
         // establish the websocket connection
         // v=4 as described at https://discord.com/developers/docs/topics/voice-connections#voice-gateway-versioning-gateway-versions
-        let url = match ::websocket::client::request::Url::parse(&format!("wss://{}?v=4", endpoint))
-        {
-            Ok(url) => url,
-            Err(_) => return Err(Error::Other("Invalid endpoint URL")),
-        };
-        let response = Client::connect(url)?.send()?;
-        response.validate()?;
-        let (mut sender, mut receiver) = response.begin().split();
+        let url = format!("wss://{}?v=4", endpoint);
+        let (mut receiver, mut sender) = WebSocket::connect(&url).await?.split();
 
         // send the handshake
         let map = json! {{
@@ -536,11 +1734,11 @@ impl InternalConnection {
                 "token": token,
             }
         }};
-        sender.send_json(&map)?;
+        send_json(&mut sender, &map).await?;
 
         let mut interval = 10_000; // crappy guess in case we fail to receive one
         let (port, ssrc, modes, ip) = loop {
-            match receiver.recv_json(VoiceEvent::decode)? {
+            match recv_voice_event(&mut receiver).await? {
                 VoiceEvent::Hello { heartbeat_interval } => {
                     interval = heartbeat_interval;
                 }
@@ -558,21 +1756,19 @@ impl InternalConnection {
                 }
             }
         };
-        if !modes.iter().any(|s| s == "xsalsa20_poly1305") {
-            return Err(Error::Protocol(
-                "Voice mode \"xsalsa20_poly1305\" unavailable",
-            ));
-        }
+        let mode = EncryptionMode::choose(&modes).ok_or(Error::Protocol(
+            "No supported voice encryption mode available",
+        ))?;
+        debug!("Using the {} voice encryption mode", mode.name());
 
         // bind a UDP socket and send the ssrc value in a packet as identification
-        let destination = {
-            use std::net::ToSocketAddrs;
-            (ip.as_ref().map(|ip| &ip[..]).unwrap_or(&endpoint[..]), port)
-                .to_socket_addrs()?
+        let destination: SocketAddr =
+            tokio::net::lookup_host((ip.as_deref().unwrap_or(&endpoint), port))
+                .await?
                 .next()
-                .ok_or(Error::Other("Failed to resolve voice hostname"))?
-        };
-        let udp = UdpSocket::bind("0.0.0.0:0")?;
+                .ok_or(Error::Other("Failed to resolve voice hostname"))?;
+        let udp = UdpSocket::bind("0.0.0.0:0").await?;
+        udp.connect(destination).await?;
         debug!("local addr = {:?}", udp.local_addr());
         {
             // https://discord.com/developers/docs/topics/voice-connections#ip-discovery
@@ -582,20 +1778,16 @@ impl InternalConnection {
             msg.write_u16::<BigEndian>(70)?;
             msg.write_u32::<BigEndian>(ssrc)?;
             debug!("sending {:x?} to {:?}", bytes, destination);
-            udp.send_to(&bytes, destination)?;
+            udp.send(&bytes).await?;
         }
 
         {
             // receive the response to the identification to get port and address info
             let mut bytes = [0; 256];
-            let (len, _) = udp.recv_from(&mut bytes)?;
-            let mut msg = &bytes[..len];
-            assert_eq!(0x2, msg.read_u16::<BigEndian>()?);
-            assert_eq!(70, msg.read_u16::<BigEndian>()?);
-            assert_eq!(ssrc, msg.read_u32::<BigEndian>()?);
-            let (addr, mut msg) = msg.split_at(64);
-            let addr = &addr[..addr.iter().position(|&x| x == 0).unwrap()];
-            let port_number = msg.read_u16::<BigEndian>()?;
+            let len = tokio::time::timeout(DISCOVERY_TIMEOUT, udp.recv(&mut bytes))
+                .await
+                .map_err(|_| Error::Other("Timed out discovering the voice IP address"))??;
+            let (addr, port_number) = parse_discovery(&bytes[..len], ssrc)?;
 
             // send the acknowledgement websocket message
             let map = json! {{
@@ -605,29 +1797,32 @@ impl InternalConnection {
                     "data": {
                         "address": addr,
                         "port": port_number,
-                        "mode": "xsalsa20_poly1305",
+                        "mode": mode.name(),
                     }
                 }
             }};
-            sender.send_json(&map)?;
+            send_json(&mut sender, &map).await?;
         }
 
         // discard websocket messages until we get the Ready
-        let encryption_key;
+        let cipher;
         loop {
-            match receiver.recv_json(VoiceEvent::decode)? {
+            match recv_voice_event(&mut receiver).await? {
                 VoiceEvent::Hello { heartbeat_interval } => {
                     // Not hit in usual operation; just for coverage.
                     interval = heartbeat_interval;
                 }
-                VoiceEvent::SessionDescription { mode, secret_key } => {
-                    encryption_key =
-                        crypto::Key::from_slice(&secret_key).expect("failed to create key");
-                    if mode != "xsalsa20_poly1305" {
+                VoiceEvent::SessionDescription {
+                    mode: selected,
+                    secret_key,
+                } => {
+                    if selected != mode.name() {
                         return Err(Error::Protocol(
-                            "Voice mode in Ready was not \"xsalsa20_poly1305\"",
+                            "Voice mode in the session description was not the one selected",
                         ));
                     }
+                    cipher = Cipher::new(mode, &secret_key)
+                        .ok_or(Error::Protocol("Invalid voice encryption key"))?;
                     break;
                 }
                 VoiceEvent::Unknown(op, value) => {
@@ -637,82 +1832,33 @@ impl InternalConnection {
             }
         }
 
-        // start two child threads: one for the voice websocket and another for UDP voice packets
-        let thread = ::std::thread::current();
-        let thread_name = thread.name().unwrap_or("discord voice");
-
-        let (udp_sender_close, udp_reader_close) = mpsc::channel();
-        let (ws_sender_close, ws_reader_close) = mpsc::channel();
-        let (receive_chan, ws_thread, udp_thread) = {
-            let (tx1, rx) = mpsc::channel();
-            let tx2 = tx1.clone();
-            let udp_clone = udp.try_clone()?;
-            let ws_thread = Some(
-                ::std::thread::Builder::new()
-                    .name(format!("{} (WS reader)", thread_name))
-                    .spawn(move || {
-                        {
-                            match *receiver.get_mut().get_mut() {
-                                WebSocketStream::Tcp(ref inner) => {
-                                    inner.set_nonblocking(true).unwrap()
-                                }
-                                WebSocketStream::Ssl(ref inner) => inner
-                                    .lock()
-                                    .unwrap()
-                                    .get_ref()
-                                    .set_nonblocking(true)
-                                    .unwrap(),
-                            };
-                        }
-                        loop {
-                            while let Ok(msg) = receiver.recv_json(VoiceEvent::decode) {
-                                match tx1.send(RecvStatus::Websocket(msg)) {
-                                    Ok(()) => {}
-                                    Err(_) => return,
-                                }
-                            }
-                            if let Ok(_) = ws_reader_close.try_recv() {
-                                return;
-                            }
-                            ::std::thread::sleep(::std::time::Duration::from_millis(25));
-                        }
-                    })?,
-            );
-            let udp_thread = Some(
-                ::std::thread::Builder::new()
-                    .name(format!("{} (UDP reader)", thread_name))
-                    .spawn(move || {
-                        udp_clone
-                            .set_read_timeout(Some(::std::time::Duration::from_millis(100)))
-                            .unwrap();
-                        let mut buffer = [0; 512];
-                        loop {
-                            if let Ok((len, _)) = udp_clone.recv_from(&mut buffer) {
-                                match tx2.send(RecvStatus::Udp(buffer[..len].to_vec())) {
-                                    Ok(()) => {}
-                                    Err(_) => return,
-                                }
-                            } else if let Ok(_) = udp_reader_close.try_recv() {
-                                return;
-                            }
-                        }
-                    })?,
-            );
-            (rx, ws_thread, udp_thread)
-        };
+        // read the voice websocket in its own task, so that reads are never cut off halfway
+        let (tx, receive_chan) = mpsc::unbounded_channel();
+        let ws_task = tokio::spawn(async move {
+            loop {
+                let event = recv_voice_event(&mut receiver).await;
+                let failed = event.is_err();
+                if tx.send(event).is_err() || failed {
+                    return;
+                }
+            }
+        });
 
         info!("Voice connected to {} ({})", endpoint, destination);
-        Ok(InternalConnection {
-            sender: sender,
-            receive_chan: receive_chan,
-            ws_close: ws_sender_close,
-            udp_close: udp_sender_close,
+        *stats.lock().expect("voice stats poisoned") = VoiceStats::default();
 
-            encryption_key: encryption_key,
-            udp: udp,
-            destination: destination,
+        let now = tokio::time::Instant::now();
+        let heartbeat = Duration::from_millis(interval);
+        let mut connection = InternalConnection {
+            sender,
+            receive_chan,
+            ws_task,
 
-            ssrc: ssrc,
+            cipher,
+            nonce: 0,
+            udp,
+
+            ssrc,
             sequence: 0,
             timestamp: 0,
             speaking: false,
@@ -725,117 +1871,219 @@ impl InternalConnection {
                 opus::Application::Audio,
             )?,
             encoder_stereo: false,
-            keepalive_timer: crate::Timer::new(interval),
-            // after 5 minutes of us sending nothing, Discord will stop sending voice data to us
-            audio_keepalive_timer: crate::Timer::new(4 * 60 * 1000),
+            encoder_config,
+            stats,
+            receive_stats: HashMap::new(),
+            connected_at: Instant::now(),
+            keepalive_counter: 0,
+            keepalive_sent: None,
+            keepalive_timer: interval_at(now + heartbeat, heartbeat),
+            audio_keepalive_timer: interval_at(now, AUDIO_KEEPALIVE_INTERVAL),
+        };
+        connection.configure_encoder(encoder_config)?;
+        Ok(connection)
+    }
 
-            ws_thread: ws_thread,
-            udp_thread: udp_thread,
-        })
+    /// Apply the encoder settings to the current encoder.
+    fn configure_encoder(&mut self, config: EncoderConfig) -> Result<()> {
+        self.encoder.set_bitrate(config.bitrate)?;
+        self.encoder.set_inband_fec(config.fec)?;
+        self.encoder
+            .set_packet_loss_perc(config.expected_packet_loss.min(100) as i32)?;
+        self.encoder_config = config;
+
+        let bitrate = match self.encoder.get_bitrate()? {
+            opus::Bitrate::Bits(bits) => Some(bits),
+            _ => None,
+        };
+        self.update_stats(|stats| stats.bitrate = bitrate);
+        Ok(())
     }
 
-    fn update(
-        &mut self,
-        source: &mut Option<Box<dyn AudioSource>>,
-        receiver: &mut Option<Box<dyn AudioReceiver>>,
-        audio_timer: &mut crate::Timer,
-    ) -> Result<()> {
-        let mut audio_buffer = [0i16; 960 * 2]; // 20 ms, stereo
-        let mut packet = [0u8; 512]; // 256 forces opus to reduce bitrate for some packets
-        let mut nonce = crypto::Nonce([0; 24]);
-
-        // Check for received voice data
-        if let Some(receiver) = receiver.as_mut() {
-            while let Ok(status) = self.receive_chan.try_recv() {
-                match status {
-                    RecvStatus::Websocket(VoiceEvent::SpeakingUpdate {
-                        user_id,
-                        ssrc,
-                        speaking,
-                    }) => {
-                        receiver.speaking_update(ssrc, user_id, speaking);
-                    }
-                    RecvStatus::Websocket(_) => {}
-                    RecvStatus::Udp(packet) => {
-                        let mut handle = &packet[2..];
-                        let sequence = handle.read_u16::<BigEndian>()?;
-                        let timestamp = handle.read_u32::<BigEndian>()?;
-                        let ssrc = handle.read_u32::<BigEndian>()?;
-                        nonce.0[..HEADER_LEN].clone_from_slice(&packet[..HEADER_LEN]);
-                        if let Ok(decrypted) =
-                            crypto::open(&packet[HEADER_LEN..], &nonce, &self.encryption_key)
-                        {
-                            let channels = opus::packet::get_nb_channels(&decrypted)?;
-                            let len = self
-                                .decoder_map
-                                .entry((ssrc, channels))
-                                .or_insert_with(|| {
-                                    opus::Decoder::new(SAMPLE_RATE, channels).unwrap()
-                                })
-                                .decode(&decrypted, &mut audio_buffer, false)?;
-                            let stereo = channels == opus::Channels::Stereo;
-                            receiver.voice_packet(
-                                ssrc,
-                                sequence,
-                                timestamp,
-                                stereo,
-                                &audio_buffer[..if stereo { len * 2 } else { len }],
-                            );
-                        }
+    fn update_stats<F: FnOnce(&mut VoiceStats)>(&self, f: F) {
+        f(&mut self.stats.lock().expect("voice stats poisoned"))
+    }
+
+    /// Record a received RTP packet for loss and jitter estimation.
+    fn record_received(&mut self, ssrc: u32, sequence: u16, timestamp: u32) {
+        let arrival = Instant::now();
+        let connected_at = self.connected_at;
+        let entry = self.receive_stats.entry(ssrc).or_insert(ReceiveStats {
+            sequence: sequence.wrapping_sub(1),
+            transit: None,
+            jitter: 0.0,
+        });
+
+        // gaps in the sequence are counted as lost, reordered packets are not
+        let gap = sequence.wrapping_sub(entry.sequence);
+        let lost = if gap > 0 && gap < 0x8000 {
+            entry.sequence = sequence;
+            (gap - 1) as u64
+        } else {
+            0
+        };
+
+        // interarrival jitter as in RFC 3550 section 6.4.1, in units of the RTP clock
+        let arrival = arrival.duration_since(connected_at).as_secs_f64() * SAMPLE_RATE as f64;
+        let transit = arrival - timestamp as f64;
+        if let Some(previous) = entry.transit {
+            let difference = (transit - previous).abs();
+            entry.jitter += (difference - entry.jitter) / 16.0;
+        }
+        entry.transit = Some(transit);
+
+        let jitter = entry.jitter;
+        self.update_stats(|stats| {
+            stats.packets_received += 1;
+            stats.packets_lost += lost;
+            stats.jitter = Duration::from_secs_f64(jitter / SAMPLE_RATE as f64);
+        });
+    }
+
+    /// Wait for the next message from the voice websocket or UDP socket and handle it,
+    /// or send a keepalive if one is due first.
+    async fn receive(&mut self, receiver: &Option<ReceiverThread>) -> Result<()> {
+        let mut packet = [0u8; 512];
+        tokio::select! {
+            event = self.receive_chan.recv() => match event {
+                Some(Ok(VoiceEvent::SpeakingUpdate {
+                    user_id,
+                    ssrc,
+                    speaking,
+                })) => {
+                    if let Some(receiver) = receiver {
+                        receiver.send(Received::Speaking { ssrc, user_id, speaking });
                     }
                 }
+                Some(Ok(_)) => {}
+                Some(Err(err)) => return Err(err),
+                None => return Err(Error::Other("Voice websocket reader stopped")),
+            },
+            len = self.udp.recv(&mut packet) => {
+                let len = len?;
+                self.handle_packet(&packet[..len], receiver)?;
+            }
+
+            // Send the voice websocket keepalive
+            _ = self.keepalive_timer.tick() => {
+                let map = json! {{
+                    "op": 3,
+                    "d": self.connected_at.elapsed().as_millis() as u64,
+                }};
+                send_json(&mut self.sender, &map).await?;
+            }
+
+            // Send the UDP keepalive
+            _ = self.audio_keepalive_timer.tick() => {
+                // an incrementing counter, which is echoed back and used to measure the round trip
+                self.keepalive_counter = self.keepalive_counter.wrapping_add(1);
+                let mut bytes = [0; 8];
+                (&mut bytes[..]).write_u64::<LittleEndian>(self.keepalive_counter)?;
+                self.udp.send(&bytes).await?;
+                self.keepalive_sent = Some((self.keepalive_counter, Instant::now()));
             }
-        } else {
-            // if there's no receiver, discard incoming events
-            while let Ok(_) = self.receive_chan.try_recv() {}
         }
+        Ok(())
+    }
 
-        // Send the voice websocket keepalive if needed
-        if self.keepalive_timer.check_tick() {
-            let map = json! {{
-                "op": 3,
-                "d": serde_json::Value::Null,
-            }};
-            self.sender.send_json(&map)?;
+    /// Handle a packet received from the voice server.
+    fn handle_packet(&mut self, packet: &[u8], receiver: &Option<ReceiverThread>) -> Result<()> {
+        if packet.len() == 8 {
+            // the voice server echoes our UDP keepalives back
+            let counter = (&packet[..]).read_u64::<LittleEndian>()?;
+            if let Some((sent, at)) = self.keepalive_sent {
+                if sent == counter {
+                    let round_trip = at.elapsed();
+                    self.update_stats(|stats| stats.round_trip = Some(round_trip));
+                }
+            }
+            return Ok(());
+        }
+        if is_rtcp(packet) {
+            self.update_stats(|stats| stats.rtcp_received += 1);
+            return Ok(());
+        }
+        if packet.len() < HEADER_LEN {
+            return Ok(());
         }
 
-        // Send the UDP keepalive if needed
-        if self.audio_keepalive_timer.check_tick() {
-            let mut bytes = [0; 4];
-            (&mut bytes[..]).write_u32::<BigEndian>(self.ssrc)?;
-            self.udp.send_to(&bytes, self.destination)?;
+        let mut handle = &packet[2..];
+        let sequence = handle.read_u16::<BigEndian>()?;
+        let timestamp = handle.read_u32::<BigEndian>()?;
+        let ssrc = handle.read_u32::<BigEndian>()?;
+        self.record_received(ssrc, sequence, timestamp);
+
+        let receiver = match receiver {
+            Some(receiver) => receiver,
+            // if there's no receiver, don't bother decoding
+            None => return Ok(()),
+        };
+        if let Some(decrypted) = self.cipher.open(packet) {
+            let mut audio_buffer = [0i16; 960 * 2]; // 20 ms, stereo
+            let channels = opus::packet::get_nb_channels(&decrypted)?;
+            let decoder = match self.decoder_map.entry((ssrc, channels)) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => entry.insert(opus::Decoder::new(SAMPLE_RATE, channels)?),
+            };
+            let len = decoder.decode(&decrypted, &mut audio_buffer, false)?;
+            let stereo = channels == opus::Channels::Stereo;
+            receiver.send(Received::Packet {
+                ssrc,
+                sequence,
+                timestamp,
+                stereo,
+                data: audio_buffer[..if stereo { len * 2 } else { len }].to_vec(),
+            });
         }
+        Ok(())
+    }
 
-        // read the audio from the source
-        let mut clear_source = false;
-        let len = if let Some(source) = source.as_mut() {
-            let stereo = source.is_stereo();
-            if stereo != self.encoder_stereo {
-                let channels = if stereo {
-                    opus::Channels::Stereo
-                } else {
-                    opus::Channels::Mono
-                };
-                self.encoder = opus::Encoder::new(SAMPLE_RATE, channels, opus::Application::Audio)?;
-                self.encoder_stereo = stereo;
+    /// Read a frame of audio from the mixer, then encode, encrypt and send it.
+    async fn send_audio(&mut self, mixer: &mut Option<Mixer>) -> Result<()> {
+        let mut packet = [0u8; 512]; // 256 forces opus to reduce bitrate for some packets
+
+        // read and mix the audio from the playing tracks, off the async threads as sources may block
+        let (stereo, len, mut audio_buffer, opus_frame) = match mixer.take() {
+            Some(mut taken) if !taken.is_empty() => {
+                let (taken, frame) = tokio::task::spawn_blocking(move || {
+                    let mut audio_buffer = [0i16; 960 * 2]; // 20 ms, stereo
+
+                    // pre-encoded audio playing on its own is sent as it is
+                    if taken.is_opus() {
+                        let opus_frame = taken.read_opus_frame().unwrap_or_default();
+                        return (taken, (false, 0, audio_buffer, Some(opus_frame)));
+                    }
+                    let stereo = taken.is_stereo();
+                    let buffer_len = if stereo { 960 * 2 } else { 960 };
+                    let len = taken
+                        .read_frame(&mut audio_buffer[..buffer_len])
+                        .unwrap_or(0);
+                    (taken, (stereo, len, audio_buffer, None))
+                })
+                .await
+                .map_err(|_| Error::Other("An audio source panicked"))?;
+                *mixer = Some(taken);
+                frame
             }
-            let buffer_len = if stereo { 960 * 2 } else { 960 };
-            match source.read_frame(&mut audio_buffer[..buffer_len]) {
-                Some(len) => len,
-                None => {
-                    clear_source = true;
-                    0
-                }
+            taken => {
+                *mixer = taken;
+                (self.encoder_stereo, 0, [0i16; 960 * 2], None)
             }
-        } else {
-            0
         };
-        if clear_source {
-            *source = None;
+        let opus_frame = opus_frame.filter(|frame| !frame.is_empty());
+        if opus_frame.is_none() && len > 0 && stereo != self.encoder_stereo {
+            let channels = if stereo {
+                opus::Channels::Stereo
+            } else {
+                opus::Channels::Mono
+            };
+            self.encoder = opus::Encoder::new(SAMPLE_RATE, channels, opus::Application::Audio)?;
+            self.encoder_stereo = stereo;
+            self.configure_encoder(self.encoder_config)?;
         }
-        if len == 0 {
+        if len == 0 && opus_frame.is_none() {
             // stop speaking, don't send any audio
-            self.set_speaking(false)?;
+            self.set_speaking(false).await?;
             if self.silence_frames > 0 {
                 // send a few frames of silence; could be optimized to be pre-encoded
                 self.silence_frames -= 1;
@@ -843,7 +2091,6 @@ impl InternalConnection {
                     *value = 0;
                 }
             } else {
-                audio_timer.sleep_until_tick();
                 return Ok(());
             }
         } else {
@@ -853,7 +2100,7 @@ impl InternalConnection {
                 *value = 0;
             }
         }
-        self.set_speaking(true)?;
+        self.set_speaking(true).await?;
 
         // prepare the packet header
         {
@@ -864,33 +2111,40 @@ impl InternalConnection {
             cursor.write_u32::<BigEndian>(self.ssrc)?;
             debug_assert!(cursor.is_empty());
         }
-        nonce.0[..HEADER_LEN].clone_from_slice(&packet[..HEADER_LEN]);
-
-        // encode the audio data
-        let extent = packet.len() - 16; // leave 16 bytes for encryption overhead
-        let buffer_len = if self.encoder_stereo { 960 * 2 } else { 960 };
-        let len = self
-            .encoder
-            .encode(&audio_buffer[..buffer_len], &mut packet[HEADER_LEN..extent])?;
-        let crypted = crypto::seal(
-            &packet[HEADER_LEN..HEADER_LEN + len],
-            &nonce,
-            &self.encryption_key,
-        );
+
+        // encode the audio data, leaving room for the encryption overhead
+        let extent = packet.len() - self.cipher.mode().overhead();
+        let len = match opus_frame {
+            Some(frame) if frame.len() > extent - HEADER_LEN => {
+                warn!("Dropping an Opus frame of {} bytes", frame.len());
+                return Ok(());
+            }
+            Some(frame) => {
+                packet[HEADER_LEN..HEADER_LEN + frame.len()].copy_from_slice(&frame);
+                frame.len()
+            }
+            None => {
+                let buffer_len = if self.encoder_stereo { 960 * 2 } else { 960 };
+                self.encoder
+                    .encode(&audio_buffer[..buffer_len], &mut packet[HEADER_LEN..extent])?
+            }
+        };
+        let (header, payload) = packet.split_at(HEADER_LEN);
+        let crypted = self.cipher.seal(header, &payload[..len], self.nonce);
         packet[HEADER_LEN..HEADER_LEN + crypted.len()].clone_from_slice(&crypted);
+        self.nonce = self.nonce.wrapping_add(1);
 
+        self.update_stats(|stats| stats.packets_sent += 1);
         self.sequence = self.sequence.wrapping_add(1);
         self.timestamp = self.timestamp.wrapping_add(960);
 
-        // wait until the right time, then transmit the packet
-        audio_timer.sleep_until_tick();
-        self.udp
-            .send_to(&packet[..HEADER_LEN + crypted.len()], self.destination)?;
-        self.audio_keepalive_timer.defer();
+        // the audio timer has already ticked, so transmit the packet straight away
+        self.udp.send(&packet[..HEADER_LEN + crypted.len()]).await?;
+        self.audio_keepalive_timer.reset();
         Ok(())
     }
 
-    fn set_speaking(&mut self, speaking: bool) -> Result<()> {
+    async fn set_speaking(&mut self, speaking: bool) -> Result<()> {
         if self.speaking == speaking {
             return Ok(());
         }
@@ -898,26 +2152,415 @@ impl InternalConnection {
         let map = json! {{
             "op": 5,
             "d": {
-                "speaking": speaking,
+                "speaking": speaking as u8,
                 "delay": 0,
+                "ssrc": self.ssrc,
             }
         }};
-        self.sender.send_json(&map)
+        send_json(&mut self.sender, &map).await
     }
 }
 
 impl Drop for InternalConnection {
     fn drop(&mut self) {
-        // Shutdown both internal threads
-        let _ = self.udp_close.send(());
-        let _ = self.udp_thread.take().unwrap().join();
-        let _ = self.ws_close.send(());
-        let _ = self.ws_thread.take().unwrap().join();
+        // Stop reading the voice websocket; the UDP socket closes as it is dropped
+        self.ws_task.abort();
         info!("Voice disconnected");
     }
 }
 
-enum RecvStatus {
-    Websocket(VoiceEvent),
-    Udp(Vec<u8>),
+/// Send a JSON message over the voice websocket.
+async fn send_json(sender: &mut WebSocketWriteHalf, value: &Value) -> Result<()> {
+    sender.send_text(serde_json::to_string(value)?).await?;
+    Ok(())
+}
+
+/// Receive the next message from the voice websocket.
+async fn recv_voice_event(receiver: &mut WebSocketReadHalf) -> Result<VoiceEvent> {
+    loop {
+        match receiver.receive().await? {
+            Frame::Text { payload, .. } => {
+                return decode_voice_event(serde_json::from_str(&payload)?);
+            }
+            Frame::Close { payload } => {
+                let (code, reason) = match payload {
+                    Some((code, reason)) => (Some(code), reason),
+                    None => (None, String::new()),
+                };
+                return Err(Error::Closed(code, reason));
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Decode a message from the voice websocket.
+fn decode_voice_event(value: Value) -> Result<VoiceEvent> {
+    #[derive(Deserialize)]
+    struct Message {
+        op: u64,
+        #[serde(default)]
+        d: Value,
+    }
+    #[derive(Deserialize)]
+    struct Hello {
+        heartbeat_interval: f64,
+    }
+    #[derive(Deserialize)]
+    struct Ready {
+        port: u16,
+        ssrc: u32,
+        modes: Vec<String>,
+        ip: Option<String>,
+    }
+    #[derive(Deserialize)]
+    struct SessionDescription {
+        mode: String,
+        secret_key: Vec<u8>,
+    }
+    #[derive(Deserialize)]
+    struct Speaking {
+        user_id: UserId,
+        ssrc: u32,
+        // a bitfield since gateway v4, a bool before
+        speaking: Value,
+    }
+
+    let Message { op, d } = serde_json::from_value(value)?;
+    Ok(match op {
+        2 => {
+            let Ready {
+                port,
+                ssrc,
+                modes,
+                ip,
+            } = serde_json::from_value(d)?;
+            VoiceEvent::VoiceReady {
+                port,
+                ssrc,
+                modes,
+                ip,
+            }
+        }
+        3 => VoiceEvent::KeepAlive,
+        4 => {
+            let SessionDescription { mode, secret_key } = serde_json::from_value(d)?;
+            VoiceEvent::SessionDescription { mode, secret_key }
+        }
+        5 => {
+            let Speaking {
+                user_id,
+                ssrc,
+                speaking,
+            } = serde_json::from_value(d)?;
+            VoiceEvent::SpeakingUpdate {
+                user_id,
+                ssrc,
+                speaking: speaking.as_bool().unwrap_or(speaking.as_u64() != Some(0)),
+            }
+        }
+        6 => VoiceEvent::HeartbeatAck,
+        8 => {
+            let Hello { heartbeat_interval } = serde_json::from_value(d)?;
+            VoiceEvent::Hello {
+                heartbeat_interval: heartbeat_interval as u64,
+            }
+        }
+        other => VoiceEvent::Unknown(other, d),
+    })
+}
+
+/// Read our external address and port from the voice server's answer to IP discovery.
+fn parse_discovery(mut msg: &[u8], ssrc: u32) -> Result<(String, u16)> {
+    if msg.len() < 74
+        || msg.read_u16::<BigEndian>()? != 0x2
+        || msg.read_u16::<BigEndian>()? != 70
+        || msg.read_u32::<BigEndian>()? != ssrc
+    {
+        return Err(Error::Protocol("Invalid IP discovery response"));
+    }
+    let (addr, mut msg) = msg.split_at(64);
+    let addr = &addr[..addr.iter().position(|&x| x == 0).unwrap_or(64)];
+    let addr = String::from_utf8_lossy(addr).into_owned();
+    let port_number = msg.read_u16::<BigEndian>()?;
+    Ok((addr, port_number))
+}
+
+/// Check whether a UDP packet is an RTCP report rather than RTP voice data.
+///
+/// RTCP packet types 200 to 204 occupy the byte where RTP has its marker bit and payload type.
+fn is_rtcp(packet: &[u8]) -> bool {
+    packet.len() >= 8 && (200..=204).contains(&packet[1])
+}
+
+/// Reception state of a single remote audio stream, for loss and jitter estimation.
+struct ReceiveStats {
+    /// The highest sequence number received.
+    sequence: u16,
+    /// The relative transit time of the previous packet, in RTP clock units.
+    transit: Option<f64>,
+    /// The running jitter estimate, in RTP clock units.
+    jitter: f64,
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use std::sync::{Arc, Mutex};
+
+    use std::collections::{HashMap, VecDeque};
+    use std::path::{Path, PathBuf};
+
+    use super::{
+        create_dca_source, create_pcm_source, decode_voice_event, parse_discovery, AudioReceiver,
+        AudioSource, MixedRecorder, Mixer, RotatingWav, Timeline, TrackEvent, TrackId,
+        UserRecorder,
+    };
+    use crate::model::{UserId, VoiceEvent};
+
+    /// A mono source playing a constant sample for some frames.
+    struct Tone(i16, usize);
+
+    impl AudioSource for Tone {
+        fn is_stereo(&mut self) -> bool {
+            false
+        }
+
+        fn read_frame(&mut self, buffer: &mut [i16]) -> Option<usize> {
+            self.1 = self.1.checked_sub(1)?;
+            buffer.fill(self.0);
+            Some(buffer.len())
+        }
+    }
+
+    /// A fresh directory for a test's recordings.
+    fn recording_dir(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("discord-{}-{}", test, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Read the channel count and samples of a finished recording.
+    fn read_wav(path: &Path) -> (u16, Vec<i16>) {
+        let bytes = std::fs::read(path).unwrap();
+        assert_eq!(&bytes[..4], b"RIFF");
+        let data_len = u32::from_le_bytes(bytes[40..44].try_into().unwrap()) as usize;
+        assert_eq!(bytes.len(), 44 + data_len);
+        let samples = bytes[44..]
+            .chunks_exact(2)
+            .map(|s| i16::from_le_bytes([s[0], s[1]]))
+            .collect();
+        (u16::from_le_bytes([bytes[22], bytes[23]]), samples)
+    }
+
+    /// A timeline on which each stream's first packet is anchored at the start.
+    fn anchored(streams: &[(u32, u32)]) -> Timeline {
+        let mut timeline = Timeline::new();
+        for &(ssrc, timestamp) in streams {
+            timeline.anchors.insert(ssrc, (timestamp, 0));
+        }
+        timeline
+    }
+
+    #[test]
+    fn rotates_recordings() {
+        let dir = recording_dir("rotating");
+        let mut wav = RotatingWav::create(dir.join("call.wav"), 1).unwrap();
+        wav.max_frames = 3;
+        for sample in 1..=4 {
+            wav.write_frame(sample, sample + 2).unwrap();
+        }
+        wav.write_silence(4).unwrap();
+        drop(wav);
+
+        assert_eq!(read_wav(&dir.join("call.wav")), (1, vec![2, 3, 4]));
+        assert_eq!(read_wav(&dir.join("call-1.wav")), (1, vec![5, 0, 0]));
+        assert_eq!(read_wav(&dir.join("call-2.wav")), (1, vec![0, 0]));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn places_packets_on_timeline() {
+        let mut timeline = Timeline::new();
+        timeline.anchors.insert(1, (u32::MAX - 10, 100));
+        // timestamps wrap around, while those slightly before the anchor are reordered packets
+        assert_eq!(timeline.position(1, 5), 116);
+        assert_eq!(timeline.position(1, u32::MAX - 20), 90);
+        assert_eq!(timeline.position(1, u32::MAX - 200), 0);
+
+        // new streams are anchored at the time their first packet arrives
+        let position = timeline.position(2, 12345);
+        assert!(position <= timeline.now());
+        assert_eq!(timeline.position(2, 12345 + 960), position + 960);
+    }
+
+    #[test]
+    fn reads_pcm_frames() {
+        let bytes: Vec<u8> = [1i16, -2, 3, -4, 5]
+            .iter()
+            .flat_map(|s| s.to_le_bytes())
+            .collect();
+        let mut source = create_pcm_source(true, std::io::Cursor::new(bytes));
+        assert!(source.is_stereo());
+
+        let mut buffer = [0; 4];
+        assert_eq!(source.read_frame(&mut buffer), Some(4));
+        assert_eq!(buffer, [1, -2, 3, -4]);
+        // a short read at the end of the stream, then nothing
+        assert_eq!(source.read_frame(&mut buffer), Some(1));
+        assert_eq!(buffer[0], 5);
+        assert_eq!(source.read_frame(&mut buffer), None);
+    }
+
+    #[test]
+    fn mixes_streams_by_timestamp() {
+        let dir = recording_dir("mixed");
+        let path = dir.join("mixed.wav");
+        let mut recorder = MixedRecorder {
+            file: RotatingWav::create(path.clone(), 2).unwrap(),
+            timeline: anchored(&[(1, 1000), (2, 5000)]),
+            buffer: VecDeque::new(),
+            buffer_start: 0,
+        };
+        recorder.voice_packet(1, 0, 1000, false, &[100, 100, 100, 100]);
+        recorder.voice_packet(2, 0, 5002, true, &[10, 20, 10, 20]);
+        // the first stream pauses, leaving silence until it speaks again
+        recorder.voice_packet(1, 1, 1008, false, &[7]);
+        drop(recorder);
+
+        let (channels, samples) = read_wav(&path);
+        assert_eq!(channels, 2);
+        #[rustfmt::skip]
+        assert_eq!(samples, [
+            100, 100, 100, 100, 110, 120, 110, 120,
+            0, 0, 0, 0, 0, 0, 0, 0, 7, 7,
+        ]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn records_each_user_apart() {
+        let dir = recording_dir("per-user");
+        let mut recorder = UserRecorder {
+            dir: dir.clone(),
+            timeline: anchored(&[(1, 0), (2, 0)]),
+            users: HashMap::new(),
+            files: HashMap::new(),
+        };
+        recorder.speaking_update(1, UserId(7), true);
+        recorder.voice_packet(1, 0, 0, false, &[5, 5]);
+        recorder.voice_packet(1, 2, 4, false, &[6, 6]);
+        // reordered audio which was already written over with silence is dropped
+        recorder.voice_packet(1, 1, 2, false, &[9]);
+        // as is audio of streams whose user isn't known yet
+        recorder.voice_packet(2, 0, 0, false, &[1]);
+        drop(recorder);
+
+        let (channels, samples) = read_wav(&dir.join("7.wav"));
+        assert_eq!(channels, 2);
+        assert_eq!(samples, [5, 5, 5, 5, 0, 0, 0, 0, 6, 6, 6, 6]);
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn queue_plays_in_order() {
+        let queued = Arc::new(Mutex::new(Vec::new()));
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut mixer = Mixer::new(queued.clone());
+        let log = events.clone();
+        mixer.on_event = Some(Box::new(move |event| log.lock().unwrap().push(event)));
+
+        mixer.enqueue(TrackId(0), Box::new(Tone(100, 1)));
+        mixer.enqueue(TrackId(1), Box::new(Tone(200, 2)));
+        assert_eq!(*queued.lock().unwrap(), [TrackId(0), TrackId(1)]);
+
+        let mut frame = [0; 960];
+        let mut play = |mixer: &mut Mixer| {
+            let len = mixer.read_frame(&mut frame).unwrap();
+            (len > 0).then_some(frame[0])
+        };
+        assert_eq!(play(&mut mixer), Some(100));
+        // the first track ends, and the second starts on the next frame
+        assert_eq!(play(&mut mixer), None);
+        assert_eq!(*queued.lock().unwrap(), [TrackId(1)]);
+
+        mixer.set_paused(TrackId(1), true);
+        assert_eq!(play(&mut mixer), None);
+        mixer.set_paused(TrackId(1), false);
+        mixer.set_gain(TrackId(1), 0.5);
+        assert_eq!(play(&mut mixer), Some(100));
+
+        mixer.remove(TrackId(1));
+        assert!(mixer.is_empty());
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                TrackEvent::Started(TrackId(0)),
+                TrackEvent::Ended(TrackId(0)),
+                TrackEvent::Started(TrackId(1)),
+                TrackEvent::Ended(TrackId(1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn passes_opus_through() {
+        let mut dca = b"DCA1".to_vec();
+        let metadata = br#"{"opus": {"channels": 1}}"#;
+        dca.extend_from_slice(&(metadata.len() as i32).to_le_bytes());
+        dca.extend_from_slice(metadata);
+        for frame in [&[1u8, 2, 3][..], &[4, 5]] {
+            dca.extend_from_slice(&(frame.len() as i16).to_le_bytes());
+            dca.extend_from_slice(frame);
+        }
+        let mut source = create_dca_source(std::io::Cursor::new(dca)).unwrap();
+        assert!(!source.is_stereo());
+
+        let mut mixer = Mixer::new(Arc::new(Mutex::new(Vec::new())));
+        mixer.enqueue(TrackId(0), source);
+        assert!(mixer.is_opus());
+        assert_eq!(mixer.read_opus_frame(), Some(vec![1, 2, 3]));
+        // mixing with another track needs the audio decoded
+        mixer.add(TrackId(1), Box::new(Tone(100, 1)), 1.0);
+        assert!(!mixer.is_opus());
+        mixer.remove(TrackId(1));
+        assert_eq!(mixer.read_opus_frame(), Some(vec![4, 5]));
+        // the track ends once its frames run out
+        assert_eq!(mixer.read_opus_frame(), Some(vec![]));
+        assert!(mixer.is_empty());
+    }
+
+    #[test]
+    fn decodes_voice_gateway() {
+        let hello = decode_voice_event(json!({"op": 8, "d": {"heartbeat_interval": 41250.5}}));
+        assert!(matches!(
+            hello.unwrap(),
+            VoiceEvent::Hello {
+                heartbeat_interval: 41250
+            }
+        ));
+
+        let speaking = json!({"op": 5, "d": {"user_id": "7", "ssrc": 3, "speaking": 1}});
+        match decode_voice_event(speaking).unwrap() {
+            VoiceEvent::SpeakingUpdate {
+                user_id,
+                ssrc,
+                speaking,
+            } => assert_eq!((user_id, ssrc, speaking), (UserId(7), 3, true)),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let mut discovery = vec![0, 2, 0, 70, 0, 0, 0, 9];
+        discovery.extend_from_slice(b"203.0.113.5");
+        discovery.resize(8 + 64, 0);
+        discovery.extend_from_slice(&[0xc3, 0x50]);
+        assert_eq!(
+            parse_discovery(&discovery, 9).unwrap(),
+            ("203.0.113.5".to_owned(), 50000)
+        );
+        assert!(parse_discovery(&discovery, 10).is_err());
+    }
 }
@@ -0,0 +1,299 @@
+//! The encryption modes voice packets can be sent with.
+//!
+//! The `_rtpsize` AEAD modes leave the RTP header unencrypted but authenticated,
+//! and append a 32-bit counter to each packet from which the nonce is made.
+//! `xsalsa20_poly1305` is the original mode, which Discord is phasing out;
+//! it is only used when the voice server offers nothing newer.
+
+use sodiumoxide::crypto::aead::{aes256gcm, xchacha20poly1305_ietf};
+use sodiumoxide::crypto::secretbox;
+
+/// The length of the RTP header without any CSRCs.
+const HEADER_LEN: usize = 12;
+
+/// The length of the counter appended to packets in the `_rtpsize` modes.
+const COUNTER_LEN: usize = 4;
+
+/// The encryption mode of a voice connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EncryptionMode {
+    /// AES-256 in GCM mode, only available on CPUs with AES instructions.
+    Aes256GcmRtpSize,
+    /// XChaCha20 with Poly1305.
+    XChaCha20Poly1305RtpSize,
+    /// XSalsa20 with Poly1305, with the RTP header as the nonce.
+    XSalsa20Poly1305,
+}
+
+impl EncryptionMode {
+    /// The modes in order of preference.
+    const PREFERENCE: [EncryptionMode; 3] = [
+        EncryptionMode::Aes256GcmRtpSize,
+        EncryptionMode::XChaCha20Poly1305RtpSize,
+        EncryptionMode::XSalsa20Poly1305,
+    ];
+
+    /// The name of the mode in the voice gateway.
+    pub fn name(self) -> &'static str {
+        match self {
+            EncryptionMode::Aes256GcmRtpSize => "aead_aes256_gcm_rtpsize",
+            EncryptionMode::XChaCha20Poly1305RtpSize => "aead_xchacha20_poly1305_rtpsize",
+            EncryptionMode::XSalsa20Poly1305 => "xsalsa20_poly1305",
+        }
+    }
+
+    /// Choose the best mode supported by both the voice server and this machine.
+    pub fn choose(modes: &[String]) -> Option<EncryptionMode> {
+        Self::PREFERENCE.into_iter().find(|mode| {
+            modes.iter().any(|name| name == mode.name())
+                && (*mode != EncryptionMode::Aes256GcmRtpSize || aes256gcm::is_available())
+        })
+    }
+
+    /// How many bytes encrypting adds to a payload.
+    pub fn overhead(self) -> usize {
+        match self {
+            EncryptionMode::Aes256GcmRtpSize => aes256gcm::TAGBYTES + COUNTER_LEN,
+            EncryptionMode::XChaCha20Poly1305RtpSize => {
+                xchacha20poly1305_ietf::TAGBYTES + COUNTER_LEN
+            }
+            EncryptionMode::XSalsa20Poly1305 => secretbox::MACBYTES,
+        }
+    }
+}
+
+/// The key of a voice connection, for its encryption mode.
+pub enum Cipher {
+    Aes256Gcm(aes256gcm::Key),
+    XChaCha20Poly1305(xchacha20poly1305_ietf::Key),
+    XSalsa20Poly1305(secretbox::Key),
+}
+
+impl Cipher {
+    /// Make the cipher for a mode from the secret key given by the voice server.
+    pub fn new(mode: EncryptionMode, key: &[u8]) -> Option<Cipher> {
+        Some(match mode {
+            EncryptionMode::Aes256GcmRtpSize => Cipher::Aes256Gcm(aes256gcm::Key::from_slice(key)?),
+            EncryptionMode::XChaCha20Poly1305RtpSize => {
+                Cipher::XChaCha20Poly1305(xchacha20poly1305_ietf::Key::from_slice(key)?)
+            }
+            EncryptionMode::XSalsa20Poly1305 => {
+                Cipher::XSalsa20Poly1305(secretbox::Key::from_slice(key)?)
+            }
+        })
+    }
+
+    /// The encryption mode of the cipher.
+    pub fn mode(&self) -> EncryptionMode {
+        match self {
+            Cipher::Aes256Gcm(_) => EncryptionMode::Aes256GcmRtpSize,
+            Cipher::XChaCha20Poly1305(_) => EncryptionMode::XChaCha20Poly1305RtpSize,
+            Cipher::XSalsa20Poly1305(_) => EncryptionMode::XSalsa20Poly1305,
+        }
+    }
+
+    /// Encrypt a payload to follow the given RTP header, using `counter` as the nonce
+    /// in the `_rtpsize` modes, which must not repeat for the same key.
+    ///
+    /// Returns everything that follows the header in the packet.
+    pub fn seal(&self, header: &[u8], payload: &[u8], counter: u32) -> Vec<u8> {
+        let mut sealed = match self {
+            Cipher::Aes256Gcm(key) => {
+                let nonce = counter_nonce::<{ aes256gcm::NONCEBYTES }>(counter);
+                let nonce = aes256gcm::Nonce(nonce);
+                aes256gcm::Aes256Gcm.seal(payload, Some(header), &nonce, key)
+            }
+            Cipher::XChaCha20Poly1305(key) => {
+                let nonce = counter_nonce::<{ xchacha20poly1305_ietf::NONCEBYTES }>(counter);
+                let nonce = xchacha20poly1305_ietf::Nonce(nonce);
+                xchacha20poly1305_ietf::seal(payload, Some(header), &nonce, key)
+            }
+            Cipher::XSalsa20Poly1305(key) => {
+                return secretbox::seal(payload, &header_nonce(header), key);
+            }
+        };
+        sealed.extend_from_slice(&counter.to_be_bytes());
+        sealed
+    }
+
+    /// Decrypt a received RTP packet, returning its payload without any header extension.
+    pub fn open(&self, packet: &[u8]) -> Option<Vec<u8>> {
+        if packet.len() < HEADER_LEN {
+            return None;
+        }
+        let csrcs = (packet[0] & 0x0f) as usize;
+        let extended = packet[0] & 0x10 != 0;
+        let mut header_len = HEADER_LEN + 4 * csrcs;
+
+        let mut decrypted = match self {
+            Cipher::XSalsa20Poly1305(key) => {
+                let encrypted = packet.get(header_len..)?;
+                secretbox::open(encrypted, &header_nonce(packet), key).ok()?
+            }
+            aead => {
+                // the extension's profile and length are left unencrypted, but not its contents
+                if extended {
+                    header_len += 4;
+                }
+                let counter_at = packet.len().checked_sub(COUNTER_LEN)?;
+                let (header, encrypted) = packet[..counter_at].split_at(header_len.min(counter_at));
+                let counter = u32::from_be_bytes(packet[counter_at..].try_into().ok()?);
+                match aead {
+                    Cipher::Aes256Gcm(key) => {
+                        let nonce = counter_nonce::<{ aes256gcm::NONCEBYTES }>(counter);
+                        let nonce = aes256gcm::Nonce(nonce);
+                        aes256gcm::Aes256Gcm
+                            .open(encrypted, Some(header), &nonce, key)
+                            .ok()?
+                    }
+                    Cipher::XChaCha20Poly1305(key) => {
+                        let nonce =
+                            counter_nonce::<{ xchacha20poly1305_ietf::NONCEBYTES }>(counter);
+                        let nonce = xchacha20poly1305_ietf::Nonce(nonce);
+                        xchacha20poly1305_ietf::open(encrypted, Some(header), &nonce, key).ok()?
+                    }
+                    Cipher::XSalsa20Poly1305(_) => unreachable!(),
+                }
+            }
+        };
+
+        if extended {
+            // the extension's length, in 32-bit words, is in the header in the AEAD modes
+            // and at the start of the decrypted payload in the original mode
+            let (length_at, skip) = match self {
+                Cipher::XSalsa20Poly1305(_) => (&decrypted[..], 4),
+                _ => (&packet[header_len - 4..], 0),
+            };
+            let words = u16::from_be_bytes([*length_at.get(2)?, *length_at.get(3)?]) as usize;
+            let extension_len = skip + 4 * words;
+            if extension_len > decrypted.len() {
+                return None;
+            }
+            decrypted.drain(..extension_len);
+        }
+        Some(decrypted)
+    }
+}
+
+/// The nonce of the `_rtpsize` modes: the counter, followed by zeroes.
+fn counter_nonce<const N: usize>(counter: u32) -> [u8; N] {
+    let mut nonce = [0; N];
+    nonce[..COUNTER_LEN].copy_from_slice(&counter.to_be_bytes());
+    nonce
+}
+
+/// The nonce of the original mode: the RTP header, followed by zeroes.
+fn header_nonce(packet: &[u8]) -> secretbox::Nonce {
+    let mut nonce = secretbox::Nonce([0; secretbox::NONCEBYTES]);
+    nonce.0[..HEADER_LEN].copy_from_slice(&packet[..HEADER_LEN]);
+    nonce
+}
+
+#[cfg(test)]
+mod tests {
+    use sodiumoxide::crypto::aead::{aes256gcm, xchacha20poly1305_ietf};
+
+    use super::{Cipher, EncryptionMode};
+
+    #[test]
+    fn negotiates_and_round_trips() {
+        sodiumoxide::init().unwrap();
+
+        let offered = ["xsalsa20_poly1305", "aead_xchacha20_poly1305_rtpsize"]
+            .map(String::from)
+            .to_vec();
+        assert_eq!(
+            EncryptionMode::choose(&offered),
+            Some(EncryptionMode::XChaCha20Poly1305RtpSize)
+        );
+        assert_eq!(EncryptionMode::choose(&["plaintext".into()]), None);
+
+        let key = xchacha20poly1305_ietf::gen_key();
+        for mode in [
+            EncryptionMode::XChaCha20Poly1305RtpSize,
+            EncryptionMode::XSalsa20Poly1305,
+        ] {
+            let cipher = Cipher::new(mode, &key.0).unwrap();
+            let header = [0x80, 0x78, 0, 1, 0, 0, 3, 192, 0, 0, 0, 9];
+            let sealed = cipher.seal(&header, b"opus", 7);
+            assert_eq!(sealed.len(), 4 + mode.overhead());
+
+            let mut packet = header.to_vec();
+            packet.extend_from_slice(&sealed);
+            assert_eq!(cipher.open(&packet).as_deref(), Some(&b"opus"[..]));
+            // tampering with the header is detected
+            packet[3] = 2;
+            assert_eq!(cipher.open(&packet), None);
+        }
+    }
+
+    #[test]
+    fn round_trips_aes256gcm() {
+        sodiumoxide::init().unwrap();
+        let offered = ["aead_aes256_gcm_rtpsize", "aead_xchacha20_poly1305_rtpsize"]
+            .map(String::from)
+            .to_vec();
+        if !aes256gcm::is_available() {
+            // without hardware support AES-GCM isn't offered to Discord at all
+            assert_eq!(
+                EncryptionMode::choose(&offered),
+                Some(EncryptionMode::XChaCha20Poly1305RtpSize)
+            );
+            return;
+        }
+        assert_eq!(
+            EncryptionMode::choose(&offered),
+            Some(EncryptionMode::Aes256GcmRtpSize)
+        );
+
+        let key = aes256gcm::Aes256Gcm.gen_key();
+        let cipher = Cipher::new(EncryptionMode::Aes256GcmRtpSize, &key.0).unwrap();
+        assert_eq!(cipher.mode(), EncryptionMode::Aes256GcmRtpSize);
+        let header = [0x80, 0x78, 0, 1, 0, 0, 3, 192, 0, 0, 0, 9];
+        let sealed = cipher.seal(&header, b"opus", 7);
+        assert_eq!(
+            sealed.len(),
+            4 + EncryptionMode::Aes256GcmRtpSize.overhead()
+        );
+        // the counter is sent after the encrypted payload
+        assert_eq!(sealed[sealed.len() - 4..], 7u32.to_be_bytes());
+
+        let mut packet = header.to_vec();
+        packet.extend_from_slice(&sealed);
+        assert_eq!(cipher.open(&packet).as_deref(), Some(&b"opus"[..]));
+        let last = packet.len() - 5;
+        packet[last] ^= 1;
+        assert_eq!(cipher.open(&packet), None);
+    }
+
+    #[test]
+    fn strips_header_extension() {
+        sodiumoxide::init().unwrap();
+        // the extension bit is set, and the extension is one 32-bit word long
+        let header = [0x90, 0x78, 0, 1, 0, 0, 3, 192, 0, 0, 0, 9];
+        let extension = [0xbe, 0xde, 0, 1];
+        let contents = [1, 2, 3, 4];
+        let key = xchacha20poly1305_ietf::gen_key();
+
+        // the AEAD modes leave the extension's profile and length unencrypted
+        let cipher = Cipher::new(EncryptionMode::XChaCha20Poly1305RtpSize, &key.0).unwrap();
+        let mut packet = [&header[..], &extension].concat();
+        let payload = [&contents[..], b"opus"].concat();
+        packet.extend_from_slice(&cipher.seal(&packet.clone(), &payload, 1));
+        assert_eq!(cipher.open(&packet).as_deref(), Some(&b"opus"[..]));
+
+        // while the original mode encrypts all of it
+        let cipher = Cipher::new(EncryptionMode::XSalsa20Poly1305, &key.0).unwrap();
+        let mut packet = header.to_vec();
+        let payload = [&extension[..], &contents, b"opus"].concat();
+        packet.extend_from_slice(&cipher.seal(&header, &payload, 1));
+        assert_eq!(cipher.open(&packet).as_deref(), Some(&b"opus"[..]));
+
+        // an extension longer than the payload is rejected
+        let cipher = Cipher::new(EncryptionMode::XChaCha20Poly1305RtpSize, &key.0).unwrap();
+        let long = [0xbe, 0xde, 0, 9];
+        let mut packet = [&header[..], &long].concat();
+        packet.extend_from_slice(&cipher.seal(&packet.clone(), b"opus", 2));
+        assert_eq!(cipher.open(&packet), None);
+    }
+}